@@ -0,0 +1,151 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use ipnet::IpNet;
+
+use crate::config::TlsConfig;
+
+// Access control shared by the runner's local HTTP servers (LAN API, admin
+// endpoint). A request must come from an allowed address and, when keys are
+// configured, carry one of them as `Authorization: Bearer <key>`.
+pub struct Policy {
+    keys: Vec<String>,
+    // Empty allows every address
+    allowed_ips: Vec<IpNet>,
+    // Also accept `?token=` for monitors that can't set headers
+    query_token: bool,
+    // Builds the 401/403 response in the server's own error format
+    reject: fn(StatusCode) -> Response,
+}
+
+impl Policy {
+    pub fn new(keys: Vec<String>, allowed_ips: Vec<IpNet>, reject: fn(StatusCode) -> Response) -> Self {
+        Self {
+            keys: keys.into_iter().filter(|k| !k.is_empty()).collect(),
+            allowed_ips,
+            query_token: false,
+            reject,
+        }
+    }
+
+    pub fn with_query_token(mut self) -> Self {
+        self.query_token = true;
+        self
+    }
+
+    pub fn protect<S: Clone + Send + Sync + 'static>(self, router: Router<S>) -> Router<S> {
+        router.route_layer(middleware::from_fn_with_state(Arc::new(self), guard))
+    }
+}
+
+async fn guard(
+    State(policy): State<Arc<Policy>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = peer.ip().to_canonical();
+    if !policy.allowed_ips.is_empty() && !policy.allowed_ips.iter().any(|net| net.contains(&ip)) {
+        return (policy.reject)(StatusCode::FORBIDDEN);
+    }
+    if policy.keys.is_empty() {
+        return next.run(request).await;
+    }
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let query = request.uri().query().filter(|_| policy.query_token).and_then(|q| {
+        url::form_urlencoded::parse(q.as_bytes())
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.into_owned())
+    });
+
+    match bearer.or(query) {
+        Some(given) if policy.keys.iter().any(|key| constant_time_eq(given.as_bytes(), key.as_bytes())) => {
+            next.run(request).await
+        }
+        _ => (policy.reject)(StatusCode::UNAUTHORIZED),
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Binds `addr` and serves `app` in the background, over HTTPS when `tls` is
+// given. Returns the bound address (useful with port 0).
+pub async fn serve(addr: SocketAddr, app: Router, tls: Option<&TlsConfig>) -> Result<SocketAddr, String> {
+    let listener = std::net::TcpListener::bind(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    match tls {
+        Some(tls) => {
+            let (cert, key) = certificate(tls)?;
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let config = RustlsConfig::from_pem_file(&cert, &key)
+                .await
+                .map_err(|e| format!("Failed to load TLS certificate {}: {}", cert.display(), e))?;
+            let server = axum_server::from_tcp_rustls(listener, config);
+            tokio::spawn(async move {
+                let _ = server.serve(service).await;
+            });
+        }
+        None => {
+            let listener = tokio::net::TcpListener::from_std(listener).map_err(|e| e.to_string())?;
+            tokio::spawn(async move {
+                let _ = axum::serve(listener, service).await;
+            });
+        }
+    }
+    Ok(local_addr)
+}
+
+// The configured certificate, or a self-signed one generated on first use
+// and kept in the app's data directory.
+fn certificate(tls: &TlsConfig) -> Result<(PathBuf, PathBuf), String> {
+    match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => return Ok((cert.clone(), key.clone())),
+        (None, None) => {}
+        _ => return Err("tls.cert and tls.key must be set together".to_string()),
+    }
+
+    let dir = dirs_next::data_dir()
+        .ok_or("No data directory for the TLS certificate")?
+        .join("bottlecap-runner")
+        .join("tls");
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+    if cert.exists() && key.exists() {
+        return Ok((cert, key));
+    }
+
+    let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+    if let Some(hostname) = hostname::get().ok().and_then(|h| h.into_string().ok()) {
+        names.push(hostname);
+    }
+    let generated = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    fs::write(&cert, generated.cert.pem()).map_err(|e| e.to_string())?;
+    fs::write(&key, generated.key_pair.serialize_pem()).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&key, fs::Permissions::from_mode(0o600));
+    }
+    Ok((cert, key))
+}
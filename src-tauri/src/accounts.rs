@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use bottlecap_runner_core::error::RunnerError;
+use bottlecap_runner_core::secret_store;
+
+// The account from before there were several: the original keyring entry,
+// connected with the Connect button, the tray and auto-connect
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub id: String,
+    pub name: String,
+}
+
+fn path() -> Option<PathBuf> {
+    tauri::api::path::config_dir().map(|dir| dir.join("bottlecap-runner").join("accounts.json"))
+}
+
+// Accounts added besides the default one. Their tokens are in the keyring.
+pub fn load() -> Vec<Account> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|source| serde_json::from_str(&source).ok())
+        .unwrap_or_default()
+}
+
+fn save(accounts: &[Account]) -> Result<(), String> {
+    let path = path().ok_or("No config directory for accounts")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(accounts).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// "token" for the default account, "token:<id>" for the others
+pub fn secret_name(id: &str) -> String {
+    if id == DEFAULT_ACCOUNT {
+        "token".to_string()
+    } else {
+        format!("token:{}", id)
+    }
+}
+
+pub fn token(id: &str) -> Result<Option<String>, RunnerError> {
+    secret_store::get(&secret_name(id))
+}
+
+// Saves the token and returns the new account, whose id is made from the name
+pub fn add(name: &str, token: &str) -> Result<Account, RunnerError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(RunnerError::InvalidRequest("Account name is empty".to_string()));
+    }
+    let mut accounts = load();
+    let base: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let base = if base.is_empty() { "account".to_string() } else { base };
+    let taken = |id: &str| id == DEFAULT_ACCOUNT || accounts.iter().any(|a| a.id == id);
+    let mut id = base.clone();
+    let mut n = 2;
+    while taken(&id) {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+
+    secret_store::set(&secret_name(&id), token)?;
+    let account = Account { id, name: name.to_string() };
+    accounts.push(account.clone());
+    save(&accounts)?;
+    Ok(account)
+}
+
+pub fn remove(id: &str) -> Result<(), RunnerError> {
+    if id == DEFAULT_ACCOUNT {
+        return Err(RunnerError::InvalidRequest("The default account can't be removed; clear its token instead".to_string()));
+    }
+    secret_store::delete(&secret_name(id))?;
+    let mut accounts = load();
+    accounts.retain(|a| a.id != id);
+    save(&accounts)?;
+    Ok(())
+}
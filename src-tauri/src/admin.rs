@@ -0,0 +1,68 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+
+use crate::access::{self, Policy};
+use crate::config::{AdminConfig, TlsConfig};
+use crate::state::RunnerState;
+
+pub const ADMIN_TOKEN_ENV: &str = "BOTTLECAP_ADMIN_TOKEN";
+
+#[derive(Clone)]
+struct AdminContext {
+    state: Arc<RunnerState>,
+}
+
+// Binds the admin endpoint and serves it in the background. Every route
+// requires the admin token, either as `Authorization: Bearer <token>` or as
+// a `?token=` query parameter for monitors that can't set headers.
+pub async fn start(
+    addr: SocketAddr,
+    token: String,
+    config: &AdminConfig,
+    tls: &TlsConfig,
+    state: Arc<RunnerState>,
+) -> Result<SocketAddr, String> {
+    let policy = Policy::new(vec![token], config.allowed_ips.clone(), reject).with_query_token();
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume));
+    let app = policy.protect(app).with_state(AdminContext { state });
+
+    access::serve(addr, app, config.tls.then_some(tls)).await
+}
+
+fn reject(status: StatusCode) -> Response {
+    let message = if status == StatusCode::FORBIDDEN { "forbidden" } else { "unauthorized" };
+    (status, message).into_response()
+}
+
+async fn healthz(State(ctx): State<AdminContext>) -> (StatusCode, String) {
+    let status = ctx.state.connection_status();
+    if status == "connected" {
+        (StatusCode::OK, "ok".to_string())
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, status)
+    }
+}
+
+async fn status(State(ctx): State<AdminContext>) -> Json<serde_json::Value> {
+    Json(ctx.state.snapshot())
+}
+
+async fn pause(State(ctx): State<AdminContext>) -> Json<serde_json::Value> {
+    ctx.state.set_paused(true);
+    Json(ctx.state.snapshot())
+}
+
+async fn resume(State(ctx): State<AdminContext>) -> Json<serde_json::Value> {
+    ctx.state.set_paused(false);
+    Json(ctx.state.snapshot())
+}
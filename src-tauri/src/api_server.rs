@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::access::{self, Policy};
+use crate::config::{ApiConfig, TlsConfig};
+use crate::events::EventSink;
+use crate::state::RunnerState;
+use crate::backend;
+use crate::connection;
+use crate::error::ErrorInfo;
+use crate::protocol::{ChatMessage, ChatOptions, ClientMessage, ToolCall};
+
+#[derive(Clone)]
+struct ApiContext {
+    state: Arc<RunnerState>,
+    events: Arc<dyn EventSink>,
+    next_id: Arc<AtomicU64>,
+}
+
+// Serves a subset of the OpenAI API so local apps and LAN clients can use
+// the runner directly. Requests wait in the same queue and go through the
+// same handler as relay traffic, and count in the same statistics.
+pub async fn start(
+    config: &ApiConfig,
+    tls: &TlsConfig,
+    state: Arc<RunnerState>,
+    events: Arc<dyn EventSink>,
+) -> Result<SocketAddr, String> {
+    let keys = config.keys();
+    if keys.is_empty() && !config.addr.ip().is_loopback() {
+        return Err(format!("api.api_key is required to serve the API on {}", config.addr));
+    }
+
+    let ctx = ApiContext {
+        state,
+        events,
+        next_id: Arc::new(AtomicU64::new(1)),
+    };
+
+    let app = Router::new()
+        .route("/v1/models", get(models))
+        .route("/v1/chat/completions", post(chat_completions));
+    let app = Policy::new(keys, config.allowed_ips.clone(), reject)
+        .protect(app)
+        .with_state(ctx);
+
+    access::serve(config.addr, app, config.tls.then_some(tls))
+        .await
+        .map_err(|e| format!("API server: {}", e))
+}
+
+fn reject(status: StatusCode) -> Response {
+    if status == StatusCode::FORBIDDEN {
+        api_error(status, "forbidden", "Client address is not allowed")
+    } else {
+        api_error(status, "invalid_api_key", "Invalid API key")
+    }
+}
+
+fn api_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = serde_json::json!({
+        "error": { "message": message, "type": code, "code": code }
+    });
+    (status, Json(body)).into_response()
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+async fn models(State(ctx): State<ApiContext>) -> Response {
+    match backend::advertised_models(&ctx.state.config()).await {
+        Ok(models) => {
+            let data: Vec<_> = models
+                .into_iter()
+                .map(|id| serde_json::json!({ "id": id, "object": "model", "owned_by": "bottlecap-runner" }))
+                .collect();
+            Json(serde_json::json!({ "object": "list", "data": data })).into_response()
+        }
+        Err(e) => api_error(StatusCode::BAD_GATEWAY, "backend_error", &e),
+    }
+}
+
+#[derive(Deserialize)]
+struct CompletionRequest {
+    model: String,
+    messages: Vec<CompletionMessage>,
+    temperature: Option<f32>,
+    max_tokens: Option<i32>,
+    #[serde(default)]
+    stream: bool,
+    frequency_penalty: Option<f32>,
+    presence_penalty: Option<f32>,
+    #[serde(default, deserialize_with = "crate::translate::stop_sequences")]
+    stop: Option<Vec<String>>,
+    logit_bias: Option<HashMap<String, f32>>,
+    response_format: Option<serde_json::Value>,
+    tools: Option<Vec<serde_json::Value>>,
+    tool_choice: Option<serde_json::Value>,
+    top_p: Option<f32>,
+    seed: Option<i64>,
+    user: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionMessage {
+    role: String,
+    #[serde(default)]
+    content: serde_json::Value,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    tool_call_id: Option<String>,
+}
+
+impl CompletionMessage {
+    // Content is either a string or an array of typed parts: text, and
+    // images given as `data:` URLs
+    fn into_chat_message(self) -> ChatMessage {
+        let (content, images) = match self.content {
+            serde_json::Value::String(text) => (text, Vec::new()),
+            serde_json::Value::Array(parts) => (
+                parts
+                    .iter()
+                    .filter_map(|part| part["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                parts
+                    .iter()
+                    .filter_map(|part| part["image_url"]["url"].as_str())
+                    .filter(|url| url.starts_with("data:"))
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => (String::new(), Vec::new()),
+        };
+        ChatMessage {
+            role: self.role,
+            content,
+            images,
+            tool_calls: self.tool_calls,
+            tool_call_id: self.tool_call_id,
+        }
+    }
+}
+
+async fn chat_completions(State(ctx): State<ApiContext>, Json(request): Json<CompletionRequest>) -> Response {
+    let request_id = format!("api-{}", ctx.next_id.fetch_add(1, Ordering::Relaxed));
+    let messages = request
+        .messages
+        .into_iter()
+        .map(CompletionMessage::into_chat_message)
+        .collect();
+    let options = ChatOptions {
+        temperature: request.temperature,
+        max_tokens: request.max_tokens,
+        stream: Some(request.stream),
+        frequency_penalty: request.frequency_penalty,
+        presence_penalty: request.presence_penalty,
+        stop: request.stop,
+        logit_bias: request.logit_bias,
+        response_format: request.response_format,
+        tools: request.tools,
+        tool_choice: request.tool_choice,
+        top_p: request.top_p,
+        seed: request.seed,
+        ..ChatOptions::default()
+    };
+    let call = Call {
+        request_id,
+        model: request.model,
+        requester: request.user,
+    };
+
+    if request.stream {
+        return stream_completion(ctx, call, messages, options).await;
+    }
+
+    let response = serve(&ctx, &call, messages, options, None).await;
+    let ClientMessage::ChatResponse { content, toolCalls, reasoning, error, usage, warnings, .. } = response else {
+        return api_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Unexpected response");
+    };
+
+    if let Some(error) = error {
+        return error_response(&error);
+    }
+
+    let (prompt_tokens, completion_tokens) = usage
+        .map(|u| (u.inputTokens, u.outputTokens))
+        .unwrap_or((0, 0));
+
+    let mut message = serde_json::json!({ "role": "assistant", "content": content.unwrap_or_default() });
+    if let Some(calls) = &toolCalls {
+        message["tool_calls"] = serde_json::json!(calls);
+    }
+    // Not part of the OpenAI schema either; the name DeepSeek's API uses
+    if let Some(reasoning) = reasoning {
+        message["reasoning_content"] = serde_json::json!(reasoning);
+    }
+    let mut body = serde_json::json!({
+        "id": format!("chatcmpl-{}", call.request_id),
+        "object": "chat.completion",
+        "created": unix_secs(),
+        "model": call.model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": if toolCalls.is_some() { "tool_calls" } else { "stop" },
+        }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    });
+    // Not part of the OpenAI schema; clients that don't know it ignore it
+    if let Some(warnings) = warnings {
+        body["warnings"] = serde_json::json!(warnings);
+    }
+    Json(body).into_response()
+}
+
+// One request to the API. `user`, OpenAI's field naming the end user, is
+// the requester the per-requester rate limits count against.
+struct Call {
+    request_id: String,
+    model: String,
+    requester: Option<String>,
+}
+
+// Through the rate limits and the scheduler's queue, then the same handling
+// as relay requests
+async fn serve(
+    ctx: &ApiContext,
+    call: &Call,
+    messages: Vec<ChatMessage>,
+    options: ChatOptions,
+    replies: Option<&mpsc::UnboundedSender<ClientMessage>>,
+) -> ClientMessage {
+    let started = SystemTime::now();
+    let requester = call.requester.as_deref();
+    let events = ctx.events.as_ref();
+    let _ticket = match connection::admit(&ctx.state, events, &call.request_id, &call.model, 0, requester).await {
+        Ok(ticket) => ticket,
+        Err(response) => {
+            connection::record_outcome(&ctx.state, &call.model, started, &response);
+            return response;
+        }
+    };
+    let request_id = call.request_id.clone();
+    let response = connection::handle_chat_request(request_id, call.model.clone(), messages, options, events, &ctx.state, replies).await;
+    connection::charge(&ctx.state, requester, &response);
+    response
+}
+
+fn error_response(error: &ErrorInfo) -> Response {
+    let status = match error.code.as_str() {
+        "runner_paused" | "runner_busy" | "backend_unavailable" => StatusCode::SERVICE_UNAVAILABLE,
+        "rate_limited" => StatusCode::TOO_MANY_REQUESTS,
+        "invalid_request" => StatusCode::BAD_REQUEST,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    let response = api_error(status, &error.code, &error.message);
+    match error.retry_after_secs {
+        Some(secs) => ([(header::RETRY_AFTER, secs.to_string())], response).into_response(),
+        None => response,
+    }
+}
+
+// Answers with server-sent events in OpenAI's `chat.completion.chunk` form.
+// The status is sent once the request is through the queue and the first
+// frame is in, so a request turned away still gets an HTTP error.
+async fn stream_completion(ctx: ApiContext, call: Call, messages: Vec<ChatMessage>, options: ChatOptions) -> Response {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut chunks = Chunks {
+        id: format!("chatcmpl-{}", call.request_id),
+        model: call.model.clone(),
+        created: unix_secs(),
+        tool_calls: 0,
+    };
+    tokio::spawn(async move {
+        // A client that hangs up drops the stream, which stops the generation
+        tokio::select! {
+            response = serve(&ctx, &call, messages, options, Some(&tx)) => {
+                let _ = tx.send(response);
+            }
+            _ = tx.closed() => {}
+        }
+    });
+
+    let Some(first) = rx.recv().await else {
+        return api_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Unexpected response");
+    };
+    if let ClientMessage::ChatResponse { error: Some(error), .. } = &first {
+        return error_response(error);
+    }
+    let frames = futures_util::stream::unfold((Some(first), rx), |(first, mut rx)| async move {
+        let frame = match first {
+            Some(frame) => frame,
+            None => rx.recv().await?,
+        };
+        Some((frame, (None, rx)))
+    });
+    let start = futures_util::stream::iter([chunks.event(serde_json::json!({ "role": "assistant", "content": "" }))]);
+    let events = start
+        .chain(frames.flat_map(move |frame| futures_util::stream::iter(chunks.events(frame))))
+        .map(Ok::<_, Infallible>);
+    Sse::new(events).into_response()
+}
+
+struct Chunks {
+    id: String,
+    model: String,
+    created: u64,
+    // Tool calls sent so far; each gets its own index
+    tool_calls: usize,
+}
+
+impl Chunks {
+    fn chunk(&self, delta: serde_json::Value, finish_reason: Option<&str>) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    }
+
+    fn event(&self, delta: serde_json::Value) -> Event {
+        Event::default().data(self.chunk(delta, None).to_string())
+    }
+
+    // What a relay frame becomes. The last chunk carries the finish reason,
+    // usage and warnings, and is followed by `[DONE]`.
+    fn events(&mut self, frame: ClientMessage) -> Vec<Event> {
+        let ClientMessage::ChatResponse { chunk, toolCalls, reasoning, done, error, usage, warnings, .. } = frame else {
+            return Vec::new();
+        };
+        if let Some(error) = error {
+            let body = serde_json::json!({ "error": { "message": error.message, "type": error.code, "code": error.code } });
+            return vec![Event::default().data(body.to_string())];
+        }
+        let mut events = Vec::new();
+        if let Some(text) = chunk {
+            events.push(self.event(serde_json::json!({ "content": text })));
+        }
+        if let Some(text) = reasoning {
+            events.push(self.event(serde_json::json!({ "reasoning_content": text })));
+        }
+        if let Some(calls) = toolCalls {
+            let first = self.tool_calls;
+            self.tool_calls += calls.len();
+            let calls: Vec<_> = calls
+                .into_iter()
+                .enumerate()
+                .map(|(i, call)| {
+                    serde_json::json!({ "index": first + i, "id": call.id, "type": call.kind, "function": call.function })
+                })
+                .collect();
+            events.push(self.event(serde_json::json!({ "tool_calls": calls })));
+        }
+        if done == Some(true) {
+            let finish_reason = if self.tool_calls > 0 { "tool_calls" } else { "stop" };
+            let mut last = self.chunk(serde_json::json!({}), Some(finish_reason));
+            if let Some(usage) = usage {
+                last["usage"] = serde_json::json!({
+                    "prompt_tokens": usage.inputTokens,
+                    "completion_tokens": usage.outputTokens,
+                    "total_tokens": usage.inputTokens + usage.outputTokens,
+                });
+            }
+            if let Some(warnings) = warnings {
+                last["warnings"] = serde_json::json!(warnings);
+            }
+            events.push(Event::default().data(last.to_string()));
+            events.push(Event::default().data("[DONE]"));
+        }
+        events
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use tauri::{AppHandle, Manager};
+
+use bottlecap_runner_core::{settings, token};
+
+use crate::commands::{connect, AppState};
+
+// How often the clock is compared for sleep detection, and how far the wall
+// clock may run ahead of the monotonic one before that counts as a wake-up.
+// Monotonic time stops while the machine sleeps on macOS and Linux.
+const WAKE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const WAKE_THRESHOLD: Duration = Duration::from_secs(30);
+
+// Connects with the saved token at launch when auto-connect is enabled, and
+// replaces the connection after the machine wakes up, since it has likely
+// gone stale during sleep. A runner disconnected by hand stays disconnected.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        connect_saved(&app, "launch").await;
+
+        let mut last_wall = SystemTime::now();
+        let mut last_mono = Instant::now();
+        loop {
+            tokio::time::sleep(WAKE_CHECK_INTERVAL).await;
+            let wall = SystemTime::now().duration_since(last_wall).unwrap_or_default();
+            let mono = last_mono.elapsed();
+            last_wall = SystemTime::now();
+            last_mono = Instant::now();
+            let connected = app.state::<AppState>().connection.lock().await.is_some();
+            if wall > mono + WAKE_THRESHOLD && connected {
+                connect_saved(&app, "wake").await;
+            }
+        }
+    });
+}
+
+async fn connect_saved(app: &AppHandle, reason: &str) {
+    if settings::load().auto_connect != Some(true) {
+        return;
+    }
+    let token = match token::load_from_keyring() {
+        Ok(Some(token)) => token,
+        _ => return,
+    };
+    tracing::info!("Auto-connecting after {}", reason);
+    connect(token, app.clone(), &app.state::<AppState>()).await;
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, Manager};
+
+use bottlecap_runner_core::settings;
+
+// Passed by the login entry, so a launch at login can start in the tray
+const LAUNCH_ARG: &str = "--autostart";
+
+#[cfg(windows)]
+const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+// Registers the app to launch at login: a value under the Run key on Windows,
+// a LaunchAgent on macOS and an XDG autostart entry elsewhere
+pub fn enable(app: &AppHandle) -> Result<(), String> {
+    let exe = executable()?;
+    let name = app.package_info().name.clone();
+    let identifier = app.config().tauri.bundle.identifier.clone();
+    write_entry(&name, &identifier, &exe)?;
+    tracing::info!("Enabled launching at login");
+    Ok(())
+}
+
+pub fn disable(app: &AppHandle) -> Result<(), String> {
+    let name = app.package_info().name.clone();
+    let identifier = app.config().tauri.bundle.identifier.clone();
+    remove_entry(&name, &identifier)?;
+    tracing::info!("Disabled launching at login");
+    Ok(())
+}
+
+pub fn is_enabled(app: &AppHandle) -> bool {
+    entry_exists(&app.package_info().name, &app.config().tauri.bundle.identifier)
+}
+
+// Hides the window when launched at login with `startMinimized` on; the tray
+// menu brings it back
+pub fn start(app: &AppHandle) {
+    let launched_at_login = std::env::args().skip(1).any(|arg| arg == LAUNCH_ARG);
+    if launched_at_login && settings::load().start_minimized == Some(true) {
+        if let Some(window) = app.get_window("main") {
+            let _ = window.hide();
+        }
+    }
+}
+
+pub fn executable() -> Result<PathBuf, String> {
+    // An AppImage runs from a temporary mount; the image itself is what to launch
+    #[cfg(target_os = "linux")]
+    if let Some(image) = std::env::var_os("APPIMAGE") {
+        return Ok(PathBuf::from(image));
+    }
+    std::env::current_exe().map_err(|e| format!("Failed to find the app's executable: {}", e))
+}
+
+#[cfg(windows)]
+pub fn reg(args: &[&str]) -> Result<(), String> {
+    use std::os::windows::process::CommandExt;
+    let output = std::process::Command::new("reg")
+        .args(args)
+        // CREATE_NO_WINDOW
+        .creation_flags(0x0800_0000)
+        .output()
+        .map_err(|e| format!("Failed to run reg: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn write_entry(name: &str, _identifier: &str, exe: &std::path::Path) -> Result<(), String> {
+    let command = format!("\"{}\" {}", exe.display(), LAUNCH_ARG);
+    reg(&["add", RUN_KEY, "/v", name, "/t", "REG_SZ", "/d", &command, "/f"])
+}
+
+#[cfg(windows)]
+fn remove_entry(name: &str, identifier: &str) -> Result<(), String> {
+    if !entry_exists(name, identifier) {
+        return Ok(());
+    }
+    reg(&["delete", RUN_KEY, "/v", name, "/f"])
+}
+
+#[cfg(windows)]
+fn entry_exists(name: &str, _identifier: &str) -> bool {
+    reg(&["query", RUN_KEY, "/v", name]).is_ok()
+}
+
+#[cfg(not(windows))]
+fn write_entry(name: &str, identifier: &str, exe: &std::path::Path) -> Result<(), String> {
+    let path = entry_path(name, identifier)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&path, entry(name, identifier, exe)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(not(windows))]
+fn remove_entry(name: &str, identifier: &str) -> Result<(), String> {
+    let path = entry_path(name, identifier)?;
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {}", path.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(windows))]
+fn entry_exists(name: &str, identifier: &str) -> bool {
+    entry_path(name, identifier).is_ok_and(|path| path.is_file())
+}
+
+// ~/Library/LaunchAgents/<identifier>.plist
+#[cfg(target_os = "macos")]
+fn entry_path(_name: &str, identifier: &str) -> Result<PathBuf, String> {
+    let home = tauri::api::path::home_dir().ok_or("No home directory")?;
+    Ok(home.join("Library").join("LaunchAgents").join(format!("{}.plist", identifier)))
+}
+
+#[cfg(target_os = "macos")]
+fn entry(_name: &str, identifier: &str, exe: &std::path::Path) -> String {
+    let exe = exe.display().to_string().replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{}</string>
+    <string>{}</string>
+  </array>
+  <key>RunAtLoad</key>
+  <true/>
+</dict>
+</plist>
+"#,
+        identifier, exe, LAUNCH_ARG
+    )
+}
+
+// $XDG_CONFIG_HOME/autostart/<identifier>.desktop
+#[cfg(not(any(windows, target_os = "macos")))]
+fn entry_path(_name: &str, identifier: &str) -> Result<PathBuf, String> {
+    let dir = tauri::api::path::config_dir().ok_or("No config directory")?;
+    Ok(dir.join("autostart").join(format!("{}.desktop", identifier)))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn entry(name: &str, _identifier: &str, exe: &std::path::Path) -> String {
+    format!(
+        "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\" {}\nTerminal=false\nX-GNOME-Autostart-enabled=true\n",
+        name,
+        exe.display(),
+        LAUNCH_ARG
+    )
+}
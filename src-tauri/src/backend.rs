@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::config::{BackendConfig, BackendKind, Config};
+use crate::error::RunnerError;
+use crate::protocol::{ChatMessage, ChatOptions, Chunk, ChunkSink, CompletionPrompt, Input, Reply};
+use crate::{balancer, mock_backend, ollama, openai_backend, tailscale};
+
+// A backend that takes longer to answer a check counts as down
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// An inference engine the runner can serve models from. New engines
+// implement this and get a `BackendKind` so they can be selected in the
+// config; everything above it (relay, local API, direct channels) only sees
+// the trait.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn reachable(&self) -> bool {
+        self.list_models().await.is_ok()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, String>;
+
+    // Request parameters the backend will drop, as human readable warnings
+    fn translation_warnings(&self, options: &ChatOptions) -> Vec<String>;
+
+    async fn chat(&self, model: &str, messages: &[ChatMessage], options: &ChatOptions) -> Result<Reply, RunnerError>;
+
+    // Like `chat`, also handing each piece of text to `on_chunk` as it is
+    // generated
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+        on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+    ) -> Result<Reply, RunnerError>;
+
+    // Continues a raw prompt, streaming when `on_chunk` is given
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &CompletionPrompt,
+        _options: &ChatOptions,
+        _on_chunk: ChunkSink<'_>,
+    ) -> Result<Reply, RunnerError> {
+        Err(RunnerError::Backend("completion requests are not supported by this backend".to_string()))
+    }
+}
+
+pub fn for_config(config: &BackendConfig) -> Box<dyn LlmBackend> {
+    match config.kind {
+        BackendKind::Ollama => Box::new(ollama::Ollama {
+            base_url: config.url.clone(),
+            keep_alive: config.keep_alive.clone(),
+        }),
+        BackendKind::Openai => Box::new(openai_backend::OpenAi {
+            base_url: config.url.clone(),
+            api_key: config.api_key.clone(),
+        }),
+        BackendKind::Mock => Box::new(mock_backend::Mock {
+            config: config.mock.clone(),
+        }),
+    }
+}
+
+// Where the configured backend runs, relative to this machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Local,
+    Lan,
+    Tailscale,
+    Ssh,
+}
+
+pub fn location(config: &Config) -> Location {
+    if config.backend.kind == BackendKind::Mock {
+        return Location::Local;
+    }
+    if config.ssh.enabled {
+        return Location::Ssh;
+    }
+    let url = match url::Url::parse(&config.backend.url) {
+        Ok(url) => url,
+        Err(_) => return Location::Local,
+    };
+    match url.host() {
+        Some(url::Host::Domain(host)) if host.eq_ignore_ascii_case("localhost") => Location::Local,
+        Some(url::Host::Ipv4(ip)) if ip.is_loopback() => Location::Local,
+        Some(url::Host::Ipv6(ip)) if ip.is_loopback() => Location::Local,
+        Some(_) if tailscale::is_tailnet_host(url.host_str().unwrap_or_default()) => Location::Tailscale,
+        Some(_) => Location::Lan,
+        None => Location::Local,
+    }
+}
+
+// With several Ollama servers, whether any of them answers
+pub async fn reachable(config: &BackendConfig) -> bool {
+    if config.kind == BackendKind::Ollama && !config.endpoints.is_empty() {
+        return list_models(config).await.is_ok();
+    }
+    for_config(config).reachable().await
+}
+
+// With several Ollama servers, the models any of them has
+pub async fn list_models(config: &BackendConfig) -> Result<Vec<String>, String> {
+    if config.kind == BackendKind::Ollama && !config.endpoints.is_empty() {
+        return balancer::list_models(config).await;
+    }
+    for_config(config).list_models().await
+}
+
+// The backend's models minus those filtered out in `[models]`
+pub async fn advertised_models(config: &Config) -> Result<Vec<String>, String> {
+    let models = list_models(&config.backend).await?;
+    let mut advertised: Vec<String> = models.into_iter().filter(|model| config.models.allows(model)).collect();
+    // Aliases of the models advertised, after them
+    let mut aliases: Vec<String> = config
+        .models
+        .aliases
+        .iter()
+        .filter(|(alias, target)| advertised.contains(target) && !advertised.contains(alias))
+        .map(|(alias, _)| alias.clone())
+        .collect();
+    aliases.sort();
+    advertised.extend(aliases);
+    Ok(advertised)
+}
+
+pub fn translation_warnings(config: &BackendConfig, options: &ChatOptions) -> Vec<String> {
+    for_config(config).translation_warnings(options)
+}
+
+pub async fn chat(
+    config: &BackendConfig,
+    model: &str,
+    messages: &[ChatMessage],
+    options: &ChatOptions,
+    on_chunk: ChunkSink<'_>,
+) -> Result<Reply, RunnerError> {
+    let backend = for_config(config);
+    match on_chunk {
+        Some(on_chunk) => backend.chat_stream(model, messages, options, on_chunk).await,
+        None => backend.chat(model, messages, options).await,
+    }
+}
+
+pub async fn complete(
+    config: &BackendConfig,
+    model: &str,
+    input: &Input,
+    options: &ChatOptions,
+    on_chunk: ChunkSink<'_>,
+) -> Result<Reply, RunnerError> {
+    match input {
+        Input::Chat(messages) => chat(config, model, messages, options, on_chunk).await,
+        Input::Completion(prompt) => for_config(config).generate(model, prompt, options, on_chunk).await,
+    }
+}
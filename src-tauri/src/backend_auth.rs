@@ -0,0 +1,37 @@
+use std::sync::RwLock;
+
+use crate::config::{BackendAuthConfig, BackendConfig};
+
+// Mirrors `[backend.auth]` for the Ollama client, which is handed a URL
+// rather than the config, the way `proxy` mirrors `[proxy]`. The servers it
+// applies to are kept alongside, so servers found by discovery or tried
+// before switching never see the credentials.
+static AUTH: RwLock<Option<(Vec<String>, BackendAuthConfig)>> = RwLock::new(None);
+
+pub fn set(config: &BackendConfig) {
+    let mut servers = vec![config.url.clone()];
+    servers.extend(config.endpoints.iter().cloned());
+    if let Ok(mut auth) = AUTH.write() {
+        *auth = Some((servers, config.auth.clone()));
+    }
+}
+
+// Adds the configured credentials to a request for the server at `base_url`
+pub fn apply(mut request: reqwest::RequestBuilder, base_url: &str) -> reqwest::RequestBuilder {
+    let Some(auth) = AUTH
+        .read()
+        .ok()
+        .and_then(|auth| auth.as_ref().filter(|(servers, _)| servers.iter().any(|url| url == base_url)).map(|(_, auth)| auth.clone()))
+    else {
+        return request;
+    };
+    if let Some(token) = &auth.bearer_token {
+        request = request.bearer_auth(token);
+    } else if let Some(username) = &auth.username {
+        request = request.basic_auth(username, auth.password.as_ref());
+    }
+    for (name, value) in &auth.headers {
+        request = request.header(name, value);
+    }
+    request
+}
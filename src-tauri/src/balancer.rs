@@ -0,0 +1,158 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::BackendConfig;
+use crate::ollama;
+
+// How long a server that stopped answering is passed over, unless a health
+// check finds it back sooner
+const DOWN_FOR: Duration = Duration::from_secs(30);
+
+// What is known about each Ollama server in `[backend]`
+struct Endpoint {
+    url: String,
+    // Requests being served there
+    busy: usize,
+    down_since: Option<Instant>,
+    // From the last health check that got an answer
+    models: Option<Vec<String>>,
+}
+
+impl Endpoint {
+    fn is_up(&self) -> bool {
+        self.down_since.is_none_or(|since| since.elapsed() >= DOWN_FOR)
+    }
+
+    // Ollama serves a name without a tag as its `latest`
+    fn has(&self, model: &str) -> bool {
+        let latest = format!("{}:latest", model);
+        self.models.as_ref().is_some_and(|models| models.iter().any(|m| m == model || *m == latest))
+    }
+}
+
+static ENDPOINTS: Mutex<Vec<Endpoint>> = Mutex::new(Vec::new());
+
+// `url` and then `endpoints`, without repeats
+pub fn urls(config: &BackendConfig) -> Vec<String> {
+    let mut urls = vec![config.url.clone()];
+    for url in &config.endpoints {
+        if !urls.contains(url) {
+            urls.push(url.clone());
+        }
+    }
+    urls
+}
+
+// Runs `update` on the entry for each of `urls`, creating missing ones and
+// forgetting servers no longer configured
+fn with_endpoints<T>(urls: &[String], update: impl FnOnce(&mut Vec<Endpoint>) -> T) -> Option<T> {
+    let mut endpoints = ENDPOINTS.lock().ok()?;
+    endpoints.retain(|endpoint| urls.contains(&endpoint.url));
+    for url in urls {
+        if !endpoints.iter().any(|endpoint| &endpoint.url == url) {
+            endpoints.push(Endpoint {
+                url: url.clone(),
+                busy: 0,
+                down_since: None,
+                models: None,
+            });
+        }
+    }
+    Some(update(&mut endpoints))
+}
+
+// The health check: asks every server for its models and merges the lists.
+// Fails only when no server answers.
+pub async fn list_models(config: &BackendConfig) -> Result<Vec<String>, String> {
+    let urls = urls(config);
+    let results = futures_util::future::join_all(urls.iter().map(|url| ollama::list_models(url))).await;
+    let mut merged: Vec<String> = Vec::new();
+    let (mut answered, mut error) = (false, None);
+    with_endpoints(&urls, |endpoints| {
+        for (url, result) in urls.iter().zip(results) {
+            let Some(endpoint) = endpoints.iter_mut().find(|endpoint| &endpoint.url == url) else {
+                continue;
+            };
+            match result {
+                Ok(models) => {
+                    if endpoint.down_since.take().is_some() {
+                        tracing::info!("Ollama at {} is answering again", url);
+                    }
+                    for model in &models {
+                        if !merged.contains(model) {
+                            merged.push(model.clone());
+                        }
+                    }
+                    endpoint.models = Some(models);
+                    answered = true;
+                }
+                Err(e) => {
+                    if endpoint.down_since.is_none() {
+                        tracing::warn!("Ollama at {} is not answering: {}", url, e);
+                        endpoint.down_since = Some(Instant::now());
+                    }
+                    error = Some(e);
+                }
+            }
+        }
+    });
+    match error {
+        Some(e) if !answered => Err(e),
+        _ => {
+            merged.sort();
+            Ok(merged)
+        }
+    }
+}
+
+// A request's place on one server, given back when dropped
+pub struct Lease {
+    pub url: String,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Ok(mut endpoints) = ENDPOINTS.lock() {
+            if let Some(endpoint) = endpoints.iter_mut().find(|endpoint| endpoint.url == self.url) {
+                endpoint.busy = endpoint.busy.saturating_sub(1);
+            }
+        }
+    }
+}
+
+// The server to send a request for `model` to: the least busy of those that
+// are up and have it. When none is known to have it, `url`, where auto_pull
+// installs models. When none is up, they are tried all the same.
+pub fn acquire(config: &BackendConfig, model: &str) -> Lease {
+    let urls = urls(config);
+    let url = with_endpoints(&urls, |endpoints| {
+        let any_up = endpoints.iter().any(Endpoint::is_up);
+        let chosen = endpoints
+            .iter_mut()
+            .filter(|endpoint| endpoint.has(model) && (endpoint.is_up() || !any_up))
+            .min_by_key(|endpoint| endpoint.busy);
+        let endpoint = match chosen {
+            Some(endpoint) => endpoint,
+            None => endpoints.iter_mut().find(|endpoint| endpoint.url == config.url)?,
+        };
+        endpoint.busy += 1;
+        Some(endpoint.url.clone())
+    })
+    .flatten();
+    Lease {
+        url: url.unwrap_or_else(|| config.url.clone()),
+    }
+}
+
+// Takes a server that failed a request out of rotation until it answers a
+// health check or `DOWN_FOR` has passed
+pub fn mark_down(url: &str) {
+    if let Ok(mut endpoints) = ENDPOINTS.lock() {
+        if let Some(endpoint) = endpoints.iter_mut().find(|endpoint| endpoint.url == url) {
+            if endpoint.down_since.is_none() {
+                tracing::warn!("Ollama at {} failed a request; sending requests elsewhere", url);
+            }
+            endpoint.down_since = Some(Instant::now());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use serde::Serialize;
+
+use crate::config::BatteryConfig;
+use crate::events::EventSink;
+use crate::state::RunnerState;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BatteryStatus {
+    pub on_battery: bool,
+    // Charge left, 0-100, when the system reports it
+    pub percent: Option<u8>,
+}
+
+impl BatteryStatus {
+    // Whether `config` calls for taking the runner offline
+    pub fn holds(&self, config: &BatteryConfig) -> bool {
+        self.on_battery
+            && (config.pause_on_battery
+                || config.min_charge_percent.is_some_and(|min| self.percent.is_some_and(|percent| percent < min)))
+    }
+}
+
+// None on machines without a battery or when it can't be read
+#[cfg(target_os = "linux")]
+pub fn read() -> Option<BatteryStatus> {
+    let supplies = std::fs::read_dir("/sys/class/power_supply").ok()?;
+    let read = |path: &std::path::Path, name: &str| std::fs::read_to_string(path.join(name)).map(|value| value.trim().to_string());
+    let mut found = None;
+    let mut mains_online = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read(&path, "type").as_deref() {
+            Ok("Battery") => {
+                let discharging = read(&path, "status").is_ok_and(|status| status == "Discharging");
+                let percent = read(&path, "capacity").ok().and_then(|capacity| capacity.parse().ok());
+                // With several batteries, the lowest charge counts
+                found = Some(match found {
+                    Some(BatteryStatus { on_battery, percent: other }) => BatteryStatus {
+                        on_battery: on_battery || discharging,
+                        percent: percent.min(other).or(percent).or(other),
+                    },
+                    None => BatteryStatus { on_battery: discharging, percent },
+                });
+            }
+            Ok("Mains") => mains_online |= read(&path, "online").is_ok_and(|online| online == "1"),
+            _ => {}
+        }
+    }
+    found.map(|status| BatteryStatus {
+        on_battery: status.on_battery && !mains_online,
+        ..status
+    })
+}
+
+// From `pmset -g batt`: "Now drawing from 'Battery Power'" and
+// " -InternalBattery-0 (id=...)	85%; discharging; ..."
+#[cfg(target_os = "macos")]
+pub fn read() -> Option<BatteryStatus> {
+    let output = std::process::Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let battery = text.lines().find(|line| line.contains("InternalBattery"))?;
+    let percent = battery
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .find_map(|word| word.strip_suffix('%'))
+        .and_then(|percent| percent.parse().ok());
+    Some(BatteryStatus {
+        on_battery: text.contains("'Battery Power'"),
+        percent,
+    })
+}
+
+#[cfg(windows)]
+pub fn read() -> Option<BatteryStatus> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    let mut status = SystemPowerStatus::default();
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 || status.battery_flag & 128 != 0 {
+        return None;
+    }
+    Some(BatteryStatus {
+        on_battery: status.ac_line_status == 0,
+        percent: (status.battery_life_percent <= 100).then_some(status.battery_life_percent),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn read() -> Option<BatteryStatus> {
+    None
+}
+
+// Called from the connection loop every `check_interval_secs`: takes the
+// runner offline when the policy says so and back online once plugged in.
+// Changes go out as `battery-status` events.
+pub async fn check(events: &dyn EventSink, state: &RunnerState) {
+    let config = state.config().battery;
+    let status = if config.pause_on_battery || config.min_charge_percent.is_some() {
+        tokio::task::spawn_blocking(read).await.ok().flatten()
+    } else {
+        None
+    };
+    let hold = status.is_some_and(|status| status.holds(&config));
+    if !state.set_battery_hold(hold) {
+        return;
+    }
+    let percent = status.and_then(|status| status.percent).map(|percent| format!(" at {}%", percent)).unwrap_or_default();
+    if hold {
+        tracing::info!("On battery{}; going offline until plugged in", percent);
+    } else {
+        tracing::info!("Back online: no longer limited by battery");
+    }
+    events.emit("battery-status", serde_json::json!({ "held": hold, "battery": status }));
+}
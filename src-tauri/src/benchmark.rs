@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::backend;
+use crate::config::BackendConfig;
+use crate::protocol::{ChatMessage, ChatOptions, Chunk};
+
+// Same prompt and length every run so results are comparable across models
+// and machines
+const PROMPT: &str = "Explain in a few paragraphs how a bicycle stays upright while moving.";
+const MAX_TOKENS: i32 = 256;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Benchmark {
+    pub model: String,
+    pub time_to_first_token_ms: u64,
+    // Generation speed after the first token
+    pub tokens_per_sec: f64,
+    pub output_tokens: u64,
+    // RFC 3339
+    pub ran_at: String,
+}
+
+pub async fn run(config: &BackendConfig, model: &str) -> Result<Benchmark, String> {
+    let messages = [ChatMessage {
+        role: "user".to_string(),
+        content: PROMPT.to_string(),
+        images: Vec::new(),
+        tool_calls: Vec::new(),
+        tool_call_id: None,
+    }];
+    let options = ChatOptions {
+        max_tokens: Some(MAX_TOKENS),
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+
+    let first_token: Mutex<Option<Instant>> = Mutex::new(None);
+    let on_chunk = |_: Chunk| {
+        if let Ok(mut first) = first_token.lock() {
+            first.get_or_insert_with(Instant::now);
+        }
+    };
+    let started = Instant::now();
+    let reply = backend::for_config(config)
+        .chat_stream(model, &messages, &options, &on_chunk)
+        .await?;
+    let finished = Instant::now();
+
+    let first_token = first_token
+        .into_inner()
+        .ok()
+        .flatten()
+        .ok_or("The model produced no output")?;
+    let output_tokens = reply.usage.outputTokens.max(0) as u64;
+    let generating = finished.duration_since(first_token).as_secs_f64();
+    // The first token is not part of the generation time
+    let tokens_per_sec = if generating > 0.0 {
+        output_tokens.saturating_sub(1) as f64 / generating
+    } else {
+        0.0
+    };
+
+    Ok(Benchmark {
+        model: model.to_string(),
+        time_to_first_token_ms: first_token.duration_since(started).as_millis() as u64,
+        tokens_per_sec: (tokens_per_sec * 10.0).round() / 10.0,
+        output_tokens,
+        ran_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("bottlecap-runner").join("benchmarks.json"))
+}
+
+// Latest result per model
+pub fn load() -> BTreeMap<String, Benchmark> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|source| serde_json::from_str(&source).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(benchmark: &Benchmark) -> Result<(), String> {
+    let path = path().ok_or("No config directory for benchmarks")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut results = load();
+    results.insert(benchmark.model.clone(), benchmark.clone());
+    let json = serde_json::to_string_pretty(&results).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
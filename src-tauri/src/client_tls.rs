@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use tokio_tungstenite::Connector;
+
+use crate::config::TlsConfig;
+use crate::proxy;
+
+// Mirrors `[tls] ca_certs` and `insecure` for outgoing connections
+static TRUST: RwLock<Trust> = RwLock::new(Trust {
+    ca_certs: Vec::new(),
+    insecure: false,
+});
+
+#[derive(Clone, Default)]
+struct Trust {
+    ca_certs: Vec<PathBuf>,
+    insecure: bool,
+}
+
+pub fn set(config: &TlsConfig) {
+    if let Ok(mut trust) = TRUST.write() {
+        *trust = Trust {
+            ca_certs: config.ca_certs.clone(),
+            insecure: config.insecure,
+        };
+    }
+    proxy::reset_client();
+}
+
+fn current() -> Trust {
+    TRUST.read().map(|trust| trust.clone()).unwrap_or_default()
+}
+
+// The certificates in a PEM file, each as its own PEM block. A file can
+// hold a whole bundle.
+pub fn read_certificates(path: &Path) -> Result<Vec<String>, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    const END: &str = "-----END CERTIFICATE-----";
+    let blocks: Vec<String> = source
+        .split_inclusive(END)
+        .filter_map(|part| part.find("-----BEGIN CERTIFICATE-----").map(|start| part[start..].to_string()))
+        .filter(|block| block.ends_with(END))
+        .collect();
+    if blocks.is_empty() {
+        return Err(format!("No PEM certificate in {}", path.display()));
+    }
+    Ok(blocks)
+}
+
+fn certificates(trust: &Trust) -> Vec<String> {
+    trust
+        .ca_certs
+        .iter()
+        .flat_map(|path| {
+            read_certificates(path).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring CA certificates: {}", e);
+                Vec::new()
+            })
+        })
+        .collect()
+}
+
+// For `wss://` relays; None keeps tungstenite's default, the system's roots
+pub fn websocket_connector() -> Option<Connector> {
+    let trust = current();
+    if trust.ca_certs.is_empty() && !trust.insecure {
+        return None;
+    }
+    let mut builder = native_tls::TlsConnector::builder();
+    for pem in certificates(&trust) {
+        match native_tls::Certificate::from_pem(pem.as_bytes()) {
+            Ok(certificate) => {
+                builder.add_root_certificate(certificate);
+            }
+            Err(e) => tracing::warn!("Ignoring a CA certificate: {}", e),
+        }
+    }
+    if trust.insecure {
+        tracing::warn!("TLS certificate verification is off ([tls] insecure)");
+        builder.danger_accept_invalid_certs(true);
+    }
+    match builder.build() {
+        Ok(connector) => Some(Connector::NativeTls(connector)),
+        Err(e) => {
+            tracing::error!("Could not set up TLS with the configured CA certificates: {}", e);
+            None
+        }
+    }
+}
+
+// The same trust for reqwest clients
+pub fn apply(mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let trust = current();
+    for pem in certificates(&trust) {
+        match reqwest::Certificate::from_pem(pem.as_bytes()) {
+            Ok(certificate) => builder = builder.add_root_certificate(certificate),
+            Err(e) => tracing::warn!("Ignoring a CA certificate: {}", e),
+        }
+    }
+    if trust.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder
+}
@@ -0,0 +1,994 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::{Manager, State};
+use tokio::sync::Mutex;
+
+use bottlecap_runner_core::connection::{self, online_status, request_frames};
+use bottlecap_runner_core::discovery::BackendFlavor;
+use bottlecap_runner_core::error::RunnerError;
+use bottlecap_runner_core::events::EventSink;
+use bottlecap_runner_core::mock_relay::MockRelay;
+use bottlecap_runner_core::protocol::{LoadedModel, ServerMessage};
+use bottlecap_runner_core::ssh_tunnel::SshTunnel;
+use bottlecap_runner_core::state::RunnerState;
+use bottlecap_runner_core::{
+    api_server, backend, benchmark, config, cost, diagnostics, discovery, e2e, history, logging, metrics, ollama, prometheus, pull, reconnect, recorder, secret_store, settings, signing,
+    tailscale, token, transcripts,
+};
+
+use crate::{accounts, autostart, device_auth, notifications, pairing, tray};
+
+// Forwards runner events to the webview, keeping the tray icon in step with
+// the connection status and showing the notifications turned on in settings
+pub struct AppEvents(pub tauri::AppHandle);
+
+impl EventSink for AppEvents {
+    fn emit(&self, event: &str, payload: serde_json::Value) {
+        if event == "connection-status" {
+            if let Some(status) = payload["status"].as_str() {
+                tray::show_status(&self.0, status);
+            }
+        }
+        // Paused by the schedule while still connected
+        if event == "schedule-status" {
+            let open = payload["open"].as_bool().unwrap_or(true);
+            let status = self.0.state::<AppState>().runner.connection_status();
+            tray::show_status(&self.0, if open { &status } else { "scheduled" });
+        }
+        notifications::on_event(&self.0, event, &payload);
+        let _ = self.0.emit_all(event, payload);
+    }
+}
+
+// Events from another account's connection, tagged with its id so the UI
+// can tell accounts apart. The tray and notifications follow the default
+// account.
+pub struct AccountEvents {
+    pub app: tauri::AppHandle,
+    pub account: String,
+}
+
+impl EventSink for AccountEvents {
+    fn emit(&self, event: &str, mut payload: serde_json::Value) {
+        if let Some(fields) = payload.as_object_mut() {
+            fields.insert("account".to_string(), serde_json::json!(self.account));
+        }
+        let _ = self.app.emit_all(event, payload);
+    }
+}
+
+// Connection state shared across the app
+pub struct AppState {
+    pub connection: Arc<Mutex<Option<ConnectionHandle>>>,
+    pub runner: Arc<RunnerState>,
+    // Connections of the accounts besides the default one, by id
+    pub accounts: std::sync::Mutex<HashMap<String, AccountConnection>>,
+    pub mock_relay: Mutex<Option<MockRelayHandle>>,
+    pub ssh_tunnel: Mutex<Option<SshTunnelHandle>>,
+}
+
+pub struct ConnectionHandle {
+    cancel_token: tokio::sync::oneshot::Sender<()>,
+}
+
+pub struct AccountConnection {
+    handle: ConnectionHandle,
+    runner: Arc<RunnerState>,
+}
+
+pub struct MockRelayHandle {
+    relay: MockRelay,
+    // Relay URL to restore when the mock relay is stopped
+    previous_relay_url: String,
+}
+
+pub struct SshTunnelHandle {
+    // Dropping the handle closes the tunnel
+    _tunnel: SshTunnel,
+    // Backend URL to restore when the tunnel is stopped
+    previous_backend_url: String,
+}
+
+// Tauri commands
+#[tauri::command]
+pub async fn get_saved_token() -> Result<Option<String>, RunnerError> {
+    token::load_from_keyring()
+}
+
+#[tauri::command]
+pub async fn save_token(token: String) -> Result<(), RunnerError> {
+    secret_store::set("token", &token)
+}
+
+#[tauri::command]
+pub async fn clear_token() -> Result<(), RunnerError> {
+    secret_store::delete("token")
+}
+
+#[tauri::command]
+pub async fn check_ollama(state: State<'_, AppState>) -> Result<bool, RunnerError> {
+    Ok(backend::reachable(&state.runner.config().backend).await)
+}
+
+#[tauri::command]
+pub async fn connect_to_partykit(
+    token: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), RunnerError> {
+    // A rejected token fails right away; if the relay can't be reached the
+    // reconnect loop keeps trying
+    if let Ok(check) = token::validate(&state.runner.relay_url(), &token).await {
+        if check.status != token::Validity::Valid {
+            return Err(RunnerError::AuthFailed(check.error.unwrap_or_else(|| "Token rejected".to_string())));
+        }
+    }
+    connect(token, app_handle, &state).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn validate_token(token: String, state: State<'_, AppState>) -> Result<token::TokenCheck, RunnerError> {
+    token::validate(&state.runner.relay_url(), &token)
+        .await
+        .map_err(RunnerError::WsConnect)
+}
+
+// Shared by the Connect button and the tray menu
+pub async fn connect(token: String, app_handle: tauri::AppHandle, state: &AppState) {
+    // Disconnect existing connection if any
+    disconnect_relay(state).await;
+
+    // Create cancel token
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+
+    // Store connection handle
+    {
+        let mut conn = state.connection.lock().await;
+        *conn = Some(ConnectionHandle {
+            cancel_token: cancel_tx,
+        });
+    }
+
+    // Spawn WebSocket connection task, reconnecting until disconnected
+    let events: Arc<dyn EventSink> = Arc::new(AppEvents(app_handle));
+    let runner = state.runner.clone();
+    runner.set_token_secret(Some("token"));
+    tokio::spawn(reconnect::run(token, events, runner, cancel_rx));
+}
+
+pub async fn disconnect_relay(state: &AppState) {
+    let mut conn = state.connection.lock().await;
+    if let Some(handle) = conn.take() {
+        let _ = handle.cancel_token.send(());
+    }
+}
+
+// Applies to the next reconnect attempt
+#[tauri::command]
+pub async fn set_reconnect_policy(policy: config::ReconnectConfig, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    if policy.min_delay_ms > policy.max_delay_ms {
+        return Err(RunnerError::InvalidRequest("min_delay_ms must not exceed max_delay_ms".to_string()));
+    }
+    state.runner.update_config(|config| config.reconnect = policy);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn disconnect(state: State<'_, AppState>) -> Result<(), RunnerError> {
+    disconnect_relay(&state).await;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct AccountStatus {
+    id: String,
+    name: String,
+    status: String,
+}
+
+// The default account first, then the others in the order they were added
+#[tauri::command]
+pub async fn list_accounts(state: State<'_, AppState>) -> Result<Vec<AccountStatus>, RunnerError> {
+    let mut list = vec![AccountStatus {
+        id: accounts::DEFAULT_ACCOUNT.to_string(),
+        name: "Default".to_string(),
+        status: state.runner.connection_status(),
+    }];
+    let connections = state.accounts.lock().map_err(|e| RunnerError::StatePoisoned(e.to_string()))?;
+    for account in accounts::load() {
+        let status = connections
+            .get(&account.id)
+            .map(|connection| connection.runner.connection_status())
+            .unwrap_or_else(|| "disconnected".to_string());
+        list.push(AccountStatus { id: account.id, name: account.name, status });
+    }
+    Ok(list)
+}
+
+#[tauri::command]
+pub async fn add_account(name: String, token: String, state: State<'_, AppState>) -> Result<accounts::Account, RunnerError> {
+    if let Ok(check) = token::validate(&state.runner.relay_url(), &token).await {
+        if check.status != token::Validity::Valid {
+            return Err(RunnerError::AuthFailed(check.error.unwrap_or_else(|| "Token rejected".to_string())));
+        }
+    }
+    accounts::add(&name, &token)
+}
+
+#[tauri::command]
+pub async fn remove_account(id: String, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    disconnect_account_relay(&state, &id);
+    accounts::remove(&id)
+}
+
+// Connects with the account's saved token, next to any other account's
+// connection. Every account serves the same backend and shares its
+// generation slots.
+#[tauri::command]
+pub async fn connect_account(id: String, app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let token = accounts::token(&id)?.ok_or_else(|| RunnerError::InvalidRequest(format!("No saved token for account {}", id)))?;
+    if id == accounts::DEFAULT_ACCOUNT {
+        connect(token, app_handle, &state).await;
+        return Ok(());
+    }
+    if !accounts::load().iter().any(|account| account.id == id) {
+        return Err(RunnerError::InvalidRequest(format!("Unknown account {}", id)));
+    }
+
+    disconnect_account_relay(&state, &id);
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+    let runner = Arc::new(state.runner.for_account());
+    runner.set_token_secret(Some(&accounts::secret_name(&id)));
+    let events: Arc<dyn EventSink> = Arc::new(AccountEvents { app: app_handle, account: id.clone() });
+    tokio::spawn(reconnect::run(token, events, runner.clone(), cancel_rx));
+    if let Ok(mut connections) = state.accounts.lock() {
+        connections.insert(id, AccountConnection { handle: ConnectionHandle { cancel_token: cancel_tx }, runner });
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn disconnect_account(id: String, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    if id == accounts::DEFAULT_ACCOUNT {
+        disconnect_relay(&state).await;
+    } else {
+        disconnect_account_relay(&state, &id);
+    }
+    Ok(())
+}
+
+fn disconnect_account_relay(state: &AppState, id: &str) {
+    let connection = state.accounts.lock().ok().and_then(|mut connections| connections.remove(id));
+    if let Some(connection) = connection {
+        let _ = connection.handle.cancel_token.send(());
+    }
+}
+
+// The other accounts' states follow the default one's config and pause
+fn sync_accounts(app_handle: &tauri::AppHandle) {
+    let state = app_handle.state::<AppState>();
+    let Ok(connections) = state.accounts.lock() else {
+        return;
+    };
+    let config = state.runner.config();
+    for connection in connections.values() {
+        connection.runner.update_config(|current| *current = config.clone());
+        connection.runner.set_paused(state.runner.is_paused());
+    }
+}
+
+#[tauri::command]
+pub async fn get_model_filter(state: State<'_, AppState>) -> Result<config::ModelFilterConfig, RunnerError> {
+    Ok(state.runner.config().models)
+}
+
+// Saved across launches; connected runners re-send their model list
+#[tauri::command]
+pub async fn set_model_filter(
+    filter: config::ModelFilterConfig,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), RunnerError> {
+    let update = settings::Settings {
+        model_filter: Some(filter),
+        ..Default::default()
+    };
+    change_settings(&app_handle, &state.runner, update)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_settings(state: State<'_, AppState>) -> Result<settings::Settings, RunnerError> {
+    Ok(settings::load().effective(&state.runner.config()))
+}
+
+// Saves the fields that are set and applies them. Relay URL, concurrency
+// and heartbeat changes take effect on the next connection.
+#[tauri::command]
+pub async fn update_settings(
+    settings: settings::Settings,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<settings::Settings, RunnerError> {
+    change_settings(&app_handle, &state.runner, settings)
+}
+
+#[tauri::command]
+pub async fn enable_autostart(app_handle: tauri::AppHandle) -> Result<(), RunnerError> {
+    autostart::enable(&app_handle).map_err(RunnerError::AutostartFailed)
+}
+
+#[tauri::command]
+pub async fn disable_autostart(app_handle: tauri::AppHandle) -> Result<(), RunnerError> {
+    autostart::disable(&app_handle).map_err(RunnerError::AutostartFailed)
+}
+
+#[tauri::command]
+pub async fn is_autostart_enabled(app_handle: tauri::AppHandle) -> Result<bool, RunnerError> {
+    Ok(autostart::is_enabled(&app_handle))
+}
+
+// A code to show as a QR code; the dashboard page it opens sends back a
+// pairing link that is accepted without asking
+#[tauri::command]
+pub async fn get_pairing_code() -> Result<pairing::PairingCode, RunnerError> {
+    Ok(pairing::new_code())
+}
+
+#[tauri::command]
+pub async fn pending_pairing() -> Result<bool, RunnerError> {
+    Ok(pairing::requested())
+}
+
+#[tauri::command]
+pub async fn accept_pairing(app_handle: tauri::AppHandle) -> Result<(), RunnerError> {
+    let token = pairing::take_requested().ok_or_else(|| RunnerError::InvalidRequest("No pairing link is waiting".to_string()))?;
+    pairing::pair(&app_handle, token);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn reject_pairing() -> Result<(), RunnerError> {
+    pairing::take_requested();
+    Ok(())
+}
+
+// Signs in through the browser instead of pasting a token: returns the code
+// to show while the approval page is open, and emits `device-auth` when the
+// flow ends. An approved token is saved and connected like a pairing.
+#[tauri::command]
+pub async fn start_device_auth(app_handle: tauri::AppHandle) -> Result<device_auth::DeviceAuth, RunnerError> {
+    device_auth::start(&app_handle).await.map_err(RunnerError::AuthFailed)
+}
+
+#[tauri::command]
+pub async fn cancel_device_auth() -> Result<(), RunnerError> {
+    device_auth::cancel();
+    Ok(())
+}
+
+// The runner's end-to-end encryption public key, base64
+#[tauri::command]
+pub async fn get_e2e_key() -> Result<String, RunnerError> {
+    e2e::public_key().map_err(RunnerError::KeyringError)
+}
+
+// The Ed25519 public key relay frames are signed with, base64
+#[tauri::command]
+pub async fn get_device_key() -> Result<String, RunnerError> {
+    signing::public_key().map_err(RunnerError::KeyringError)
+}
+
+// Replaces the key pair; the relay gets the new public key right away
+#[tauri::command]
+pub async fn rotate_e2e_key(state: State<'_, AppState>) -> Result<String, RunnerError> {
+    let key = e2e::rotate().map_err(RunnerError::KeyringError)?;
+    state.runner.announce_status();
+    Ok(key)
+}
+
+fn change_settings(
+    app_handle: &tauri::AppHandle,
+    state: &RunnerState,
+    update: settings::Settings,
+) -> Result<settings::Settings, RunnerError> {
+    let update = update.validated().map_err(RunnerError::InvalidRequest)?;
+    // Both change what the online status carries
+    let status_changed = update.model_filter.is_some() || update.e2e.is_some();
+
+    let mut saved = settings::load();
+    saved.merge(update.clone());
+    settings::save(&saved).map_err(RunnerError::Storage)?;
+    state.update_config(|config| *config = update.apply(config.clone()));
+    logging::set_level(state.config().logging.level);
+    if status_changed {
+        state.announce_status();
+    }
+
+    sync_accounts(app_handle);
+
+    let current = saved.effective(&state.config());
+    let _ = app_handle.emit_all("settings-changed", serde_json::to_value(&current).unwrap_or_default());
+    Ok(current)
+}
+
+#[tauri::command]
+pub async fn pause_runner(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    set_paused(&app_handle, &state.runner, true);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn resume_runner(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    set_paused(&app_handle, &state.runner, false);
+    Ok(())
+}
+
+// Paused runners stay connected but reject chat requests with
+// `runner_paused`; connections re-announce their status to the server.
+pub fn set_paused(app_handle: &tauri::AppHandle, state: &RunnerState, paused: bool) {
+    state.set_paused(paused);
+    sync_accounts(app_handle);
+    tray::show_paused(app_handle, paused);
+    let _ = app_handle.emit_all("runner-paused", serde_json::json!({ "paused": paused }));
+}
+
+// Runner status plus per-day usage with energy/cost estimates
+#[tauri::command]
+pub async fn get_stats(state: State<'_, AppState>) -> Result<serde_json::Value, RunnerError> {
+    Ok(stats(&state.runner))
+}
+
+fn stats(state: &RunnerState) -> serde_json::Value {
+    let config = state.config();
+    let days: Vec<_> = state
+        .daily_usage()
+        .into_iter()
+        .map(|(date, day)| {
+            let estimate = cost::estimate(&config.cost, day.input_tokens + day.output_tokens, day.busy);
+            serde_json::json!({
+                "date": date,
+                "requests": day.requests,
+                "inputTokens": day.input_tokens,
+                "outputTokens": day.output_tokens,
+                "busySecs": day.busy.as_secs_f64(),
+                "energyWh": estimate.energy_wh,
+                "cost": estimate.cost,
+            })
+        })
+        .collect();
+
+    let mut stats = state.snapshot();
+    stats["currency"] = config.cost.currency.into();
+    stats["days"] = days.into();
+    stats["models"] = state.metrics().snapshot();
+    stats
+}
+
+// Saves the per-model counters and pushes `get_stats` to the dashboard as
+// `stats-updated` every `[metrics] update_interval_secs`. Also starts the
+// Prometheus endpoint when one is configured.
+pub fn start_stats_updates(app: tauri::AppHandle) {
+    let runner = app.state::<AppState>().runner.clone();
+    if let Err(e) = runner.metrics().restore(&runner.config().metrics) {
+        tracing::error!("{}", e);
+    }
+    if let Some(addr) = runner.config().metrics.prometheus_addr {
+        let runner = runner.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = prometheus::start(addr, runner).await {
+                tracing::error!("Prometheus endpoint: {}", e);
+            }
+        });
+    }
+    tauri::async_runtime::spawn(metrics::run(runner.clone(), move |saved| {
+        if let Err(e) = saved {
+            tracing::error!("{}", e);
+        }
+        let _ = app.emit_all("stats-updated", stats(&runner));
+    }));
+}
+
+// Serves the local OpenAI-compatible API when `apiAddr` is set
+pub fn start_api(app: tauri::AppHandle) {
+    let runner = app.state::<AppState>().runner.clone();
+    let config = runner.config();
+    if !config.api.enabled {
+        return;
+    }
+    let events: Arc<dyn EventSink> = Arc::new(AppEvents(app.clone()));
+    tauri::async_runtime::spawn(async move {
+        match api_server::start(&config.api, &config.tls, runner, events).await {
+            Ok(addr) => tracing::info!("Serving the API on {}", addr),
+            Err(e) => tracing::error!("{}", e),
+        }
+    });
+}
+
+// Most recent runner log records, oldest first, for the activity log.
+// `level` leaves out less severe records.
+#[tauri::command]
+pub async fn get_recent_logs(limit: Option<usize>, level: Option<config::LogLevel>) -> Result<Vec<logging::Record>, RunnerError> {
+    Ok(logging::recent(limit.unwrap_or(100), level))
+}
+
+// Launches a local Ollama server that is installed but not running
+#[tauri::command]
+pub async fn start_ollama(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let config = state.runner.config();
+    if config.backend.kind != config::BackendKind::Ollama || backend::location(&config) != backend::Location::Local {
+        return Err(RunnerError::Unsupported("The configured backend is not a local Ollama server".to_string()));
+    }
+    ollama::start(&config.backend.url, &AppEvents(app_handle)).await.map_err(RunnerError::Backend)
+}
+
+// Progress arrives as `model-pull-progress` events; resolves when the pull
+// finishes, fails or is cancelled
+#[tauri::command]
+pub async fn pull_model(model: String, app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    pull::run(&AppEvents(app_handle), &state.runner, &model, pull::PullOptions::default())
+        .await
+        .map_err(RunnerError::PullFailed)
+}
+
+#[tauri::command]
+pub async fn cancel_pull(model: String, state: State<'_, AppState>) -> Result<bool, RunnerError> {
+    Ok(state.runner.cancel_pull(&model))
+}
+
+#[tauri::command]
+pub async fn list_models_detailed(state: State<'_, AppState>) -> Result<Vec<ollama::ModelInfo>, RunnerError> {
+    let config = state.runner.config();
+    if config.backend.kind != config::BackendKind::Ollama {
+        return Err(RunnerError::Unsupported("Model details are only available from Ollama".to_string()));
+    }
+    ollama::list_models_detailed(&config.backend.url).await.map_err(RunnerError::Backend)
+}
+
+// Removes a model from Ollama; the relay gets the shorter model list
+#[tauri::command]
+pub async fn delete_model(model: String, app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let config = state.runner.config();
+    if config.backend.kind != config::BackendKind::Ollama {
+        return Err(RunnerError::Unsupported("Models can only be deleted from Ollama".to_string()));
+    }
+    ollama::delete_model(&config.backend.url, &model).await.map_err(RunnerError::Backend)?;
+    tracing::info!(model = %model, "Deleted {}", model);
+    let _ = connection::refresh_models(&AppEvents(app_handle), &state.runner).await;
+    Ok(())
+}
+
+// Which models Ollama has in memory right now; also emitted as
+// `loaded-models` while connected
+#[tauri::command]
+pub async fn get_loaded_models(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<LoadedModel>, RunnerError> {
+    if state.runner.config().backend.kind != config::BackendKind::Ollama {
+        return Err(RunnerError::Unsupported("Only Ollama reports loaded models".to_string()));
+    }
+    connection::refresh_loaded_models(&AppEvents(app_handle), &state.runner)
+        .await
+        .map_err(RunnerError::Backend)
+}
+
+// Loads a model into Ollama's memory ahead of requests, kept there for the
+// configured keep-alive
+#[tauri::command]
+pub async fn preload_model(model: String, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let config = state.runner.config();
+    if config.backend.kind != config::BackendKind::Ollama {
+        return Err(RunnerError::Unsupported("Only Ollama models can be preloaded".to_string()));
+    }
+    let keep_alive = config.backend.keep_alive.for_model(&model);
+    ollama::preload(&config.backend.url, &model, keep_alive).await.map_err(RunnerError::Backend)?;
+    tracing::info!(model = %model, "Loaded {}", model);
+    Ok(())
+}
+
+// Only meaningful when Ollama runs on this machine
+#[tauri::command]
+pub async fn get_model_disk_usage(state: State<'_, AppState>) -> Result<ollama::DiskUsage, RunnerError> {
+    if backend::location(&state.runner.config()) != backend::Location::Local {
+        return Err(RunnerError::Unsupported("The backend runs on another machine".to_string()));
+    }
+    ollama::disk_usage().map_err(RunnerError::Storage)
+}
+
+// Checks for models added or removed in the backend right away instead of
+// waiting for the next periodic check
+#[tauri::command]
+pub async fn refresh_models(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<Vec<String>, RunnerError> {
+    connection::refresh_models(&AppEvents(app_handle), &state.runner)
+        .await
+        .map_err(RunnerError::Backend)
+}
+
+// Times a fixed prompt on `model` and saves the result; when benchmarks are
+// reported, connected runners re-send their status
+#[tauri::command]
+pub async fn run_benchmark(model: String, state: State<'_, AppState>) -> Result<benchmark::Benchmark, RunnerError> {
+    let config = state.runner.config();
+    let result = benchmark::run(&config.backend, &model).await.map_err(RunnerError::Backend)?;
+    benchmark::save(&result).map_err(RunnerError::Storage)?;
+    if config.relay.report_benchmarks {
+        state.runner.announce_status();
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_benchmarks() -> Result<Vec<benchmark::Benchmark>, RunnerError> {
+    Ok(benchmark::load().into_values().collect())
+}
+
+#[tauri::command]
+pub async fn discover_backends(state: State<'_, AppState>) -> Result<Vec<discovery::DiscoveredBackend>, RunnerError> {
+    discovery::discover(&state.runner.config().discovery)
+        .await
+        .map_err(RunnerError::DiscoveryFailed)
+}
+
+// Tailnet peers, with the ones serving Ollama first
+#[tauri::command]
+pub async fn tailnet_backends() -> Result<Vec<tailscale::TailnetBackend>, RunnerError> {
+    tailscale::backends().await.map_err(RunnerError::DiscoveryFailed)
+}
+
+// Switches to a discovered server after checking it responds, and remembers
+// it across launches. A bare hostname (e.g. a MagicDNS name) gets Ollama's
+// default scheme and port; `flavor` defaults to Ollama.
+#[tauri::command]
+pub async fn use_discovered_backend(
+    url: String,
+    flavor: Option<BackendFlavor>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, RunnerError> {
+    let flavor = flavor.unwrap_or(BackendFlavor::Ollama);
+    let url = config::ollama_url(&url).map_err(RunnerError::InvalidRequest)?;
+    let (kind, url) = flavor.backend(&url);
+    let candidate = config::BackendConfig { kind, url: url.clone(), ..Default::default() };
+    let models = backend::for_config(&candidate)
+        .list_models()
+        .await
+        .map_err(|e| RunnerError::Backend(format!("{} is not an {} server: {}", url, flavor.name(), e)))?;
+    let update = settings::Settings {
+        ollama_url: Some(url),
+        backend_kind: Some(kind),
+        ..Default::default()
+    };
+    change_settings(&app_handle, &state.runner, update)?;
+    Ok(models)
+}
+
+// Named relays `relayUrl` accepts in place of a URL
+#[tauri::command]
+pub async fn get_relay_presets() -> Result<serde_json::Value, RunnerError> {
+    Ok(config::RELAY_PRESETS
+        .iter()
+        .map(|(name, url)| serde_json::json!({ "name": name, "url": url }))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_ollama_url(state: State<'_, AppState>) -> Result<String, RunnerError> {
+    Ok(state.runner.config().backend.url)
+}
+
+// Points the runner at another Ollama server and remembers it across
+// launches. Returns whether the server responds; it is saved either way so a
+// server that is not up yet can be configured ahead of time.
+#[tauri::command]
+pub async fn set_ollama_url(url: String, app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<bool, RunnerError> {
+    let update = settings::Settings {
+        ollama_url: Some(url),
+        backend_kind: Some(config::BackendKind::Ollama),
+        ..Default::default()
+    };
+    let current = change_settings(&app_handle, &state.runner, update)?;
+    Ok(ollama::reachable(&current.ollama_url.unwrap_or_default()).await)
+}
+
+// Dev tools
+// Runs a captured `chat_request` payload through the same handling and
+// serialization as relay traffic and returns the frames that would have
+// been sent back, also emitting each as a `simulated-response` event.
+#[tauri::command]
+pub async fn simulate_request(
+    mut payload: serde_json::Value,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, RunnerError> {
+    if let Some(fields) = payload.as_object_mut() {
+        fields
+            .entry("type")
+            .or_insert_with(|| serde_json::json!("chat_request"));
+    }
+
+    let message: ServerMessage = serde_json::from_value(payload)
+        .map_err(|e| RunnerError::InvalidRequest(format!("Invalid payload: {}", e)))?;
+    let message = e2e::open(&state.runner, message).map_err(|(_, e)| e)?;
+    let Some((request_id, model, input, options)) = message.into_request() else {
+        return Err(RunnerError::InvalidRequest(
+            "Only chat_request and completion_request payloads can be simulated".to_string(),
+        ));
+    };
+
+    let events: Arc<dyn EventSink> = Arc::new(AppEvents(app_handle));
+    let responses = request_frames(request_id, model, input, options, events.as_ref(), &state.runner).await;
+
+    let mut produced = Vec::new();
+    for response in responses {
+        let sent = serde_json::to_value(&response).map_err(|e| RunnerError::Other(e.to_string()))?;
+        events.emit("simulated-response", sent.clone());
+        produced.push(sent);
+    }
+    Ok(produced)
+}
+
+#[tauri::command]
+pub async fn set_session_recording(
+    path: Option<String>,
+    include_content: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<(), RunnerError> {
+    state.runner.update_config(|config| {
+        config.recording.enabled = path.is_some();
+        config.recording.path = path.map(std::path::PathBuf::from);
+        config.recording.include_content = include_content.unwrap_or(false);
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_transcripts_enabled(enabled: bool, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    state.runner.update_config(|config| config.transcripts.enabled = enabled);
+    Ok(())
+}
+
+// Saved conversations as Markdown or JSON lines, optionally limited to a
+// time range
+#[tauri::command]
+pub async fn export_transcripts(
+    range: Option<transcripts::TimeRange>,
+    format: transcripts::ExportFormat,
+    state: State<'_, AppState>,
+) -> Result<String, RunnerError> {
+    transcripts::export(
+        &state.runner.config().transcripts,
+        &range.unwrap_or_default(),
+        format,
+    )
+    .map_err(RunnerError::Storage)
+}
+
+// Zips logs, sanitized settings, system details and the last `requests`
+// request summaries (100 by default) for a bug report; returns the path
+#[tauri::command]
+pub async fn export_diagnostics(
+    path: Option<String>,
+    requests: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<String, RunnerError> {
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => diagnostics::default_path()
+            .ok_or_else(|| RunnerError::Storage("No directory to save diagnostics in".to_string()))?,
+    };
+    diagnostics::export(&state.runner, &path, requests.unwrap_or(100).clamp(1, 500))
+        .await
+        .map_err(RunnerError::Storage)?;
+    Ok(path.display().to_string())
+}
+
+// Newest first, `page_size` entries per page (50 by default)
+#[tauri::command]
+pub async fn get_request_history(
+    range: Option<transcripts::TimeRange>,
+    page: Option<u32>,
+    page_size: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<history::Page, RunnerError> {
+    history::query(
+        &state.runner.config().history,
+        &range.unwrap_or_default(),
+        page.unwrap_or(0),
+        page_size.unwrap_or(50).clamp(1, 500),
+    )
+    .map_err(RunnerError::Storage)
+}
+
+// Clears the given range, or everything; returns how many entries went
+#[tauri::command]
+pub async fn clear_request_history(
+    range: Option<transcripts::TimeRange>,
+    state: State<'_, AppState>,
+) -> Result<u64, RunnerError> {
+    history::clear(&state.runner.config().history, &range.unwrap_or_default()).map_err(RunnerError::Storage)
+}
+
+// Feeds the inbound frames of a recorded session back through the message
+// handlers and returns the frames the runner produces now.
+#[tauri::command]
+pub async fn replay_session(
+    path: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<serde_json::Value>, RunnerError> {
+    let frames = recorder::read_inbound(std::path::Path::new(&path)).map_err(RunnerError::Storage)?;
+    let events: Arc<dyn EventSink> = Arc::new(AppEvents(app_handle));
+    let mut produced = Vec::new();
+
+    for frame in frames {
+        let responses = match serde_json::from_value::<ServerMessage>(frame) {
+            Ok(ServerMessage::AuthSuccess { .. }) => online_status(events.as_ref(), &state.runner)
+                .await
+                .into_iter()
+                .collect(),
+            Ok(ServerMessage::AuthError { .. }) => break,
+            Ok(ServerMessage::CancelRequest { .. })
+            | Ok(ServerMessage::Ack { .. })
+            | Ok(ServerMessage::RtcOffer { .. })
+            | Ok(ServerMessage::RtcClose { .. })
+            | Ok(ServerMessage::Capabilities { .. })
+            | Ok(ServerMessage::TokenRefreshed { .. })
+            | Ok(ServerMessage::Pong)
+            | Ok(ServerMessage::Unknown) => Vec::new(),
+            Ok(message @ (ServerMessage::ChatRequest { .. } | ServerMessage::CompletionRequest { .. })) => {
+                match e2e::open(&state.runner, message).map(ServerMessage::into_request) {
+                    Ok(Some((request_id, model, input, options))) => {
+                        request_frames(request_id, model, input, options, events.as_ref(), &state.runner).await
+                    }
+                    Ok(None) | Err(_) => Vec::new(),
+                }
+            }
+            Err(_) => Vec::new(),
+        };
+
+        for response in responses {
+            let sent = serde_json::to_value(&response).map_err(|e| RunnerError::Other(e.to_string()))?;
+            events.emit("replayed-response", sent.clone());
+            produced.push(sent);
+        }
+    }
+
+    Ok(produced)
+}
+
+#[tauri::command]
+pub async fn start_mock_relay(
+    port: Option<u16>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, RunnerError> {
+    if !cfg!(debug_assertions) {
+        return Err(RunnerError::Unsupported("The mock relay is only available in development builds".to_string()));
+    }
+
+    let mut mock = state.mock_relay.lock().await;
+    if let Some(handle) = mock.as_ref() {
+        return Ok(handle.relay.url().to_string());
+    }
+
+    let relay = MockRelay::start(port.unwrap_or(0), Arc::new(AppEvents(app_handle)))
+        .await
+        .map_err(|e| RunnerError::MockRelay(format!("Failed to start mock relay: {}", e)))?;
+    let url = relay.url().to_string();
+
+    // Point the next connect at the mock relay
+    let previous_relay_url = state.runner.config().relay.url;
+    state.runner.update_config(|config| config.relay.url = url.clone());
+    *mock = Some(MockRelayHandle {
+        relay,
+        previous_relay_url,
+    });
+
+    Ok(url)
+}
+
+#[tauri::command]
+pub async fn mock_relay_send(message: serde_json::Value, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let mock = state.mock_relay.lock().await;
+    let handle = mock
+        .as_ref()
+        .ok_or_else(|| RunnerError::MockRelay("The mock relay is not running".to_string()))?;
+    handle.relay.send(message).map_err(RunnerError::MockRelay)
+}
+
+#[tauri::command]
+pub async fn stop_mock_relay(state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let mut mock = state.mock_relay.lock().await;
+    if let Some(handle) = mock.take() {
+        state
+            .runner
+            .update_config(|config| config.relay.url = handle.previous_relay_url);
+    }
+    Ok(())
+}
+
+// Forwards a local port to a backend on a remote machine and points the
+// runner at it. Reconnects automatically until stopped or the app exits, and
+// is opened again at the next launch until stopped.
+#[tauri::command]
+pub async fn start_ssh_tunnel(
+    mut settings: config::SshConfig,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, RunnerError> {
+    settings.enabled = true;
+    let url = open_ssh_tunnel(&app_handle, &state, settings.clone()).await?;
+    let update = settings::Settings {
+        ssh: Some(settings),
+        ..Default::default()
+    };
+    change_settings(&app_handle, &state.runner, update)?;
+    Ok(url)
+}
+
+#[tauri::command]
+pub async fn stop_ssh_tunnel(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), RunnerError> {
+    let mut current = state.ssh_tunnel.lock().await;
+    if let Some(handle) = current.take() {
+        state
+            .runner
+            .update_config(|config| config.backend.url = handle.previous_backend_url);
+    }
+    drop(current);
+    let update = settings::Settings {
+        ssh: Some(config::SshConfig {
+            enabled: false,
+            ..state.runner.config().ssh
+        }),
+        ..Default::default()
+    };
+    change_settings(&app_handle, &state.runner, update)?;
+    Ok(())
+}
+
+// Opens the tunnel saved in the settings, if any
+pub fn start_saved_ssh_tunnel(app: tauri::AppHandle) {
+    let settings = app.state::<AppState>().runner.config().ssh;
+    if !settings.enabled {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        if let Err(e) = open_ssh_tunnel(&app, &state, settings).await {
+            tracing::error!("{}", e);
+        }
+    });
+}
+
+// Replaces the tunnel open, if any, and points the backend at the new one
+async fn open_ssh_tunnel(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    settings: config::SshConfig,
+) -> Result<String, RunnerError> {
+    let mut current = state.ssh_tunnel.lock().await;
+    let previous_backend_url = match current.take() {
+        Some(handle) => handle.previous_backend_url,
+        None => state.runner.config().backend.url,
+    };
+
+    let tunnel = match SshTunnel::start(&settings, Arc::new(AppEvents(app_handle.clone()))).await {
+        Ok(tunnel) => tunnel,
+        Err(e) => {
+            state
+                .runner
+                .update_config(|config| config.backend.url = previous_backend_url);
+            return Err(RunnerError::SshTunnel(e));
+        }
+    };
+    let url = tunnel.local_url().to_string();
+    state.runner.update_config(|config| {
+        config.backend.url = url.clone();
+        config.ssh = settings;
+    });
+    *current = Some(SshTunnelHandle {
+        _tunnel: tunnel,
+        previous_backend_url,
+    });
+
+    Ok(url)
+}
@@ -0,0 +1,30 @@
+use std::io::{Read, Write};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// Largest frame accepted once inflated, so a small frame can't expand into
+// gigabytes
+const MAX_INFLATED: u64 = 64 * 1024 * 1024;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec can't fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(data)
+        .take(MAX_INFLATED + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| e.to_string())?;
+    if inflated.len() as u64 > MAX_INFLATED {
+        return Err(format!("inflates to more than {} MB", MAX_INFLATED / 1024 / 1024));
+    }
+    Ok(inflated)
+}
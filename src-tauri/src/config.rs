@@ -0,0 +1,1091 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::local_socket;
+use crate::reasoning::ReasoningMode;
+
+pub const DEFAULT_RELAY_URL: &str = "wss://bottlecap-runners.limartinyk.partykit.dev/party/main";
+pub const RELAY_URL_ENV: &str = "BOTTLECAP_RELAY_URL";
+// Names accepted wherever a relay URL is
+pub const RELAY_PRESETS: &[(&str, &str)] = &[
+    ("production", DEFAULT_RELAY_URL),
+    ("staging", "wss://staging.bottlecap-runners.limartinyk.partykit.dev/party/main"),
+    ("local", "ws://localhost:1999/party/main"),
+];
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+pub const OLLAMA_HOST_ENV: &str = "OLLAMA_HOST";
+
+// Runner configuration. Headless setups load it from `config.toml`; every
+// section and key is optional and unknown keys are rejected so typos are
+// reported instead of silently ignored.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub relay: RelayConfig,
+    pub backend: BackendConfig,
+    pub logging: LoggingConfig,
+    pub admin: AdminConfig,
+    pub supervisor: SupervisorConfig,
+    pub recording: RecordingConfig,
+    pub api: ApiConfig,
+    pub discovery: DiscoveryConfig,
+    pub webrtc: WebRtcConfig,
+    pub ssh: SshConfig,
+    pub transcripts: TranscriptConfig,
+    pub tls: TlsConfig,
+    pub cost: CostConfig,
+    pub reconnect: ReconnectConfig,
+    pub history: HistoryConfig,
+    pub metrics: MetricsConfig,
+    pub privacy: PrivacyConfig,
+    pub power: PowerConfig,
+    pub battery: BatteryConfig,
+    pub schedule: ScheduleConfig,
+    pub models: ModelFilterConfig,
+    pub proxy: ProxyConfig,
+    pub e2e: E2eConfig,
+    pub signing: SigningConfig,
+    pub rate_limit: RateLimitConfig,
+    pub limits: LimitsConfig,
+    pub policy: PolicyConfig,
+    pub system_prompt: SystemPromptConfig,
+    pub health: HealthConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    #[serde(deserialize_with = "relay_url_field")]
+    pub url: String,
+    // Tried in order when `url` keeps failing, then back to `url`
+    #[serde(deserialize_with = "relay_url_list")]
+    pub fallback_urls: Vec<String>,
+    // Dropped connections on one relay before moving to the next; a relay
+    // that can't be reached at all is left right away
+    pub failover_after_disconnects: u32,
+    // Chat requests generated at the same time; the rest wait their turn
+    pub max_concurrent_requests: usize,
+    // Lower limits for single models, e.g. one job at a time for a 70B model
+    pub model_concurrency: HashMap<String, usize>,
+    // Requests waiting for a slot; more are refused with `runner_busy`
+    pub max_queued_requests: usize,
+    // Final responses kept until the relay acks them; 0 keeps none
+    pub max_unacked_responses: usize,
+    // Unacked responses older than this are not sent again
+    pub unacked_ttl_secs: u64,
+    // Request ids remembered to catch the relay delivering one twice; 0
+    // turns the check off
+    pub recent_requests: usize,
+    pub duplicate_requests: DuplicateAction,
+    // Gzip frames longer than `compress_min_bytes`, if the relay lists
+    // `gzip` in its capabilities
+    pub compression: bool,
+    pub compress_min_bytes: usize,
+    // MessagePack frames instead of JSON, if the relay lists `msgpack`
+    pub msgpack: bool,
+    // How long before an expiring token runs out a new one is asked for
+    pub refresh_before_secs: u64,
+    pub heartbeat_interval_secs: u64,
+    // Reconnect when nothing, not even a pong, arrived for this long
+    pub stale_after_secs: u64,
+    // How often queue depth and throughput are reported; 0 turns it off
+    pub load_report_interval_secs: u64,
+    // CPU, RAM, GPUs and OS in the online status
+    pub report_hardware: bool,
+    // Saved benchmark results in the online status
+    pub report_benchmarks: bool,
+}
+
+impl RelayConfig {
+    // `url` first, then the fallbacks
+    pub fn endpoints(&self) -> Vec<&str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.fallback_urls.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            url: std::env::var(RELAY_URL_ENV)
+                .ok()
+                .and_then(|url| relay_url(&url).ok())
+                .unwrap_or_else(|| DEFAULT_RELAY_URL.to_string()),
+            fallback_urls: Vec::new(),
+            failover_after_disconnects: 3,
+            max_concurrent_requests: 4,
+            model_concurrency: HashMap::new(),
+            max_queued_requests: 32,
+            max_unacked_responses: 64,
+            unacked_ttl_secs: 600,
+            recent_requests: 256,
+            duplicate_requests: DuplicateAction::Replay,
+            compression: false,
+            compress_min_bytes: 1024,
+            msgpack: false,
+            refresh_before_secs: 300,
+            heartbeat_interval_secs: 30,
+            stale_after_secs: 90,
+            load_report_interval_secs: 10,
+            report_hardware: true,
+            report_benchmarks: false,
+        }
+    }
+}
+
+// What a repeated requestId gets once its request was answered; one still
+// being served is ignored either way
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    // The frames sent the first time, again
+    #[default]
+    Replay,
+    // A `duplicate_request` error
+    Reject,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    // For Ollama, also a Unix socket or named pipe (see local_socket.rs)
+    #[serde(deserialize_with = "backend_url")]
+    pub url: String,
+    // More Ollama servers, e.g. one per GPU, that requests are spread over
+    // along with `url`
+    #[serde(deserialize_with = "ollama_url_list")]
+    pub endpoints: Vec<String>,
+    // Sent as a bearer token to OpenAI-compatible servers
+    pub api_key: Option<String>,
+    // Credentials for Ollama servers behind a reverse proxy
+    pub auth: BackendAuthConfig,
+    // Generations still running after this long are abandoned
+    pub request_timeout_secs: u64,
+    // How often the model list is checked for changes; 0 turns it off
+    pub model_refresh_secs: u64,
+    // How often Ollama is asked which models are in memory; 0 turns it off
+    pub loaded_models_refresh_secs: u64,
+    // Pull models that are requested but not installed (Ollama only), up to
+    // `auto_pull_max_gb`
+    pub auto_pull: bool,
+    pub auto_pull_max_gb: f64,
+    pub keep_alive: KeepAliveConfig,
+    pub retry: RetryConfig,
+    // Largest image attachment accepted, per image
+    pub max_image_mb: f64,
+    // What happens to `<think>` blocks when a request doesn't say
+    pub reasoning: ReasoningMode,
+    pub mock: MockBackendConfig,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: BackendKind::default(),
+            url: std::env::var(OLLAMA_HOST_ENV)
+                .ok()
+                .and_then(|host| ollama_url(&host).ok())
+                .unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string()),
+            endpoints: Vec::new(),
+            api_key: None,
+            auth: BackendAuthConfig::default(),
+            request_timeout_secs: 300,
+            model_refresh_secs: 30,
+            loaded_models_refresh_secs: 10,
+            auto_pull: false,
+            auto_pull_max_gb: 10.0,
+            keep_alive: KeepAliveConfig::default(),
+            retry: RetryConfig::default(),
+            max_image_mb: 10.0,
+            reasoning: ReasoningMode::default(),
+            mock: MockBackendConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    // OpenAI-compatible `/v1/chat/completions` server
+    Openai,
+    Mock,
+}
+
+// Canned responses for exercising the runner without a real model
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockBackendConfig {
+    pub models: Vec<String>,
+    // Fixed response text; lorem ipsum of `response_tokens` words if unset
+    pub response: Option<String>,
+    pub response_tokens: usize,
+    pub first_token_delay_ms: u64,
+    pub token_delay_ms: u64,
+    // Probability (0.0 - 1.0) that a request fails
+    #[serde(deserialize_with = "probability")]
+    pub failure_rate: f64,
+}
+
+impl Default for MockBackendConfig {
+    fn default() -> Self {
+        Self {
+            models: vec!["mock:latest".to_string()],
+            response: None,
+            response_tokens: 64,
+            first_token_delay_ms: 200,
+            token_delay_ms: 20,
+            failure_rate: 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: LogLevel,
+    pub output: LogOutput,
+    // Daily log files, kept for `keep_days`
+    pub file: bool,
+    // Defaults to logs/ in the app's data directory
+    pub dir: Option<PathBuf>,
+    pub keep_days: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::default(),
+            output: LogOutput::default(),
+            file: true,
+            dir: None,
+            keep_days: 7,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    Stdout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub addr: Option<SocketAddr>,
+    pub token: Option<String>,
+    // Empty allows every address
+    pub allowed_ips: Vec<ipnet::IpNet>,
+    pub tls: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    pub max_memory_mb: Option<u64>,
+    pub max_retries: Option<u32>,
+}
+
+// Reconnect policy for the app's relay connection (headless mode uses the
+// supervisor settings instead)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectConfig {
+    pub min_delay_ms: u64,
+    pub max_delay_ms: u64,
+    // Unlimited when unset
+    pub max_retries: Option<u32>,
+    // A connection that stays up this long resets the attempt count
+    pub stable_after_secs: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            min_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            max_retries: None,
+            stable_after_secs: 60,
+        }
+    }
+}
+
+// Opt-in capture of relay frames for later replay
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConfig {
+    pub enabled: bool,
+    pub path: Option<PathBuf>,
+    // Keep prompt and response text instead of redacting it
+    pub include_content: bool,
+}
+
+// Conversations served by this runner, kept for `export_transcripts`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptConfig {
+    pub enabled: bool,
+    // Defaults to transcripts.jsonl in the app's data directory
+    pub path: Option<PathBuf>,
+}
+
+// Which local models are advertised to the relay and served. A pattern
+// without a tag covers every tag of that model, and a trailing `*` matches
+// any suffix. An empty allowlist allows everything not denied.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelFilterConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    // Names requesters may use for a local model, e.g. "fast" for
+    // "llama3.1:8b-instruct-q4_K_M". The filter applies to the local name.
+    pub aliases: HashMap<String, String>,
+}
+
+impl ModelFilterConfig {
+    // The local model a requested name stands for. Aliases don't chain.
+    pub fn resolve<'a>(&'a self, model: &'a str) -> &'a str {
+        self.aliases.get(model).map_or(model, String::as_str)
+    }
+
+    pub fn allows(&self, model: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None if pattern.contains(':') => model == pattern,
+            None => model.split(':').next() == Some(pattern.as_str()),
+        };
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+// Sent with every request to the configured Ollama servers: a bearer token,
+// or else basic auth, and any other headers the proxy in front wants
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendAuthConfig {
+    pub bearer_token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(deserialize_with = "header_map")]
+    pub headers: HashMap<String, String>,
+}
+
+// Requests sent again when the backend can't be reached or fails with a
+// 5xx, e.g. while Ollama restarts or a model is loading. 4xx answers would
+// fail the same way and are never retried.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    // 0 turns retries off
+    pub max_retries: u32,
+    pub min_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            min_delay_ms: 500,
+            max_delay_ms: 5000,
+        }
+    }
+}
+
+// How long Ollama keeps a model in memory after a request: a duration like
+// "10m" or "1h30m", a number of seconds, or a negative number to keep it
+// loaded. Unset leaves Ollama's own default (5 minutes).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeepAliveConfig {
+    #[serde(deserialize_with = "keep_alive", skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    // By model name, overriding `default`
+    #[serde(deserialize_with = "keep_alive_by_model")]
+    pub models: HashMap<String, String>,
+}
+
+impl KeepAliveConfig {
+    pub fn for_model(&self, model: &str) -> Option<&str> {
+        self.models.get(model).or(self.default.as_ref()).map(String::as_str)
+    }
+
+    pub fn validated(self) -> Result<Self, String> {
+        for value in self.default.iter().chain(self.models.values()) {
+            keep_alive_value(value)?;
+        }
+        Ok(self)
+    }
+}
+
+// Ollama takes numbers as seconds and strings as Go durations, so "-1" has
+// to go out as a number
+pub fn keep_alive_value(value: &str) -> Result<serde_json::Value, String> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Ok(serde_json::json!(seconds));
+    }
+    let invalid = || format!("Invalid keep-alive `{}`: expected e.g. 10m, 1h30m, 300 or -1", value);
+    let mut rest = value.strip_prefix('-').unwrap_or(value);
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(invalid)?;
+        let unit_len = ["ns", "us", "ms", "h", "m", "s"]
+            .iter()
+            .find(|unit| rest[digits..].starts_with(*unit))
+            .map(|unit| unit.len())
+            .ok_or_else(invalid)?;
+        if digits == 0 || rest[..digits].parse::<f64>().is_err() {
+            return Err(invalid());
+        }
+        rest = &rest[digits + unit_len..];
+    }
+    Ok(serde_json::json!(value))
+}
+
+// Per-request metadata (timing, tokens, outcome) for `get_request_history`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    // Defaults to history.sqlite3 in the app's data directory
+    pub path: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+// With `enabled`, message content is never written anywhere: transcripts
+// are not kept, session recordings and log fields have content redacted,
+// and request history stores error codes instead of messages
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    pub enabled: bool,
+}
+
+// End-to-end encryption of chat content between requesters and this runner.
+// When enabled the online status carries the runner's public key.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct E2eConfig {
+    pub enabled: bool,
+    // Refuse chat requests that aren't sealed
+    pub required: bool,
+}
+
+// Ed25519 signatures on relay frames. With `enabled` the runner signs what
+// it sends with its device key. With `relay_key` set, chat and completion
+// requests must be signed with it, recently and only once.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    pub enabled: bool,
+    // The relay's public key, base64
+    #[serde(deserialize_with = "public_key_field", skip_serializing_if = "Option::is_none")]
+    pub relay_key: Option<String>,
+    // Requests signed longer ago than this, or this far ahead, are refused
+    pub max_age_secs: u64,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            relay_key: None,
+            max_age_secs: 300,
+        }
+    }
+}
+
+impl SigningConfig {
+    // An empty `relay_key` clears it
+    pub fn validated(mut self) -> Result<Self, String> {
+        self.relay_key = self
+            .relay_key
+            .filter(|key| !key.trim().is_empty())
+            .map(|key| public_key(&key))
+            .transpose()?;
+        Ok(self)
+    }
+}
+
+// Caps on what requesters get from this runner, as token buckets: a
+// requester (by the `requesterId` on its requests) and the runner as a whole
+// each get their own. 0 leaves a cap off.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: u32,
+    // Prompt and generated tokens, counted once a request is answered
+    pub tokens_per_hour: u64,
+    pub global_requests_per_minute: u32,
+    pub global_tokens_per_hour: u64,
+}
+
+// Largest prompts accepted and most tokens generated; 0 leaves a limit off.
+// Prompt tokens are counted locally (see tokens.rs).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_prompt_chars: usize,
+    pub max_prompt_tokens: usize,
+    // Messages in a chat request, system and tool results included
+    pub max_messages: usize,
+    // Ceiling on the tokens generated per request; requests asking for more,
+    // or for no limit, get this
+    pub max_tokens: i32,
+    // Ceilings for single models, in place of `max_tokens`
+    pub model_max_tokens: HashMap<String, i32>,
+}
+
+// Checks prompts go through before they reach the model, in order; the first
+// one to object refuses the request with `policy_violation`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub filters: Vec<PolicyFilter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PolicyFilter {
+    // Regular expressions no message may match
+    Blocklist {
+        #[serde(deserialize_with = "regex_list")]
+        patterns: Vec<String>,
+    },
+    // Images across all messages
+    MaxAttachments { max: usize },
+    // A local moderation model such as Llama Guard, which answers `safe`, or
+    // `unsafe` and the categories it found
+    Moderation { model: String },
+}
+
+// A system prompt put ahead of every request's messages, with `{device_name}`
+// and `{model}` filled in
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemPromptConfig {
+    pub default: Option<String>,
+    // Per model, in place of `default`; "" adds none for that model
+    pub models: HashMap<String, String>,
+}
+
+// Per-model counters behind `get_stats`, kept across restarts
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    // Defaults to metrics.json in the app's data directory
+    pub path: Option<PathBuf>,
+    // How often the counters are saved and `stats-updated` is emitted
+    pub update_interval_secs: u64,
+    // Serves Prometheus metrics at `/metrics` when set
+    pub prometheus_addr: Option<SocketAddr>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            update_interval_secs: 5,
+            prometheus_addr: None,
+        }
+    }
+}
+
+// Keeps the machine from sleeping while connected and serving requests
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub prevent_sleep: bool,
+    // How long after the last request the machine stays awake
+    pub recent_secs: u64,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            prevent_sleep: true,
+            recent_secs: 300,
+        }
+    }
+}
+
+// Backend checks while connected. After `failure_threshold` failures in a
+// row, requests are turned away with `backend_unavailable` until one passes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    // 0 turns the checks, and so the circuit breaker, off
+    pub check_interval_secs: u64,
+    pub failure_threshold: u32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 10,
+            failure_threshold: 3,
+        }
+    }
+}
+
+// Takes the runner offline on battery power, back online when plugged in
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    // Offline whenever running on battery
+    pub pause_on_battery: bool,
+    // Offline on battery below this charge
+    pub min_charge_percent: Option<u8>,
+    pub check_interval_secs: u64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            pause_on_battery: false,
+            min_charge_percent: None,
+            check_interval_secs: 30,
+        }
+    }
+}
+
+// Weekly hours the runner serves in, in local time. Outside them it pauses,
+// or disconnects with `action = "disconnect"`. No windows means no limit.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub enabled: bool,
+    pub action: ScheduleAction,
+    pub windows: Vec<ScheduleWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleAction {
+    #[default]
+    Pause,
+    Disconnect,
+}
+
+// From `start` to `end` ("HH:MM") on each of `days` ("mon" to "sun", every
+// day when empty). An `end` at or before `start` runs into the next day.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleWindow {
+    #[serde(default, deserialize_with = "weekdays")]
+    pub days: Vec<String>,
+    #[serde(deserialize_with = "clock_time")]
+    pub start: String,
+    #[serde(deserialize_with = "clock_time")]
+    pub end: String,
+}
+
+// OpenAI-compatible HTTP API for clients on the local network
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    pub addr: SocketAddr,
+    // A key is required unless `addr` is a loopback address
+    pub api_key: Option<String>,
+    // Additional accepted keys, e.g. one per client
+    pub api_keys: Vec<String>,
+    // Empty allows every address
+    pub allowed_ips: Vec<ipnet::IpNet>,
+    pub tls: bool,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            addr: SocketAddr::from(([127, 0, 0, 1], 11435)),
+            api_key: None,
+            api_keys: Vec::new(),
+            allowed_ips: Vec::new(),
+            tls: false,
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn keys(&self) -> Vec<String> {
+        self.api_key.iter().chain(&self.api_keys).cloned().collect()
+    }
+}
+
+// Certificate for local servers with `tls = true`. Without one a
+// self-signed certificate is generated on first use. `ca_certs` and
+// `insecure` are for outgoing connections: the relay and HTTPS backends.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    // PEM files of root certificates trusted besides the system's, e.g. a
+    // corporate proxy's that re-signs traffic
+    pub ca_certs: Vec<PathBuf>,
+    // Skips certificate verification, for self-hosted relays with
+    // self-signed certificates. Development only.
+    pub insecure: bool,
+}
+
+// What serving requests costs on this machine; see cost.rs for how the
+// values combine. Nothing is estimated when all of them are unset.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostConfig {
+    pub wh_per_token: Option<f64>,
+    // Extra power draw while generating
+    pub watts: Option<f64>,
+    pub price_per_kwh: Option<f64>,
+    pub cost_per_hour: Option<f64>,
+    pub currency: String,
+    // Attach per-request estimates to chat responses sent to the relay
+    pub report: bool,
+}
+
+impl Default for CostConfig {
+    fn default() -> Self {
+        Self {
+            wh_per_token: None,
+            watts: None,
+            price_per_kwh: None,
+            cost_per_hour: None,
+            currency: "USD".to_string(),
+            report: false,
+        }
+    }
+}
+
+// Where `discover_backends` looks for Ollama / LM Studio servers
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    pub mdns: bool,
+    // Probed on the default ports; the local /24 when empty
+    pub subnets: Vec<ipnet::Ipv4Net>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            mdns: true,
+            subnets: Vec::new(),
+        }
+    }
+}
+
+// Direct data channels to requesters (needs the `webrtc` cargo feature)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebRtcConfig {
+    pub enabled: bool,
+    pub ice_servers: Vec<String>,
+}
+
+impl Default for WebRtcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ice_servers: vec!["stun:stun.l.google.com:19302".to_string()],
+        }
+    }
+}
+
+// A proxy for the relay connection and HTTP calls, for networks without
+// direct egress. Without `url`, HTTPS_PROXY, HTTP_PROXY and ALL_PROXY (and
+// NO_PROXY) from the environment apply.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    // http://, socks5:// or socks5h:// (name resolved by the proxy), with
+    // optional user:password@
+    #[serde(deserialize_with = "proxy_url_field", skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // Hosts reached directly: names (matching subdomains too), IP addresses
+    // or CIDR ranges, or `*`. Loopback addresses always are.
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    // An empty `url` clears it
+    pub fn validated(mut self) -> Result<Self, String> {
+        self.url = self
+            .url
+            .filter(|url| !url.trim().is_empty())
+            .map(|url| proxy_url(&url))
+            .transpose()?;
+        Ok(self)
+    }
+}
+
+// Reach a backend on another machine through an SSH port forward. When
+// enabled the backend URL is replaced with the local end of the tunnel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SshConfig {
+    pub enabled: bool,
+    pub host: Option<String>,
+    pub port: u16,
+    // Defaults to the ssh client's own choice (~/.ssh/config, $USER)
+    pub user: Option<String>,
+    // Falls back to the ssh agent and default keys
+    pub identity_file: Option<PathBuf>,
+    // Backend address as seen from the SSH host
+    pub remote_addr: String,
+    pub local_port: u16,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: None,
+            port: 22,
+            user: None,
+            identity_file: None,
+            remote_addr: "127.0.0.1:11434".to_string(),
+            local_port: 11436,
+        }
+    }
+}
+
+pub struct ConfigError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let source = std::fs::read_to_string(path).map_err(|e| ConfigError {
+        message: format!("Failed to read {}: {}", path.display(), e),
+        line: None,
+        column: None,
+    })?;
+
+    toml::from_str(&source).map_err(|e| {
+        let position = e.span().map(|span| line_column(&source, span.start));
+        let location = match position {
+            Some((line, column)) => format!("{}:{}:{}", path.display(), line, column),
+            None => path.display().to_string(),
+        };
+        ConfigError {
+            message: format!("{}: {}", location, e.message()),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        }
+    })
+}
+
+// Accepts the forms `OLLAMA_HOST` takes: a full URL, `host:port` or a bare
+// host, which gets Ollama's default scheme and port. A wildcard bind address
+// means the server is on this machine.
+pub fn ollama_url(value: &str) -> Result<String, String> {
+    let value = value.trim().trim_end_matches('/');
+    if value.is_empty() {
+        return Err("Ollama URL is empty".to_string());
+    }
+    if local_socket::is_socket_url(value) {
+        return Ok(value.to_string());
+    }
+    let value = if value.contains("://") {
+        value.to_string()
+    } else if value.contains(':') && !value.ends_with(']') {
+        format!("http://{}", value)
+    } else {
+        format!("http://{}:11434", value)
+    };
+    let mut url = url::Url::parse(&value).map_err(|e| format!("Invalid Ollama URL `{}`: {}", value, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("expected an http or https URL, got `{}`", value));
+    }
+    if matches!(url.host_str(), Some("0.0.0.0") | Some("[::]")) {
+        let _ = url.set_host(Some("localhost"));
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+// 1-based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn url_with_scheme<'de, D>(deserializer: D, schemes: &[&str]) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    check_url(&value, schemes).map_err(serde::de::Error::custom)
+}
+
+fn check_url(value: &str, schemes: &[&str]) -> Result<String, String> {
+    let parsed = url::Url::parse(value).map_err(|e| e.to_string())?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(format!("expected a {} URL, got `{}`", schemes.join(" or "), value));
+    }
+    Ok(value.trim_end_matches('/').to_string())
+}
+
+// A ws:// or wss:// URL, or the name of one of `RELAY_PRESETS`
+pub fn relay_url(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if let Some((_, url)) = RELAY_PRESETS.iter().find(|(name, _)| name.eq_ignore_ascii_case(value)) {
+        return Ok(url.to_string());
+    }
+    if !value.contains("://") {
+        let names: Vec<&str> = RELAY_PRESETS.iter().map(|(name, _)| *name).collect();
+        return Err(format!("Invalid relay URL: expected a ws:// or wss:// URL or one of {}", names.join(", ")));
+    }
+    check_url(value, &["ws", "wss"]).map_err(|e| format!("Invalid relay URL: {}", e))
+}
+
+pub fn proxy_url(value: &str) -> Result<String, String> {
+    check_url(value.trim(), &["http", "socks5", "socks5h"]).map_err(|e| format!("Invalid proxy URL: {}", e))
+}
+
+// A base64 Ed25519 public key
+pub fn public_key(value: &str) -> Result<String, String> {
+    use base64::Engine;
+    let value = value.trim();
+    match base64::engine::general_purpose::STANDARD.decode(value) {
+        Ok(bytes) if bytes.len() == 32 => Ok(value.to_string()),
+        _ => Err(format!("Invalid public key {:?}: expected 32 bytes in base64", value)),
+    }
+}
+
+fn public_key_field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    public_key(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn regex_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    regex::RegexSet::new(&patterns).map_err(serde::de::Error::custom)?;
+    Ok(patterns)
+}
+
+fn probability<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(serde::de::Error::custom(format!(
+            "expected a value between 0.0 and 1.0, got {}",
+            value
+        )));
+    }
+    Ok(value)
+}
+
+fn keep_alive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    keep_alive_value(&value).map_err(serde::de::Error::custom)?;
+    Ok(Some(value))
+}
+
+fn keep_alive_by_model<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    let values = HashMap::<String, String>::deserialize(deserializer)?;
+    for value in values.values() {
+        keep_alive_value(value).map_err(serde::de::Error::custom)?;
+    }
+    Ok(values)
+}
+
+fn weekdays<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let days = Vec::<String>::deserialize(deserializer)?;
+    for day in &days {
+        day.parse::<chrono::Weekday>()
+            .map_err(|_| serde::de::Error::custom(format!("Invalid day `{}`: expected mon, tue, ... sun", day)))?;
+    }
+    Ok(days)
+}
+
+fn clock_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    chrono::NaiveTime::parse_from_str(&value, "%H:%M")
+        .map_err(|_| serde::de::Error::custom(format!("Invalid time `{}`: expected HH:MM", value)))?;
+    Ok(value)
+}
+
+fn relay_url_field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    relay_url(&value).map_err(serde::de::Error::custom)
+}
+
+fn relay_url_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|url| relay_url(url).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn ollama_url_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|url| ollama_url(url).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+// Header names and values reqwest will send
+fn header_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    let headers = HashMap::<String, String>::deserialize(deserializer)?;
+    for (name, value) in &headers {
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| serde::de::Error::custom(format!("Invalid header name `{}`", name)))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| serde::de::Error::custom(format!("Invalid value for header `{}`", name)))?;
+    }
+    Ok(headers)
+}
+
+fn backend_url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    url_with_scheme(deserializer, &["http", "https", "unix", "npipe"])
+}
+
+fn proxy_url_field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    proxy_url(&value).map(Some).map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,221 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::config::{BackendKind, Config, MockBackendConfig};
+use crate::events::EventSink;
+use crate::state::RunnerState;
+
+// Matches any value in an expected message (e.g. the device name)
+const ANY: &str = "<any>";
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A scripted relay session. Steps run in order against a runner connected
+// to a local WebSocket server and backed by the mock backend.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Transcript {
+    name: String,
+    #[serde(default = "default_token")]
+    token: String,
+    #[serde(default)]
+    mock: Option<MockBackendConfig>,
+    steps: Vec<Step>,
+}
+
+fn default_token() -> String {
+    "conformance-token".to_string()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    // Server message sent to the runner
+    Send(serde_json::Value),
+    // The same, gzipped in a binary frame
+    SendGzip(serde_json::Value),
+    // The same as MessagePack in a binary frame
+    SendMsgpack(serde_json::Value),
+    // Next client message, compared exactly apart from "<any>" values
+    Expect(serde_json::Value),
+    // The runner closes the connection
+    ExpectClose,
+}
+
+struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit(&self, _event: &str, _payload: serde_json::Value) {}
+}
+
+// Runs every `*.json` transcript in `dir` and prints one JSON line per
+// transcript. Returns the process exit code.
+pub fn run(dir: &Path) -> i32 {
+    let mut paths: Vec<_> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", dir.display(), e);
+            return 2;
+        }
+    };
+    paths.sort();
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start runtime: {}", e);
+            return 2;
+        }
+    };
+
+    let mut failures = 0;
+    for path in &paths {
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| serde_json::from_str::<Transcript>(&source).map_err(|e| e.to_string()))
+            .map(|transcript| {
+                let name = transcript.name.clone();
+                (name, runtime.block_on(run_transcript(transcript)))
+            });
+
+        let line = match result {
+            Ok((name, Ok(()))) => serde_json::json!({ "transcript": name, "result": "pass" }),
+            Ok((name, Err(error))) => {
+                failures += 1;
+                serde_json::json!({ "transcript": name, "result": "fail", "error": error })
+            }
+            Err(error) => {
+                failures += 1;
+                serde_json::json!({ "transcript": path.display().to_string(), "result": "invalid", "error": error })
+            }
+        };
+        println!("{}", line);
+    }
+
+    println!("{}", serde_json::json!({ "total": paths.len(), "failed": failures }));
+    if failures == 0 { 0 } else { 1 }
+}
+
+async fn run_transcript(transcript: Transcript) -> Result<(), String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+
+    let mut config = Config::default();
+    config.relay.url = format!("ws://{}", addr);
+    config.backend.kind = BackendKind::Mock;
+    config.history.enabled = false;
+    config.relay.load_report_interval_secs = 0;
+    config.relay.report_hardware = false;
+    // Only used once a transcript's relay lists `gzip` or `msgpack`
+    config.relay.compression = true;
+    config.relay.msgpack = true;
+    config.backend.mock = transcript.mock.unwrap_or_else(|| MockBackendConfig {
+        first_token_delay_ms: 0,
+        token_delay_ms: 0,
+        ..MockBackendConfig::default()
+    });
+    let state = Arc::new(RunnerState::new(config));
+
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+    let runner = tokio::spawn(crate::connection::run_connection(
+        transcript.token,
+        Arc::new(NullSink),
+        state,
+        cancel_rx,
+    ));
+
+    let (stream, _) = tokio::time::timeout(STEP_TIMEOUT, listener.accept())
+        .await
+        .map_err(|_| "runner did not connect".to_string())?
+        .map_err(|e| e.to_string())?;
+    let mut ws = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let result = run_steps(&mut ws, &transcript.steps).await;
+
+    let _ = cancel_tx.send(());
+    let _ = runner.await;
+    result
+}
+
+async fn run_steps(ws: &mut WebSocketStream<TcpStream>, steps: &[Step]) -> Result<(), String> {
+    for (index, step) in steps.iter().enumerate() {
+        let step_no = index + 1;
+        match step {
+            Step::Send(message) => {
+                ws.send(Message::Text(message.to_string()))
+                    .await
+                    .map_err(|e| format!("step {}: send failed: {}", step_no, e))?;
+            }
+            Step::SendGzip(message) => {
+                ws.send(Message::Binary(crate::compression::gzip(message.to_string().as_bytes())))
+                    .await
+                    .map_err(|e| format!("step {}: send failed: {}", step_no, e))?;
+            }
+            Step::SendMsgpack(message) => {
+                let data = crate::msgpack::to_vec(message).map_err(|e| format!("step {}: {}", step_no, e))?;
+                ws.send(Message::Binary(data))
+                    .await
+                    .map_err(|e| format!("step {}: send failed: {}", step_no, e))?;
+            }
+            Step::Expect(expected) => {
+                let actual = next_message(ws)
+                    .await
+                    .ok_or_else(|| format!("step {}: expected {} but the connection ended", step_no, expected))??;
+                if !matches(expected, &actual) {
+                    return Err(format!("step {}: expected {} got {}", step_no, expected, actual));
+                }
+            }
+            Step::ExpectClose => {
+                if let Some(message) = next_message(ws).await {
+                    return Err(format!("step {}: expected close got {:?}", step_no, message));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Next text or binary frame as JSON; None once the runner disconnects
+async fn next_message(ws: &mut WebSocketStream<TcpStream>) -> Option<Result<serde_json::Value, String>> {
+    loop {
+        let frame = match tokio::time::timeout(STEP_TIMEOUT, ws.next()).await {
+            Ok(frame) => frame,
+            Err(_) => return Some(Err("timed out waiting for the runner".to_string())),
+        };
+        match frame {
+            Some(Ok(data @ (Message::Text(_) | Message::Binary(_)))) => {
+                return Some(crate::framing::decode(&data).map_err(|e| format!("invalid frame from runner: {}", e)));
+            }
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return None,
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+fn matches(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    use serde_json::Value;
+    match (expected, actual) {
+        (Value::String(s), _) if s == ANY => true,
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .all(|(key, value)| actual.get(key).is_some_and(|a| matches(value, a)))
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| matches(e, a))
+        }
+        _ => expected == actual,
+    }
+}
@@ -0,0 +1,1161 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::dedup::Seen;
+use crate::error::RunnerError;
+use crate::events::EventSink;
+use crate::protocol::{
+    self, BackendNote, ChatMessage, ChatOptions, Chunk, ChunkSink, ClientMessage, Input, LoadedModel, Reply,
+    ServerMessage, ToolCall, Usage,
+};
+use crate::reasoning::{self, ReasoningMode};
+use crate::recorder::{Direction, Recorder};
+use crate::framing::Framing;
+use crate::scheduler::{Limits, Ticket};
+use crate::state::RunnerState;
+use crate::ollama::PullProgress;
+use crate::{
+    backend, balancer, battery, benchmark, config, cost, e2e, framing, hardware, health, history, images, limits, ollama,
+    policy, proxy, pull, retry, schedule, signing, system_prompt, token, tokens, transcripts, translate, webrtc_direct,
+};
+
+// How a relay connection ended. The GUI only cares about the events emitted
+// along the way; headless mode maps this to a process exit code.
+pub enum ConnectionOutcome {
+    Cancelled,
+    Closed,
+    // Outside the schedule, with `action = "disconnect"`
+    Scheduled,
+    ConnectFailed(String),
+    AuthFailed(String),
+    SocketError(String),
+}
+
+// How long a `refresh_token` waits for an answer before it is sent again
+const REFRESH_RETRY: std::time::Duration = std::time::Duration::from_secs(60);
+
+// Why requests are cancelled when their connection ends
+const CONNECTION_CLOSED: &str = "aborted, relay connection closed";
+
+// A backend check run aside, so the relay is still served while a backend
+// that doesn't answer holds it up. What it finds goes into `state`. A check
+// is skipped while the last one is still running, and is aborted with the
+// connection.
+#[derive(Default)]
+struct Probe(Option<tokio::task::JoinHandle<()>>);
+
+impl Probe {
+    fn start(&mut self, check: impl std::future::Future<Output = ()> + Send + 'static) {
+        if self.0.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        self.0 = Some(tokio::spawn(async move {
+            if tokio::time::timeout(backend::PROBE_TIMEOUT, check).await.is_err() {
+                tracing::warn!("The backend did not answer within {}s", backend::PROBE_TIMEOUT.as_secs());
+            }
+        }));
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abort();
+        }
+    }
+}
+
+// Requests still being served when the connection ends, whether cancelled
+// or dropped, are aborted so their generations stop using the GPU
+pub async fn run_connection(
+    token: String,
+    events: Arc<dyn EventSink>,
+    state: Arc<RunnerState>,
+    cancel_rx: tokio::sync::oneshot::Receiver<()>,
+) -> ConnectionOutcome {
+    let outcome = serve_connection(token, events, state.clone(), cancel_rx).await;
+    state.cancel_all_requests(CONNECTION_CLOSED);
+    outcome
+}
+
+async fn serve_connection(
+    token: String,
+    events: Arc<dyn EventSink>,
+    state: Arc<RunnerState>,
+    mut cancel_rx: tokio::sync::oneshot::Receiver<()>,
+) -> ConnectionOutcome {
+    let relay_url = state.relay_url();
+    state.set_relay_capabilities(None);
+    let report_status = |mut status: serde_json::Value| {
+        if let Some(s) = status["status"].as_str() {
+            state.set_connection_status(s);
+        }
+        status["relay"] = serde_json::json!(relay_url);
+        events.emit("connection-status", status);
+    };
+
+    let schedule = state.config().schedule;
+    if schedule.action == config::ScheduleAction::Disconnect && !schedule::is_open_now(&schedule) {
+        report_status(serde_json::json!({
+            "status": "scheduled"
+        }));
+        return ConnectionOutcome::Scheduled;
+    }
+
+    // Emit connecting status
+    report_status(serde_json::json!({
+        "status": "connecting"
+    }));
+
+    // Connect to WebSocket
+    let ws_result = proxy::connect_websocket(relay_url.as_str()).await;
+
+    let (ws_stream, _) = match ws_result {
+        Ok(stream) => stream,
+        Err(e) => {
+            let error = format!("WebSocket connection failed: {}", e);
+            report_status(serde_json::json!({
+                "status": "error",
+                "error": error
+            }));
+            return ConnectionOutcome::ConnectFailed(error);
+        }
+    };
+
+    let (mut write, mut read) = ws_stream.split();
+
+    let recorder = match Recorder::open(&state.config().recording) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            tracing::error!("Session recording disabled: {}", e);
+            None
+        }
+    };
+    let recorder = recorder.as_ref();
+    let mut direct = webrtc_direct::Sessions::default();
+
+    // Chat requests run in their own tasks so a slow generation doesn't hold
+    // up the read loop; everything they send goes through one writer channel.
+    let (replies_tx, mut replies_rx) = mpsc::unbounded_channel::<ClientMessage>();
+    let mut status_changed = state.watch_status();
+    let mut authenticated = false;
+
+    // Our own pings keep traffic flowing; a connection that has sent nothing
+    // (not even a pong) for `stale_after_secs` is treated as dead.
+    let relay_config = state.config().relay;
+    let heartbeat_period = std::time::Duration::from_secs(relay_config.heartbeat_interval_secs.max(1));
+    let stale_after = std::time::Duration::from_secs(relay_config.stale_after_secs);
+    let load_reports = relay_config.load_report_interval_secs > 0;
+    let load_period = std::time::Duration::from_secs(relay_config.load_report_interval_secs.max(1));
+    let mut load_report = tokio::time::interval_at(tokio::time::Instant::now() + load_period, load_period);
+    let model_refresh_secs = state.config().backend.model_refresh_secs;
+    let model_period = std::time::Duration::from_secs(model_refresh_secs.max(1));
+    let mut model_refresh = tokio::time::interval_at(tokio::time::Instant::now() + model_period, model_period);
+    let loaded_refresh_secs = state.config().backend.loaded_models_refresh_secs;
+    let polls_loaded = state.config().backend.kind == config::BackendKind::Ollama;
+    let loaded_period = std::time::Duration::from_secs(loaded_refresh_secs.max(1));
+    let mut loaded_refresh = tokio::time::interval(loaded_period);
+    let battery_period = std::time::Duration::from_secs(state.config().battery.check_interval_secs.max(1));
+    let mut battery_check = tokio::time::interval(battery_period);
+    let mut schedule_check = tokio::time::interval(schedule::CHECK_INTERVAL);
+    let health_secs = state.config().health.check_interval_secs;
+    let mut health_check = tokio::time::interval(std::time::Duration::from_secs(health_secs.max(1)));
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + heartbeat_period, heartbeat_period);
+    let mut last_seen = std::time::Instant::now();
+    let mut models_probe = Probe::default();
+    let mut loaded_probe = Probe::default();
+    let mut health_probe = Probe::default();
+
+    // Compression and MessagePack are set once the relay lists its capabilities
+    let signs = state.config().signing.enabled;
+    let mut framing = Framing { sign: signs, ..Framing::default() };
+
+    // Send auth message
+    let device_key = signs.then(signing::public_key).and_then(|key| {
+        key.map_err(|e| tracing::error!("Frames go out unsigned: {}", e)).ok()
+    });
+    // `token` stays the one the connection was started with, for
+    // `current_token` to recognize
+    let original = token;
+    let mut token = state.current_token(&original);
+    let mut expires_at = token::expiry(&token);
+    // When to ask for a new token; set on auth_success
+    let mut refresh_at: Option<tokio::time::Instant> = None;
+    let auth_msg = ClientMessage::auth(token.clone(), device_key.clone());
+    if let Err(e) = send_message(&mut write, recorder, framing, &auth_msg).await {
+        let error = format!("Failed to send auth: {}", e);
+        report_status(serde_json::json!({
+            "status": "error",
+            "error": error
+        }));
+        return ConnectionOutcome::ConnectFailed(error);
+    }
+
+    // Process messages
+    loop {
+        tokio::select! {
+            _ = &mut cancel_rx => {
+                report_status(serde_json::json!({
+                    "status": "disconnected"
+                }));
+                return ConnectionOutcome::Cancelled;
+            }
+            Some(reply) = replies_rx.recv() => {
+                let _ = send_message(&mut write, recorder, framing, &reply).await;
+            }
+            // Tell the server when the runner is paused or its models change
+            Ok(()) = status_changed.changed(), if authenticated => {
+                if let Some(status_msg) = online_status(events.as_ref(), &state).await {
+                    let _ = send_message(&mut write, recorder, framing, &status_msg).await;
+                }
+            }
+            _ = load_report.tick(), if authenticated && load_reports => {
+                let _ = send_message(&mut write, recorder, framing, &load_status(&state)).await;
+            }
+            _ = model_refresh.tick(), if authenticated && model_refresh_secs > 0 => {
+                let (events, state) = (events.clone(), state.clone());
+                models_probe.start(async move {
+                    let _ = refresh_models(events.as_ref(), &state).await;
+                });
+            }
+            _ = loaded_refresh.tick(), if authenticated && loaded_refresh_secs > 0 && polls_loaded => {
+                let (events, state) = (events.clone(), state.clone());
+                loaded_probe.start(async move {
+                    let _ = refresh_loaded_models(events.as_ref(), &state).await;
+                });
+            }
+            _ = battery_check.tick(), if authenticated => {
+                battery::check(events.as_ref(), &state).await;
+            }
+            _ = health_check.tick(), if authenticated && health_secs > 0 => {
+                let (events, state) = (events.clone(), state.clone());
+                health_probe.start(async move {
+                    health::check(events.as_ref(), &state).await;
+                });
+            }
+            _ = schedule_check.tick(), if authenticated => {
+                if schedule::check(events.as_ref(), &state) {
+                    report_status(serde_json::json!({
+                        "status": "scheduled"
+                    }));
+                    return ConnectionOutcome::Scheduled;
+                }
+            }
+            _ = async { tokio::time::sleep_until(refresh_at.unwrap_or_else(tokio::time::Instant::now)).await }, if authenticated && refresh_at.is_some() => {
+                if expires_at.is_some_and(|at| at <= unix_now()) {
+                    tracing::warn!("The runner token expired before the relay refreshed it");
+                    refresh_at = None;
+                    continue;
+                }
+                tracing::info!("Asking the relay for a new token before this one expires");
+                let _ = send_message(&mut write, recorder, framing, &ClientMessage::RefreshToken { token: token.clone() }).await;
+                // Asked again if no new token comes
+                refresh_at = Some(tokio::time::Instant::now() + REFRESH_RETRY);
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() >= stale_after {
+                    let error = format!("No messages from the relay for {}s", last_seen.elapsed().as_secs());
+                    report_status(serde_json::json!({
+                        "status": "stale",
+                        "error": error
+                    }));
+                    return ConnectionOutcome::SocketError(error);
+                }
+                let _ = write.send(Message::Ping(Vec::new())).await;
+            }
+            msg = read.next() => {
+                if let Some(Ok(_)) = msg {
+                    last_seen = std::time::Instant::now();
+                }
+                match msg {
+                    // Gzipped or MessagePack frames are read as the same JSON
+                    Some(Ok(data @ (Message::Text(_) | Message::Binary(_)))) => {
+                        let frame = match framing::decode(&data) {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                tracing::error!("Malformed relay message: {}", e);
+                                continue;
+                            }
+                        };
+                        if let Some(recorder) = recorder {
+                            match &data {
+                                Message::Text(text) => recorder.record(Direction::In, text),
+                                _ => recorder.record(Direction::In, &frame.to_string()),
+                            }
+                        }
+                        let server_msg = match ServerMessage::deserialize(&frame) {
+                            Ok(server_msg) => server_msg,
+                            Err(e) => {
+                                tracing::error!("Malformed relay message: {}", e);
+                                continue;
+                            }
+                        };
+                        match server_msg {
+                            ServerMessage::AuthSuccess { expiresAt, .. } => {
+                                expires_at = expiresAt.or(expires_at);
+                                refresh_at = refresh_deadline(expires_at, state.config().relay.refresh_before_secs);
+                                // Answering a reauthentication with a refreshed token;
+                                // requests carry on
+                                if authenticated {
+                                    tracing::info!("Reauthenticated with the refreshed token");
+                                    continue;
+                                }
+                                authenticated = true;
+                                status_changed.mark_unchanged();
+                                report_status(serde_json::json!({
+                                    "status": "connected"
+                                }));
+
+                                // Send available models to server
+                                if let Some(status_msg) = online_status(events.as_ref(), &state).await {
+                                    let _ = send_message(&mut write, recorder, framing, &status_msg).await;
+                                }
+
+                                // Responses the last connection may have lost
+                                let unacked = state.outbox().unacked(&state.config().relay);
+                                if !unacked.is_empty() {
+                                    tracing::info!("Resending {} unacknowledged responses", unacked.len());
+                                }
+                                for json in unacked {
+                                    let _ = send_text(&mut write, recorder, framing, json).await;
+                                }
+                            }
+                            ServerMessage::AuthError { error } => {
+                                let error = error.unwrap_or_else(|| "Authentication failed".to_string());
+                                report_status(serde_json::json!({
+                                    "status": "auth_failed",
+                                    "error": error
+                                }));
+                                return ConnectionOutcome::AuthFailed(error);
+                            }
+                            message @ (ServerMessage::ChatRequest { .. } | ServerMessage::CompletionRequest { .. }) => {
+                                let request_id = message.request_id().unwrap_or_default().to_string();
+                                match signing::verify(&state.config().signing, &frame) {
+                                    Ok(()) => {}
+                                    Err(signing::Refusal::Replayed) => {
+                                        tracing::warn!(requestId = %request_id, "Ignoring replayed request {}", request_id);
+                                        continue;
+                                    }
+                                    Err(signing::Refusal::Invalid(e)) => {
+                                        tracing::warn!(requestId = %request_id, "Refused request {}: {}", request_id, e);
+                                        let _ = send_message(&mut write, recorder, framing, &error_response(request_id, RunnerError::BadSignature(e))).await;
+                                        continue;
+                                    }
+                                }
+                                let message = match e2e::open(&state, message) {
+                                    Ok(message) => message,
+                                    Err((request_id, e)) => {
+                                        let _ = send_message(&mut write, recorder, framing, &error_response(request_id, e)).await;
+                                        continue;
+                                    }
+                                };
+                                let priority = message.priority();
+                                let requester = message.requester_id().map(str::to_string);
+                                let Some((request_id, model, input, options)) = message.into_request() else {
+                                    continue;
+                                };
+                                let relay = state.config().relay;
+                                let seen = state.recent_requests().begin(&request_id, relay.recent_requests);
+                                // Opening a repeat of an answered sealed request started a
+                                // session nothing else would end
+                                if matches!(seen, Seen::Done(_)) {
+                                    state.e2e_sessions().end(&request_id);
+                                }
+                                match seen {
+                                    Seen::New => {}
+                                    Seen::Running => {
+                                        tracing::info!(requestId = %request_id, "Ignoring duplicate request {}: still being served", request_id);
+                                        continue;
+                                    }
+                                    Seen::Done(frames) if relay.duplicate_requests == config::DuplicateAction::Replay => {
+                                        tracing::info!(requestId = %request_id, "Replaying the response to duplicate request {}", request_id);
+                                        for json in frames {
+                                            let _ = send_text(&mut write, recorder, framing, json).await;
+                                        }
+                                        continue;
+                                    }
+                                    Seen::Done(_) => {
+                                        tracing::info!(requestId = %request_id, "Rejected duplicate request {}", request_id);
+                                        let _ = send_message(&mut write, recorder, framing, &error_response(request_id, RunnerError::Duplicate)).await;
+                                        continue;
+                                    }
+                                }
+                                let replies = replies_tx.clone();
+                                let events = events.clone();
+                                let state = state.clone();
+                                let mut cancelled = state.track_request(&request_id);
+                                tokio::spawn(async move {
+                                    let id = request_id.clone();
+                                    let name = model.clone();
+                                    let streaming = options.stream == Some(true);
+                                    let started = std::time::SystemTime::now();
+                                    let handling = async {
+                                        let _ticket = match admit(&state, events.as_ref(), &request_id, &model, priority, requester.as_deref()).await {
+                                            Ok(ticket) => ticket,
+                                            Err(response) => {
+                                                record_outcome(&state, &model, started, &response);
+                                                return response;
+                                            }
+                                        };
+                                        handle_request(request_id, model, input, options, events.as_ref(), &state, Some(&replies)).await
+                                    };
+                                    // Dropping the handling future drops the backend
+                                    // request, which stops the generation
+                                    let mut connection_lost = false;
+                                    let response = tokio::select! {
+                                        response = handling => response,
+                                        Ok(reason) = &mut cancelled => {
+                                            connection_lost = reason == CONNECTION_CLOSED;
+                                            tracing::info!(requestId = %id, model = %name, "Request {} {}", id, reason);
+                                            let error = if streaming && connection_lost {
+                                                RunnerError::StreamInterrupted
+                                            } else {
+                                                RunnerError::Cancelled
+                                            };
+                                            let mut response = error_response(id.clone(), error);
+                                            // Numbered after the chunks that went out, so the
+                                            // requester can tell how many it missed
+                                            if let ClientMessage::ChatResponse { seq, .. } = &mut response {
+                                                *seq = streaming.then(|| state.frames_sent(&id).load(Ordering::Relaxed));
+                                            }
+                                            record_outcome(&state, &name, started, &response);
+                                            response
+                                        }
+                                    };
+                                    charge(&state, requester.as_deref(), &response);
+                                    let response = e2e::seal(&state, response);
+                                    state.finish_request(&id);
+                                    state.recent_requests().finish(&response);
+                                    let relay = state.config().relay;
+                                    if state.relay_supports("ack") && relay.max_unacked_responses > 0 {
+                                        state.outbox().keep(&response, &relay);
+                                    } else if connection_lost {
+                                        // The connection it came in on is gone; sent once
+                                        // reconnected, so the requester isn't left waiting on
+                                        // the rest of the stream
+                                        state.outbox().resend(&response, &relay);
+                                    }
+                                    let _ = replies.send(response);
+                                });
+                            }
+                            ServerMessage::CancelRequest { requestId } => {
+                                state.cancel_request(&requestId);
+                            }
+                            ServerMessage::Ack { requestId } => {
+                                state.outbox().ack(&requestId);
+                            }
+                            ServerMessage::RtcOffer { sessionId, sdp } => {
+                                let answer = direct.answer(sessionId, sdp, events.clone(), state.clone()).await;
+                                let _ = send_message(&mut write, recorder, framing, &answer).await;
+                            }
+                            ServerMessage::RtcClose { sessionId } => direct.close(&sessionId),
+                            ServerMessage::Capabilities { protocolVersion, capabilities } => {
+                                let version = protocolVersion.unwrap_or(1);
+                                if version > protocol::PROTOCOL_VERSION {
+                                    tracing::info!(
+                                        "The relay speaks protocol version {} and this runner {}; update the runner for newer features",
+                                        version,
+                                        protocol::PROTOCOL_VERSION
+                                    );
+                                }
+                                let relay = state.config().relay;
+                                let lists = |name: &str| capabilities.iter().any(|c| c == name);
+                                framing = Framing {
+                                    gzip_above: (relay.compression && lists("gzip")).then_some(relay.compress_min_bytes),
+                                    msgpack: relay.msgpack && lists("msgpack"),
+                                    ..framing
+                                };
+                                state.set_relay_capabilities(Some(capabilities));
+                            }
+                            ServerMessage::TokenRefreshed { token: Some(refreshed), expiresAt, .. } => {
+                                state.token_refreshed(&original, &token, &refreshed);
+                                token = refreshed;
+                                expires_at = expiresAt.or_else(|| token::expiry(&token));
+                                events.emit("token-refreshed", serde_json::json!({ "expiresAt": expires_at }));
+                                // On this connection, so requests being served aren't dropped
+                                let auth_msg = ClientMessage::auth(token.clone(), device_key.clone());
+                                let _ = send_message(&mut write, recorder, framing, &auth_msg).await;
+                                refresh_at = None;
+                            }
+                            ServerMessage::TokenRefreshed { error, .. } => {
+                                tracing::warn!("The relay did not refresh the token: {}", error.unwrap_or_default());
+                            }
+                            ServerMessage::Pong => {}
+                            ServerMessage::Unknown => {
+                                let kind = frame["type"].as_str().unwrap_or_default();
+                                tracing::info!("Ignoring unknown relay message type: {}", kind);
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        let _ = write.send(Message::Pong(data)).await;
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        report_status(serde_json::json!({
+                            "status": "disconnected"
+                        }));
+                        return ConnectionOutcome::Closed;
+                    }
+                    Some(Err(e)) => {
+                        let error = format!("WebSocket error: {}", e);
+                        report_status(serde_json::json!({
+                            "status": "error",
+                            "error": error
+                        }));
+                        return ConnectionOutcome::SocketError(error);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// `refresh_before_secs` ahead of `expires_at`, or right away if that's past
+fn refresh_deadline(expires_at: Option<u64>, refresh_before_secs: u64) -> Option<tokio::time::Instant> {
+    let wait = expires_at?.saturating_sub(refresh_before_secs).saturating_sub(unix_now());
+    Some(tokio::time::Instant::now() + std::time::Duration::from_secs(wait))
+}
+
+pub(crate) type WsWriter = futures_util::stream::SplitSink<
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+    Message,
+>;
+
+async fn send_message(
+    write: &mut WsWriter,
+    recorder: Option<&Recorder>,
+    framing: Framing,
+    message: &ClientMessage,
+) -> Result<(), String> {
+    if framing.sign {
+        let frame = serde_json::to_value(message).map_err(|e| e.to_string())?;
+        return send_signed(write, recorder, framing, frame).await;
+    }
+    if let Some(recorder) = recorder {
+        recorder.record(Direction::Out, &serde_json::to_string(message).map_err(|e| e.to_string())?);
+    }
+    write.send(framing.encode(message)?).await.map_err(|e| e.to_string())
+}
+
+// A frame kept as JSON text, e.g. to be resent
+async fn send_text(
+    write: &mut WsWriter,
+    recorder: Option<&Recorder>,
+    framing: Framing,
+    json: String,
+) -> Result<(), String> {
+    if framing.sign {
+        let frame = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        return send_signed(write, recorder, framing, frame).await;
+    }
+    if let Some(recorder) = recorder {
+        recorder.record(Direction::Out, &json);
+    }
+    write.send(framing.encode_json(json)?).await.map_err(|e| e.to_string())
+}
+
+// Recorded as JSON once signed, whatever `framing` turns it into
+async fn send_signed(
+    write: &mut WsWriter,
+    recorder: Option<&Recorder>,
+    framing: Framing,
+    frame: serde_json::Value,
+) -> Result<(), String> {
+    let frame = signing::sign(frame);
+    if let Some(recorder) = recorder {
+        recorder.record(Direction::Out, &frame.to_string());
+    }
+    write.send(framing.encode(&frame)?).await.map_err(|e| e.to_string())
+}
+
+// `status: online` with the current model list, sent after auth
+pub async fn online_status(events: &dyn EventSink, state: &RunnerState) -> Option<ClientMessage> {
+    let config = state.config();
+    let models = backend::advertised_models(&config).await.ok()?;
+    state.set_models(models.clone());
+    events.emit("models-updated", serde_json::json!(models));
+
+    let hostname = hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok());
+
+    let network = match backend::location(&config) {
+        backend::Location::Local => None,
+        backend::Location::Lan => Some(None),
+        backend::Location::Tailscale => Some(Some("tailscale".to_string())),
+        backend::Location::Ssh => Some(Some("ssh".to_string())),
+    };
+
+    let benchmarks = config.relay.report_benchmarks.then(|| {
+        let mut results = benchmark::load();
+        models.iter().filter_map(|model| results.remove(model)).collect()
+    });
+
+    let e2e_key = config.e2e.enabled.then(e2e::public_key).and_then(|key| {
+        key.map_err(|e| tracing::error!("End-to-end encryption is unavailable: {}", e)).ok()
+    });
+
+    Some(ClientMessage::Status {
+        status: state.availability().to_string(),
+        reason: state.hold_reason().map(String::from),
+        protocolVersion: Some(protocol::PROTOCOL_VERSION),
+        capabilities: Some(protocol::capabilities()),
+        models: Some(models),
+        deviceName: hostname,
+        backend: network.map(|network| BackendNote { remote: true, network }),
+        hardware: if config.relay.report_hardware {
+            Some(hardware::detect().await)
+        } else {
+            None
+        },
+        benchmarks,
+        loadedModels: (config.backend.kind == config::BackendKind::Ollama).then(|| state.loaded_models()),
+        e2eKey: e2e_key,
+        load: None,
+    })
+}
+
+// Fetches the model list and compares it with the last one seen. On a
+// change, connected runners send their status again (which emits
+// `models-updated`); otherwise the event is emitted here.
+pub async fn refresh_models(events: &dyn EventSink, state: &RunnerState) -> Result<Vec<String>, String> {
+    let models = backend::advertised_models(&state.config()).await?;
+    let previous = state.models();
+    if models == previous {
+        return Ok(models);
+    }
+
+    let added: Vec<&String> = models.iter().filter(|model| !previous.contains(model)).collect();
+    let removed: Vec<&String> = previous.iter().filter(|model| !models.contains(model)).collect();
+    for (change, list) in [("added", &added), ("removed", &removed)] {
+        if !list.is_empty() {
+            let names = list.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ");
+            tracing::info!("Models {}: {}", change, names);
+        }
+    }
+
+    state.set_models(models.clone());
+    if state.connection_status() == "connected" {
+        state.announce_status();
+    } else {
+        events.emit("models-updated", serde_json::json!(models));
+    }
+    Ok(models)
+}
+
+// Asks Ollama which models it has in memory and emits `loaded-models` when
+// that changes. Connected runners send their status again when a model is
+// loaded or unloaded, not when only an expiry moves.
+pub async fn refresh_loaded_models(events: &dyn EventSink, state: &RunnerState) -> Result<Vec<LoadedModel>, String> {
+    let loaded = ollama::loaded_models(&state.config().backend.url).await?;
+    let previous = state.loaded_models();
+    if loaded == previous {
+        return Ok(loaded);
+    }
+
+    state.set_loaded_models(loaded.clone());
+    events.emit("loaded-models", serde_json::json!(loaded));
+    let names = |models: &[LoadedModel]| models.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+    if names(&loaded) != names(&previous) && state.connection_status() == "connected" {
+        state.announce_status();
+    }
+    Ok(loaded)
+}
+
+// Sent every `load_report_interval_secs` so the relay can route work to the
+// least loaded runner. Models are left out; the last list sent stands.
+fn load_status(state: &RunnerState) -> ClientMessage {
+    ClientMessage::Status {
+        status: state.availability().to_string(),
+        reason: state.hold_reason().map(String::from),
+        protocolVersion: None,
+        capabilities: None,
+        models: None,
+        deviceName: None,
+        backend: None,
+        hardware: None,
+        benchmarks: None,
+        loadedModels: None,
+        e2eKey: None,
+        load: Some(state.scheduler().load()),
+    }
+}
+
+fn error_response(request_id: String, error: RunnerError) -> ClientMessage {
+    ClientMessage::ChatResponse {
+        requestId: request_id,
+        content: None,
+        chunk: None,
+        toolCalls: None,
+        reasoning: None,
+        done: Some(true),
+        error: Some(error.info()),
+        usage: None,
+        warnings: None,
+        estimate: None,
+        seq: None,
+        sealed: None,
+    }
+}
+
+// Lets a request in past the rate limits and into the scheduler's queue for
+// its model, waiting for its turn. The ticket holds its place until dropped;
+// a request turned away gets its error response instead. Relay requests and
+// the local API both come through here.
+pub async fn admit<'a>(
+    state: &'a RunnerState,
+    events: &dyn EventSink,
+    request_id: &str,
+    model: &str,
+    priority: i32,
+    requester: Option<&str>,
+) -> Result<Ticket<'a>, ClientMessage> {
+    if let Err(e) = state.rate_limiter().admit(&state.config().rate_limit, requester) {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request {}: {}", request_id, e);
+        return Err(error_response(request_id.to_string(), e));
+    }
+    let config = state.config();
+    let local = config.models.resolve(model);
+    let limits = Limits::for_model(&config.relay, local);
+    let mut ticket = match state.scheduler().enqueue(local, limits, priority) {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: {}", model, e);
+            return Err(error_response(request_id.to_string(), e));
+        }
+    };
+    if ticket.is_queued() {
+        tracing::debug!(requestId = %request_id, model = %model, "Request {} queued at priority {}", request_id, priority);
+        events.emit("queue-changed", serde_json::json!(state.scheduler().load()));
+        ticket.start().await;
+        events.emit("queue-changed", serde_json::json!(state.scheduler().load()));
+    }
+    Ok(ticket)
+}
+
+// Takes an answered request's tokens off the requester's token budget
+pub fn charge(state: &RunnerState, requester: Option<&str>, response: &ClientMessage) {
+    if let ClientMessage::ChatResponse { usage: Some(usage), .. } = response {
+        let tokens = (usage.inputTokens.max(0) + usage.outputTokens.max(0)) as u64;
+        state.rate_limiter().charge(&state.config().rate_limit, requester, tokens);
+    }
+}
+
+pub async fn handle_chat_request(
+    request_id: String,
+    model: String,
+    messages: Vec<ChatMessage>,
+    options: ChatOptions,
+    events: &dyn EventSink,
+    state: &RunnerState,
+    replies: Option<&mpsc::UnboundedSender<ClientMessage>>,
+) -> ClientMessage {
+    handle_request(request_id, model, Input::Chat(messages), options, events, state, replies).await
+}
+
+// Handles one chat or completion request and returns the final
+// `chat_response`. When the request asks for streaming and `replies` is
+// given, `chunk` frames are sent there as text arrives and the final frame
+// only carries `done` and usage.
+pub async fn handle_request(
+    request_id: String,
+    model: String,
+    input: Input,
+    options: ChatOptions,
+    events: &dyn EventSink,
+    state: &RunnerState,
+    replies: Option<&mpsc::UnboundedSender<ClientMessage>>,
+) -> ClientMessage {
+    let started = std::time::SystemTime::now();
+    let config = state.config();
+    let local = config.models.resolve(&model);
+    if local != model {
+        tracing::debug!(requestId = %request_id, model = %local, "Request for {} served by {}", model, local);
+    }
+    let model = local.to_string();
+    let name = model.clone();
+    let response = serve_request(request_id, model, input, options, events, state, replies).await;
+    record_outcome(state, &name, started, &response);
+    response
+}
+
+// Latency and speed for `usage`. A streamed reply's time to first token is
+// when its first chunk came; otherwise only the backend knows it, if it says.
+fn add_timings(usage: &mut Usage, busy: std::time::Duration, first_token: Option<std::time::Duration>) {
+    usage.durationMs = Some(busy.as_millis() as u64);
+    if let Some(first_token) = first_token {
+        usage.timeToFirstTokenMs = Some(first_token.as_millis() as u64);
+    }
+    if usage.tokensPerSecond.is_none() && usage.outputTokens > 0 {
+        let first_token = std::time::Duration::from_millis(usage.timeToFirstTokenMs.unwrap_or(0));
+        let generating = busy.saturating_sub(first_token).max(std::time::Duration::from_millis(1));
+        let speed = usage.outputTokens as f64 / generating.as_secs_f64();
+        usage.tokensPerSecond = Some((speed * 10.0).round() / 10.0);
+    }
+}
+
+// Counts the final response for Prometheus and adds it to the history
+pub fn record_outcome(
+    state: &RunnerState,
+    model: &str,
+    started: std::time::SystemTime,
+    response: &ClientMessage,
+) {
+    let config = state.config().history;
+    let ClientMessage::ChatResponse { requestId, error, usage, .. } = response else {
+        return;
+    };
+    let duration = started.elapsed().unwrap_or_default();
+    state.prometheus().record_request(model, duration, usage.as_ref(), error.as_ref());
+    if !config.enabled {
+        return;
+    }
+    let mut entry = history::Entry::new(requestId, model, started, error.as_ref());
+    if let Some(usage) = usage {
+        entry.input_tokens = usage.inputTokens.max(0) as u64;
+        entry.output_tokens = usage.outputTokens.max(0) as u64;
+    }
+    if let Err(e) = history::record(&config, &entry) {
+        tracing::error!(requestId = %requestId, "Failed to save request history: {}", e);
+    }
+}
+
+async fn serve_request(
+    request_id: String,
+    model: String,
+    mut input: Input,
+    mut options: ChatOptions,
+    events: &dyn EventSink,
+    state: &RunnerState,
+    replies: Option<&mpsc::UnboundedSender<ClientMessage>>,
+) -> ClientMessage {
+    if state.is_paused() || state.hold_reason().is_some() {
+        let reason = match state.hold_reason() {
+            _ if state.is_paused() => "runner is paused",
+            Some("battery") => "runner is offline on battery",
+            _ => "outside the runner's schedule",
+        };
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: {}", model, reason);
+
+        return error_response(request_id, RunnerError::Paused);
+    }
+
+    if state.backend_down() {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: the backend is down", model);
+
+        return error_response(request_id, RunnerError::BackendUnavailable);
+    }
+
+    let config = state.config();
+    if !config.models.allows(&model) {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: not in the model filter", model);
+
+        return error_response(request_id, RunnerError::ModelNotAllowed);
+    }
+
+    if let Err(e) = limits::check(&config.limits, &input) {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: {}", model, e);
+
+        return error_response(request_id, RunnerError::PayloadTooLarge(e));
+    }
+
+    let max_image_bytes = (config.backend.max_image_mb * 1e6) as u64;
+    let checked = match &mut input {
+        Input::Chat(messages) => images::validate(messages, max_image_bytes),
+        Input::Completion(_) => Ok(()),
+    };
+    if let Err(e) = checked {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: {}", model, e);
+
+        return error_response(request_id, RunnerError::InvalidRequest(e));
+    }
+
+    if let Err(e) = policy::check(&config.policy, &config.backend, &input).await {
+        tracing::info!(requestId = %request_id, model = %model, "Rejected request for model {}: {}", model, e);
+
+        return error_response(request_id, RunnerError::PolicyViolation(e));
+    }
+
+    if let Err(e) = auto_pull(&request_id, &model, events, state, replies).await {
+        return error_response(request_id, RunnerError::PullFailed(e));
+    }
+
+    tracing::info!(requestId = %request_id, model = %model, "Request for model: {}", model);
+
+    system_prompt::apply(&config.system_prompt, &model, &mut input);
+    let max_tokens_cap = limits::clamp_max_tokens(&config.limits, &model, &mut options.max_tokens);
+    if let Some(cap) = max_tokens_cap {
+        tracing::info!(requestId = %request_id, model = %model, "Request for {}: max_tokens capped at {}", model, cap);
+    }
+
+    // Forward to the backend
+    let warnings = backend::translation_warnings(&config.backend, &options);
+    for warning in &warnings {
+        tracing::info!(requestId = %request_id, model = %model, "Request for {}: {}", model, warning);
+    }
+    let replies = replies.filter(|_| options.stream == Some(true));
+    // Chunk frames sent so far, which numbers the next one
+    let sent = state.frames_sent(&request_id);
+    let first_chunk = OnceLock::new();
+    let reasoning_mode = options.reasoning.unwrap_or(config.backend.reasoning);
+    let splitter = Mutex::new(reasoning::Splitter::default());
+    let send_frame = |text: Option<String>, tool_calls: Option<Vec<ToolCall>>, reasoning: Option<String>| {
+        if let Some(replies) = replies {
+            let frame = ClientMessage::ChatResponse {
+                requestId: request_id.clone(),
+                content: None,
+                chunk: text,
+                toolCalls: tool_calls,
+                reasoning,
+                done: Some(false),
+                error: None,
+                usage: None,
+                warnings: None,
+                estimate: None,
+                seq: Some(sent.fetch_add(1, Ordering::Relaxed)),
+                sealed: None,
+            };
+            let frame = e2e::seal(state, frame);
+            state.recent_requests().record(&frame);
+            let _ = replies.send(frame);
+        }
+    };
+    let send_split = |split: reasoning::Split| {
+        if !split.answer.is_empty() {
+            send_frame(Some(split.answer), None, None);
+        }
+        if reasoning_mode == ReasoningMode::Separate && !split.reasoning.is_empty() {
+            send_frame(None, None, Some(split.reasoning));
+        }
+    };
+    let send_chunk = |chunk: Chunk| {
+        first_chunk.get_or_init(std::time::Instant::now);
+        match chunk {
+            Chunk::Text(text) if reasoning_mode == ReasoningMode::Passthrough => send_frame(Some(text), None, None),
+            Chunk::Text(text) => send_split(splitter.lock().map(|mut splitter| splitter.push(&text)).unwrap_or_default()),
+            Chunk::ToolCalls(calls) => send_frame(None, Some(calls), None),
+        }
+    };
+    let on_chunk: ChunkSink = replies.map(|_| &send_chunk as &(dyn Fn(Chunk) + Send + Sync));
+
+    // Transient failures are retried as long as nothing was streamed yet,
+    // each try going to the least busy Ollama server that is up
+    let attempts = async {
+        let mut attempt = 0;
+        loop {
+            let lease = (config.backend.kind == config::BackendKind::Ollama).then(|| balancer::acquire(&config.backend, &model));
+            let mut target = config.backend.clone();
+            if let Some(lease) = &lease {
+                target.url = lease.url.clone();
+            }
+            let result = backend::complete(&target, &model, &input, &options, on_chunk).await;
+            if let (Some(lease), Err(RunnerError::OllamaUnreachable(_))) = (&lease, &result) {
+                balancer::mark_down(&lease.url);
+            }
+            match result {
+                Err(e) if attempt < config.backend.retry.max_retries && retry::is_transient(&e) && first_chunk.get().is_none() => {
+                    attempt += 1;
+                    let delay = retry::backoff(&config.backend.retry, attempt);
+                    tracing::warn!(
+                        requestId = %request_id,
+                        model = %model,
+                        "Request for {} failed, retrying in {}ms: {}",
+                        model,
+                        delay.as_millis(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => break result,
+            }
+        }
+    };
+    let started = std::time::Instant::now();
+    let timeout = std::time::Duration::from_secs(config.backend.request_timeout_secs);
+    let result = match tokio::time::timeout(timeout, attempts).await {
+        Ok(result) => result,
+        Err(_) => {
+            tracing::error!(requestId = %request_id, model = %model, "Request for {} timed out after {}s", model, timeout.as_secs());
+            Err(RunnerError::Timeout)
+        }
+    };
+    let busy = started.elapsed();
+    // A tag's first characters may still be held back
+    send_split(splitter.lock().map(|mut splitter| splitter.finish()).unwrap_or_default());
+    // `<think>` text taken out of the reply
+    let mut thinking = String::new();
+    let result = result.map(|mut reply| {
+        if reasoning_mode != ReasoningMode::Passthrough {
+            let split = reasoning::split(&reply.content);
+            reply.content = split.answer;
+            thinking = split.reasoning;
+        }
+        reply
+    });
+    // The mock backend ignores response_format
+    let json_expected = config.backend.kind != config::BackendKind::Mock
+        && matches!(translate::output_format(&options), Ok(translate::OutputFormat::Json | translate::OutputFormat::Schema(_)));
+    let result = result.and_then(|reply| {
+        if json_expected && reply.tool_calls.is_empty() {
+            if let Err(e) = serde_json::from_str::<serde_json::Value>(&reply.content) {
+                return Err(RunnerError::InvalidJson(e.to_string()));
+            }
+        }
+        Ok(reply)
+    });
+    let result = result.map(|mut reply| {
+        tokens::reconcile(&mut reply.usage, &input);
+        let first_token = first_chunk.get().map(|at| at.duration_since(started));
+        add_timings(&mut reply.usage, busy, first_token);
+        reply
+    });
+    let idle = state.idle_for();
+    state.record_request(result.is_ok());
+    state.metrics().record(&model, result.as_ref().ok().map(|reply| &reply.usage), busy);
+
+    match result {
+        Ok(Reply { content, mut usage, tool_calls }) => {
+            usage.maxTokensCap = max_tokens_cap;
+            let (input_tokens, output_tokens) = (usage.inputTokens.max(0) as u64, usage.outputTokens.max(0) as u64);
+            state.record_usage(input_tokens, output_tokens, busy);
+            events.emit("request-served", serde_json::json!({
+                "requestId": request_id,
+                "model": model,
+                "idleMs": idle.map(|idle| idle.as_millis() as u64),
+            }));
+            let estimate = cost::estimate(&config.cost, input_tokens + output_tokens, busy);
+
+            if config.transcripts.enabled {
+                if let Err(e) = transcripts::append(&config.transcripts, &request_id, &model, &input.messages(), &content, &usage) {
+                    tracing::error!(requestId = %request_id, "Failed to save transcript: {}", e);
+                }
+            }
+            tracing::info!(
+                requestId = %request_id,
+                model = %model,
+                durationMs = busy.as_millis() as u64,
+                inputTokens = usage.inputTokens,
+                outputTokens = usage.outputTokens,
+                success = true,
+                "Completed: {} tokens",
+                usage.inputTokens + usage.outputTokens
+            );
+
+            ClientMessage::ChatResponse {
+                requestId: request_id,
+                // Already sent as chunks when streaming
+                content: replies.is_none().then_some(content),
+                chunk: None,
+                toolCalls: (replies.is_none() && !tool_calls.is_empty()).then_some(tool_calls),
+                reasoning: (replies.is_none() && reasoning_mode == ReasoningMode::Separate && !thinking.is_empty())
+                    .then_some(thinking),
+                done: Some(true),
+                error: None,
+                usage: Some(usage),
+                warnings: (!warnings.is_empty()).then_some(warnings),
+                estimate: (config.cost.report && !estimate.is_empty()).then_some(estimate),
+                seq: replies.map(|_| sent.load(Ordering::Relaxed)),
+                sealed: None,
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                requestId = %request_id,
+                model = %model,
+                durationMs = busy.as_millis() as u64,
+                code = e.code(),
+                "Error: {}",
+                e
+            );
+
+            let mut response = error_response(request_id, e);
+            if let ClientMessage::ChatResponse { seq, .. } = &mut response {
+                *seq = replies.map(|_| sent.load(Ordering::Relaxed));
+            }
+            response
+        }
+    }
+}
+
+// With `auto_pull` on, installs a requested model that Ollama does not have
+// before the request is served, telling the requester how the download is
+// going with `request_status` frames
+async fn auto_pull(
+    request_id: &str,
+    model: &str,
+    events: &dyn EventSink,
+    state: &RunnerState,
+    replies: Option<&mpsc::UnboundedSender<ClientMessage>>,
+) -> Result<(), String> {
+    let config = state.config().backend;
+    if !config.auto_pull || config.kind != config::BackendKind::Ollama || state.models().iter().any(|m| m == model) {
+        return Ok(());
+    }
+    if backend::list_models(&config).await?.iter().any(|m| m == model) {
+        return Ok(());
+    }
+
+    tracing::info!(requestId = %request_id, model = %model, "Model {} is not installed, pulling it", model);
+    let last_sent: std::sync::Mutex<Option<(String, Option<u8>)>> = std::sync::Mutex::new(None);
+    let report = |progress: &PullProgress| {
+        let Some(replies) = replies.filter(|_| state.relay_supports("request_status")) else {
+            return;
+        };
+        let percent = progress
+            .total
+            .filter(|total| *total > 0)
+            .map(|total| (progress.completed.unwrap_or(0) * 100 / total).min(100) as u8);
+        // One frame per step or per 5% of a layer
+        let current = (progress.status.clone(), percent.map(|p| p - p % 5));
+        if let Ok(mut last) = last_sent.lock() {
+            if last.as_ref() == Some(&current) {
+                return;
+            }
+            *last = Some(current);
+        }
+        let _ = replies.send(ClientMessage::RequestStatus {
+            requestId: request_id.to_string(),
+            status: "pulling_model".to_string(),
+            detail: Some(progress.status.clone()),
+            progress: percent,
+        });
+    };
+    let options = pull::PullOptions {
+        max_bytes: Some((config.auto_pull_max_gb * 1e9) as u64),
+        on_progress: Some(&report),
+    };
+    pull::ensure(events, state, model, options).await
+}
+
+// Every frame a chat or completion request produces, streamed chunks first
+pub async fn request_frames(
+    request_id: String,
+    model: String,
+    input: Input,
+    options: ChatOptions,
+    events: &dyn EventSink,
+    state: &RunnerState,
+) -> Vec<ClientMessage> {
+    let (replies, mut chunks) = mpsc::unbounded_channel();
+    let response = handle_request(request_id, model, input, options, events, state, Some(&replies)).await;
+    drop(replies);
+
+    let mut frames = Vec::new();
+    while let Some(chunk) = chunks.recv().await {
+        frames.push(chunk);
+    }
+    frames.push(e2e::seal(state, response));
+    frames
+}
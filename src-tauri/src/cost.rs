@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::CostConfig;
+
+// Estimated energy use and running cost of serving requests on this machine
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Estimate {
+    pub energy_wh: Option<f64>,
+    pub cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+impl Estimate {
+    pub fn is_empty(&self) -> bool {
+        self.energy_wh.is_none() && self.cost.is_none()
+    }
+}
+
+// Energy comes from `wh_per_token`, or from `watts` over the time spent
+// generating. Cost comes from `cost_per_hour` of generation time, or from
+// the energy estimate priced at `price_per_kwh`.
+pub fn estimate(config: &CostConfig, tokens: u64, busy: Duration) -> Estimate {
+    let hours = busy.as_secs_f64() / 3600.0;
+    let energy_wh = config
+        .wh_per_token
+        .map(|wh| wh * tokens as f64)
+        .or_else(|| config.watts.map(|watts| watts * hours));
+    let cost = match (config.cost_per_hour, config.price_per_kwh, energy_wh) {
+        (Some(per_hour), _, _) => Some(per_hour * hours),
+        (None, Some(per_kwh), Some(wh)) => Some(per_kwh * wh / 1000.0),
+        _ => None,
+    };
+    Estimate {
+        energy_wh,
+        cost,
+        currency: cost.map(|_| config.currency.clone()),
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::protocol::ClientMessage;
+
+// The last requestIds the relay sent and the frames sent back for them, so
+// a request delivered twice (e.g. after the relay's own reconnect) isn't run
+// twice. Oldest first; a repeat moves to the back.
+#[derive(Default)]
+pub struct RecentRequests {
+    entries: Mutex<VecDeque<Recent>>,
+}
+
+struct Recent {
+    request_id: String,
+    // `chat_response` frames as sent, the final one last
+    frames: Vec<String>,
+    done: bool,
+}
+
+pub enum Seen {
+    New,
+    // Still being served; its response is on the way
+    Running,
+    // Answered with these frames
+    Done(Vec<String>),
+}
+
+impl RecentRequests {
+    // Remembers `request_id` unless it was already seen, forgetting the
+    // oldest past `capacity`. A capacity of 0 remembers nothing.
+    pub fn begin(&self, request_id: &str, capacity: usize) -> Seen {
+        if capacity == 0 {
+            return Seen::New;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return Seen::New;
+        };
+        if let Some(index) = entries.iter().position(|e| e.request_id == request_id) {
+            let Some(entry) = entries.remove(index) else {
+                return Seen::New;
+            };
+            let seen = if entry.done { Seen::Done(entry.frames.clone()) } else { Seen::Running };
+            entries.push_back(entry);
+            return seen;
+        }
+        while entries.len() >= capacity {
+            entries.pop_front();
+        }
+        entries.push_back(Recent {
+            request_id: request_id.to_string(),
+            frames: Vec::new(),
+            done: false,
+        });
+        Seen::New
+    }
+
+    // Adds a streamed frame for a request being served
+    pub fn record(&self, frame: &ClientMessage) {
+        self.add(frame, false);
+    }
+
+    // Adds the final response. One with a retryable error is forgotten
+    // instead, so the relay can deliver the request again.
+    pub fn finish(&self, response: &ClientMessage) {
+        let ClientMessage::ChatResponse { requestId, error, .. } = response else {
+            return;
+        };
+        if error.as_ref().is_some_and(|e| e.retryable) {
+            if let Ok(mut entries) = self.entries.lock() {
+                entries.retain(|e| e.request_id != *requestId);
+            }
+            return;
+        }
+        self.add(response, true);
+    }
+
+    fn add(&self, frame: &ClientMessage, done: bool) {
+        let ClientMessage::ChatResponse { requestId, .. } = frame else {
+            return;
+        };
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let Some(entry) = entries.iter_mut().find(|e| e.request_id == *requestId && !e.done) else {
+            return;
+        };
+        if let Ok(json) = serde_json::to_string(frame) {
+            entry.frames.push(json);
+        }
+        entry.done = done;
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use bottlecap_runner_core::proxy;
+
+use crate::pairing;
+
+// The OAuth device authorization grant (RFC 8628): the runner gets a code,
+// the user approves it on bottlecap.ai, and the runner collects its token
+const CODE_URL: &str = "https://bottlecap.ai/api/runner/device/code";
+const TOKEN_URL: &str = "https://bottlecap.ai/api/runner/device/token";
+const CLIENT_ID: &str = "bottlecap-runner";
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+// Poll interval when the server doesn't give one, and the step `slow_down` adds
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Bumped by every start and cancel; a poll loop stops once it's not its own
+static FLOW: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Deserialize)]
+struct CodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    interval: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceAuth {
+    // To show in the window, for checking against the one on the page
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in_secs: u64,
+}
+
+// Gets a device code, opens the approval page in the browser and polls for
+// the token in the background. Replaces a flow already under way.
+pub async fn start(app: &AppHandle) -> Result<DeviceAuth, String> {
+    let flow = FLOW.fetch_add(1, Ordering::SeqCst) + 1;
+    let device = hostname::get()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_default();
+    let response = proxy::http_client()
+        .post(CODE_URL)
+        .form(&[("client_id", CLIENT_ID), ("device_name", device.as_str())])
+        .send()
+        .await
+        .map_err(|e| format!("Could not reach bottlecap.ai: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("bottlecap.ai refused a device code: HTTP {}", response.status()));
+    }
+    let code: CodeResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Unexpected device code response: {}", e))?;
+
+    let page = code.verification_uri_complete.as_deref().unwrap_or(&code.verification_uri);
+    if let Err(e) = tauri::api::shell::open(&app.shell_scope(), page, None) {
+        // The window shows the page and code to open by hand
+        tracing::warn!("Could not open the browser: {}", e);
+    }
+    tracing::info!("Waiting for the device code to be approved");
+
+    let auth = DeviceAuth {
+        user_code: code.user_code,
+        verification_uri: code.verification_uri,
+        expires_in_secs: code.expires_in,
+    };
+    let interval = code.interval.map(Duration::from_secs).unwrap_or(DEFAULT_INTERVAL);
+    let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let status = poll(flow, code.device_code, interval, deadline).await;
+        match status {
+            Ok(Some(token)) => {
+                let _ = app.emit_all("device-auth", serde_json::json!({ "status": "approved" }));
+                pairing::pair(&app, token);
+            }
+            // Cancelled or replaced; the new flow reports for itself
+            Ok(None) => {}
+            Err((status, error)) => {
+                tracing::warn!("Device authorization ended: {}", error);
+                let _ = app.emit_all("device-auth", serde_json::json!({ "status": status, "error": error }));
+            }
+        }
+    });
+    Ok(auth)
+}
+
+// Stops waiting for the current flow, if any
+pub fn cancel() {
+    FLOW.fetch_add(1, Ordering::SeqCst);
+}
+
+// The token once approved, None if the flow was cancelled, or the status
+// (`denied`, `expired` or `failed`) and reason it ended with
+async fn poll(
+    flow: u64,
+    device_code: String,
+    mut interval: Duration,
+    deadline: Instant,
+) -> Result<Option<String>, (&'static str, String)> {
+    let client = proxy::http_client();
+    loop {
+        tokio::time::sleep(interval).await;
+        if FLOW.load(Ordering::SeqCst) != flow {
+            return Ok(None);
+        }
+        if Instant::now() >= deadline {
+            return Err(("expired", "The code expired before it was approved".to_string()));
+        }
+        let response = client
+            .post(TOKEN_URL)
+            .form(&[("grant_type", GRANT_TYPE), ("device_code", &device_code), ("client_id", CLIENT_ID)])
+            .send()
+            .await;
+        // Network trouble is retried until the code expires
+        let Ok(response) = response else {
+            continue;
+        };
+        let Ok(body) = response.json::<TokenResponse>().await else {
+            continue;
+        };
+        if FLOW.load(Ordering::SeqCst) != flow {
+            return Ok(None);
+        }
+        if let Some(token) = body.access_token {
+            return Ok(Some(token));
+        }
+        let reason = body.error_description.clone().unwrap_or_default();
+        match body.error.as_deref() {
+            Some("authorization_pending") => {}
+            Some("slow_down") => interval += DEFAULT_INTERVAL,
+            Some("access_denied") => return Err(("denied", "The request was denied".to_string())),
+            Some("expired_token") => return Err(("expired", "The code expired before it was approved".to_string())),
+            Some(error) => return Err(("failed", format!("{} {}", error, reason).trim().to_string())),
+            None => return Err(("failed", "No token in the response".to_string())),
+        }
+    }
+}
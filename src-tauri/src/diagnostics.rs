@@ -0,0 +1,166 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Timelike};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::state::RunnerState;
+use crate::transcripts::TimeRange;
+use crate::{backend, hardware, history, logging, protocol, settings};
+
+// Daily log files included, newest first
+const LOG_FILES: usize = 3;
+
+// Where `export_diagnostics` writes when no path is given
+pub fn default_path() -> Option<PathBuf> {
+    let name = chrono::Local::now()
+        .format("bottlecap-diagnostics-%Y%m%d-%H%M%S.zip")
+        .to_string();
+    dirs_next::download_dir()
+        .or_else(dirs_next::home_dir)
+        .map(|dir| dir.join(name))
+}
+
+// Writes a zip for bug reports: recent logs, settings with credentials
+// removed, version and system details, and the last `requests` request
+// summaries. Prompts and responses are never included.
+pub async fn export(state: &RunnerState, path: &Path, requests: u32) -> Result<(), String> {
+    let config = state.config();
+    let mut zip = ZipWriter::default();
+
+    let mut settings = serde_json::to_value(settings::load().effective(&config)).map_err(|e| e.to_string())?;
+    sanitize(&mut settings);
+    zip.add("settings.json", &serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())?)?;
+
+    let system = serde_json::json!({
+        "runner": state.snapshot(),
+        "protocolVersion": protocol::PROTOCOL_VERSION,
+        "capabilities": protocol::capabilities(),
+        "backend": {
+            "kind": format!("{:?}", config.backend.kind).to_lowercase(),
+            "location": format!("{:?}", backend::location(&config)).to_lowercase(),
+        },
+        "privacyMode": config.privacy.enabled,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "hardware": hardware::detect().await,
+    });
+    zip.add("system.json", &serde_json::to_vec_pretty(&system).map_err(|e| e.to_string())?)?;
+
+    // History may be off or unreadable; the rest of the bundle is still useful
+    let summaries = match history::query(&config.history, &TimeRange::default(), 0, requests) {
+        Ok(page) => serde_json::json!(page.entries),
+        Err(e) => serde_json::json!({ "error": e }),
+    };
+    zip.add("requests.json", &serde_json::to_vec_pretty(&summaries).map_err(|e| e.to_string())?)?;
+
+    let recent = logging::recent(usize::MAX, None);
+    zip.add("logs/recent.json", &serde_json::to_vec_pretty(&recent).map_err(|e| e.to_string())?)?;
+    if let Some(dir) = logging::dir(&config.logging) {
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        files.retain(|file| file.extension().is_some_and(|ext| ext == "log"));
+        files.sort();
+        for file in files.iter().rev().take(LOG_FILES) {
+            let Some(name) = file.file_name().map(|name| name.to_string_lossy().to_string()) else {
+                continue;
+            };
+            let contents = fs::read(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+            zip.add(&format!("logs/{}", name), &contents)?;
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, zip.finish()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Drops passwords and query strings (which may hold tokens) from every URL
+fn sanitize(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => {
+            if let Ok(mut url) = url::Url::parse(text) {
+                if url.has_host() && (url.password().is_some() || !url.username().is_empty() || url.query().is_some()) {
+                    let _ = url.set_username("");
+                    let _ = url.set_password(None);
+                    url.set_query(None);
+                    *text = url.to_string();
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(sanitize),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(sanitize),
+        _ => {}
+    }
+}
+
+// Just enough of the zip format for a handful of deflated files
+#[derive(Default)]
+struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).map_err(|e| e.to_string())?;
+        let compressed = encoder.finish().map_err(|e| e.to_string())?;
+
+        let now = chrono::Local::now();
+        // MS-DOS format: two-second resolution, years from 1980
+        let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let year = (now.year().clamp(1980, 2107) - 1980) as u32;
+        let date = ((year << 9) | (now.month() << 5) | now.day()) as u16;
+        let crc = crc32fast::hash(data);
+        let offset = self.out.len() as u32;
+
+        // Shared by the local header and the central directory entry: version
+        // needed, UTF-8 names, deflate, time, date, CRC and sizes
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0x0800u16.to_le_bytes());
+        common.extend_from_slice(&8u16.to_le_bytes());
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        self.out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(&compressed);
+
+        self.central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        self.central.extend_from_slice(&20u16.to_le_bytes());
+        self.central.extend_from_slice(&common);
+        // Comment length, disk, internal and external attributes
+        self.central.extend_from_slice(&[0; 10]);
+        self.central.extend_from_slice(&offset.to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        let size = self.central.len() as u32;
+        self.out.append(&mut self.central);
+        self.out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.out.extend_from_slice(&[0; 4]);
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&self.entries.to_le_bytes());
+        self.out.extend_from_slice(&size.to_le_bytes());
+        self.out.extend_from_slice(&offset.to_le_bytes());
+        self.out.extend_from_slice(&0u16.to_le_bytes());
+        self.out
+    }
+}
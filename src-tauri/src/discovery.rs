@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use futures_util::stream::{self, StreamExt};
+use ipnet::Ipv4Net;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BackendKind, DiscoveryConfig};
+
+const MDNS_SERVICES: &[(&str, BackendFlavor)] = &[
+    ("_ollama._tcp.local.", BackendFlavor::Ollama),
+    ("_lmstudio._tcp.local.", BackendFlavor::LmStudio),
+];
+const PROBE_PORTS: &[(u16, BackendFlavor)] = &[(11434, BackendFlavor::Ollama), (1234, BackendFlavor::LmStudio)];
+const MDNS_BROWSE_TIME: Duration = Duration::from_secs(3);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(2);
+const PROBE_CONCURRENCY: usize = 128;
+// Larger subnets are refused rather than scanned
+const MAX_PREFIX_HOSTS: usize = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendFlavor {
+    Ollama,
+    LmStudio,
+}
+
+impl BackendFlavor {
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendFlavor::Ollama => "Ollama",
+            BackendFlavor::LmStudio => "LM Studio",
+        }
+    }
+
+    // The backend that talks to a server found at `url`, and its URL for it.
+    // LM Studio is used through its OpenAI-compatible API.
+    pub fn backend(self, url: &str) -> (BackendKind, String) {
+        match self {
+            BackendFlavor::Ollama => (BackendKind::Ollama, url.to_string()),
+            BackendFlavor::LmStudio => (BackendKind::Openai, format!("{}/v1", url)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscoverySource {
+    Mdns,
+    Probe,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredBackend {
+    pub flavor: BackendFlavor,
+    pub url: String,
+    pub source: DiscoverySource,
+    pub models: Vec<String>,
+}
+
+// Finds Ollama / LM Studio servers advertised over mDNS or listening on
+// their default ports in the configured subnets (the local /24 if none).
+// Only servers that answer their model list endpoint are returned.
+pub async fn discover(config: &DiscoveryConfig) -> Result<Vec<DiscoveredBackend>, String> {
+    let mut candidates: BTreeMap<SocketAddr, (BackendFlavor, DiscoverySource)> = BTreeMap::new();
+
+    if config.mdns {
+        for (addr, flavor) in browse_mdns().await {
+            candidates.insert(addr, (flavor, DiscoverySource::Mdns));
+        }
+    }
+
+    let subnets = if config.subnets.is_empty() {
+        local_subnet().into_iter().collect()
+    } else {
+        config.subnets.clone()
+    };
+    for subnet in &subnets {
+        if subnet.hosts().count() > MAX_PREFIX_HOSTS {
+            return Err(format!("Subnet {} is too large to probe (max /22)", subnet));
+        }
+    }
+    for (addr, flavor) in probe_subnets(&subnets).await {
+        candidates.entry(addr).or_insert((flavor, DiscoverySource::Probe));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(VERIFY_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let verified = stream::iter(candidates)
+        .map(|(addr, (flavor, source))| {
+            let client = client.clone();
+            async move {
+                let url = format!("http://{}", addr);
+                let models = list_models(&client, &url, flavor).await?;
+                Some(DiscoveredBackend { flavor, url, source, models })
+            }
+        })
+        .buffer_unordered(16)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await;
+
+    Ok(verified)
+}
+
+async fn browse_mdns() -> Vec<(SocketAddr, BackendFlavor)> {
+    let daemon = match mdns_sd::ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(_) => return Vec::new(),
+    };
+
+    // Browse all service types at once for MDNS_BROWSE_TIME
+    let deadline = tokio::time::Instant::now() + MDNS_BROWSE_TIME;
+    let browses = MDNS_SERVICES.iter().filter_map(|(service, flavor)| {
+        let receiver = daemon.browse(service).ok()?;
+        Some(async move {
+            let mut found = Vec::new();
+            while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+                if let mdns_sd::ServiceEvent::ServiceResolved(info) = event {
+                    for ip in info.get_addresses() {
+                        found.push((SocketAddr::new(*ip, info.get_port()), *flavor));
+                    }
+                }
+            }
+            found
+        })
+    });
+    let found = futures_util::future::join_all(browses).await;
+
+    let _ = daemon.shutdown();
+    found.into_iter().flatten().collect()
+}
+
+async fn probe_subnets(subnets: &[Ipv4Net]) -> Vec<(SocketAddr, BackendFlavor)> {
+    let targets: Vec<(SocketAddr, BackendFlavor)> = subnets
+        .iter()
+        .flat_map(|subnet| subnet.hosts())
+        .flat_map(|ip| {
+            PROBE_PORTS
+                .iter()
+                .map(move |(port, flavor)| (SocketAddr::new(IpAddr::V4(ip), *port), *flavor))
+        })
+        .collect();
+
+    stream::iter(targets)
+        .map(|(addr, flavor)| async move {
+            let connect = tokio::net::TcpStream::connect(addr);
+            match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+                Ok(Ok(_)) => Some((addr, flavor)),
+                _ => None,
+            }
+        })
+        .buffer_unordered(PROBE_CONCURRENCY)
+        .filter_map(|open| async move { open })
+        .collect()
+        .await
+}
+
+async fn list_models(client: &reqwest::Client, url: &str, flavor: BackendFlavor) -> Option<Vec<String>> {
+    let (path, list_key, name_key) = match flavor {
+        BackendFlavor::Ollama => ("/api/tags", "models", "name"),
+        BackendFlavor::LmStudio => ("/v1/models", "data", "id"),
+    };
+    let response = client.get(format!("{}{}", url, path)).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let body: serde_json::Value = response.json().await.ok()?;
+    let models = body[list_key]
+        .as_array()?
+        .iter()
+        .filter_map(|m| m[name_key].as_str().map(str::to_string))
+        .collect();
+    Some(models)
+}
+
+// The /24 of the interface used for the default route. Connecting a UDP
+// socket sends nothing; it only selects the outgoing interface.
+fn local_subnet() -> Option<Ipv4Net> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_loopback() && !ip.is_unspecified() => {
+            Ipv4Net::new(ip, 24).ok().map(|net| net.trunc())
+        }
+        _ => None,
+    }
+}
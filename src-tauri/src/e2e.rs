@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hkdf::Hkdf;
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::error::RunnerError;
+use crate::protocol::{ChatMessage, ClientMessage, Sealed, ServerMessage, ToolCall};
+use crate::secret_store;
+use crate::state::RunnerState;
+
+// End-to-end encryption of chat content, so the relay only sees ciphertext.
+// The runner has a long-lived X25519 key pair and publishes the public half
+// in its online status. A requester makes a key pair per request, and both
+// sides derive two ChaCha20-Poly1305 keys from the shared secret, one per
+// direction, so a nonce never repeats under the same key. The requestId is
+// authenticated with each frame, so the relay can't move a sealed payload
+// to another request.
+
+// Secret store entry with the runner's private key, base64
+const KEY_NAME: &str = "e2e_key";
+const REQUEST_INFO: &[u8] = b"bottlecap-runner e2e v1 request";
+const RESPONSE_INFO: &[u8] = b"bottlecap-runner e2e v1 response";
+
+// Read from the secret store once
+static RUNNER_KEY: Mutex<Option<StaticSecret>> = Mutex::new(None);
+
+// What a sealed request holds
+#[derive(Deserialize)]
+struct RequestPayload {
+    messages: Vec<ChatMessage>,
+}
+
+// What a sealed response frame holds
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct ResponsePayload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    toolCalls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<String>,
+}
+
+// Response keys of the sealed requests being served, by requestId
+#[derive(Default)]
+pub struct Sessions(Mutex<HashMap<String, [u8; 32]>>);
+
+impl Sessions {
+    fn insert(&self, request_id: &str, key: [u8; 32]) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.insert(request_id.to_string(), key);
+        }
+    }
+
+    // Removed with the final frame
+    fn get(&self, request_id: &str, last: bool) -> Option<[u8; 32]> {
+        let mut sessions = self.0.lock().ok()?;
+        if last {
+            sessions.remove(request_id)
+        } else {
+            sessions.get(request_id).copied()
+        }
+    }
+
+    pub fn end(&self, request_id: &str) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.remove(request_id);
+        }
+    }
+}
+
+fn runner_key() -> Result<StaticSecret, String> {
+    let mut cached = RUNNER_KEY.lock().map_err(|e| e.to_string())?;
+    if let Some(key) = cached.as_ref() {
+        return Ok(key.clone());
+    }
+    let key = match secret_store::get(KEY_NAME).map_err(String::from)? {
+        Some(saved) => StaticSecret::from(key_bytes(&saved).ok_or("The saved end-to-end key is invalid")?),
+        None => {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            secret_store::set(KEY_NAME, &STANDARD.encode(bytes)).map_err(String::from)?;
+            tracing::info!("Created an end-to-end encryption key");
+            StaticSecret::from(bytes)
+        }
+    };
+    *cached = Some(key.clone());
+    Ok(key)
+}
+
+fn key_bytes(encoded: &str) -> Option<[u8; 32]> {
+    STANDARD.decode(encoded.trim()).ok()?.try_into().ok()
+}
+
+// The runner's public key, base64, created on first use
+pub fn public_key() -> Result<String, String> {
+    runner_key().map(|key| STANDARD.encode(PublicKey::from(&key).as_bytes()))
+}
+
+// Replaces the key pair. Requesters sealing for the old public key get
+// `seal_failed` until they pick up the new one from the next online status.
+pub fn rotate() -> Result<String, String> {
+    secret_store::delete(KEY_NAME).map_err(String::from)?;
+    if let Ok(mut cached) = RUNNER_KEY.lock() {
+        *cached = None;
+    }
+    public_key()
+}
+
+// The request and response keys for a requester's public key
+fn derive(runner: &StaticSecret, requester_key: &str) -> Result<([u8; 32], [u8; 32]), String> {
+    let requester = PublicKey::from(key_bytes(requester_key).ok_or("invalid requester key")?);
+    let shared = runner.diffie_hellman(&requester);
+    if !shared.was_contributory() {
+        return Err("invalid requester key".to_string());
+    }
+    // Both public keys as the salt, binding the keys to this pair
+    let mut salt = requester.as_bytes().to_vec();
+    salt.extend_from_slice(PublicKey::from(runner).as_bytes());
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes());
+    let (mut request, mut response) = ([0u8; 32], [0u8; 32]);
+    hkdf.expand(REQUEST_INFO, &mut request).map_err(|e| e.to_string())?;
+    hkdf.expand(RESPONSE_INFO, &mut response).map_err(|e| e.to_string())?;
+    Ok((request, response))
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    // 32 bytes is ChaCha20-Poly1305's key length, so this can't fail
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("ChaCha20-Poly1305 key"))
+}
+
+fn seal_with(key: &[u8; 32], request_id: &str, plaintext: Vec<u8>) -> Result<Sealed, String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let mut sealed = plaintext;
+    aead_key(key)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(request_id.as_bytes()), &mut sealed)
+        .map_err(|_| "encryption failed".to_string())?;
+    Ok(Sealed {
+        key: None,
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(sealed),
+    })
+}
+
+fn open_with(key: &[u8; 32], request_id: &str, sealed: &Sealed) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = STANDARD
+        .decode(&sealed.nonce)
+        .ok()
+        .and_then(|nonce| nonce.try_into().ok())
+        .ok_or("invalid nonce")?;
+    let mut data = STANDARD.decode(&sealed.ciphertext).map_err(|e| format!("invalid ciphertext: {}", e))?;
+    let plaintext = aead_key(key)
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(request_id.as_bytes()), &mut data)
+        .map_err(|_| "wrong key or tampered ciphertext".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+// A sealed chat_request with its messages in the clear, its responses to be
+// sealed by `seal`. Other messages pass through, but with `[e2e] required`
+// unsealed requests fail with the requestId to answer.
+pub fn open(state: &RunnerState, message: ServerMessage) -> Result<ServerMessage, (String, RunnerError)> {
+    let config = state.config().e2e;
+    match message {
+        ServerMessage::ChatRequest { requestId, model, options, priority, sealed: Some(sealed), requesterId, .. } => {
+            if !config.enabled {
+                let error = "end-to-end encryption is off on this runner".to_string();
+                return Err((requestId, RunnerError::SealFailed(error)));
+            }
+            let opened = sealed
+                .key
+                .as_deref()
+                .ok_or_else(|| "no requester key".to_string())
+                .and_then(|key| derive(&runner_key()?, key))
+                .and_then(|(request_key, response_key)| {
+                    let plaintext = open_with(&request_key, &requestId, &sealed)?;
+                    let payload: RequestPayload = serde_json::from_slice(&plaintext).map_err(|e| e.to_string())?;
+                    Ok((payload, response_key))
+                });
+            match opened {
+                Ok((payload, response_key)) => {
+                    state.e2e_sessions().insert(&requestId, response_key);
+                    Ok(ServerMessage::ChatRequest {
+                        requestId,
+                        model,
+                        messages: payload.messages,
+                        options,
+                        priority,
+                        sealed: None,
+                        requesterId,
+                    })
+                }
+                Err(e) => {
+                    tracing::info!(requestId = %requestId, "Rejected sealed request: {}", e);
+                    Err((requestId, RunnerError::SealFailed(e)))
+                }
+            }
+        }
+        ServerMessage::ChatRequest { requestId, .. } | ServerMessage::CompletionRequest { requestId, .. }
+            if config.enabled && config.required =>
+        {
+            tracing::info!(requestId = %requestId, "Rejected request {}: not sealed", requestId);
+            Err((requestId, RunnerError::SealRequired))
+        }
+        message => Ok(message),
+    }
+}
+
+// Moves the content of a frame answering a sealed request into `sealed`.
+// Frames for other requests are returned as they are. The final frame ends
+// the session.
+pub fn seal(state: &RunnerState, mut frame: ClientMessage) -> ClientMessage {
+    let ClientMessage::ChatResponse { requestId, content, chunk, toolCalls, reasoning, done, sealed, .. } = &mut frame else {
+        return frame;
+    };
+    let Some(key) = state.e2e_sessions().get(requestId, *done != Some(false)) else {
+        return frame;
+    };
+    if content.is_none() && chunk.is_none() && toolCalls.is_none() && reasoning.is_none() {
+        return frame;
+    }
+    let payload = ResponsePayload {
+        content: content.take(),
+        chunk: chunk.take(),
+        toolCalls: toolCalls.take(),
+        reasoning: reasoning.take(),
+    };
+    // Serializing plain strings and tool calls can't fail
+    let plaintext = serde_json::to_vec(&payload).unwrap_or_default();
+    match seal_with(&key, requestId, plaintext) {
+        Ok(encrypted) => *sealed = Some(encrypted),
+        Err(e) => tracing::error!(requestId = %requestId, "Could not seal the response: {}", e),
+    }
+    frame
+}
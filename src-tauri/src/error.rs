@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+
+// Failures surfaced to the frontend and reported to the relay in
+// `chat_response.error`. Most code still passes `String` errors around;
+// those convert into `Other` and back.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum RunnerError {
+    #[error("Keyring error: {0}")]
+    KeyringError(String),
+    #[error("Backend unreachable: {0}")]
+    OllamaUnreachable(String),
+    #[error("Backend returned HTTP {status}: {message}")]
+    OllamaHttp { status: u16, message: String },
+    #[error("Backend error: {0}")]
+    Backend(String),
+    #[error("WebSocket connection failed: {0}")]
+    WsConnect(String),
+    #[error("Authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Request cancelled")]
+    Cancelled,
+    #[error("Stream interrupted: the relay connection closed")]
+    StreamInterrupted,
+    #[error("Backend unavailable: it failed its last health checks")]
+    BackendUnavailable,
+    #[error("Runner is paused")]
+    Paused,
+    #[error("Runner is busy: too many requests queued")]
+    Busy,
+    #[error("Rate limited: try again in {retry_after_secs}s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("Duplicate request: this requestId was already answered")]
+    Duplicate,
+    #[error("Model is not in the model filter")]
+    ModelNotAllowed,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Request too large: {0}")]
+    PayloadTooLarge(String),
+    #[error("Refused by the runner's content policy: {0}")]
+    PolicyViolation(String),
+    #[error("Could not open the sealed request: {0}")]
+    SealFailed(String),
+    #[error("This runner only takes end-to-end encrypted requests")]
+    SealRequired,
+    #[error("Request signature rejected: {0}")]
+    BadSignature(String),
+    #[error("The model's output is not valid JSON: {0}")]
+    InvalidJson(String),
+    #[error("Could not pull the model: {0}")]
+    PullFailed(String),
+    // Settings, history, transcripts and other files the runner keeps
+    #[error("{0}")]
+    Storage(String),
+    // Not possible with the configured backend or in this build
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("Backend discovery failed: {0}")]
+    DiscoveryFailed(String),
+    #[error("{0}")]
+    AutostartFailed(String),
+    #[error("{0}")]
+    MockRelay(String),
+    #[error("{0}")]
+    SshTunnel(String),
+    // A lock whose holder panicked
+    #[error("Runner state unavailable: {0}")]
+    StatePoisoned(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+// Wire form of a `RunnerError`, e.g.
+// `{"code": "timeout", "message": "Request timed out", "retryable": true}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorInfo {
+    pub code: String,
+    pub message: String,
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    // When a `rate_limited` request would be let through
+    #[serde(rename = "retryAfterSecs", default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+}
+
+impl RunnerError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RunnerError::KeyringError(_) => "keyring_error",
+            RunnerError::OllamaUnreachable(_) => "backend_unreachable",
+            RunnerError::OllamaHttp { .. } => "backend_http",
+            RunnerError::Backend(_) => "backend_error",
+            RunnerError::WsConnect(_) => "ws_connect",
+            RunnerError::AuthFailed(_) => "auth_failed",
+            RunnerError::Timeout => "timeout",
+            RunnerError::Cancelled => "cancelled",
+            RunnerError::StreamInterrupted => "stream_interrupted",
+            RunnerError::BackendUnavailable => "backend_unavailable",
+            RunnerError::Paused => "runner_paused",
+            RunnerError::Busy => "runner_busy",
+            RunnerError::RateLimited { .. } => "rate_limited",
+            RunnerError::Duplicate => "duplicate_request",
+            RunnerError::ModelNotAllowed => "model_not_allowed",
+            RunnerError::InvalidRequest(_) => "invalid_request",
+            RunnerError::PayloadTooLarge(_) => "payload_too_large",
+            RunnerError::PolicyViolation(_) => "policy_violation",
+            RunnerError::SealFailed(_) => "seal_failed",
+            RunnerError::SealRequired => "seal_required",
+            RunnerError::BadSignature(_) => "bad_signature",
+            RunnerError::InvalidJson(_) => "invalid_json",
+            RunnerError::PullFailed(_) => "pull_failed",
+            RunnerError::Storage(_) => "storage_error",
+            RunnerError::Unsupported(_) => "unsupported",
+            RunnerError::DiscoveryFailed(_) => "discovery_failed",
+            RunnerError::AutostartFailed(_) => "autostart_failed",
+            RunnerError::MockRelay(_) => "mock_relay_error",
+            RunnerError::SshTunnel(_) => "ssh_tunnel_failed",
+            RunnerError::StatePoisoned(_) => "state_poisoned",
+            RunnerError::Other(_) => "internal",
+        }
+    }
+
+    // Whether the same request may succeed if sent again, here or on
+    // another runner
+    pub fn retryable(&self) -> bool {
+        match self {
+            RunnerError::OllamaUnreachable(_)
+            | RunnerError::WsConnect(_)
+            | RunnerError::Timeout
+            | RunnerError::BackendUnavailable
+            | RunnerError::Paused
+            | RunnerError::Busy
+            | RunnerError::RateLimited { .. }
+            | RunnerError::StreamInterrupted
+            | RunnerError::InvalidJson(_) => true,
+            RunnerError::OllamaHttp { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    pub fn info(&self) -> ErrorInfo {
+        ErrorInfo {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            retryable: self.retryable(),
+            status: match self {
+                RunnerError::OllamaHttp { status, .. } => Some(*status),
+                _ => None,
+            },
+            retry_after_secs: match self {
+                RunnerError::RateLimited { retry_after_secs } => Some(*retry_after_secs),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Serialize for RunnerError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.info().serialize(serializer)
+    }
+}
+
+impl From<String> for RunnerError {
+    fn from(error: String) -> Self {
+        RunnerError::Other(error)
+    }
+}
+
+impl From<RunnerError> for String {
+    fn from(error: RunnerError) -> Self {
+        error.to_string()
+    }
+}
+
+impl From<keyring::Error> for RunnerError {
+    fn from(error: keyring::Error) -> Self {
+        RunnerError::KeyringError(error.to_string())
+    }
+}
+
+// Send failures mean nothing answered; anything else got a response
+impl From<reqwest::Error> for RunnerError {
+    fn from(error: reqwest::Error) -> Self {
+        match error.status() {
+            Some(status) => RunnerError::OllamaHttp { status: status.as_u16(), message: error.to_string() },
+            None if error.is_connect() || error.is_timeout() => RunnerError::OllamaUnreachable(error.to_string()),
+            None => RunnerError::Backend(error.to_string()),
+        }
+    }
+}
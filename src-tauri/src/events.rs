@@ -0,0 +1,5 @@
+// Destination for runner events. The GUI forwards them to the webview,
+// headless mode writes them to stderr.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &str, payload: serde_json::Value);
+}
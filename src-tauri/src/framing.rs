@@ -0,0 +1,76 @@
+use serde::Serialize;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{compression, msgpack};
+
+// How the runner's frames go out, settled by the relay's `capabilities`
+// message. Until then everything is plain JSON text.
+#[derive(Clone, Copy, Default)]
+pub struct Framing {
+    // Frames longer than this many bytes are gzipped
+    pub gzip_above: Option<usize>,
+    // MessagePack instead of JSON, in binary frames
+    pub msgpack: bool,
+    // Signed with the device key; see `signing`
+    pub sign: bool,
+}
+
+impl Framing {
+    pub fn encode<T: Serialize + ?Sized>(self, message: &T) -> Result<Message, String> {
+        if self.msgpack {
+            Ok(self.binary(msgpack::to_vec(message)?))
+        } else {
+            serde_json::to_string(message).map(|json| self.text(json)).map_err(|e| e.to_string())
+        }
+    }
+
+    // A frame kept as JSON text, such as one being resent
+    pub fn encode_json(self, json: String) -> Result<Message, String> {
+        if self.msgpack {
+            let value: Value = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+            self.encode(&value)
+        } else {
+            Ok(self.text(json))
+        }
+    }
+
+    fn text(self, json: String) -> Message {
+        if self.gzip_above.is_some_and(|limit| json.len() > limit) {
+            Message::Binary(compression::gzip(json.as_bytes()))
+        } else {
+            Message::Text(json)
+        }
+    }
+
+    fn binary(self, data: Vec<u8>) -> Message {
+        if self.gzip_above.is_some_and(|limit| data.len() > limit) {
+            Message::Binary(compression::gzip(&data))
+        } else {
+            Message::Binary(data)
+        }
+    }
+}
+
+// A text or binary frame as the JSON it holds. A binary frame holds JSON or
+// MessagePack, either of them possibly gzipped; a JSON message always starts
+// with `{` and a MessagePack map never does.
+pub fn decode(message: &Message) -> Result<Value, String> {
+    let data = match message {
+        Message::Text(text) => return serde_json::from_str(text).map_err(|e| e.to_string()),
+        Message::Binary(data) => data,
+        _ => return Err("not a data frame".to_string()),
+    };
+    let inflated;
+    let data = if data.starts_with(&compression::GZIP_MAGIC) {
+        inflated = compression::gunzip(data)?;
+        &inflated[..]
+    } else {
+        data
+    };
+    if data.first() == Some(&b'{') {
+        serde_json::from_slice(data).map_err(|e| e.to_string())
+    } else {
+        msgpack::from_slice(data)
+    }
+}
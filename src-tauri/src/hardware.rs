@@ -0,0 +1,110 @@
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use crate::protocol::{Gpu, Hardware};
+
+static DETECTED: OnceCell<Hardware> = OnceCell::const_new();
+
+// Detected once per run; nothing reported here changes while the app runs
+pub async fn detect() -> Hardware {
+    DETECTED.get_or_init(probe).await.clone()
+}
+
+async fn probe() -> Hardware {
+    let mut system = sysinfo::System::new();
+    system.refresh_cpu();
+    system.refresh_memory();
+
+    let total_ram_mb = system.total_memory() / (1024 * 1024);
+    let mut gpus = nvidia_gpus().await;
+    if gpus.is_empty() && cfg!(target_os = "macos") {
+        gpus = mac_gpus(total_ram_mb).await;
+    }
+
+    Hardware {
+        cpuModel: system
+            .cpus()
+            .first()
+            .map(|cpu| cpu.brand().trim().to_string())
+            .unwrap_or_default(),
+        cpuCores: system.physical_core_count().unwrap_or(system.cpus().len()),
+        totalRamMb: total_ram_mb,
+        gpus,
+        os: sysinfo::System::long_os_version().unwrap_or_else(|| std::env::consts::OS.to_string()),
+    }
+}
+
+// One `name, memory.total` line per card, memory in MiB
+async fn nvidia_gpus() -> Vec<Gpu> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=name,memory.total", "--format=csv,noheader,nounits"])
+        .output()
+        .await;
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, vram) = line.rsplit_once(',')?;
+            Some(Gpu {
+                name: name.trim().to_string(),
+                vramMb: vram.trim().parse().ok(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct DisplaysJson {
+    #[serde(rename = "SPDisplaysDataType", default)]
+    displays: Vec<Display>,
+}
+
+#[derive(Deserialize)]
+struct Display {
+    #[serde(rename = "sppci_model")]
+    model: Option<String>,
+    // e.g. "8 GB"; absent on Apple silicon
+    #[serde(rename = "spdisplays_vram")]
+    vram: Option<String>,
+}
+
+// Apple silicon GPUs share system memory, so that is their VRAM
+async fn mac_gpus(total_ram_mb: u64) -> Vec<Gpu> {
+    let output = Command::new("system_profiler")
+        .args(["SPDisplaysDataType", "-json"])
+        .output()
+        .await;
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    let Ok(json) = serde_json::from_slice::<DisplaysJson>(&output.stdout) else {
+        return Vec::new();
+    };
+    json.displays
+        .into_iter()
+        .filter_map(|display| {
+            let name = display.model?;
+            let vram_mb = match display.vram {
+                Some(vram) => parse_size_mb(&vram),
+                None if name.starts_with("Apple") => Some(total_ram_mb),
+                None => None,
+            };
+            Some(Gpu { name, vramMb: vram_mb })
+        })
+        .collect()
+}
+
+fn parse_size_mb(size: &str) -> Option<u64> {
+    let (amount, unit) = size.trim().split_once(' ')?;
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "GB" => Some(amount * 1024),
+        "MB" => Some(amount),
+        _ => None,
+    }
+}
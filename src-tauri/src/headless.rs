@@ -0,0 +1,328 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+
+use crate::config::{self, Config, LogLevel, LogOutput};
+use crate::events::EventSink;
+use crate::ssh_tunnel::SshTunnel;
+use crate::state::RunnerState;
+use crate::{admin, api_server, backend, logging, metrics, power, prometheus, supervisor, token};
+
+static LOG_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+
+// Values that must never appear in log output (the runner token)
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn add_secret(secret: &str) {
+    if let Ok(mut secrets) = SECRETS.lock() {
+        secrets.push(secret.to_string());
+    }
+}
+
+fn redact(line: String) -> String {
+    let secrets = match SECRETS.lock() {
+        Ok(secrets) => secrets,
+        Err(_) => return line,
+    };
+    secrets
+        .iter()
+        .filter(|s| !s.is_empty())
+        .fold(line, |line, secret| line.replace(secret.as_str(), "[redacted]"))
+}
+
+// Process exit codes for headless mode. Values follow sysexits.h so that
+// supervisors can tell "fix the configuration" apart from "retry later".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    Success,
+    Usage,
+    BackendUnreachable,
+    Internal,
+    RelayUnreachable,
+    AuthFailed,
+    ConfigInvalid,
+}
+
+impl ExitReason {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitReason::Success => 0,
+            ExitReason::Usage => 64,
+            ExitReason::BackendUnreachable => 69,
+            ExitReason::Internal => 70,
+            ExitReason::RelayUnreachable => 75,
+            ExitReason::AuthFailed => 77,
+            ExitReason::ConfigInvalid => 78,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ExitReason::Success => "success",
+            ExitReason::Usage => "usage",
+            ExitReason::BackendUnreachable => "backend_unreachable",
+            ExitReason::Internal => "internal",
+            ExitReason::RelayUnreachable => "relay_unreachable",
+            ExitReason::AuthFailed => "auth_failed",
+            ExitReason::ConfigInvalid => "config_invalid",
+        }
+    }
+}
+
+// Writes every runner event as a single JSON line on stderr.
+pub struct StderrSink;
+
+impl EventSink for StderrSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) {
+        let is_error = payload["type"] == "error" || payload["status"] == "error" || payload["status"] == "auth_failed";
+        let level = if is_error { LogLevel::Error } else { LogLevel::Info };
+        log_line(level, event, payload);
+    }
+}
+
+pub fn log_line(level: LogLevel, event: &str, fields: serde_json::Value) {
+    if !logging::enabled(level) {
+        return;
+    }
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+
+    let mut line = serde_json::json!({
+        "ts": ts,
+        "level": level.as_str(),
+        "event": event,
+    });
+
+    match fields {
+        serde_json::Value::Object(fields) => {
+            if let Some(line) = line.as_object_mut() {
+                line.extend(fields);
+            }
+        }
+        serde_json::Value::Null => {}
+        data => line["data"] = data,
+    }
+
+    let line = redact(line.to_string());
+    if LOG_TO_STDOUT.load(Ordering::Relaxed) {
+        let _ = writeln!(std::io::stdout().lock(), "{}", line);
+    } else {
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+}
+
+fn exit(reason: ExitReason, message: &str) -> ExitReason {
+    let level = if reason == ExitReason::Success {
+        LogLevel::Info
+    } else {
+        LogLevel::Error
+    };
+    log_line(level, "exit", serde_json::json!({
+        "code": reason.code(),
+        "reason": reason.as_str(),
+        "message": message,
+    }));
+    reason
+}
+
+/// Serve relay requests without opening a window
+#[derive(Parser)]
+#[command(name = "bottlecap-runner", version, about)]
+struct Args {
+    /// Run without the desktop window (required)
+    #[arg(long)]
+    headless: bool,
+    /// Load settings from a TOML file
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Runner token. Visible to other local users in the process list;
+    /// prefer BOTTLECAP_TOKEN or --token-file on shared machines
+    #[arg(long, value_name = "TOKEN")]
+    token: Option<String>,
+    /// Read the runner token from a file
+    #[arg(long, value_name = "PATH")]
+    token_file: Option<PathBuf>,
+    /// Restart the process above this resident memory (0 disables)
+    #[arg(long, value_name = "MB")]
+    max_memory_mb: Option<u64>,
+    /// Exit after this many consecutive connection failures
+    #[arg(long, value_name = "N")]
+    max_retries: Option<u32>,
+    /// Serve the admin endpoint on this address, e.g. 127.0.0.1:8765
+    #[arg(long, value_name = "ADDR")]
+    admin_addr: Option<SocketAddr>,
+    /// Token required by the admin endpoint
+    #[arg(long, value_name = "TOKEN", env = admin::ADMIN_TOKEN_ENV, hide_env_values = true)]
+    admin_token: Option<String>,
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9464
+    #[arg(long, value_name = "ADDR")]
+    prometheus_addr: Option<SocketAddr>,
+    /// Where log lines go
+    #[arg(long, value_enum, value_name = "STREAM")]
+    log_output: Option<LogOutput>,
+}
+
+pub fn run() -> ExitReason {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) if !e.use_stderr() => {
+            // --help and --version
+            let _ = e.print();
+            return ExitReason::Success;
+        }
+        Err(e) => return exit(ExitReason::Usage, e.to_string().trim()),
+    };
+
+    let mut config = match &args.config {
+        Some(path) => match config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                log_line(LogLevel::Error, "config-invalid", serde_json::json!({
+                    "path": path.display().to_string(),
+                    "line": e.line,
+                    "column": e.column,
+                }));
+                return exit(ExitReason::ConfigInvalid, &e.message);
+            }
+        },
+        None => Config::default(),
+    };
+    // Runner log lines join the other events on stderr (or stdout)
+    logging::init(&config.logging, |record| {
+        let mut fields = record.fields.clone();
+        fields.insert("message".to_string(), record.message.clone().into());
+        log_line(record.level, "log-message", fields.into());
+    });
+    let log_output = args.log_output.unwrap_or(config.logging.output);
+    LOG_TO_STDOUT.store(log_output == LogOutput::Stdout, Ordering::Relaxed);
+
+    // Command line flags win over the config file
+    config.admin.addr = args.admin_addr.or(config.admin.addr);
+    config.metrics.prometheus_addr = args.prometheus_addr.or(config.metrics.prometheus_addr);
+    config.admin.token = args.admin_token.or(config.admin.token).filter(|t| !t.is_empty());
+    config.supervisor.max_memory_mb = args.max_memory_mb.or(config.supervisor.max_memory_mb);
+    config.supervisor.max_retries = args.max_retries.or(config.supervisor.max_retries);
+
+    let (token, from_keyring) = match token::resolve(args.token.as_deref(), args.token_file.as_deref()) {
+        Ok(Some((token, source))) => {
+            add_secret(&token);
+            log_line(LogLevel::Info, "token-loaded", serde_json::json!({ "source": source.describe() }));
+            (token, matches!(source, token::TokenSource::Keyring))
+        }
+        Ok(None) => {
+            let message = format!(
+                "No runner token: set {} or {}, pass --token or --token-file, or save one in the app",
+                token::TOKEN_ENV,
+                token::TOKEN_FILE_ENV
+            );
+            return exit(ExitReason::ConfigInvalid, &message);
+        }
+        Err(e) => return exit(ExitReason::ConfigInvalid, &format!("Failed to read token: {}", e)),
+    };
+
+    if let Some(admin_token) = &config.admin.token {
+        add_secret(admin_token);
+    }
+    for api_key in config.api.keys() {
+        add_secret(&api_key);
+    }
+    if let Some(api_key) = &config.backend.api_key {
+        add_secret(api_key);
+    }
+    // Short header values, like a tenant name, would garble the log and are
+    // unlikely to be keys
+    let auth = &config.backend.auth;
+    let header_keys = auth.headers.values().filter(|value| value.len() >= 8);
+    for secret in auth.bearer_token.iter().chain(&auth.password).chain(header_keys) {
+        add_secret(secret);
+    }
+    if config.admin.addr.is_some() && config.admin.token.is_none() {
+        let message = format!(
+            "The admin endpoint requires a token (--admin-token, {} or admin.token)",
+            admin::ADMIN_TOKEN_ENV
+        );
+        return exit(ExitReason::ConfigInvalid, &message);
+    }
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => return exit(ExitReason::Internal, &format!("Failed to start runtime: {}", e)),
+    };
+
+    runtime.block_on(async move {
+        let events: Arc<dyn EventSink> = Arc::new(StderrSink);
+
+        // Kept alive for the rest of the run
+        let tunnel = if config.ssh.enabled {
+            match SshTunnel::start(&config.ssh, events.clone()).await {
+                Ok(tunnel) => {
+                    config.backend.url = tunnel.local_url().to_string();
+                    Some(tunnel)
+                }
+                Err(e) => return exit(ExitReason::BackendUnreachable, &e),
+            }
+        } else {
+            None
+        };
+
+        if !backend::reachable(&config.backend).await {
+            let message = format!("Backend is not reachable at {}", config.backend.url);
+            return exit(ExitReason::BackendUnreachable, &message);
+        }
+
+        let mut policy = supervisor::Policy::default();
+        if let Some(mb) = config.supervisor.max_memory_mb {
+            policy.max_memory_bytes = (mb > 0).then_some(mb * 1024 * 1024);
+        }
+        policy.max_failures = config.supervisor.max_retries;
+
+        let admin = config.admin.clone();
+        let api = config.api.clone();
+        let tls = config.tls.clone();
+        let state = Arc::new(RunnerState::new(config));
+        // Refreshed tokens can only be saved back to the keyring
+        state.set_token_secret(from_keyring.then_some("token"));
+        if let Err(e) = state.metrics().restore(&state.config().metrics) {
+            log_line(LogLevel::Warn, "metrics", serde_json::json!({ "error": e }));
+        }
+        tokio::spawn(metrics::run(state.clone(), |saved| {
+            if let Err(e) = saved {
+                log_line(LogLevel::Warn, "metrics", serde_json::json!({ "error": e }));
+            }
+        }));
+
+        tokio::spawn(power::run(state.clone()));
+
+        if api.enabled {
+            match api_server::start(&api, &tls, state.clone(), events.clone()).await {
+                Ok(addr) => log_line(LogLevel::Info, "api-listening", serde_json::json!({ "addr": addr.to_string() })),
+                Err(e) => return exit(ExitReason::ConfigInvalid, &e),
+            }
+        }
+
+        if let Some(addr) = state.config().metrics.prometheus_addr {
+            match prometheus::start(addr, state.clone()).await {
+                Ok(addr) => log_line(LogLevel::Info, "prometheus-listening", serde_json::json!({ "addr": addr.to_string() })),
+                Err(e) => return exit(ExitReason::ConfigInvalid, &format!("Prometheus endpoint: {}", e)),
+            }
+        }
+
+        if let (Some(addr), Some(admin_token)) = (admin.addr, admin.token.clone()) {
+            match admin::start(addr, admin_token, &admin, &tls, state.clone()).await {
+                Ok(addr) => log_line(LogLevel::Info, "admin-listening", serde_json::json!({ "addr": addr.to_string() })),
+                Err(e) => return exit(ExitReason::ConfigInvalid, &format!("Admin endpoint: {}", e)),
+            }
+        }
+
+        let (reason, message) = supervisor::run(token, events, state, policy, tunnel).await;
+        exit(reason, &message)
+    })
+}
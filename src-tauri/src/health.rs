@@ -0,0 +1,28 @@
+use crate::backend;
+use crate::events::EventSink;
+use crate::state::RunnerState;
+
+// Called from the connection loop every `[health] check_interval_secs`.
+// After `failure_threshold` failed checks in a row the circuit opens:
+// requests are turned away at once with `backend_unavailable` rather than
+// each waiting out a timeout, and the relay is sent `status: "degraded"`.
+// Checks go on as probes, and the first that passes closes it again.
+// Changes go out as `backend-health` events.
+pub async fn check(events: &dyn EventSink, state: &RunnerState) {
+    let config = state.config();
+    let up = tokio::time::timeout(backend::PROBE_TIMEOUT, backend::reachable(&config.backend)).await.unwrap_or(false);
+    let failures = state.record_backend_check(up);
+    let down = failures >= config.health.failure_threshold.max(1);
+    if !state.set_backend_down(down) {
+        return;
+    }
+    if down {
+        tracing::warn!("The backend failed {} checks in a row; turning requests away until it answers", failures);
+    } else {
+        tracing::info!("The backend is answering again; taking requests");
+    }
+    events.emit("backend-health", serde_json::json!({
+        "status": if down { "down" } else { "up" },
+        "failures": failures,
+    }));
+}
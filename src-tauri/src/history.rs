@@ -0,0 +1,179 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use crate::config::HistoryConfig;
+use crate::error::ErrorInfo;
+use crate::privacy;
+use crate::transcripts::TimeRange;
+
+// One served (or refused) chat request. Only metadata is kept; prompt and
+// response text go to transcripts when those are enabled.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub request_id: String,
+    pub model: String,
+    // Unix milliseconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub duration_ms: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // completed, failed, cancelled, timeout or rejected
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Page {
+    pub entries: Vec<Entry>,
+    // Entries in the range across all pages
+    pub total: u64,
+}
+
+impl Entry {
+    pub fn new(request_id: &str, model: &str, started: SystemTime, error: Option<&ErrorInfo>) -> Self {
+        let finished = SystemTime::now();
+        Self {
+            request_id: request_id.to_string(),
+            model: model.to_string(),
+            started_at: unix_ms(started),
+            finished_at: unix_ms(finished),
+            duration_ms: finished.duration_since(started).unwrap_or_default().as_millis() as u64,
+            input_tokens: 0,
+            output_tokens: 0,
+            outcome: outcome(error).to_string(),
+            // Messages may quote the backend, and with it the prompt
+            error: error.map(|e| if privacy::enabled() { e.code.clone() } else { e.message.clone() }),
+        }
+    }
+}
+
+pub fn outcome(error: Option<&ErrorInfo>) -> &'static str {
+    match error.map(|e| e.code.as_str()) {
+        None => "completed",
+        Some("cancelled") | Some("stream_interrupted") => "cancelled",
+        Some("timeout") => "timeout",
+        Some("runner_paused") | Some("runner_busy") | Some("model_not_allowed") => "rejected",
+        Some(_) => "failed",
+    }
+}
+
+pub fn path(config: &HistoryConfig) -> Option<PathBuf> {
+    config.path.clone().or_else(|| {
+        dirs_next::data_dir().map(|dir| dir.join("bottlecap-runner").join("history.sqlite3"))
+    })
+}
+
+fn open(config: &HistoryConfig) -> Result<Connection, String> {
+    let path = path(config).ok_or("No data directory for request history")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let db = Connection::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    db.busy_timeout(Duration::from_secs(2)).map_err(|e| e.to_string())?;
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS requests (
+            id INTEGER PRIMARY KEY,
+            request_id TEXT NOT NULL,
+            model TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            finished_at INTEGER NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            input_tokens INTEGER NOT NULL,
+            output_tokens INTEGER NOT NULL,
+            outcome TEXT NOT NULL,
+            error TEXT
+        );
+        CREATE INDEX IF NOT EXISTS requests_started_at ON requests (started_at);",
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(db)
+}
+
+pub fn record(config: &HistoryConfig, entry: &Entry) -> Result<(), String> {
+    let db = open(config)?;
+    db.execute(
+        "INSERT INTO requests (request_id, model, started_at, finished_at, duration_ms, input_tokens, output_tokens, outcome, error)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            entry.request_id,
+            entry.model,
+            entry.started_at as i64,
+            entry.finished_at as i64,
+            entry.duration_ms as i64,
+            entry.input_tokens as i64,
+            entry.output_tokens as i64,
+            entry.outcome,
+            entry.error,
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Newest first. `page` counts from 0.
+pub fn query(config: &HistoryConfig, range: &TimeRange, page: u32, page_size: u32) -> Result<Page, String> {
+    let db = open(config)?;
+    let from = range.from.unwrap_or(0) as i64;
+    let to = range.to.map(|to| to as i64).unwrap_or(i64::MAX);
+
+    let total: i64 = db
+        .query_row(
+            "SELECT COUNT(*) FROM requests WHERE started_at BETWEEN ?1 AND ?2",
+            params![from, to],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut statement = db
+        .prepare(
+            "SELECT request_id, model, started_at, finished_at, duration_ms, input_tokens, output_tokens, outcome, error
+             FROM requests WHERE started_at BETWEEN ?1 AND ?2
+             ORDER BY started_at DESC, id DESC LIMIT ?3 OFFSET ?4",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map(
+            params![from, to, page_size as i64, page as i64 * page_size as i64],
+            |row| {
+                Ok(Entry {
+                    request_id: row.get(0)?,
+                    model: row.get(1)?,
+                    started_at: row.get::<_, i64>(2)? as u64,
+                    finished_at: row.get::<_, i64>(3)? as u64,
+                    duration_ms: row.get::<_, i64>(4)? as u64,
+                    input_tokens: row.get::<_, i64>(5)? as u64,
+                    output_tokens: row.get::<_, i64>(6)? as u64,
+                    outcome: row.get(7)?,
+                    error: row.get(8)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    let entries = rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+
+    Ok(Page {
+        entries,
+        total: total as u64,
+    })
+}
+
+// Returns the number of entries removed
+pub fn clear(config: &HistoryConfig, range: &TimeRange) -> Result<u64, String> {
+    let db = open(config)?;
+    let from = range.from.unwrap_or(0) as i64;
+    let to = range.to.map(|to| to as i64).unwrap_or(i64::MAX);
+    let removed = db
+        .execute("DELETE FROM requests WHERE started_at BETWEEN ?1 AND ?2", params![from, to])
+        .map_err(|e| e.to_string())?;
+    Ok(removed as u64)
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
@@ -0,0 +1,60 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::protocol::ChatMessage;
+
+// Image formats vision models take, by their leading bytes
+const SIGNATURES: &[(&str, &[u8])] = &[
+    ("image/png", b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", b"\xff\xd8\xff"),
+    ("image/gif", b"GIF8"),
+];
+
+// Checks the images attached to `messages`: each must be base64 for a PNG,
+// JPEG, GIF or WebP of at most `max_bytes`. `data:` URLs are reduced to their
+// base64 part, which is what Ollama expects.
+pub fn validate(messages: &mut [ChatMessage], max_bytes: u64) -> Result<(), String> {
+    for (m, message) in messages.iter_mut().enumerate() {
+        for (i, image) in message.images.iter_mut().enumerate() {
+            let name = || format!("Image {} of message {}", i + 1, m + 1);
+            let data = match image.strip_prefix("data:").and_then(|url| url.split_once(";base64,")) {
+                Some((_, data)) => data,
+                None => image.as_str(),
+            }
+            .trim()
+            .to_string();
+            // Checked before decoding so oversized uploads are not decoded
+            if data.len() as u64 / 4 * 3 > max_bytes.saturating_add(2) {
+                return Err(format!("{} is larger than {} bytes", name(), max_bytes));
+            }
+            let bytes = STANDARD
+                .decode(&data)
+                .map_err(|e| format!("{} is not valid base64: {}", name(), e))?;
+            if bytes.len() as u64 > max_bytes {
+                return Err(format!("{} is larger than {} bytes", name(), max_bytes));
+            }
+            if media_type(&bytes).is_none() {
+                return Err(format!("{} is not a PNG, JPEG, GIF or WebP image", name()));
+            }
+            *image = data;
+        }
+    }
+    Ok(())
+}
+
+pub fn media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(_, signature)| bytes.starts_with(signature))
+        .map(|(media_type, _)| *media_type)
+}
+
+// For backends that take images as URLs (OpenAI's `image_url` parts)
+pub fn data_url(image: &str) -> String {
+    // 16 base64 characters are 12 bytes, enough for every signature
+    let head = STANDARD.decode(image.get(..16).unwrap_or(image)).unwrap_or_default();
+    format!("data:{};base64,{}", media_type(&head).unwrap_or("image/png"), image)
+}
@@ -0,0 +1,152 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::{pairing, tray};
+
+// How long a second launch waits for the first one to start listening
+const FORWARD_ATTEMPTS: u32 = 20;
+const FORWARD_RETRY: Duration = Duration::from_millis(250);
+
+// Held for as long as this process is the app's one instance
+static LOCK: OnceLock<File> = OnceLock::new();
+
+// Where the running app takes launches of a second copy, including the
+// `bottlecap://` links Windows and Linux open that way: a loopback port, and
+// a secret so other users on the machine can't send them
+#[derive(Serialize, Deserialize)]
+struct Listening {
+    port: u16,
+    secret: String,
+}
+
+fn dir() -> Option<PathBuf> {
+    tauri::api::path::config_dir().map(|dir| dir.join("bottlecap-runner"))
+}
+
+// Whether this is the only instance of the app. Two would run two runners
+// with the same token, each taking the connection from the other. When the
+// lock file can't be used at all, the launch goes ahead rather than failing.
+pub fn claim() -> bool {
+    let Some(dir) = dir() else {
+        return true;
+    };
+    let file = fs::create_dir_all(&dir).and_then(|_| {
+        File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join("instance.lock"))
+    });
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::warn!("Could not check for a running instance: {}", e);
+            return true;
+        }
+    };
+    match file.try_lock() {
+        Ok(()) => {
+            let _ = LOCK.set(file);
+            true
+        }
+        Err(fs::TryLockError::WouldBlock) => false,
+        Err(fs::TryLockError::Error(e)) => {
+            tracing::warn!("Could not check for a running instance: {}", e);
+            true
+        }
+    }
+}
+
+// Hands this launch's arguments to the running instance, which shows its
+// window and opens any links among them. False when it couldn't be reached.
+pub fn forward(args: &[String]) -> bool {
+    (0..FORWARD_ATTEMPTS).any(|attempt| {
+        if attempt > 0 {
+            std::thread::sleep(FORWARD_RETRY);
+        }
+        send(args).is_some()
+    })
+}
+
+fn send(args: &[String]) -> Option<()> {
+    let source = fs::read_to_string(dir()?.join("instance.json")).ok()?;
+    let listening: Listening = serde_json::from_str(&source).ok()?;
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, listening.port));
+    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(1)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    let args = serde_json::to_string(args).ok()?;
+    writeln!(stream, "{}\n{}", listening.secret, args).ok()?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).ok()?;
+    (reply.trim() == "ok").then_some(())
+}
+
+pub fn listen(app: AppHandle) {
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::warn!("Later launches can't reach this instance: {}", e);
+            return;
+        }
+    };
+    let secret: String = rand::thread_rng().gen::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let written = listener
+        .local_addr()
+        .map_err(|e| e.to_string())
+        .and_then(|address| write(&Listening { port: address.port(), secret: secret.clone() }));
+    if let Err(e) = written {
+        tracing::warn!("Later launches can't reach this instance: {}", e);
+        return;
+    }
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Some(args) = receive(stream, &secret) else {
+                continue;
+            };
+            tracing::info!("Another launch was handed to this instance");
+            tray::show_window(&app);
+            for link in args.iter().filter(|arg| arg.starts_with(&format!("{}://", pairing::SCHEME))) {
+                pairing::handle_url(&app, link);
+            }
+        }
+    });
+}
+
+fn write(listening: &Listening) -> Result<(), String> {
+    let dir = dir().ok_or("No config directory")?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join("instance.json");
+    let json = serde_json::to_string(listening).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+    }
+    Ok(())
+}
+
+// The secret, then the arguments as a JSON array, a line each
+fn receive(stream: TcpStream, secret: &str) -> Option<Vec<String>> {
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    let mut reader = BufReader::new(stream.try_clone().ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    if line.trim_end() != secret {
+        return None;
+    }
+    line.clear();
+    reader.read_line(&mut line).ok()?;
+    let args = serde_json::from_str(line.trim_end()).ok()?;
+    writeln!(&stream, "ok").ok()?;
+    Some(args)
+}
@@ -0,0 +1,67 @@
+// The runner without the desktop shell: relay protocol and connection loop,
+// backends, and headless mode. The Tauri app in main.rs builds on this.
+
+pub mod access;
+pub mod admin;
+pub mod api_server;
+pub mod backend;
+pub mod backend_auth;
+pub mod balancer;
+pub mod battery;
+pub mod benchmark;
+pub mod client_tls;
+pub mod compression;
+pub mod config;
+pub mod connection;
+pub mod cost;
+pub mod dedup;
+pub mod diagnostics;
+pub mod discovery;
+pub mod e2e;
+pub mod error;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod events;
+pub mod framing;
+pub mod hardware;
+pub mod images;
+pub mod limits;
+pub mod local_socket;
+pub mod logging;
+pub mod metrics;
+pub mod msgpack;
+pub mod headless;
+pub mod health;
+pub mod history;
+pub mod mock_backend;
+pub mod mock_relay;
+pub mod ollama;
+pub mod outbox;
+pub mod policy;
+pub mod power;
+pub mod openai_backend;
+pub mod protocol;
+pub mod privacy;
+pub mod prometheus;
+pub mod proxy;
+pub mod pull;
+pub mod rate_limit;
+pub mod reasoning;
+pub mod reconnect;
+pub mod retry;
+pub mod recorder;
+pub mod schedule;
+pub mod scheduler;
+pub mod secret_store;
+pub mod settings;
+pub mod signing;
+pub mod state;
+pub mod supervisor;
+pub mod system_prompt;
+pub mod tailscale;
+pub mod ssh_tunnel;
+pub mod token;
+pub mod tokens;
+pub mod transcripts;
+pub mod translate;
+pub mod webrtc_direct;
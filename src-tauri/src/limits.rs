@@ -0,0 +1,50 @@
+use crate::config::LimitsConfig;
+use crate::protocol::Input;
+use crate::tokens;
+
+// The prompt's text in characters: every message's content, or a
+// completion's prompt and system prompt. Images are limited separately.
+pub fn prompt_chars(input: &Input) -> usize {
+    match input {
+        Input::Chat(messages) => messages.iter().map(|message| message.content.chars().count()).sum(),
+        Input::Completion(prompt) => {
+            prompt.prompt.chars().count() + prompt.system.as_deref().map_or(0, |system| system.chars().count())
+        }
+    }
+}
+
+// The `max_tokens` a request is held to for `model`, if it has a cap
+pub fn max_tokens_cap(config: &LimitsConfig, model: &str) -> Option<i32> {
+    let cap = config.model_max_tokens.get(model).copied().unwrap_or(config.max_tokens);
+    (cap > 0).then_some(cap)
+}
+
+// Lowers `max_tokens` to the cap. Unset, 0 or negative (Ollama's "no limit")
+// count as asking for more. Returns the cap when it changed the request.
+pub fn clamp_max_tokens(config: &LimitsConfig, model: &str, max_tokens: &mut Option<i32>) -> Option<i32> {
+    let cap = max_tokens_cap(config, model)?;
+    if max_tokens.is_some_and(|requested| requested > 0 && requested <= cap) {
+        return None;
+    }
+    *max_tokens = Some(cap);
+    Some(cap)
+}
+
+// Refuses prompts over the configured size, before they reach a backend that
+// would run out of memory or swap on them
+pub fn check(config: &LimitsConfig, input: &Input) -> Result<(), String> {
+    if let Input::Chat(messages) = input {
+        if config.max_messages > 0 && messages.len() > config.max_messages {
+            return Err(format!("{} messages, more than the {} allowed", messages.len(), config.max_messages));
+        }
+    }
+    let chars = prompt_chars(input);
+    if config.max_prompt_chars > 0 && chars > config.max_prompt_chars {
+        return Err(format!("The prompt is {} characters, more than the {} allowed", chars, config.max_prompt_chars));
+    }
+    let tokens = tokens::count_input(input);
+    if config.max_prompt_tokens > 0 && tokens > config.max_prompt_tokens {
+        return Err(format!("The prompt is about {} tokens, more than the {} allowed", tokens, config.max_prompt_tokens));
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+use std::io;
+use std::sync::Mutex;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use url::Url;
+
+// Ollama listening on a Unix socket (`unix:///run/ollama/ollama.sock`) or a
+// Windows named pipe (`npipe://./pipe/ollama`) instead of a TCP port.
+// reqwest only speaks TCP in this build, so requests go to a made-up host
+// that `proxy::http_client` sends through its loopback bridge, which
+// connects to the socket. The host ends in `.localhost`, which Ollama's
+// check of the Host header lets through.
+const HOST_SUFFIX: &str = ".socket.localhost";
+
+// Socket URLs handed out so far; a made-up host is its index here
+static SOCKETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub fn is_socket_url(url: &str) -> bool {
+    url.starts_with("unix:") || url.starts_with("npipe:")
+}
+
+// The http:// base URL that reaches `base_url`, which is returned as it is
+// unless it names a socket
+pub fn http_base(base_url: &str) -> Cow<'_, str> {
+    if !is_socket_url(base_url) {
+        return Cow::Borrowed(base_url);
+    }
+    let Ok(mut sockets) = SOCKETS.lock() else {
+        return Cow::Borrowed(base_url);
+    };
+    let index = match sockets.iter().position(|socket| socket == base_url) {
+        Some(index) => index,
+        None => {
+            sockets.push(base_url.to_string());
+            sockets.len() - 1
+        }
+    };
+    Cow::Owned(format!("http://s{}{}", index, HOST_SUFFIX))
+}
+
+// The socket URL a made-up host from `http_base` stands for
+pub fn socket_for(target: &Url) -> Option<String> {
+    let index: usize = target.host_str()?.strip_suffix(HOST_SUFFIX)?.strip_prefix('s')?.parse().ok()?;
+    SOCKETS.lock().ok()?.get(index).cloned()
+}
+
+// The path after the scheme, with or without `//`
+fn socket_path(socket_url: &str) -> &str {
+    let rest = socket_url.split_once(':').map_or(socket_url, |(_, rest)| rest);
+    rest.strip_prefix("//").unwrap_or(rest)
+}
+
+#[cfg(unix)]
+pub async fn connect(socket_url: &str) -> io::Result<Box<dyn Stream>> {
+    if !socket_url.starts_with("unix:") {
+        return Err(io::Error::other(format!("{} is not a Unix socket", socket_url)));
+    }
+    let stream = tokio::net::UnixStream::connect(socket_path(socket_url)).await?;
+    Ok(Box::new(stream))
+}
+
+// `npipe://./pipe/ollama` is `\\.\pipe\ollama`
+#[cfg(windows)]
+pub async fn connect(socket_url: &str) -> io::Result<Box<dyn Stream>> {
+    if !socket_url.starts_with("npipe:") {
+        return Err(io::Error::other(format!("{} is not a named pipe", socket_url)));
+    }
+    let name = format!(r"\\{}", socket_path(socket_url).replace('/', r"\"));
+    let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(name)?;
+    Ok(Box::new(pipe))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub async fn connect(socket_url: &str) -> io::Result<Box<dyn Stream>> {
+    Err(io::Error::other(format!("{} is not supported on this platform", socket_url)))
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+use crate::config::{LogLevel, LoggingConfig};
+use crate::privacy;
+
+// Lines below this level are dropped
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+// Kept for `get_recent_logs`
+const RECENT_KEPT: usize = 1000;
+static RECENT: Mutex<VecDeque<Record>> = Mutex::new(VecDeque::new());
+
+// One `tracing` event from the runner, e.g.
+// `{"ts": 1718000000000, "level": "info", "message": "Completed: 42 tokens", "requestId": "r1", "model": "llama3"}`
+#[derive(Serialize, Clone, Debug)]
+pub struct Record {
+    // Unix milliseconds
+    pub ts: u64,
+    pub level: LogLevel,
+    pub message: String,
+    #[serde(flatten)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Record {
+    // The `log-message` event the window shows; `type` is info, error or
+    // success (events logged with `success = true`)
+    pub fn event_payload(&self) -> serde_json::Value {
+        let kind = match self.level {
+            LogLevel::Warn | LogLevel::Error => "error",
+            _ if self.fields.get("success") == Some(&serde_json::Value::Bool(true)) => "success",
+            _ => "info",
+        };
+        let mut payload = serde_json::json!(self);
+        payload["type"] = kind.into();
+        payload
+    }
+}
+
+pub fn set_level(level: LogLevel) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 >= MIN_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn dir(config: &LoggingConfig) -> Option<PathBuf> {
+    config
+        .dir
+        .clone()
+        .or_else(|| dirs_next::data_dir().map(|dir| dir.join("bottlecap-runner").join("logs")))
+}
+
+// Routes the runner's `tracing` events to the recent log buffer, the daily
+// log file (when enabled) and `forward`, which shows them live. Only the
+// first call in a process takes effect.
+pub fn init(config: &LoggingConfig, forward: impl Fn(&Record) + Send + Sync + 'static) {
+    set_level(config.level);
+    let file = config.file.then(|| dir(config)).flatten().map(|dir| {
+        Mutex::new(LogFile {
+            dir,
+            keep_days: config.keep_days,
+            date: String::new(),
+            file: None,
+        })
+    });
+    let layer = RunnerLayer {
+        file,
+        forward: Box::new(forward),
+    };
+    let _ = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer));
+}
+
+// Newest last; `level` leaves out anything less severe
+pub fn recent(limit: usize, level: Option<LogLevel>) -> Vec<Record> {
+    let Ok(recent) = RECENT.lock() else {
+        return Vec::new();
+    };
+    let matching: Vec<&Record> = recent
+        .iter()
+        .filter(|record| level.is_none_or(|level| record.level >= level))
+        .collect();
+    matching[matching.len().saturating_sub(limit)..].iter().map(|record| (*record).clone()).collect()
+}
+
+struct RunnerLayer {
+    file: Option<Mutex<LogFile>>,
+    forward: Box<dyn Fn(&Record) + Send + Sync>,
+}
+
+impl<S: tracing::Subscriber> Layer<S> for RunnerLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Dependencies log through `tracing` too
+        if !metadata.target().starts_with("bottlecap_runner") {
+            return;
+        }
+        let level = match *metadata.level() {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warn,
+            tracing::Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        };
+        if !enabled(level) {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut fields = serde_json::Value::Object(visitor.fields);
+        if privacy::enabled() {
+            privacy::redact(&mut fields);
+        }
+        let record = Record {
+            ts: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            level,
+            message: visitor.message,
+            fields: match fields {
+                serde_json::Value::Object(fields) => fields,
+                _ => serde_json::Map::new(),
+            },
+        };
+
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                file.write(&record);
+            }
+        }
+        (self.forward)(&record);
+        if let Ok(mut recent) = RECENT.lock() {
+            recent.push_back(record);
+            while recent.len() > RECENT_KEPT {
+                recent.pop_front();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.insert(field, value.into());
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+// One JSON line per record in `runner-YYYY-MM-DD.log`, starting a new file
+// each day and deleting those older than `keep_days`
+struct LogFile {
+    dir: PathBuf,
+    keep_days: u32,
+    date: String,
+    file: Option<File>,
+}
+
+impl LogFile {
+    fn write(&mut self, record: &Record) {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        if self.file.is_none() || self.date != today {
+            self.date = today;
+            self.file = self.open().ok();
+        }
+        if let (Some(file), Ok(line)) = (&mut self.file, serde_json::to_string(record)) {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn open(&self) -> std::io::Result<File> {
+        fs::create_dir_all(&self.dir)?;
+        self.prune();
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(format!("runner-{}.log", self.date)))
+    }
+
+    fn prune(&self) {
+        let oldest = (chrono::Local::now() - chrono::Duration::days(self.keep_days.into()))
+            .format("runner-%Y-%m-%d.log")
+            .to_string();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Names sort by date
+            if name.starts_with("runner-") && name.ends_with(".log") && name < oldest {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MetricsConfig;
+use crate::protocol::Usage;
+use crate::state::RunnerState;
+
+// Totals for one model since the counters were first saved
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ModelStats {
+    // Completed requests; failed ones only count as errors
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // Time spent waiting on the backend for completed requests
+    pub busy_ms: u64,
+}
+
+impl ModelStats {
+    pub fn avg_latency_ms(&self) -> u64 {
+        self.busy_ms.checked_div(self.requests).unwrap_or(0)
+    }
+
+    pub fn tokens_per_sec(&self) -> f64 {
+        if self.busy_ms == 0 {
+            return 0.0;
+        }
+        let rate = self.output_tokens as f64 * 1000.0 / self.busy_ms as f64;
+        (rate * 10.0).round() / 10.0
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    models: Mutex<BTreeMap<String, ModelStats>>,
+    // Changed since the last save
+    dirty: AtomicBool,
+}
+
+impl Metrics {
+    // `usage` is None for a failed request
+    pub fn record(&self, model: &str, usage: Option<&Usage>, busy: Duration) {
+        let Ok(mut models) = self.models.lock() else {
+            return;
+        };
+        let stats = models.entry(model.to_string()).or_default();
+        match usage {
+            Some(usage) => {
+                stats.requests += 1;
+                stats.input_tokens += usage.inputTokens.max(0) as u64;
+                stats.output_tokens += usage.outputTokens.max(0) as u64;
+                stats.busy_ms += busy.as_millis() as u64;
+            }
+            None => stats.errors += 1,
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn models(&self) -> BTreeMap<String, ModelStats> {
+        self.models.lock().map(|models| models.clone()).unwrap_or_default()
+    }
+
+    // One entry per model with the averages worked out, for `get_stats`
+    pub fn snapshot(&self) -> serde_json::Value {
+        self.models()
+            .into_iter()
+            .map(|(model, stats)| {
+                serde_json::json!({
+                    "model": model,
+                    "requests": stats.requests,
+                    "errors": stats.errors,
+                    "inputTokens": stats.input_tokens,
+                    "outputTokens": stats.output_tokens,
+                    "avgLatencyMs": stats.avg_latency_ms(),
+                    "tokensPerSec": stats.tokens_per_sec(),
+                })
+            })
+            .collect()
+    }
+
+    // Adds what an earlier run saved to anything counted since
+    pub fn restore(&self, config: &MetricsConfig) -> Result<(), String> {
+        let Some(path) = path(config) else {
+            return Ok(());
+        };
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let saved: BTreeMap<String, ModelStats> =
+            serde_json::from_str(&source).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        if let Ok(mut models) = self.models.lock() {
+            for (model, old) in saved {
+                let stats = models.entry(model).or_default();
+                stats.requests += old.requests;
+                stats.errors += old.errors;
+                stats.input_tokens += old.input_tokens;
+                stats.output_tokens += old.output_tokens;
+                stats.busy_ms += old.busy_ms;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the counters when they changed since the last save
+    pub fn save(&self, config: &MetricsConfig) -> Result<(), String> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let path = path(config).ok_or("No data directory for metrics")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(&self.models()).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| {
+            self.dirty.store(true, Ordering::Relaxed);
+            format!("Failed to write {}: {}", path.display(), e)
+        })
+    }
+}
+
+pub fn path(config: &MetricsConfig) -> Option<PathBuf> {
+    config
+        .path
+        .clone()
+        .or_else(|| dirs_next::data_dir().map(|dir| dir.join("bottlecap-runner").join("metrics.json")))
+}
+
+// Saves the counters every `update_interval_secs` and calls `on_update`
+// after each save, for the dashboard
+pub async fn run(state: Arc<RunnerState>, on_update: impl Fn(Result<(), String>)) {
+    loop {
+        let config = state.config().metrics;
+        tokio::time::sleep(Duration::from_secs(config.update_interval_secs.max(1))).await;
+        on_update(state.metrics().save(&config));
+    }
+}
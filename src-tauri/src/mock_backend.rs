@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::Rng;
+
+use crate::backend::LlmBackend;
+use crate::config::MockBackendConfig;
+use crate::error::RunnerError;
+use crate::protocol::{ChatMessage, ChatOptions, Chunk, ChunkSink, CompletionPrompt, Input, Reply, Usage};
+use crate::translate;
+
+const LOREM: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do",
+    "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua", "enim",
+    "ad", "minim", "veniam", "quis", "nostrud", "exercitation", "ullamco", "laboris", "nisi",
+    "aliquip", "ex", "ea", "commodo", "consequat",
+];
+
+pub struct Mock {
+    pub config: MockBackendConfig,
+}
+
+#[async_trait]
+impl LlmBackend for Mock {
+    async fn reachable(&self) -> bool {
+        true
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, String> {
+        Ok(self.config.models.clone())
+    }
+
+    fn translation_warnings(&self, options: &ChatOptions) -> Vec<String> {
+        translate::mock(options)
+    }
+
+    async fn chat(&self, model: &str, messages: &[ChatMessage], options: &ChatOptions) -> Result<Reply, RunnerError> {
+        chat(&self.config, model, messages, options, None).await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+        on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+    ) -> Result<Reply, RunnerError> {
+        chat(&self.config, model, messages, options, Some(on_chunk)).await
+    }
+
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &CompletionPrompt,
+        options: &ChatOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Reply, RunnerError> {
+        let messages = Input::Completion(prompt.clone()).messages();
+        chat(&self.config, model, &messages, options, on_chunk).await
+    }
+}
+
+// One "token" per whitespace separated word, capped by max_tokens and cut
+// before the first word containing a stop sequence
+fn response_words(config: &MockBackendConfig, options: &ChatOptions) -> Vec<String> {
+    let words: Vec<String> = match &config.response {
+        Some(response) => response.split_whitespace().map(str::to_string).collect(),
+        None => LOREM
+            .iter()
+            .cycle()
+            .take(config.response_tokens)
+            .map(|w| w.to_string())
+            .collect(),
+    };
+    let limit = options
+        .max_tokens
+        .and_then(|n| usize::try_from(n).ok())
+        .unwrap_or(words.len());
+    let stops = options.stop.as_deref().unwrap_or_default();
+    words
+        .into_iter()
+        .take(limit)
+        .take_while(|word| !stops.iter().any(|stop| !stop.is_empty() && word.contains(stop.as_str())))
+        .collect()
+}
+
+async fn chat(
+    config: &MockBackendConfig,
+    model: &str,
+    messages: &[ChatMessage],
+    options: &ChatOptions,
+    on_chunk: ChunkSink<'_>,
+) -> Result<Reply, RunnerError> {
+    if !config.models.iter().any(|m| m == model) {
+        return Err(RunnerError::Backend(format!("mock model '{}' not found", model)));
+    }
+
+    tokio::time::sleep(Duration::from_millis(config.first_token_delay_ms)).await;
+
+    let fail = rand::thread_rng().gen_bool(config.failure_rate);
+    if fail {
+        return Err(RunnerError::Backend("mock injected failure".to_string()));
+    }
+
+    let words = response_words(config, options);
+    let token_delay = Duration::from_millis(config.token_delay_ms);
+    match on_chunk {
+        Some(on_chunk) => {
+            for (i, word) in words.iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(token_delay).await;
+                }
+                on_chunk(Chunk::Text(if i == 0 { word.clone() } else { format!(" {}", word) }));
+            }
+        }
+        None => tokio::time::sleep(token_delay.saturating_mul(words.len() as u32)).await,
+    }
+
+    let usage = Usage {
+        inputTokens: messages
+            .iter()
+            .map(|m| m.content.split_whitespace().count() as i32)
+            .sum(),
+        outputTokens: words.len() as i32,
+        ..Usage::default()
+    };
+
+    Ok(Reply {
+        content: words.join(" "),
+        usage,
+        tool_calls: Vec::new(),
+    })
+}
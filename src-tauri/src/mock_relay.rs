@@ -0,0 +1,139 @@
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::events::EventSink;
+
+// Auth with this token is rejected, everything else is accepted
+pub const INVALID_TOKEN: &str = "invalid";
+
+type ClientSlot = Arc<Mutex<Option<mpsc::UnboundedSender<Message>>>>;
+
+// In-process stand-in for the PartyKit relay. It answers `auth` itself and
+// otherwise only relays: every client message is emitted as a `mock-relay`
+// event and server messages are injected with `send`.
+pub struct MockRelay {
+    url: String,
+    client: ClientSlot,
+    events: Arc<dyn EventSink>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockRelay {
+    pub async fn start(port: u16, events: Arc<dyn EventSink>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let url = format!("ws://{}", listener.local_addr()?);
+        let client: ClientSlot = Arc::new(Mutex::new(None));
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        let accept_client = client.clone();
+        let accept_events = events.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => {
+                        if let Ok((stream, _)) = accepted {
+                            tokio::spawn(serve_client(stream, accept_client.clone(), accept_events.clone()));
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            url,
+            client,
+            events,
+            shutdown: Some(shutdown_tx),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn send(&self, message: serde_json::Value) -> Result<(), String> {
+        let client = self.client.lock().map_err(|e| e.to_string())?;
+        let client = client.as_ref().ok_or("No runner is connected to the mock relay")?;
+        client
+            .send(Message::Text(message.to_string()))
+            .map_err(|_| "Mock relay client disconnected".to_string())?;
+        self.events.emit("mock-relay", serde_json::json!({
+            "direction": "sent",
+            "message": message
+        }));
+        Ok(())
+    }
+}
+
+impl Drop for MockRelay {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Ok(mut client) = self.client.lock() {
+            client.take();
+        }
+    }
+}
+
+async fn serve_client(stream: TcpStream, client: ClientSlot, events: Arc<dyn EventSink>) {
+    let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws_stream) => ws_stream,
+        Err(_) => return,
+    };
+    let (mut write, mut read) = ws_stream.split();
+
+    // The newest connection replaces any previous one
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    if let Ok(mut client) = client.lock() {
+        *client = Some(tx.clone());
+    }
+
+    loop {
+        tokio::select! {
+            outbound = rx.recv() => match outbound {
+                Some(message) => {
+                    if write.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            inbound = read.next() => match inbound {
+                Some(Ok(Message::Text(text))) => {
+                    let message: serde_json::Value =
+                        serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text));
+                    events.emit("mock-relay", serde_json::json!({
+                        "direction": "received",
+                        "message": message
+                    }));
+
+                    if message["type"] == "auth" {
+                        let reply = if message["token"] == INVALID_TOKEN {
+                            serde_json::json!({ "type": "auth_error", "error": "Invalid token" })
+                        } else {
+                            serde_json::json!({ "type": "auth_success", "runnerId": "mock-runner" })
+                        };
+                        let _ = tx.send(Message::Text(reply.to_string()));
+                    }
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    let _ = tx.send(Message::Pong(data));
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    if let Ok(mut client) = client.lock() {
+        if client.as_ref().is_some_and(|c| c.same_channel(&tx)) {
+            *client = None;
+        }
+    }
+}
@@ -0,0 +1,946 @@
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::de::{self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{self, Serialize};
+use serde::Deserialize;
+use serde_json::Value;
+
+// MessagePack for relay frames, written from and read into the runner's
+// message types through serde, the same way as JSON. `bin` values are read
+// as base64 strings, so images sent as raw bytes arrive where a base64
+// string is expected.
+
+// Nesting accepted when decoding, against stack exhaustion
+const MAX_DEPTH: usize = 128;
+
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+    let mut encoder = Encoder { out: Vec::new() };
+    value.serialize(&mut encoder).map_err(|e| e.0)?;
+    Ok(encoder.out)
+}
+
+pub fn from_slice<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, String> {
+    let mut reader = Reader { data, pos: 0, depth: 0 };
+    let value = T::deserialize(&mut reader).map_err(|e| e.0)?;
+    if reader.pos != data.len() {
+        return Err("trailing bytes after MessagePack value".to_string());
+    }
+    Ok(value)
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+const FIXARRAY: u8 = 0x90;
+const FIXMAP: u8 = 0x80;
+
+struct Encoder {
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    fn uint(&mut self, n: u64) {
+        match n {
+            0..=0x7f => self.out.push(n as u8),
+            0x80..=0xff => self.out.extend_from_slice(&[0xcc, n as u8]),
+            0x100..=0xffff => {
+                self.out.push(0xcd);
+                self.out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                self.out.push(0xce);
+                self.out.extend_from_slice(&(n as u32).to_be_bytes());
+            }
+            _ => {
+                self.out.push(0xcf);
+                self.out.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+    }
+
+    fn int(&mut self, n: i64) {
+        if n >= 0 {
+            self.uint(n as u64);
+        } else if n >= -32 {
+            self.out.push(n as u8);
+        } else if n >= i8::MIN as i64 {
+            self.out.extend_from_slice(&[0xd0, n as u8]);
+        } else if n >= i16::MIN as i64 {
+            self.out.push(0xd1);
+            self.out.extend_from_slice(&(n as i16).to_be_bytes());
+        } else if n >= i32::MIN as i64 {
+            self.out.push(0xd2);
+            self.out.extend_from_slice(&(n as i32).to_be_bytes());
+        } else {
+            self.out.push(0xd3);
+            self.out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+
+    fn str(&mut self, text: &str) {
+        if text.len() < 32 {
+            self.out.push(0xa0 | text.len() as u8);
+        } else {
+            self.len(text.len(), [0xd9, 0xda, 0xdb]);
+        }
+        self.out.extend_from_slice(text.as_bytes());
+    }
+
+    fn bin(&mut self, bytes: &[u8]) {
+        self.len(bytes.len(), [0xc4, 0xc5, 0xc6]);
+        self.out.extend_from_slice(bytes);
+    }
+
+    // Array or map header, by its fix marker
+    fn header(&mut self, len: usize, fix: u8) {
+        if len < 16 {
+            self.out.push(fix | len as u8);
+        } else if fix == FIXARRAY {
+            self.len(len, [0, 0xdc, 0xdd]);
+        } else {
+            self.len(len, [0, 0xde, 0xdf]);
+        }
+    }
+
+    // With the 8, 16 and 32 bit length markers; a 0 marker means that width
+    // doesn't exist for the type and the next one is used
+    fn len(&mut self, len: usize, markers: [u8; 3]) {
+        if len < 256 && markers[0] != 0 {
+            self.out.push(markers[0]);
+            self.out.push(len as u8);
+        } else if len < 65536 {
+            self.out.push(markers[1]);
+            self.out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            self.out.push(markers[2]);
+            self.out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+
+    fn begin(&mut self, len: Option<usize>, fix: u8) -> Compound<'_> {
+        let pending_header = match len {
+            Some(len) => {
+                self.header(len, fix);
+                None
+            }
+            None => Some((self.out.len(), fix)),
+        };
+        Compound { encoder: self, pending_header, count: 0 }
+    }
+
+    // The one-entry map an enum variant with content is wrapped in
+    fn variant(&mut self, variant: &str) {
+        self.header(1, FIXMAP);
+        self.str(variant);
+    }
+}
+
+// An array or map being written. One serialized without a length up front
+// (e.g. a struct with a flattened field) gets its header inserted at the
+// end, once the count is known.
+struct Compound<'a> {
+    encoder: &'a mut Encoder,
+    pending_header: Option<(usize, u8)>,
+    count: usize,
+}
+
+impl Compound<'_> {
+    fn item<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.count += 1;
+        value.serialize(&mut *self.encoder)
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.count += 1;
+        self.encoder.str(key);
+        value.serialize(&mut *self.encoder)
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if let Some((start, fix)) = self.pending_header {
+            let mut header = Encoder { out: Vec::new() };
+            header.header(self.count, fix);
+            self.encoder.out.splice(start..start, header.out);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Encoder {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.out.push(if v { 0xc3 } else { 0xc2 });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.int(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.uint(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.out.push(0xca);
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.out.push(0xcb);
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.str(v.encode_utf8(&mut [0; 4]));
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.str(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.bin(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.out.push(0xc0);
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), Error> {
+        self.str(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.variant(variant);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.begin(len, FIXARRAY))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.begin(Some(len), FIXARRAY))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.begin(Some(len), FIXARRAY))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        self.variant(variant);
+        Ok(self.begin(Some(len), FIXARRAY))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.begin(len, FIXMAP))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.begin(Some(len), FIXMAP))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        self.variant(variant);
+        Ok(self.begin(Some(len), FIXMAP))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.item(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut *self.encoder)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+struct Reader<'de> {
+    data: &'de [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'de> Reader<'de> {
+    fn take(&mut self, n: usize) -> Result<&'de [u8], Error> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.data.len());
+        let end = end.ok_or_else(|| Error("truncated MessagePack value".to_string()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, Error> {
+        self.data.get(self.pos).copied().ok_or_else(|| Error("truncated MessagePack value".to_string()))
+    }
+
+    fn uint(&mut self, width: usize) -> Result<u64, Error> {
+        Ok(self.take(width)?.iter().fold(0u64, |n, byte| n << 8 | *byte as u64))
+    }
+
+    fn int(&mut self, width: usize) -> Result<i64, Error> {
+        let n = self.uint(width)?;
+        let shift = 64 - 8 * width as u32;
+        Ok(((n << shift) as i64) >> shift)
+    }
+
+    fn string<V: Visitor<'de>>(&mut self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let bytes = self.take(len)?;
+        let text = std::str::from_utf8(bytes).map_err(|e| Error(e.to_string()))?;
+        visitor.visit_borrowed_str(text)
+    }
+
+    // Every array item takes at least a byte and every map entry two, so a
+    // bogus length fails here rather than in an allocation
+    fn nested<V: Visitor<'de>>(&mut self, len: usize, map: bool, visitor: V) -> Result<V::Value, Error> {
+        let min_size = if map { 2 } else { 1 };
+        if len.saturating_mul(min_size) > self.data.len() - self.pos {
+            return Err(Error("truncated MessagePack value".to_string()));
+        }
+        if self.depth == MAX_DEPTH {
+            return Err(Error("MessagePack value nested too deeply".to_string()));
+        }
+        self.depth += 1;
+        let mut items = Items { reader: self, left: len };
+        let value = if map { visitor.visit_map(&mut items) } else { visitor.visit_seq(&mut items) }?;
+        let left = items.left;
+        self.depth -= 1;
+        if left > 0 {
+            return Err(Error(format!("{} more items than expected", left)));
+        }
+        Ok(value)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Reader<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let marker = self.take(1)?[0];
+        match marker {
+            0x00..=0x7f => visitor.visit_u64(marker as u64),
+            0x80..=0x8f => self.nested((marker & 0x0f) as usize, true, visitor),
+            0x90..=0x9f => self.nested((marker & 0x0f) as usize, false, visitor),
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize, visitor),
+            0xc0 => visitor.visit_unit(),
+            0xc2 => visitor.visit_bool(false),
+            0xc3 => visitor.visit_bool(true),
+            0xc4..=0xc6 => {
+                let len = self.uint(1 << (marker - 0xc4))? as usize;
+                visitor.visit_string(STANDARD.encode(self.take(len)?))
+            }
+            0xca => visitor.visit_f64(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => visitor.visit_f64(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => visitor.visit_u64(self.uint(1 << (marker - 0xcc))?),
+            0xd0..=0xd3 => visitor.visit_i64(self.int(1 << (marker - 0xd0))?),
+            0xd9..=0xdb => {
+                let len = self.uint(1 << (marker - 0xd9))? as usize;
+                self.string(len, visitor)
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(if marker == 0xdc { 2 } else { 4 })? as usize;
+                self.nested(len, false, visitor)
+            }
+            0xde | 0xdf => {
+                let len = self.uint(if marker == 0xde { 2 } else { 4 })? as usize;
+                self.nested(len, true, visitor)
+            }
+            0xe0..=0xff => visitor.visit_i64(marker as i8 as i64),
+            _ => Err(Error(format!("unsupported MessagePack type 0x{:02x}", marker))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.peek()? == 0xc0 {
+            self.pos += 1;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // A unit variant is its name; one with content a map of its name to it
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if self.peek()? == FIXMAP | 1 {
+            self.pos += 1;
+            visitor.visit_enum(Variant(self))
+        } else {
+            let variant = String::deserialize(&mut *self)?;
+            visitor.visit_enum(variant.into_deserializer())
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Items<'a, 'de> {
+    reader: &'a mut Reader<'de>,
+    left: usize,
+}
+
+impl<'de> SeqAccess<'de> for Items<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.reader).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de> MapAccess<'de> for Items<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(Key(&mut *self.reader)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.reader)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+// A map key. JSON only has string keys, so any other is read as its JSON text.
+struct Key<'a, 'de>(&'a mut Reader<'de>);
+
+impl<'de> de::Deserializer<'de> for Key<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.peek()? {
+            0xa0..=0xbf | 0xd9..=0xdb => self.0.deserialize_any(visitor),
+            _ => match Value::deserialize(&mut *self.0)? {
+                Value::String(key) => visitor.visit_string(key),
+                other => visitor.visit_string(other.to_string()),
+            },
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Variant<'a, 'de>(&'a mut Reader<'de>);
+
+impl<'de> EnumAccess<'de> for Variant<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(&mut *self.0)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        <()>::deserialize(self.0)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.0)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self.0, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.0, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        to_vec(value).unwrap()
+    }
+
+    fn decode(data: &[u8]) -> Result<Value, String> {
+        from_slice(data)
+    }
+
+    // Encodes to exactly `bytes` and decodes back
+    fn check(value: Value, bytes: &[u8]) {
+        assert_eq!(encode(&value), bytes, "encoding {}", value);
+        assert_eq!(decode(bytes).unwrap(), value);
+    }
+
+    // Encodes to `marker` and a big-endian `len` of `width` bytes, then the body
+    fn check_prefix(value: Value, marker: u8, width: usize, len: usize) {
+        let data = encode(&value);
+        assert_eq!(data[0], marker);
+        assert_eq!(data[1..1 + width], len.to_be_bytes()[8 - width..]);
+        assert_eq!(decode(&data).unwrap(), value);
+    }
+
+    #[test]
+    fn nil_and_bool() {
+        check(Value::Null, &[0xc0]);
+        check(json!(false), &[0xc2]);
+        check(json!(true), &[0xc3]);
+    }
+
+    #[test]
+    fn unsigned_widths() {
+        check(json!(0), &[0x00]);
+        check(json!(0x7f), &[0x7f]);
+        check(json!(0x80), &[0xcc, 0x80]);
+        check(json!(0xff), &[0xcc, 0xff]);
+        check(json!(0x100), &[0xcd, 0x01, 0x00]);
+        check(json!(0xffff), &[0xcd, 0xff, 0xff]);
+        check(json!(0x1_0000), &[0xce, 0x00, 0x01, 0x00, 0x00]);
+        check(json!(u32::MAX), &[0xce, 0xff, 0xff, 0xff, 0xff]);
+        check(json!(u32::MAX as u64 + 1), &[0xcf, 0, 0, 0, 0x01, 0, 0, 0, 0]);
+        check(json!(u64::MAX), &[0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn negative_widths() {
+        check(json!(-1), &[0xff]);
+        check(json!(-32), &[0xe0]);
+        check(json!(-33), &[0xd0, 0xdf]);
+        check(json!(-128), &[0xd0, 0x80]);
+        check(json!(-129), &[0xd1, 0xff, 0x7f]);
+        check(json!(-32768), &[0xd1, 0x80, 0x00]);
+        check(json!(-32769), &[0xd2, 0xff, 0xff, 0x7f, 0xff]);
+        check(json!(i32::MIN), &[0xd2, 0x80, 0x00, 0x00, 0x00]);
+        check(json!(i32::MIN as i64 - 1), &[0xd3, 0xff, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff]);
+        check(json!(i64::MIN), &[0xd3, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn signed_markers_with_positive_values() {
+        // Other encoders may use the signed types for any value
+        assert_eq!(decode(&[0xd0, 0x05]).unwrap(), json!(5));
+        assert_eq!(decode(&[0xd1, 0x01, 0x00]).unwrap(), json!(256));
+        assert_eq!(decode(&[0xd2, 0x00, 0x01, 0x00, 0x00]).unwrap(), json!(65536));
+        assert_eq!(decode(&[0xd3, 0, 0, 0, 0x01, 0, 0, 0, 0]).unwrap(), json!(1u64 << 32));
+    }
+
+    #[test]
+    fn floats() {
+        check(json!(1.5), &[0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(decode(&[0xca, 0x3f, 0xc0, 0x00, 0x00]).unwrap(), json!(1.5));
+        assert_eq!(to_vec(&1.5f32).unwrap(), [0xca, 0x3f, 0xc0, 0x00, 0x00]);
+        // JSON has no NaN
+        assert_eq!(decode(&[0xca, 0x7f, 0xc0, 0x00, 0x00]).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn string_widths() {
+        check(json!(""), &[0xa0]);
+        let mut fixstr = vec![0xbf];
+        fixstr.extend_from_slice(&[b'a'; 31]);
+        check(json!("a".repeat(31)), &fixstr);
+        check_prefix(json!("a".repeat(32)), 0xd9, 1, 32);
+        check_prefix(json!("a".repeat(255)), 0xd9, 1, 255);
+        check_prefix(json!("a".repeat(256)), 0xda, 2, 256);
+        check_prefix(json!("a".repeat(65535)), 0xda, 2, 65535);
+        check_prefix(json!("a".repeat(65536)), 0xdb, 4, 65536);
+        check(json!("é"), &[0xa2, 0xc3, 0xa9]);
+    }
+
+    #[test]
+    fn bin_reads_as_base64() {
+        assert_eq!(decode(&[0xc4, 0x03, 1, 2, 3]).unwrap(), json!("AQID"));
+        assert_eq!(decode(&[0xc5, 0x00, 0x03, 1, 2, 3]).unwrap(), json!("AQID"));
+        assert_eq!(decode(&[0xc6, 0x00, 0x00, 0x00, 0x03, 1, 2, 3]).unwrap(), json!("AQID"));
+        assert_eq!(decode(&[0xc4, 0x00]).unwrap(), json!(""));
+        let long = Bytes(vec![7u8; 300]);
+        let data = to_vec(&long).unwrap();
+        assert_eq!(data[..3], [0xc5, 0x01, 0x2c]);
+        assert_eq!(decode(&data).unwrap(), json!(STANDARD.encode(&long.0)));
+    }
+
+    // A `Vec<u8>` serializes as a sequence; this as bytes
+    struct Bytes(Vec<u8>);
+
+    impl Serialize for Bytes {
+        fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    #[test]
+    fn array_widths() {
+        check(json!([]), &[0x90]);
+        check(json!([1, 2]), &[0x92, 0x01, 0x02]);
+        check_prefix(Value::Array(vec![json!(0); 15]), 0x9f, 0, 0);
+        check_prefix(Value::Array(vec![json!(0); 16]), 0xdc, 2, 16);
+        check_prefix(Value::Array(vec![json!(0); 65535]), 0xdc, 2, 65535);
+        check_prefix(Value::Array(vec![json!(0); 65536]), 0xdd, 4, 65536);
+    }
+
+    #[test]
+    fn map_widths() {
+        check(json!({}), &[0x80]);
+        check(json!({"a": 1}), &[0x81, 0xa1, b'a', 0x01]);
+        let map = |len: usize| Value::Object((0..len).map(|i| (format!("{:05}", i), json!(i))).collect());
+        check_prefix(map(15), 0x8f, 0, 0);
+        check_prefix(map(16), 0xde, 2, 16);
+        check_prefix(map(65535), 0xde, 2, 65535);
+        check_prefix(map(65536), 0xdf, 4, 65536);
+    }
+
+    #[test]
+    fn non_string_keys_read_as_json_text() {
+        assert_eq!(decode(&[0x81, 0x01, 0xa1, b'x']).unwrap(), json!({"1": "x"}));
+        assert_eq!(decode(&[0x81, 0xc3, 0x00]).unwrap(), json!({"true": 0}));
+        assert_eq!(decode(&[0x81, 0xc4, 0x01, 0x00, 0x00]).unwrap(), json!({"AA==": 0}));
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(tag = "type")]
+    enum Frame {
+        #[serde(rename = "chat")]
+        Chat {
+            id: String,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            seq: Option<u64>,
+            role: Role,
+            parts: Vec<Part>,
+            #[serde(flatten)]
+            extra: BTreeMap<String, Value>,
+        },
+        #[serde(rename = "ping")]
+        Ping,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        User,
+        Assistant,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Part {
+        Text(String),
+        Pair(u8, i8),
+        Image { width: u32, height: u32 },
+        Empty,
+    }
+
+    #[test]
+    fn messages_match_their_json() {
+        let frames = [
+            Frame::Chat {
+                id: "r1".to_string(),
+                seq: Some(3),
+                role: Role::Assistant,
+                parts: vec![
+                    Part::Text("hi".to_string()),
+                    Part::Pair(1, -2),
+                    Part::Image { width: 640, height: 480 },
+                    Part::Empty,
+                ],
+                extra: BTreeMap::from([("model".to_string(), json!("m")), ("n".to_string(), json!(1.25))]),
+            },
+            Frame::Chat {
+                id: "r2".to_string(),
+                seq: None,
+                role: Role::User,
+                parts: Vec::new(),
+                extra: BTreeMap::new(),
+            },
+            Frame::Ping,
+        ];
+        for frame in frames {
+            let data = to_vec(&frame).unwrap();
+            assert_eq!(decode(&data).unwrap(), serde_json::to_value(&frame).unwrap());
+            assert_eq!(from_slice::<Frame>(&data).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        let cases: &[&[u8]] = &[
+            &[],
+            &[0xcc],
+            &[0xcd, 0x01],
+            &[0xce, 0x00, 0x00, 0x01],
+            &[0xcf, 0, 0, 0, 0, 0, 0, 0],
+            &[0xd0],
+            &[0xd3, 0xff],
+            &[0xca, 0x00],
+            &[0xcb, 0, 0, 0, 0],
+            &[0xa3, b'a', b'b'],
+            &[0xd9],
+            &[0xd9, 0x05, b'a'],
+            &[0xda, 0x00],
+            &[0xdb, 0x00, 0x00, 0x00, 0x02, b'a'],
+            &[0xc4, 0x03, 0x01],
+            &[0xc5, 0x00],
+            &[0xc6, 0x00, 0x00, 0x00, 0x01],
+            &[0x92, 0x01],
+            &[0xdc, 0x00],
+            &[0xdd, 0x00, 0x00, 0x00, 0x02, 0x01],
+            &[0x81, 0xa1, b'k'],
+            &[0x82, 0xa1, b'k', 0x01],
+            &[0xde, 0x00, 0x01, 0xa1, b'k'],
+            &[0xdf, 0x00, 0x00, 0x00],
+        ];
+        for data in cases {
+            assert!(decode(data).is_err(), "decoded {:02x?}", data);
+        }
+    }
+
+    #[test]
+    fn bogus_lengths_fail_before_allocating() {
+        assert!(decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode(&[0xdf, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode(&[0xdb, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode(&[0xc6, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn malformed_input() {
+        // Invalid UTF-8 in a string
+        assert!(decode(&[0xa1, 0xff]).is_err());
+        // Never used, and the ext types
+        for marker in [0xc1, 0xc7, 0xc8, 0xc9, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8] {
+            assert!(decode(&[marker, 0x00, 0x00]).is_err(), "decoded 0x{:02x}", marker);
+        }
+        assert_eq!(decode(&[0x01, 0x02]).unwrap_err(), "trailing bytes after MessagePack value");
+        // Values the target type can't hold
+        assert!(from_slice::<u8>(&[0xcd, 0x01, 0x00]).is_err());
+        assert!(from_slice::<String>(&[0x01]).is_err());
+        assert!(from_slice::<Role>(&[0xa3, b'b', b'o', b't']).is_err());
+        assert!(from_slice::<Frame>(&[0x81, 0xa4, b't', b'y', b'p', b'e', 0xa4, b'c', b'h', b'a', b't']).is_err());
+        // More items than a tuple variant has
+        assert!(from_slice::<Part>(&[0x81, 0xa4, b'P', b'a', b'i', b'r', 0x93, 0x01, 0x02, 0x03]).is_err());
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| [vec![0x91; depth], vec![0xc0]].concat();
+        assert!(decode(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(decode(&nested(MAX_DEPTH + 1)).unwrap_err(), "MessagePack value nested too deeply");
+        assert!(decode(&nested(100_000)).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tauri::api::notification::Notification;
+use tauri::{AppHandle, Manager};
+
+use bottlecap_runner_core::backend;
+use bottlecap_runner_core::settings::{self, NotificationSettings};
+
+use crate::commands::AppState;
+
+// How often the backend is checked while `backendUnreachable` is on
+const BACKEND_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// The last connection status, so only a connection that was up counts as lost
+static LAST_STATUS: Mutex<String> = Mutex::new(String::new());
+
+// Watches the backend for `backendUnreachable`; the other notifications
+// come from runner events through `on_event`
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut backend_up = true;
+        loop {
+            tokio::time::sleep(BACKEND_CHECK_INTERVAL).await;
+            if !enabled().backend_unreachable {
+                backend_up = true;
+                continue;
+            }
+            let config = app.state::<AppState>().runner.config();
+            let up = backend::reachable(&config.backend).await;
+            if backend_up && !up {
+                show(&app, "Ollama is unreachable", &format!("No answer from {}", config.backend.url));
+            }
+            backend_up = up;
+        }
+    });
+}
+
+pub fn on_event(app: &AppHandle, event: &str, payload: &serde_json::Value) {
+    match event {
+        "connection-status" => {
+            let status = payload["status"].as_str().unwrap_or_default();
+            let previous = LAST_STATUS
+                .lock()
+                .map(|mut last| std::mem::replace(&mut *last, status.to_string()))
+                .unwrap_or_default();
+            let error = payload["error"].as_str();
+            if status == "auth_failed" && enabled().auth_failed {
+                show(app, "Authentication failed", error.unwrap_or("The relay rejected the runner's token"));
+            } else if previous == "connected" && matches!(status, "reconnecting" | "stale" | "error") && enabled().connection_lost {
+                show(app, "Connection lost", error.unwrap_or("The connection to the relay dropped"));
+            }
+        }
+        "request-served" => {
+            let settings = enabled();
+            let idle_ms = payload["idleMs"].as_u64();
+            if settings.first_request && idle_ms.is_none_or(|ms| ms >= settings.idle_minutes * 60_000) {
+                let model = payload["model"].as_str().unwrap_or_default();
+                show(app, "Serving requests", &format!("Served a request for {}", model));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn enabled() -> NotificationSettings {
+    settings::load().notifications.unwrap_or_default()
+}
+
+fn show(app: &AppHandle, title: &str, body: &str) {
+    let result = Notification::new(&app.config().tauri.bundle.identifier)
+        .title(title)
+        .body(body)
+        .show();
+    if let Err(e) = result {
+        tracing::error!("Failed to show notification: {}", e);
+    }
+}
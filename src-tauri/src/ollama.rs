@@ -0,0 +1,719 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::LlmBackend;
+use crate::config::{self, KeepAliveConfig, DEFAULT_OLLAMA_URL, OLLAMA_HOST_ENV};
+use crate::error::RunnerError;
+use crate::events::EventSink;
+use crate::protocol::{ChatMessage, ChatOptions, Chunk, ChunkSink, CompletionPrompt, FunctionCall, LoadedModel, Reply, ToolCall, Usage};
+use crate::{backend_auth, local_socket, proxy, translate};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaResponse {
+    message: Option<OllamaMessage>,
+    // Set instead of `message` by /api/generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    done: Option<bool>,
+    prompt_eval_count: Option<i32>,
+    eval_count: Option<i32>,
+    // Nanoseconds, on the final object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    load_duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt_eval_duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    eval_duration: Option<u64>,
+    // Set on stream lines when generation fails midway
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl OllamaResponse {
+    // Token counts and Ollama's own timings. Its time to first token leaves
+    // out the HTTP round trip, which only matters for remote servers.
+    fn usage(&self) -> Usage {
+        let ms = |ns: u64| ns / 1_000_000;
+        let first_token = match (self.load_duration, self.prompt_eval_duration) {
+            (None, None) => None,
+            (load, prompt) => Some(ms(load.unwrap_or(0) + prompt.unwrap_or(0))),
+        };
+        let speed = match (self.eval_count, self.eval_duration) {
+            (Some(count), Some(ns)) if count > 0 && ns > 0 => Some(count as f64 / (ns as f64 / 1e9)),
+            _ => None,
+        };
+        Usage {
+            inputTokens: self.prompt_eval_count.unwrap_or(0),
+            outputTokens: self.eval_count.unwrap_or(0),
+            timeToFirstTokenMs: first_token,
+            tokensPerSecond: speed.map(|speed| (speed * 10.0).round() / 10.0),
+            ..Usage::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+// Ollama gives the arguments as an object and calls no ids
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+impl OllamaToolCall {
+    // `index` numbers the calls within one reply
+    fn into_tool_call(self, index: usize) -> ToolCall {
+        ToolCall {
+            id: format!("call_{}", index),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: self.function.name,
+                arguments: self.function.arguments.to_string(),
+            },
+        }
+    }
+}
+
+fn message_json(message: &ChatMessage) -> serde_json::Value {
+    let mut json = serde_json::json!({ "role": message.role, "content": message.content });
+    if !message.images.is_empty() {
+        json["images"] = serde_json::json!(message.images);
+    }
+    if !message.tool_calls.is_empty() {
+        json["tool_calls"] = message
+            .tool_calls
+            .iter()
+            .map(|call| {
+                let arguments: serde_json::Value =
+                    serde_json::from_str(&call.function.arguments).unwrap_or_else(|_| serde_json::json!({}));
+                serde_json::json!({ "function": { "name": call.function.name, "arguments": arguments } })
+            })
+            .collect();
+    }
+    json
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaModelsResponse {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OllamaModel {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    modified_at: String,
+    #[serde(default)]
+    details: OllamaModelDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct OllamaModelDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+// An installed model as listed in the runner UI
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified_at: String,
+    pub family: Option<String>,
+    // e.g. "8.0B"
+    pub parameter_size: Option<String>,
+    // e.g. "Q4_0"
+    pub quantization: Option<String>,
+}
+
+// Space taken by the models on disk and left on the disk holding them
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskUsage {
+    pub models_dir: PathBuf,
+    pub models_bytes: u64,
+    pub available_bytes: Option<u64>,
+}
+
+pub struct Ollama {
+    pub base_url: String,
+    pub keep_alive: KeepAliveConfig,
+}
+
+#[async_trait]
+impl LlmBackend for Ollama {
+    async fn reachable(&self) -> bool {
+        reachable(&self.base_url).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, String> {
+        list_models(&self.base_url).await
+    }
+
+    fn translation_warnings(&self, options: &ChatOptions) -> Vec<String> {
+        translate::ollama(options).warnings
+    }
+
+    async fn chat(&self, model: &str, messages: &[ChatMessage], options: &ChatOptions) -> Result<Reply, RunnerError> {
+        let keep_alive = self.keep_alive.for_model(model);
+        chat(&self.base_url, model, messages, options, keep_alive, None).await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+        on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+    ) -> Result<Reply, RunnerError> {
+        let keep_alive = self.keep_alive.for_model(model);
+        chat(&self.base_url, model, messages, options, keep_alive, Some(on_chunk)).await
+    }
+
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &CompletionPrompt,
+        options: &ChatOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Reply, RunnerError> {
+        let keep_alive = self.keep_alive.for_model(model);
+        generate(&self.base_url, model, prompt, options, keep_alive, on_chunk).await
+    }
+}
+
+// Where the installers put the binary, tried after PATH
+#[cfg(target_os = "macos")]
+const INSTALL_PATHS: &[&str] = &[
+    "/Applications/Ollama.app/Contents/Resources/ollama",
+    "/opt/homebrew/bin/ollama",
+    "/usr/local/bin/ollama",
+];
+#[cfg(target_os = "linux")]
+const INSTALL_PATHS: &[&str] = &["/usr/local/bin/ollama", "/usr/bin/ollama"];
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+const INSTALL_PATHS: &[&str] = &[];
+
+// How long a started server gets to answer
+const START_TIMEOUT: Duration = Duration::from_secs(30);
+const START_POLL: Duration = Duration::from_millis(500);
+
+pub fn locate() -> Option<PathBuf> {
+    let name = if cfg!(windows) { "ollama.exe" } else { "ollama" };
+    let on_path: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join(name)).collect())
+        .unwrap_or_default();
+    // %LOCALAPPDATA%\Programs\Ollama on Windows
+    let per_user = std::env::var_os("LOCALAPPDATA")
+        .map(|dir| PathBuf::from(dir).join("Programs").join("Ollama").join(name));
+    on_path
+        .into_iter()
+        .chain(INSTALL_PATHS.iter().map(PathBuf::from))
+        .chain(per_user)
+        .find(|path| path.is_file())
+}
+
+// Runs `ollama serve` in the background, listening where `base_url` points,
+// and waits until it answers. Progress goes out as `ollama-start-progress`
+// events with a `stage` of locating, starting, waiting, ready or failed.
+// The server keeps running when the app exits.
+pub async fn start(base_url: &str, events: &dyn EventSink) -> Result<(), String> {
+    let result = launch(base_url, events).await;
+    let (stage, message) = match &result {
+        Ok(()) => ("ready", "Ollama is running".to_string()),
+        Err(e) => ("failed", e.clone()),
+    };
+    events.emit("ollama-start-progress", serde_json::json!({ "stage": stage, "message": message }));
+    result
+}
+
+async fn launch(base_url: &str, events: &dyn EventSink) -> Result<(), String> {
+    let progress = |stage: &str, message: String| {
+        events.emit("ollama-start-progress", serde_json::json!({ "stage": stage, "message": message }));
+    };
+    if reachable(base_url).await {
+        return Ok(());
+    }
+    if local_socket::is_socket_url(base_url) {
+        return Err(format!("Ollama is not answering on {}; the runner only starts it on a TCP port", base_url));
+    }
+
+    progress("locating", "Looking for Ollama".to_string());
+    let binary = locate().ok_or("Ollama is not installed; download it from https://ollama.com/download")?;
+
+    progress("starting", format!("Starting {}", binary.display()));
+    let mut command = std::process::Command::new(&binary);
+    command
+        .arg("serve")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(host) = listen_address(base_url) {
+        command.env(OLLAMA_HOST_ENV, host);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NO_WINDOW
+        command.creation_flags(0x0800_0000);
+    }
+    let mut child = command.spawn().map_err(|e| format!("Failed to start {}: {}", binary.display(), e))?;
+
+    progress("waiting", "Waiting for Ollama to answer".to_string());
+    let deadline = Instant::now() + START_TIMEOUT;
+    while Instant::now() < deadline {
+        tokio::time::sleep(START_POLL).await;
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("Ollama exited right away ({})", status));
+        }
+        if reachable(base_url).await {
+            return Ok(());
+        }
+    }
+    Err(format!("Ollama did not answer within {}s", START_TIMEOUT.as_secs()))
+}
+
+// `host:port` for OLLAMA_HOST when the configured URL is not the default
+fn listen_address(base_url: &str) -> Option<String> {
+    let url = url::Url::parse(base_url).ok()?;
+    let port = url.port_or_known_default()?;
+    let host = url.host_str()?;
+    (base_url.trim_end_matches('/') != DEFAULT_OLLAMA_URL).then(|| format!("{}:{}", host, port))
+}
+
+// A request for `path` on the server at `base_url`, which may be a local
+// socket, with the credentials configured for it
+fn request(method: reqwest::Method, base_url: &str, path: &str) -> reqwest::RequestBuilder {
+    let url = format!("{}{}", local_socket::http_base(base_url), path);
+    backend_auth::apply(proxy::http_client().request(method, url), base_url)
+}
+
+pub async fn reachable(base_url: &str) -> bool {
+    match request(reqwest::Method::GET, base_url, "/api/tags").send().await {
+        Ok(resp) => resp.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+pub async fn list_models(base_url: &str) -> Result<Vec<String>, String> {
+    let response = request(reqwest::Method::GET, base_url, "/api/tags")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let data: OllamaModelsResponse = response.json().await.map_err(|e| e.to_string())?;
+    Ok(data.models.into_iter().map(|m| m.name).collect())
+}
+
+// Largest first
+pub async fn list_models_detailed(base_url: &str) -> Result<Vec<ModelInfo>, String> {
+    let response = request(reqwest::Method::GET, base_url, "/api/tags")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let data: OllamaModelsResponse = response.json().await.map_err(|e| e.to_string())?;
+    let mut models: Vec<ModelInfo> = data
+        .models
+        .into_iter()
+        .map(|m| ModelInfo {
+            name: m.name,
+            size_bytes: m.size,
+            modified_at: m.modified_at,
+            family: m.details.family,
+            parameter_size: m.details.parameter_size,
+            quantization: m.details.quantization_level,
+        })
+        .collect();
+    models.sort_by_key(|model| std::cmp::Reverse(model.size_bytes));
+    Ok(models)
+}
+
+#[derive(Deserialize)]
+struct OllamaPsResponse {
+    models: Vec<OllamaRunningModel>,
+}
+
+#[derive(Deserialize)]
+struct OllamaRunningModel {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    size_vram: u64,
+    expires_at: Option<String>,
+}
+
+// Models currently in memory, from `/api/ps`
+pub async fn loaded_models(base_url: &str) -> Result<Vec<LoadedModel>, String> {
+    let response = request(reqwest::Method::GET, base_url, "/api/ps")
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Ollama error: {}", response.status()));
+    }
+    let data: OllamaPsResponse = response.json().await.map_err(|e| e.to_string())?;
+    Ok(data
+        .models
+        .into_iter()
+        .map(|model| LoadedModel {
+            name: model.name,
+            sizeBytes: model.size,
+            vramBytes: model.size_vram,
+            expiresAt: model.expires_at,
+        })
+        .collect())
+}
+
+pub async fn delete_model(base_url: &str, model: &str) -> Result<(), String> {
+    let response = request(reqwest::Method::DELETE, base_url, "/api/delete")
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        reqwest::StatusCode::NOT_FOUND => Err(format!("Model {} is not installed", model)),
+        status => Err(format!("Ollama error: {}", status)),
+    }
+}
+
+// Where a local Ollama keeps its models: OLLAMA_MODELS, then ~/.ollama/models,
+// then the Linux service account's home
+pub fn models_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("OLLAMA_MODELS") {
+        return Some(PathBuf::from(dir));
+    }
+    let user = dirs_next::home_dir().map(|home| home.join(".ollama").join("models"));
+    let service = PathBuf::from("/usr/share/ollama/.ollama/models");
+    user.into_iter().chain([service]).find(|dir| dir.is_dir())
+}
+
+pub fn disk_usage() -> Result<DiskUsage, String> {
+    let models_dir = models_dir().ok_or("Ollama's model directory was not found")?;
+    let models_bytes = dir_size(&models_dir);
+    // The disk whose mount point is the longest prefix of the directory
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    let available_bytes = disks
+        .iter()
+        .filter(|disk| models_dir.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space());
+    Ok(DiskUsage {
+        models_dir,
+        models_bytes,
+        available_bytes,
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PullProgress {
+    // Absent on error lines
+    #[serde(default)]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    // Bytes of the layer being downloaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    #[serde(default, skip_serializing)]
+    error: Option<String>,
+}
+
+// Downloads `model`, handing each progress line to `on_progress`: `pulling
+// manifest`, then `pulling <digest>` with byte counts for each layer, then
+// `verifying sha256 digest`, `writing manifest` and `success`. An error from
+// `on_progress` stops the download.
+pub async fn pull(
+    base_url: &str,
+    model: &str,
+    on_progress: &(dyn Fn(PullProgress) -> Result<(), String> + Send + Sync),
+) -> Result<(), String> {
+    let response = request(reqwest::Method::POST, base_url, "/api/pull")
+        .timeout(proxy::LONG_REQUEST_TIMEOUT)
+        .json(&serde_json::json!({ "model": model, "stream": true }))
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Ollama error: {}", response.status()));
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+    let mut succeeded = false;
+    while let Some(bytes) = stream.next().await {
+        let bytes = bytes.map_err(|e| format!("Ollama stream failed: {}", e))?;
+        buffer.extend_from_slice(&bytes);
+
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let progress = read_progress(&line)?;
+            succeeded |= progress.status == "success";
+            on_progress(progress)?;
+        }
+    }
+    if succeeded {
+        Ok(())
+    } else {
+        Err("Pull ended before it finished".to_string())
+    }
+}
+
+// A line of `/api/pull` output; Ollama reports failures as `{"error": "..."}`
+fn read_progress(line: &[u8]) -> Result<PullProgress, String> {
+    let progress: PullProgress = serde_json::from_slice(line).map_err(|e| e.to_string())?;
+    match progress.error {
+        Some(error) => Err(format!("Pull failed: {}", error)),
+        None => Ok(progress),
+    }
+}
+
+// Loads `model` into memory without generating anything, so the first
+// request for it does not wait for the load
+pub async fn preload(base_url: &str, model: &str, keep_alive: Option<&str>) -> Result<(), String> {
+    let mut body = serde_json::json!({ "model": model, "stream": false });
+    if let Some(keep_alive) = keep_alive {
+        body["keep_alive"] = config::keep_alive_value(keep_alive)?;
+    }
+    let response = request(reqwest::Method::POST, base_url, "/api/generate")
+        .timeout(proxy::LONG_REQUEST_TIMEOUT)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Ollama request failed: {}", e))?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        reqwest::StatusCode::NOT_FOUND => Err(format!("Model {} is not installed", model)),
+        status => Err(format!("Ollama error: {}", status)),
+    }
+}
+
+async fn chat(
+    base_url: &str,
+    model: &str,
+    messages: &[ChatMessage],
+    options: &ChatOptions,
+    keep_alive: Option<&str>,
+    on_chunk: ChunkSink<'_>,
+) -> Result<Reply, RunnerError> {
+    let params = translate::ollama(options);
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+        "stream": on_chunk.is_some(),
+        "options": params.options,
+    });
+    if let Some(tools) = params.tools {
+        body["tools"] = serde_json::json!(tools);
+    }
+    if let Some(format) = params.format {
+        body["format"] = format;
+    }
+    if let Some(keep_alive) = keep_alive {
+        body["keep_alive"] = config::keep_alive_value(keep_alive)?;
+    }
+
+    let response = post(base_url, "/api/chat", &body).await?;
+
+    if let Some(on_chunk) = on_chunk {
+        return read_stream(response, on_chunk).await;
+    }
+
+    let data: OllamaResponse = response.json().await.map_err(|e| RunnerError::Backend(e.to_string()))?;
+
+    let usage = data.usage();
+    let (content, tool_calls) = data
+        .message
+        .map(|m| {
+            let calls = m.tool_calls.into_iter().enumerate().map(|(i, call)| call.into_tool_call(i)).collect();
+            (m.content, calls)
+        })
+        .unwrap_or_default();
+
+    Ok(Reply {
+        content,
+        usage,
+        tool_calls,
+    })
+}
+
+// `raw` skips the model's prompt template, so the prompt is sent as is
+async fn generate(
+    base_url: &str,
+    model: &str,
+    prompt: &CompletionPrompt,
+    options: &ChatOptions,
+    keep_alive: Option<&str>,
+    on_chunk: ChunkSink<'_>,
+) -> Result<Reply, RunnerError> {
+    // /api/generate takes no tools
+    let params = translate::ollama(options);
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "prompt": prompt.prompt,
+        "raw": prompt.raw,
+        "stream": on_chunk.is_some(),
+        "options": params.options,
+    });
+    if let Some(system) = &prompt.system {
+        body["system"] = serde_json::json!(system);
+    }
+    if let Some(template) = &prompt.template {
+        body["template"] = serde_json::json!(template);
+    }
+    if let Some(format) = params.format {
+        body["format"] = format;
+    }
+    if let Some(keep_alive) = keep_alive {
+        body["keep_alive"] = config::keep_alive_value(keep_alive)?;
+    }
+
+    let response = post(base_url, "/api/generate", &body).await?;
+
+    if let Some(on_chunk) = on_chunk {
+        return read_stream(response, on_chunk).await;
+    }
+
+    let data: OllamaResponse = response.json().await.map_err(|e| RunnerError::Backend(e.to_string()))?;
+    Ok(Reply {
+        usage: data.usage(),
+        content: data.response.unwrap_or_default(),
+        tool_calls: Vec::new(),
+    })
+}
+
+// Error statuses carry `{"error": "..."}`
+async fn post(base_url: &str, path: &str, body: &serde_json::Value) -> Result<reqwest::Response, RunnerError> {
+    let response = request(reqwest::Method::POST, base_url, path)
+        .timeout(proxy::LONG_REQUEST_TIMEOUT)
+        .json(body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        return Err(RunnerError::OllamaHttp {
+            status: status.as_u16(),
+            message: body["error"].as_str().or(status.canonical_reason()).unwrap_or_default().to_string(),
+        });
+    }
+    Ok(response)
+}
+
+// Ollama streams one JSON object per line; the last one has `done: true`
+// and the token counts.
+async fn read_stream(
+    response: reqwest::Response,
+    on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+) -> Result<Reply, RunnerError> {
+    let mut reply = Reply::default();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+
+    while let Some(bytes) = stream.next().await {
+        let bytes = bytes.map_err(|e| RunnerError::Backend(format!("stream failed: {}", e)))?;
+        buffer.extend_from_slice(&bytes);
+
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let data: OllamaResponse = serde_json::from_slice(&line).map_err(|e| RunnerError::Backend(e.to_string()))?;
+            if let Some(error) = data.error {
+                return Err(RunnerError::Backend(error));
+            }
+            let usage = (data.done == Some(true)).then(|| data.usage());
+            if let Some(message) = data.message {
+                if !message.tool_calls.is_empty() {
+                    let first = reply.tool_calls.len();
+                    let calls: Vec<ToolCall> = message
+                        .tool_calls
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, call)| call.into_tool_call(first + i))
+                        .collect();
+                    reply.tool_calls.extend(calls.iter().cloned());
+                    on_chunk(Chunk::ToolCalls(calls));
+                }
+                if !message.content.is_empty() {
+                    reply.content.push_str(&message.content);
+                    on_chunk(Chunk::Text(message.content));
+                }
+            }
+            if let Some(text) = data.response.filter(|text| !text.is_empty()) {
+                reply.content.push_str(&text);
+                on_chunk(Chunk::Text(text));
+            }
+            if let Some(usage) = usage {
+                reply.usage = usage;
+            }
+        }
+    }
+
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_progress_lines() {
+        let progress = read_progress(br#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a0746a1ec1a","total":4661211424,"completed":1048576}"#).unwrap();
+        assert_eq!(progress.status, "pulling 6a0746a1ec1a");
+        assert_eq!(progress.total, Some(4661211424));
+        assert_eq!(progress.completed, Some(1048576));
+    }
+
+    #[test]
+    fn fails_on_error_lines() {
+        let error = read_progress(br#"{"error":"pull model manifest: file does not exist"}"#).unwrap_err();
+        assert_eq!(error, "Pull failed: pull model manifest: file does not exist");
+    }
+}
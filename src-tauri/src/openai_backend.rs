@@ -0,0 +1,307 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::Deserialize;
+
+use crate::backend::LlmBackend;
+use crate::error::RunnerError;
+use crate::protocol::{ChatMessage, ChatOptions, Chunk, ChunkSink, CompletionPrompt, FunctionCall, Reply, ToolCall, Usage};
+use crate::{images, proxy, translate};
+
+// Any server speaking the OpenAI chat completions API: LM Studio, vLLM,
+// llamafile, llama.cpp's server. `base_url` includes the `/v1` prefix.
+pub struct OpenAi {
+    pub base_url: String,
+    pub api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    // `message` on complete responses, `delta` on stream chunks
+    message: Option<Content>,
+    delta: Option<Content>,
+    // Both, for the legacy /completions endpoint
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallPiece>,
+}
+
+// A whole tool call in complete responses; a piece of one, numbered by
+// `index`, on stream chunks
+#[derive(Deserialize)]
+struct ToolCallPiece {
+    index: Option<usize>,
+    id: Option<String>,
+    function: Option<FunctionPiece>,
+}
+
+#[derive(Deserialize)]
+struct FunctionPiece {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionUsage {
+    prompt_tokens: i32,
+    completion_tokens: i32,
+}
+
+impl OpenAi {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = proxy::http_client().request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    // `body` holds the model and input; `options` are merged into it
+    async fn complete(
+        &self,
+        path: &str,
+        mut body: serde_json::Value,
+        options: &ChatOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Reply, RunnerError> {
+        body["stream"] = serde_json::json!(on_chunk.is_some());
+        if on_chunk.is_some() {
+            body["stream_options"] = serde_json::json!({ "include_usage": true });
+        }
+        // Parameters never replace the model, input or stream flag
+        if let Some(fields) = body.as_object_mut() {
+            for (key, value) in translate::openai(options).body {
+                fields.entry(key).or_insert(value);
+            }
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, path)
+            .timeout(proxy::LONG_REQUEST_TIMEOUT)
+            .json(&body)
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+
+        if let Some(on_chunk) = on_chunk {
+            return read_stream(response, on_chunk).await;
+        }
+
+        let data: Completion = response.json().await.map_err(|e| RunnerError::Backend(e.to_string()))?;
+        let mut reply = Reply {
+            usage: usage(data.usage),
+            ..Reply::default()
+        };
+        let Some(choice) = data.choices.into_iter().next() else {
+            return Ok(reply);
+        };
+        if let Some(message) = choice.message {
+            reply.content = message.content.unwrap_or_default();
+            for piece in message.tool_calls {
+                add_tool_call_piece(&mut reply.tool_calls, piece);
+            }
+        }
+        if let Some(text) = choice.text {
+            reply.content = text;
+        }
+        Ok(reply)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OpenAi {
+    async fn list_models(&self) -> Result<Vec<String>, String> {
+        let response = self
+            .request(reqwest::Method::GET, "/models")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let data: ModelsResponse = check_status(response).await?.json().await.map_err(|e| e.to_string())?;
+        Ok(data.data.into_iter().map(|m| m.id).collect())
+    }
+
+    fn translation_warnings(&self, options: &ChatOptions) -> Vec<String> {
+        translate::openai(options).warnings
+    }
+
+    async fn chat(&self, model: &str, messages: &[ChatMessage], options: &ChatOptions) -> Result<Reply, RunnerError> {
+        self.complete("/chat/completions", chat_body(model, messages), options, None).await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        options: &ChatOptions,
+        on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+    ) -> Result<Reply, RunnerError> {
+        self.complete("/chat/completions", chat_body(model, messages), options, Some(on_chunk)).await
+    }
+
+    // The legacy endpoint has no system prompt or template; the prompt is
+    // always sent as is
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &CompletionPrompt,
+        options: &ChatOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Reply, RunnerError> {
+        let body = serde_json::json!({ "model": model, "prompt": prompt.prompt });
+        self.complete("/completions", body, options, on_chunk).await
+    }
+}
+
+fn chat_body(model: &str, messages: &[ChatMessage]) -> serde_json::Value {
+    serde_json::json!({
+        "model": model,
+        "messages": messages.iter().map(message_json).collect::<Vec<_>>(),
+    })
+}
+
+// Error bodies look like `{"error": {"message": "..."}}`
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, RunnerError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    Err(RunnerError::OllamaHttp {
+        status: status.as_u16(),
+        message: body["error"]["message"].as_str().or(status.canonical_reason()).unwrap_or_default().to_string(),
+    })
+}
+
+fn usage(usage: Option<CompletionUsage>) -> Usage {
+    let usage = usage.unwrap_or(CompletionUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+    });
+    Usage {
+        inputTokens: usage.prompt_tokens,
+        outputTokens: usage.completion_tokens,
+        ..Usage::default()
+    }
+}
+
+// Server-sent events: `data: <chunk>` lines, ending with `data: [DONE]`.
+// Usage arrives on a final chunk with no choices. Tool calls arrive in
+// pieces by index; a call is complete once the next one starts or the
+// stream ends.
+async fn read_stream(
+    response: reqwest::Response,
+    on_chunk: &(dyn Fn(Chunk) + Send + Sync),
+) -> Result<Reply, RunnerError> {
+    let mut reply = Reply::default();
+    let mut totals = None;
+    let mut calls_sent = 0;
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut stream = response.bytes_stream();
+
+    'read: while let Some(bytes) = stream.next().await {
+        let bytes = bytes.map_err(|e| RunnerError::Backend(format!("stream failed: {}", e)))?;
+        buffer.extend_from_slice(&bytes);
+
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break 'read;
+            }
+            let chunk: Completion = serde_json::from_str(data).map_err(|e| RunnerError::Backend(e.to_string()))?;
+            let choice = chunk.choices.into_iter().next();
+            if let Some(text) = choice.as_ref().and_then(|choice| choice.text.clone()).filter(|text| !text.is_empty()) {
+                reply.content.push_str(&text);
+                on_chunk(Chunk::Text(text));
+            }
+            if let Some(delta) = choice.and_then(|choice| choice.delta) {
+                if let Some(text) = delta.content.filter(|text| !text.is_empty()) {
+                    reply.content.push_str(&text);
+                    on_chunk(Chunk::Text(text));
+                }
+                for piece in delta.tool_calls {
+                    add_tool_call_piece(&mut reply.tool_calls, piece);
+                }
+                let complete = reply.tool_calls.len().saturating_sub(1);
+                if complete > calls_sent {
+                    on_chunk(Chunk::ToolCalls(reply.tool_calls[calls_sent..complete].to_vec()));
+                    calls_sent = complete;
+                }
+            }
+            if chunk.usage.is_some() {
+                totals = chunk.usage;
+            }
+        }
+    }
+
+    if reply.tool_calls.len() > calls_sent {
+        on_chunk(Chunk::ToolCalls(reply.tool_calls[calls_sent..].to_vec()));
+    }
+    reply.usage = usage(totals);
+    Ok(reply)
+}
+
+fn add_tool_call_piece(calls: &mut Vec<ToolCall>, piece: ToolCallPiece) {
+    let index = piece.index.unwrap_or(calls.len());
+    while calls.len() <= index {
+        calls.push(ToolCall {
+            id: format!("call_{}", calls.len()),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+    }
+    let call = &mut calls[index];
+    if let Some(id) = piece.id {
+        call.id = id;
+    }
+    if let Some(function) = piece.function {
+        call.function.name.push_str(function.name.as_deref().unwrap_or_default());
+        call.function.arguments.push_str(function.arguments.as_deref().unwrap_or_default());
+    }
+}
+
+// Messages with images become content parts
+fn message_json(message: &ChatMessage) -> serde_json::Value {
+    let mut json = serde_json::json!({ "role": message.role, "content": message.content });
+    if !message.images.is_empty() {
+        let mut parts = vec![serde_json::json!({ "type": "text", "text": message.content })];
+        parts.extend(message.images.iter().map(|image| {
+            serde_json::json!({ "type": "image_url", "image_url": { "url": images::data_url(image) } })
+        }));
+        json["content"] = serde_json::json!(parts);
+    }
+    if !message.tool_calls.is_empty() {
+        json["tool_calls"] = serde_json::json!(message.tool_calls);
+    }
+    if let Some(id) = &message.tool_call_id {
+        json["tool_call_id"] = serde_json::json!(id);
+    }
+    json
+}
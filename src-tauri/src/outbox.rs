@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::RelayConfig;
+use crate::protocol::ClientMessage;
+
+// Final responses kept until the relay acks them, so one that was lost with
+// the socket (or never made it out) is sent again after reconnecting. A
+// relay that doesn't ack gets the ones that could not be sent at all once,
+// on the next connection.
+#[derive(Default)]
+pub struct Outbox {
+    pending: Mutex<VecDeque<Pending>>,
+}
+
+struct Pending {
+    request_id: String,
+    // The `chat_response` as sent
+    json: String,
+    kept_at: Instant,
+    // Dropped once sent instead of waiting for an ack
+    once: bool,
+}
+
+impl Outbox {
+    // Keeps `response` if it is a request's final frame; the oldest response
+    // goes once `max_unacked_responses` are waiting
+    pub fn keep(&self, response: &ClientMessage, config: &RelayConfig) {
+        if config.max_unacked_responses > 0 {
+            self.push(response, config.max_unacked_responses, false);
+        }
+    }
+
+    // Keeps a final frame that had no connection to go out on, for the next
+    // one, whether or not the relay acks
+    pub fn resend(&self, response: &ClientMessage, config: &RelayConfig) {
+        self.push(response, config.max_unacked_responses.max(1), true);
+    }
+
+    fn push(&self, response: &ClientMessage, capacity: usize, once: bool) {
+        let ClientMessage::ChatResponse { requestId, done: Some(true), .. } = response else {
+            return;
+        };
+        let Ok(json) = serde_json::to_string(response) else {
+            return;
+        };
+        let Ok(mut pending) = self.pending.lock() else {
+            return;
+        };
+        pending.retain(|p| p.request_id != *requestId);
+        while pending.len() >= capacity {
+            if let Some(dropped) = pending.pop_front() {
+                tracing::warn!(requestId = %dropped.request_id, "Dropping unacknowledged response for {}: outbox full", dropped.request_id);
+            }
+        }
+        pending.push_back(Pending {
+            request_id: requestId.clone(),
+            json,
+            kept_at: Instant::now(),
+            once,
+        });
+    }
+
+    // False when nothing was waiting for the ack
+    pub fn ack(&self, request_id: &str) -> bool {
+        let Ok(mut pending) = self.pending.lock() else {
+            return false;
+        };
+        let before = pending.len();
+        pending.retain(|p| p.request_id != request_id);
+        pending.len() < before
+    }
+
+    // Responses to send again, oldest first. Those older than
+    // `unacked_ttl_secs` are given up on, and those kept by `resend` are
+    // forgotten as they are handed out.
+    pub fn unacked(&self, config: &RelayConfig) -> Vec<String> {
+        let Ok(mut pending) = self.pending.lock() else {
+            return Vec::new();
+        };
+        let ttl = Duration::from_secs(config.unacked_ttl_secs);
+        pending.retain(|p| {
+            let fresh = p.kept_at.elapsed() < ttl;
+            if !fresh {
+                tracing::warn!(requestId = %p.request_id, "Giving up on response for {}: never acknowledged", p.request_id);
+            }
+            fresh
+        });
+        let unacked = pending.iter().map(|p| p.json.clone()).collect();
+        pending.retain(|p| !p.once);
+        unacked
+    }
+}
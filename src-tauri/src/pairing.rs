@@ -0,0 +1,236 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use bottlecap_runner_core::secret_store;
+
+use crate::commands::{self, AppState};
+use crate::{autostart, tray};
+
+pub const SCHEME: &str = "bottlecap";
+
+// How long a pairing code shown by `get_pairing_code` is accepted
+const CODE_TTL: Duration = Duration::from_secs(600);
+// No 0/O or 1/I, for codes read off a screen
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const PAIR_PAGE: &str = "https://bottlecap.ai/dashboard/runners";
+
+static PENDING_CODE: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+// The token from a pairing link without a matching code
+static REQUESTED: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingCode {
+    pub code: String,
+    // What to put in the QR code: the dashboard page that creates a token
+    // for this runner and hands it back with `bottlecap://pair`
+    pub qr_payload: String,
+    pub expires_in_secs: u64,
+}
+
+// A new code, replacing any earlier one
+pub fn new_code() -> PairingCode {
+    let mut rng = rand::thread_rng();
+    let code: String = (0..8)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect();
+    if let Ok(mut pending) = PENDING_CODE.lock() {
+        *pending = Some((code.clone(), Instant::now()));
+    }
+    let device = hostname::get()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_default();
+    let qr_payload = url::Url::parse_with_params(PAIR_PAGE, &[("pair", code.as_str()), ("device", device.as_str())])
+        .map(String::from)
+        .unwrap_or_else(|_| PAIR_PAGE.to_string());
+    PairingCode {
+        code,
+        qr_payload,
+        expires_in_secs: CODE_TTL.as_secs(),
+    }
+}
+
+// Whether `code` is the one shown, which is used up by matching
+fn take_code(code: &str) -> bool {
+    let Ok(mut pending) = PENDING_CODE.lock() else {
+        return false;
+    };
+    match pending.as_ref() {
+        Some((expected, created)) if expected == code && created.elapsed() < CODE_TTL => {
+            *pending = None;
+            true
+        }
+        _ => false,
+    }
+}
+
+// The `bottlecap://` argument the app was launched with, if any
+pub fn url_arg() -> Option<String> {
+    std::env::args().skip(1).find(|arg| arg.starts_with(&format!("{}://", SCHEME)))
+}
+
+// `bottlecap://pair?token=bc_runner_...[&code=...]`. With the code from
+// `get_pairing_code` the token is saved and connected right away. Otherwise
+// it waits for the user to accept it in the window (`pairing-requested`), so
+// a link from anywhere can't quietly swap the runner's account.
+pub fn handle_url(app: &AppHandle, link: &str) {
+    let parsed = match url::Url::parse(link) {
+        Ok(parsed) if parsed.scheme() == SCHEME && parsed.host_str() == Some("pair") => parsed,
+        _ => {
+            tracing::warn!("Ignoring unsupported link {}", link.split('?').next().unwrap_or_default());
+            return;
+        }
+    };
+    let param = |name: &str| parsed.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let Some(token) = param("token").filter(|token| token.starts_with("bc_runner_")) else {
+        tracing::warn!("Ignoring pairing link without a runner token");
+        return;
+    };
+
+    tray::show_window(app);
+    if param("code").is_some_and(|code| take_code(&code)) {
+        pair(app, token);
+    } else if let Ok(mut requested) = REQUESTED.lock() {
+        *requested = Some(token);
+        let _ = app.emit_all("pairing-requested", serde_json::json!({}));
+    }
+}
+
+// Whether a pairing link is waiting to be accepted or rejected
+pub fn requested() -> bool {
+    REQUESTED.lock().map(|requested| requested.is_some()).unwrap_or(false)
+}
+
+pub fn take_requested() -> Option<String> {
+    REQUESTED.lock().ok().and_then(|mut requested| requested.take())
+}
+
+// Saves `token` as the default account's and connects with it
+pub fn pair(app: &AppHandle, token: String) {
+    tracing::info!("Paired with BottleCapAI");
+    if let Err(e) = secret_store::set("token", &token) {
+        tracing::error!("Failed to save the paired token: {}", e);
+    }
+    let _ = app.emit_all("paired", serde_json::json!({}));
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        commands::connect(token, app.clone(), &state).await;
+    });
+}
+
+// Makes this executable the handler for `bottlecap://` links. Run at every
+// start, so the entry follows the app when it moves. macOS takes the scheme
+// from the bundle's Info.plist instead.
+pub fn register(app: &AppHandle) {
+    let result = autostart::executable().and_then(|exe| write_handler(app, &exe));
+    if let Err(e) = result {
+        tracing::warn!("Could not register {}:// links: {}", SCHEME, e);
+    }
+    #[cfg(target_os = "macos")]
+    apple_events::listen(app.clone());
+}
+
+#[cfg(windows)]
+fn write_handler(app: &AppHandle, exe: &std::path::Path) -> Result<(), String> {
+    let key = format!(r"HKCU\Software\Classes\{}", SCHEME);
+    let description = format!("URL:{}", app.package_info().name);
+    autostart::reg(&["add", &key, "/ve", "/d", &description, "/f"])?;
+    autostart::reg(&["add", &key, "/v", "URL Protocol", "/d", "", "/f"])?;
+    let command = format!("\"{}\" \"%1\"", exe.display());
+    autostart::reg(&["add", &format!(r"{}\shell\open\command", key), "/ve", "/d", &command, "/f"])
+}
+
+#[cfg(target_os = "macos")]
+fn write_handler(_app: &AppHandle, _exe: &std::path::Path) -> Result<(), String> {
+    Ok(())
+}
+
+// A hidden .desktop entry for the scheme, made the default handler
+#[cfg(not(any(windows, target_os = "macos")))]
+fn write_handler(app: &AppHandle, exe: &std::path::Path) -> Result<(), String> {
+    let dir = tauri::api::path::data_dir().ok_or("No data directory")?.join("applications");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let file_name = format!("{}-handler.desktop", app.config().tauri.bundle.identifier);
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\" %u\nNoDisplay=true\nTerminal=false\nMimeType=x-scheme-handler/{};\n",
+        app.package_info().name,
+        exe.display(),
+        SCHEME
+    );
+    std::fs::write(dir.join(&file_name), entry).map_err(|e| e.to_string())?;
+    let status = std::process::Command::new("xdg-mime")
+        .args(["default", &file_name, &format!("x-scheme-handler/{}", SCHEME)])
+        .status()
+        .map_err(|e| format!("Failed to run xdg-mime: {}", e))?;
+    if !status.success() {
+        return Err(format!("xdg-mime exited with {}", status));
+    }
+    Ok(())
+}
+
+// Links opened while the app runs, or that launched it, arrive as a GetURL
+// Apple event rather than as an argument
+#[cfg(target_os = "macos")]
+mod apple_events {
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+    use std::sync::OnceLock;
+
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use tauri::AppHandle;
+
+    // 'GURL' and '----'
+    const INTERNET_EVENT_CLASS: u32 = 0x4755_524c;
+    const GET_URL: u32 = 0x4755_524c;
+    const DIRECT_OBJECT: u32 = 0x2d2d_2d2d;
+
+    static APP: OnceLock<AppHandle> = OnceLock::new();
+
+    extern "C" fn handle_get_url(_this: &Object, _cmd: Sel, event: *mut Object, _reply: *mut Object) {
+        unsafe {
+            let descriptor: *mut Object = msg_send![event, paramDescriptorForKeyword: DIRECT_OBJECT];
+            if descriptor.is_null() {
+                return;
+            }
+            let string: *mut Object = msg_send![descriptor, stringValue];
+            if string.is_null() {
+                return;
+            }
+            let utf8: *const c_char = msg_send![string, UTF8String];
+            let link = CStr::from_ptr(utf8).to_string_lossy().into_owned();
+            if let Some(app) = APP.get() {
+                super::handle_url(app, &link);
+            }
+        }
+    }
+
+    pub fn listen(app: AppHandle) {
+        if APP.set(app).is_err() {
+            return;
+        }
+        let Some(mut decl) = ClassDecl::new("BottleCapUrlHandler", class!(NSObject)) else {
+            return;
+        };
+        unsafe {
+            decl.add_method(
+                sel!(handleGetURLEvent:withReplyEvent:),
+                handle_get_url as extern "C" fn(&Object, Sel, *mut Object, *mut Object),
+            );
+            let handler: *mut Object = msg_send![decl.register(), new];
+            let manager: *mut Object = msg_send![class!(NSAppleEventManager), sharedAppleEventManager];
+            let _: () = msg_send![manager,
+                setEventHandler: handler
+                andSelector: sel!(handleGetURLEvent:withReplyEvent:)
+                forEventClass: INTERNET_EVENT_CLASS
+                andEventID: GET_URL];
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use regex::RegexSet;
+
+use crate::backend;
+use crate::config::{BackendConfig, PolicyConfig, PolicyFilter};
+use crate::protocol::{ChatMessage, ChatOptions, Input};
+
+// Runs a prompt past `[policy]`'s filters. The error says which filter
+// refused it, but not what matched, so blocklists aren't spelled out to
+// requesters.
+pub async fn check(config: &PolicyConfig, backend: &BackendConfig, input: &Input) -> Result<(), String> {
+    if config.filters.is_empty() {
+        return Ok(());
+    }
+    let messages = input.messages();
+    for filter in &config.filters {
+        match filter {
+            PolicyFilter::Blocklist { patterns } => {
+                let set = RegexSet::new(patterns).map_err(|e| e.to_string())?;
+                if messages.iter().any(|message| set.is_match(&message.content)) {
+                    return Err("the prompt matches a blocked pattern".to_string());
+                }
+                if let Input::Completion(prompt) = input {
+                    if prompt.system.as_deref().is_some_and(|system| set.is_match(system)) {
+                        return Err("the system prompt matches a blocked pattern".to_string());
+                    }
+                }
+            }
+            PolicyFilter::MaxAttachments { max } => {
+                let images: usize = messages.iter().map(|message| message.images.len()).sum();
+                if images > *max {
+                    return Err(format!("{} images attached, more than the {} allowed", images, max));
+                }
+            }
+            PolicyFilter::Moderation { model } => moderate(backend, model, &messages).await?,
+        }
+    }
+    Ok(())
+}
+
+// Asks the moderation model about the conversation's text. A model that
+// can't be asked refuses the request, rather than letting it through
+// unchecked.
+async fn moderate(backend: &BackendConfig, model: &str, messages: &[ChatMessage]) -> Result<(), String> {
+    let conversation = messages
+        .iter()
+        .filter(|message| message.role == "user" || message.role == "assistant")
+        .map(|message| ChatMessage {
+            role: message.role.clone(),
+            content: message.content.clone(),
+            images: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        })
+        .collect();
+    let options = ChatOptions {
+        temperature: Some(0.0),
+        max_tokens: Some(16),
+        stream: Some(false),
+        ..ChatOptions::default()
+    };
+    let reply = backend::complete(backend, model, &Input::Chat(conversation), &options, None)
+        .await
+        .map_err(|e| {
+            tracing::error!("Moderation with {} failed: {}", model, e);
+            "the moderation check could not run".to_string()
+        })?;
+    let mut verdict = reply.content.split_whitespace();
+    match verdict.next().map(str::to_lowercase).as_deref() {
+        Some("safe") => Ok(()),
+        Some("unsafe") => {
+            let categories: Vec<&str> = verdict.collect();
+            if categories.is_empty() {
+                Err("the moderation model flagged the prompt".to_string())
+            } else {
+                Err(format!("the moderation model flagged the prompt ({})", categories.join(" ")))
+            }
+        }
+        _ => {
+            tracing::error!("Moderation with {}: unexpected answer {:?}", model, reply.content);
+            Err("the moderation check could not run".to_string())
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::PowerConfig;
+use crate::state::RunnerState;
+
+// How often the runner's activity is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Keeps the system awake, but lets the display turn off, for as long as it
+// is held: `caffeinate -i` on macOS, a `systemd-inhibit` sleep lock on Linux
+// and `SetThreadExecutionState` on Windows
+pub struct Inhibitor {
+    #[cfg(not(windows))]
+    child: std::process::Child,
+    // Dropping it ends the thread holding the execution state
+    #[cfg(windows)]
+    _release: std::sync::mpsc::Sender<()>,
+}
+
+impl Inhibitor {
+    #[cfg(target_os = "macos")]
+    pub fn acquire() -> Result<Self, String> {
+        // `-w` ends it with the runner, however the runner exits
+        let pid = std::process::id().to_string();
+        spawn("caffeinate", &["-i", "-w", &pid])
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    pub fn acquire() -> Result<Self, String> {
+        // The lock lasts as long as the command it runs, here one that
+        // waits for the runner to exit
+        let pid = std::process::id().to_string();
+        spawn(
+            "systemd-inhibit",
+            &[
+                "--what=sleep",
+                "--who=BottleCapAI Runner",
+                "--why=Serving requests",
+                "--mode=block",
+                "tail",
+                "--pid",
+                &pid,
+                "-f",
+                "/dev/null",
+            ],
+        )
+    }
+
+    #[cfg(windows)]
+    pub fn acquire() -> Result<Self, String> {
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn SetThreadExecutionState(flags: u32) -> u32;
+        }
+        const ES_CONTINUOUS: u32 = 0x8000_0000;
+        const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+        // The state belongs to the thread that set it
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let (acquired, result) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let set = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } != 0;
+            let _ = acquired.send(set);
+            if set {
+                let _ = released.recv();
+                unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+            }
+        });
+        match result.recv() {
+            Ok(true) => Ok(Self { _release: release }),
+            _ => Err("SetThreadExecutionState failed".to_string()),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn spawn(program: &str, args: &[&str]) -> Result<Inhibitor, String> {
+    let child = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    Ok(Inhibitor { child })
+}
+
+#[cfg(not(windows))]
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Connected, with requests being served or waiting, or one served within
+// `recent_secs`
+pub fn busy(state: &RunnerState, config: &PowerConfig) -> bool {
+    if state.connection_status() != "connected" {
+        return false;
+    }
+    let load = state.scheduler().load();
+    load.activeRequests + load.queueDepth > 0
+        || state.idle_for().is_some_and(|idle| idle < Duration::from_secs(config.recent_secs))
+}
+
+// Holds an `Inhibitor` while `prevent_sleep` is on and the runner is busy
+pub async fn run(state: Arc<RunnerState>) {
+    let mut inhibitor: Option<Inhibitor> = None;
+    // Logged once, not on every check
+    let mut failed = false;
+    loop {
+        let config = state.config().power;
+        let wanted = config.prevent_sleep && busy(&state, &config);
+        if wanted && inhibitor.is_none() {
+            match Inhibitor::acquire() {
+                Ok(acquired) => {
+                    tracing::info!("Preventing system sleep while serving requests");
+                    inhibitor = Some(acquired);
+                    failed = false;
+                }
+                Err(e) if !failed => {
+                    tracing::error!("Could not prevent system sleep: {}", e);
+                    failed = true;
+                }
+                Err(_) => {}
+            }
+        } else if !wanted && inhibitor.take().is_some() {
+            tracing::info!("Allowing system sleep again");
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Mirrors `[privacy] enabled` for the layers that write to disk (logs,
+// history, transcripts, session recordings), so none of them can store
+// message content while it is on, whatever their own settings say
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Keys whose values are message content wherever they appear
+const CONTENT_KEYS: &[&str] = &[
+    "content", "chunk", "prompt", "system", "template", "images", "toolCalls", "tool_calls", "response", "reasoning",
+];
+
+pub const REDACTED: &str = "[redacted]";
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Replaces message content anywhere in `value`, keeping everything else.
+// Only strings are replaced, so redacted frames still parse (and replay).
+pub fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                // A request's `options.reasoning` says how to handle the
+                // model's reasoning; it isn't reasoning itself
+                if key == "options" {
+                    if let Some(options) = field.as_object_mut() {
+                        for (key, option) in options.iter_mut().filter(|(key, _)| *key != "reasoning") {
+                            redact_field(key, option);
+                        }
+                        continue;
+                    }
+                }
+                redact_field(key, field);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn redact_field(key: &str, field: &mut serde_json::Value) {
+    if CONTENT_KEYS.contains(&key) {
+        blank(field);
+    } else {
+        redact(field);
+    }
+}
+
+fn blank(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => *text = REDACTED.to_string(),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(blank),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(blank),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_content_and_reasoning() {
+        let mut frame = json!({
+            "type": "chat_response",
+            "requestId": "r1",
+            "content": "4",
+            "reasoning": "2 + 2 is 4",
+            "toolCalls": [{ "id": "c1", "function": { "name": "add", "arguments": "{}" } }],
+            "usage": { "inputTokens": 3, "outputTokens": 1 },
+        });
+        redact(&mut frame);
+        assert_eq!(frame["content"], REDACTED);
+        assert_eq!(frame["reasoning"], REDACTED);
+        assert_eq!(frame["toolCalls"][0]["function"]["name"], REDACTED);
+        assert_eq!(frame["requestId"], "r1");
+        assert_eq!(frame["usage"]["inputTokens"], 3);
+    }
+
+    #[test]
+    fn keeps_the_reasoning_option() {
+        let mut frame = json!({
+            "type": "chat_request",
+            "messages": [{ "role": "user", "content": "What is 2 + 2?" }],
+            "options": { "reasoning": "separate", "temperature": 0.2 },
+        });
+        redact(&mut frame);
+        assert_eq!(frame["messages"][0]["content"], REDACTED);
+        assert_eq!(frame["messages"][0]["role"], "user");
+        assert_eq!(frame["options"]["reasoning"], "separate");
+    }
+}
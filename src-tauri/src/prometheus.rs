@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::access;
+use crate::error::ErrorInfo;
+use crate::history;
+use crate::protocol::Usage;
+use crate::state::RunnerState;
+
+// Upper bounds of the request duration buckets, in seconds
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+// Error codes that mean the backend itself failed
+const BACKEND_ERRORS: &[&str] = &["backend_unreachable", "backend_unavailable", "backend_http", "backend_error"];
+
+#[derive(Default, Clone)]
+struct Histogram {
+    // One count per bucket, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        self.buckets.resize(DURATION_BUCKETS.len(), 0);
+        if let Some(i) = DURATION_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    // By model and outcome
+    requests: BTreeMap<(String, &'static str), u64>,
+    // By model and direction
+    tokens: BTreeMap<(String, &'static str), u64>,
+    durations: BTreeMap<String, Histogram>,
+    // By error code
+    backend_errors: BTreeMap<String, u64>,
+    reconnects: u64,
+}
+
+// Counters since the process started, served in Prometheus' text format
+#[derive(Default)]
+pub struct Registry {
+    counters: Mutex<Counters>,
+}
+
+impl Registry {
+    pub fn record_request(&self, model: &str, duration: Duration, usage: Option<&Usage>, error: Option<&ErrorInfo>) {
+        let Ok(mut counters) = self.counters.lock() else {
+            return;
+        };
+        *counters.requests.entry((model.to_string(), history::outcome(error))).or_default() += 1;
+        counters.durations.entry(model.to_string()).or_default().observe(duration.as_secs_f64());
+        if let Some(usage) = usage {
+            *counters.tokens.entry((model.to_string(), "input")).or_default() += usage.inputTokens.max(0) as u64;
+            *counters.tokens.entry((model.to_string(), "output")).or_default() += usage.outputTokens.max(0) as u64;
+        }
+        if let Some(error) = error.filter(|e| BACKEND_ERRORS.contains(&e.code.as_str())) {
+            *counters.backend_errors.entry(error.code.clone()).or_default() += 1;
+        }
+    }
+
+    pub fn record_reconnect(&self) {
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.reconnects += 1;
+        }
+    }
+}
+
+// Binds `/metrics` without authentication, so it should listen on a
+// loopback or otherwise private address
+pub async fn start(addr: SocketAddr, state: Arc<RunnerState>) -> Result<SocketAddr, String> {
+    let app = Router::new().route("/metrics", get(metrics)).with_state(state);
+    access::serve(addr, app, None).await
+}
+
+async fn metrics(State(state): State<Arc<RunnerState>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], render(&state))
+}
+
+pub fn render(state: &RunnerState) -> String {
+    let mut out = String::new();
+    let load = state.scheduler().load();
+    gauge(&mut out, "bottlecap_connected", "Whether the runner is connected to the relay.", (state.connection_status() == "connected") as u8);
+    gauge(&mut out, "bottlecap_paused", "Whether the runner is paused.", state.is_paused() as u8);
+    gauge(&mut out, "bottlecap_queue_depth", "Requests waiting for a generation slot.", load.queueDepth);
+    gauge(&mut out, "bottlecap_active_requests", "Requests being generated.", load.activeRequests);
+
+    let Ok(counters) = state.prometheus().counters.lock() else {
+        return out;
+    };
+
+    header(&mut out, "bottlecap_requests_total", "Requests handled, by model and outcome.", "counter");
+    for ((model, outcome), count) in &counters.requests {
+        let _ = writeln!(out, "bottlecap_requests_total{{model=\"{}\",outcome=\"{}\"}} {}", escape(model), outcome, count);
+    }
+
+    header(&mut out, "bottlecap_tokens_total", "Tokens processed, by model and direction.", "counter");
+    for ((model, direction), count) in &counters.tokens {
+        let _ = writeln!(out, "bottlecap_tokens_total{{model=\"{}\",direction=\"{}\"}} {}", escape(model), direction, count);
+    }
+
+    let name = "bottlecap_request_duration_seconds";
+    header(&mut out, name, "Time from receiving a request to answering it.", "histogram");
+    for (model, histogram) in &counters.durations {
+        let model = escape(model);
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{model=\"{}\",le=\"{}\"}} {}", name, model, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{model=\"{}\",le=\"+Inf\"}} {}", name, model, histogram.count);
+        let _ = writeln!(out, "{}_sum{{model=\"{}\"}} {}", name, model, histogram.sum);
+        let _ = writeln!(out, "{}_count{{model=\"{}\"}} {}", name, model, histogram.count);
+    }
+
+    header(&mut out, "bottlecap_backend_errors_total", "Requests the backend failed, by error code.", "counter");
+    for (code, count) in &counters.backend_errors {
+        let _ = writeln!(out, "bottlecap_backend_errors_total{{code=\"{}\"}} {}", code, count);
+    }
+
+    header(&mut out, "bottlecap_reconnects_total", "Relay connections retried after a failure.", "counter");
+    let _ = writeln!(out, "bottlecap_reconnects_total {}", counters.reconnects);
+    out
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
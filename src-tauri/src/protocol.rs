@@ -0,0 +1,487 @@
+// Message fields are named as they go over the wire, in camelCase
+#![allow(non_snake_case)]
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::ErrorInfo;
+use crate::reasoning::ReasoningMode;
+use crate::{benchmark, cost, translate};
+
+// Bumped when a message changes incompatibly. Runners that send no version
+// speak version 1.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+// Optional features this build handles, sent with `auth` and the online
+// status so the relay only uses what the runner understands
+pub fn capabilities() -> Vec<String> {
+    let mut capabilities = vec![
+        "streaming",
+        "cancel",
+        "request_status",
+        "vision",
+        "tools",
+        "completion",
+        "ack",
+        "gzip",
+        "msgpack",
+        "refresh_token",
+    ];
+    if cfg!(feature = "webrtc") {
+        capabilities.push("webrtc");
+    }
+    capabilities.into_iter().map(str::to_string).collect()
+}
+
+// Message types for WebSocket communication
+// Parsed once per frame and consumed right away, so the size of
+// chat_request is not worth boxing
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    #[serde(rename = "auth_success")]
+    AuthSuccess {
+        runnerId: String,
+        // Unix seconds; the runner refreshes its token ahead of it
+        #[serde(default)]
+        expiresAt: Option<u64>,
+    },
+    #[serde(rename = "auth_error")]
+    AuthError { error: Option<String> },
+    #[serde(rename = "chat_request")]
+    ChatRequest {
+        requestId: String,
+        model: String,
+        // Empty when sealed; the messages are inside
+        #[serde(default)]
+        messages: Vec<ChatMessage>,
+        options: ChatOptions,
+        // Higher runs first when requests queue for a slot
+        #[serde(default)]
+        priority: i32,
+        sealed: Option<Sealed>,
+        // Who the request is for, as the relay knows them; rate limits are
+        // kept per requester
+        requesterId: Option<String>,
+    },
+    // A raw prompt to continue, for clients that apply their own template
+    #[serde(rename = "completion_request")]
+    CompletionRequest {
+        requestId: String,
+        model: String,
+        prompt: String,
+        #[serde(default)]
+        raw: bool,
+        system: Option<String>,
+        template: Option<String>,
+        #[serde(default)]
+        options: ChatOptions,
+        #[serde(default)]
+        priority: i32,
+        requesterId: Option<String>,
+    },
+    #[serde(rename = "cancel_request")]
+    CancelRequest { requestId: String },
+    // The relay has the final response to the request; it isn't resent
+    #[serde(rename = "ack")]
+    Ack { requestId: String },
+    #[serde(rename = "rtc_offer")]
+    RtcOffer { sessionId: String, sdp: String },
+    #[serde(rename = "rtc_close")]
+    RtcClose { sessionId: String },
+    // What the relay supports, sent after `auth_success` by relays that
+    // negotiate. Without it the runner assumes everything.
+    #[serde(rename = "capabilities")]
+    Capabilities {
+        protocolVersion: Option<u32>,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    // Answer to `refresh_token`: the token to authenticate with from now on,
+    // or why there is none
+    #[serde(rename = "token_refreshed")]
+    TokenRefreshed {
+        token: Option<String>,
+        expiresAt: Option<u64>,
+        error: Option<String>,
+    },
+    // Application-level keepalive answer; nothing to do
+    #[serde(rename = "pong")]
+    Pong,
+    // Message types added to the relay after this version
+    #[serde(other)]
+    Unknown,
+}
+
+impl ServerMessage {
+    // The id, model, input and options of a chat or completion request
+    pub fn into_request(self) -> Option<(String, String, Input, ChatOptions)> {
+        match self {
+            ServerMessage::ChatRequest { requestId, model, messages, options, .. } => {
+                Some((requestId, model, Input::Chat(messages), options))
+            }
+            ServerMessage::CompletionRequest { requestId, model, prompt, raw, system, template, options, .. } => {
+                let prompt = CompletionPrompt { prompt, raw, system, template };
+                Some((requestId, model, Input::Completion(prompt), options))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            ServerMessage::ChatRequest { requestId, .. } | ServerMessage::CompletionRequest { requestId, .. } => {
+                Some(requestId)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn requester_id(&self) -> Option<&str> {
+        match self {
+            ServerMessage::ChatRequest { requesterId, .. } | ServerMessage::CompletionRequest { requesterId, .. } => {
+                requesterId.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    // 0 for anything but a request without one
+    pub fn priority(&self) -> i32 {
+        match self {
+            ServerMessage::ChatRequest { priority, .. } | ServerMessage::CompletionRequest { priority, .. } => *priority,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    #[serde(rename = "auth")]
+    Auth {
+        token: String,
+        #[serde(default)]
+        protocolVersion: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+        // Ed25519 public key the runner signs its frames with, base64
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deviceKey: Option<String>,
+    },
+    // Asks for a new token before the current one expires
+    #[serde(rename = "refresh_token")]
+    RefreshToken { token: String },
+    #[serde(rename = "chat_response")]
+    ChatResponse {
+        requestId: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chunk: Option<String>,
+        // Functions the model wants called. Streams send each call in its own
+        // frame as soon as it is complete.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        toolCalls: Option<Vec<ToolCall>>,
+        // The model's `<think>` text when the request asked for it
+        // separately. Streams send it in frames of its own, as it arrives.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reasoning: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        done: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<ErrorInfo>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        usage: Option<Usage>,
+        // Request parameters the backend could not honour
+        #[serde(skip_serializing_if = "Option::is_none")]
+        warnings: Option<Vec<String>>,
+        // Only sent when cost reporting is enabled
+        #[serde(skip_serializing_if = "Option::is_none")]
+        estimate: Option<cost::Estimate>,
+        // Numbers a stream's chunk frames from 0. On its final frame, the
+        // number of chunk frames sent, so a requester can tell it missed some.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        // `content`, `chunk`, `toolCalls` and `reasoning`, encrypted, for a sealed request
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sealed: Option<Sealed>,
+    },
+    #[serde(rename = "status")]
+    Status {
+        status: String,
+        // Why the runner is offline: "battery" or "schedule"
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        protocolVersion: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        capabilities: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        models: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        deviceName: Option<String>,
+        // Only sent when the backend is on another machine
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backend: Option<BackendNote>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        hardware: Option<Hardware>,
+        // Saved `run_benchmark` results for the advertised models
+        #[serde(skip_serializing_if = "Option::is_none")]
+        benchmarks: Option<Vec<benchmark::Benchmark>>,
+        // Models Ollama has in memory, which answer without a load delay
+        #[serde(skip_serializing_if = "Option::is_none")]
+        loadedModels: Option<Vec<LoadedModel>>,
+        // X25519 public key, base64, for sealing requests to this runner
+        #[serde(skip_serializing_if = "Option::is_none")]
+        e2eKey: Option<String>,
+        // Periodic load reports only
+        #[serde(flatten)]
+        load: Option<Load>,
+    },
+    // Interim progress on a request that is not being generated yet
+    #[serde(rename = "request_status")]
+    RequestStatus {
+        requestId: String,
+        // "pulling_model"
+        status: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+        // Percent of the current download
+        #[serde(skip_serializing_if = "Option::is_none")]
+        progress: Option<u8>,
+    },
+    // Either `sdp` or `error`; an error means "stay on the relay"
+    #[serde(rename = "rtc_answer")]
+    RtcAnswer {
+        sessionId: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sdp: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl ClientMessage {
+    pub fn auth(token: String, device_key: Option<String>) -> Self {
+        ClientMessage::Auth {
+            token,
+            protocolVersion: PROTOCOL_VERSION,
+            capabilities: capabilities(),
+            deviceKey: device_key,
+        }
+    }
+}
+
+// Chat content only the requester and the runner can read; see `e2e`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sealed {
+    // The requester's X25519 public key, base64, on requests only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    // ChaCha20-Poly1305 nonce and ciphertext with tag, base64
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackendNote {
+    pub remote: bool,
+    // "tailscale" or "ssh"; absent for other hosts on the network
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+}
+
+// What the runner's machine has, so large models can go to runners that
+// can hold them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hardware {
+    pub cpuModel: String,
+    // Physical cores
+    pub cpuCores: usize,
+    pub totalRamMb: u64,
+    pub gpus: Vec<Gpu>,
+    pub os: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Gpu {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vramMb: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoadedModel {
+    pub name: String,
+    pub sizeBytes: u64,
+    // The part of `sizeBytes` in GPU memory
+    pub vramBytes: u64,
+    // RFC 3339; when Ollama unloads it if no request comes in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiresAt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Load {
+    // Requests waiting for a generation slot
+    pub queueDepth: usize,
+    pub activeRequests: usize,
+    // Moving average over recent requests
+    pub avgTokensPerSec: f64,
+    // `queueDepth` split by request priority
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub queuedByPriority: BTreeMap<i32, usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    // Base64 attachments for vision models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    // Calls an assistant message asked for earlier in the conversation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // On `tool` messages: which call this is the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+// A `completion_request`'s prompt. With `raw` the prompt goes to the model
+// as is; otherwise the model's template (or `template`) wraps it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletionPrompt {
+    pub prompt: String,
+    pub raw: bool,
+    pub system: Option<String>,
+    pub template: Option<String>,
+}
+
+// What a request asks the backend to continue
+#[derive(Debug, Clone)]
+pub enum Input {
+    Chat(Vec<ChatMessage>),
+    Completion(CompletionPrompt),
+}
+
+impl Input {
+    // A completion reads as one user message, e.g. in transcripts
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        match self {
+            Input::Chat(messages) => messages.clone(),
+            Input::Completion(prompt) => vec![ChatMessage {
+                role: "user".to_string(),
+                content: prompt.prompt.clone(),
+                images: Vec::new(),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+        }
+    }
+}
+
+// OpenAI's form, with the arguments as a JSON string
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChatOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub stream: Option<bool>,
+    // OpenAI-style parameters, translated per backend (see translate.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, deserialize_with = "translate::stop_sequences", skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<String, f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    // OpenAI function definitions and `tool_choice`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    // Sampling options beyond OpenAI's, named as in Ollama
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<i32>,
+    // `<think>` handling, overriding the runner's `backend.reasoning`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningMode>,
+    // Backend options passed through untouched, so new ones need no
+    // protocol change. Named options above take precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Usage {
+    pub inputTokens: i32,
+    pub outputTokens: i32,
+    // What the backend reported for the prompt, if anything, and the
+    // runner's own count; `inputTokens` falls back to the latter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backendInputTokens: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub localInputTokens: Option<i32>,
+    // Set when `inputTokens` is the runner's count
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inputTokensEstimated: bool,
+    // The runner's `max_tokens` cap, when it lowered what the request asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maxTokensCap: Option<i32>,
+    // Time spent generating, from the request reaching the backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub durationMs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeToFirstTokenMs: Option<u64>,
+    // Generation speed after the first token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokensPerSecond: Option<f64>,
+}
+
+// What a backend generated for a chat request
+#[derive(Debug, Default)]
+pub struct Reply {
+    pub content: String,
+    pub usage: Usage,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+// A piece of a streamed reply
+pub enum Chunk {
+    Text(String),
+    ToolCalls(Vec<ToolCall>),
+}
+
+// Receives the reply as it is generated when streaming
+pub type ChunkSink<'a> = Option<&'a (dyn Fn(Chunk) + Send + Sync)>;
@@ -0,0 +1,450 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::handshake::client::Response;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+use crate::client_tls;
+use crate::config::ProxyConfig;
+use crate::local_socket;
+
+// Mirrors `[proxy]` for everything that opens connections, the way
+// `privacy` mirrors `[privacy]`
+static CONFIG: RwLock<ProxyConfig> = RwLock::new(ProxyConfig {
+    url: None,
+    no_proxy: Vec::new(),
+});
+
+// Longest proxy response or request head read
+const MAX_HEAD: usize = 16 * 1024;
+
+// Shared by every HTTP call, so connections are reused. Rebuilt after the
+// settings it was built from change.
+static CLIENT: RwLock<Option<reqwest::Client>> = RwLock::new(None);
+
+// Model lists, health checks and sign-in give up after these
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// For generations, which `request_timeout_secs` cuts short, and model
+// downloads, which can be cancelled
+pub const LONG_REQUEST_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub fn set(config: &ProxyConfig) {
+    if let Ok(mut current) = CONFIG.write() {
+        *current = config.clone();
+    }
+    reset_client();
+}
+
+// The proxy to reach `target` through, from `[proxy]` or else the
+// environment, or None to connect directly
+pub fn for_url(target: &Url) -> Option<Url> {
+    let host = target.host_str()?;
+    let config = CONFIG.read().map(|c| c.clone()).unwrap_or_default();
+    let (proxy, mut no_proxy) = match config.url {
+        Some(url) => (url, config.no_proxy),
+        None => (env_proxy(target.scheme())?, config.no_proxy),
+    };
+    no_proxy.extend(
+        ["NO_PROXY", "no_proxy"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .flat_map(|list| list.split(',').map(|entry| entry.trim().to_string()).collect::<Vec<_>>()),
+    );
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let loopback = host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    if loopback || no_proxy.iter().any(|pattern| bypasses(pattern, host)) {
+        return None;
+    }
+    // `HTTPS_PROXY=proxy:3128` is common shorthand
+    let proxy = if proxy.contains("://") { proxy } else { format!("http://{}", proxy) };
+    Url::parse(&proxy).ok()
+}
+
+fn env_proxy(scheme: &str) -> Option<String> {
+    let names: &[&str] = match scheme {
+        "https" | "wss" => &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"],
+        _ => &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"],
+    };
+    names.iter().find_map(|name| std::env::var(name).ok().filter(|value| !value.trim().is_empty()))
+}
+
+fn bypasses(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return false;
+    }
+    if pattern == "*" {
+        return true;
+    }
+    if let (Ok(net), Ok(ip)) = (pattern.parse::<ipnet::IpNet>(), host.parse::<IpAddr>()) {
+        return net.contains(&ip);
+    }
+    let domain = pattern.trim_start_matches("*.").trim_start_matches('.');
+    host.eq_ignore_ascii_case(domain)
+        || host.to_ascii_lowercase().ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+}
+
+// A TCP connection to `host:port`, tunneled through `proxy`
+pub async fn connect(proxy: &Url, host: &str, port: u16) -> io::Result<TcpStream> {
+    let proxy_host = proxy.host_str().ok_or_else(|| io::Error::other("Proxy URL has no host"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match proxy.scheme() {
+        "socks5" | "socks5h" => {
+            let mut stream = TcpStream::connect((proxy_host, proxy.port().unwrap_or(1080))).await?;
+            socks5_handshake(&mut stream, proxy, host, port).await?;
+            Ok(stream)
+        }
+        _ => {
+            let mut stream = TcpStream::connect((proxy_host, proxy.port_or_known_default().unwrap_or(8080))).await?;
+            http_connect(&mut stream, proxy, host, port).await?;
+            Ok(stream)
+        }
+    }
+}
+
+fn credentials(proxy: &Url) -> Option<(String, String)> {
+    if proxy.username().is_empty() {
+        return None;
+    }
+    let decode = |value: &str| percent_encoding::percent_decode_str(value).decode_utf8_lossy().into_owned();
+    Some((decode(proxy.username()), decode(proxy.password().unwrap_or_default())))
+}
+
+async fn http_connect(stream: &mut TcpStream, proxy: &Url, host: &str, port: u16) -> io::Result<()> {
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((user, password)) = credentials(proxy) {
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            STANDARD.encode(format!("{}:{}", user, password))
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    let head = read_head(stream).await?;
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::other(format!("Proxy refused the tunnel: {}", status.trim())));
+    }
+    Ok(())
+}
+
+// RFC 1928, with RFC 1929 username/password auth
+async fn socks5_handshake(stream: &mut TcpStream, proxy: &Url, host: &str, port: u16) -> io::Result<()> {
+    let credentials = credentials(proxy);
+    let greeting: &[u8] = if credentials.is_some() { &[5, 2, 0, 2] } else { &[5, 1, 0] };
+    stream.write_all(greeting).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    match (choice[1], &credentials) {
+        (0, _) => {}
+        (2, Some((user, password))) => {
+            if user.len() > 255 || password.len() > 255 {
+                return Err(io::Error::other("SOCKS credentials are too long"));
+            }
+            let mut auth = vec![1, user.len() as u8];
+            auth.extend_from_slice(user.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(io::Error::other("SOCKS proxy rejected the credentials"));
+            }
+        }
+        _ => return Err(io::Error::other("SOCKS proxy accepts none of our auth methods")),
+    }
+
+    let mut request = vec![5, 1, 0];
+    let address = match host.parse::<IpAddr>() {
+        Ok(ip) => Some(ip),
+        // socks5:// resolves names here, socks5h:// leaves them to the proxy
+        Err(_) if proxy.scheme() == "socks5" => tokio::net::lookup_host((host, port)).await?.next().map(|a| a.ip()),
+        Err(_) => None,
+    };
+    match address {
+        Some(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Some(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        None => {
+            if host.len() > 255 {
+                return Err(io::Error::other("Host name too long for SOCKS"));
+            }
+            request.push(3);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        let reason = match reply[1] {
+            2 => "not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            _ => "general failure",
+        };
+        return Err(io::Error::other(format!("SOCKS proxy could not connect to {}: {}", host, reason)));
+    }
+    // The bound address, which isn't needed
+    let skip = match reply[3] {
+        1 => 4,
+        4 => 16,
+        _ => stream.read_u8().await? as usize,
+    };
+    let mut bound = vec![0u8; skip + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+// Up to and including the blank line, a byte at a time so nothing after it
+// is consumed
+async fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD {
+            return Err(io::Error::other("Proxy response head too long"));
+        }
+        head.push(stream.read_u8().await?);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+// `connect_async`, through the proxy where one applies and trusting the
+// configured CA certificates
+pub async fn connect_websocket(
+    relay_url: &str,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), tokio_tungstenite::tungstenite::Error> {
+    let target = Url::parse(relay_url).ok();
+    let route = target.as_ref().and_then(|target| {
+        let proxy = for_url(target)?;
+        Some((proxy, target.host_str()?.to_string(), target.port_or_known_default()?))
+    });
+    let connector = client_tls::websocket_connector();
+    match route {
+        None => tokio_tungstenite::connect_async_tls_with_config(relay_url, None, false, connector).await,
+        Some((proxy, host, port)) => {
+            let stream = connect(&proxy, &host, port).await?;
+            tokio_tungstenite::client_async_tls_with_config(relay_url, stream, None, connector).await
+        }
+    }
+}
+
+// A reqwest client that goes through the proxy where one applies. reqwest
+// speaks HTTP proxies itself; SOCKS proxies, and Ollama on a local socket,
+// are reached through a loopback HTTP proxy of our own.
+pub fn http_client() -> reqwest::Client {
+    if let Some(client) = CLIENT.read().ok().and_then(|client| client.clone()) {
+        return client;
+    }
+    let client = build_client();
+    if let Ok(mut cached) = CLIENT.write() {
+        *cached = Some(client.clone());
+    }
+    client
+}
+
+// Makes the next `http_client` build a client with the current settings
+pub fn reset_client() {
+    if let Ok(mut client) = CLIENT.write() {
+        *client = None;
+    }
+}
+
+fn build_client() -> reqwest::Client {
+    client_tls::apply(reqwest::Client::builder())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .proxy(reqwest::Proxy::custom(|target| {
+            if local_socket::socket_for(target).is_some() {
+                return bridge_url();
+            }
+            let proxy = for_url(target)?;
+            if proxy.scheme() == "http" {
+                Some(proxy)
+            } else {
+                bridge_url()
+            }
+        }))
+        .build()
+        .unwrap_or_default()
+}
+
+struct Bridge {
+    port: u16,
+    secret: String,
+}
+
+static BRIDGE: OnceLock<Option<Bridge>> = OnceLock::new();
+
+fn bridge_url() -> Option<Url> {
+    let bridge = BRIDGE
+        .get_or_init(|| match start_bridge() {
+            Ok(bridge) => Some(bridge),
+            Err(e) => {
+                tracing::error!("Could not start the SOCKS bridge: {}", e);
+                None
+            }
+        })
+        .as_ref()?;
+    Url::parse(&format!("http://bridge:{}@127.0.0.1:{}", bridge.secret, bridge.port)).ok()
+}
+
+// On a thread of its own, so it outlives whichever runtime first needed it
+fn start_bridge() -> io::Result<Bridge> {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    listener.set_nonblocking(true)?;
+    let port = listener.local_addr()?.port();
+    let secret: String = rand::thread_rng().gen::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let authorization = format!("Basic {}", STANDARD.encode(format!("bridge:{}", secret)));
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let Ok(listener) = tokio::net::TcpListener::from_std(listener) else {
+                return;
+            };
+            while let Ok((client, _)) = listener.accept().await {
+                let authorization = authorization.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = bridge(client, &authorization).await {
+                        tracing::warn!("SOCKS bridge: {}", e);
+                    }
+                });
+            }
+        });
+    });
+    Ok(Bridge { port, secret })
+}
+
+// One connection from reqwest: a CONNECT tunnel for https, or a request in
+// absolute form for plain http, which is passed on in origin form. Requests
+// for a local socket's made-up host go to the socket.
+async fn bridge(mut client: TcpStream, authorization: &str) -> io::Result<()> {
+    let head = read_head(&mut client).await?;
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, target, version) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("HTTP/1.1"),
+    );
+    let mut headers: Vec<&str> = lines.filter(|line| !line.is_empty()).collect();
+    let authorized = headers.iter().any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.eq_ignore_ascii_case("proxy-authorization") && value.trim() == authorization
+        })
+    });
+    if !authorized {
+        client.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await?;
+        return Ok(());
+    }
+    headers.retain(|line| {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        !name.eq_ignore_ascii_case("proxy-authorization") && !name.eq_ignore_ascii_case("proxy-connection")
+    });
+
+    let (target, tunnel) = if method.eq_ignore_ascii_case("CONNECT") {
+        (Url::parse(&format!("https://{}", target)), true)
+    } else {
+        (Url::parse(target), false)
+    };
+    let target = target.map_err(|e| io::Error::other(format!("Bad request target: {}", e)))?;
+    let host = target.host_str().unwrap_or_default().to_string();
+    let port = target.port_or_known_default().unwrap_or(80);
+    let upstream = match (local_socket::socket_for(&target), for_url(&target)) {
+        (Some(socket), _) => local_socket::connect(&socket).await,
+        (None, Some(proxy)) => connect(&proxy, &host, port).await.map(|stream| Box::new(stream) as Box<dyn local_socket::Stream>),
+        (None, None) => TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port))
+            .await
+            .map(|stream| Box::new(stream) as Box<dyn local_socket::Stream>),
+    };
+    let mut upstream = match upstream {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await;
+            return Err(e);
+        }
+    };
+
+    if tunnel {
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await?;
+    } else {
+        let path = &target[url::Position::BeforePath..url::Position::AfterQuery];
+        let mut forwarded = format!("{} {} {}\r\n", method, path, version);
+        for header in headers {
+            forwarded.push_str(header);
+            forwarded.push_str("\r\n");
+        }
+        forwarded.push_str("\r\n");
+        upstream.write_all(forwarded.as_bytes()).await?;
+    }
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bypasses_domains_and_subdomains() {
+        for pattern in ["example.com", ".example.com", "*.example.com", " Example.COM "] {
+            assert!(bypasses(pattern, "example.com"), "{} for example.com", pattern);
+            assert!(bypasses(pattern, "api.example.com"), "{} for api.example.com", pattern);
+            assert!(bypasses(pattern, "API.Example.Com"), "{} for API.Example.Com", pattern);
+            assert!(!bypasses(pattern, "notexample.com"), "{} for notexample.com", pattern);
+            assert!(!bypasses(pattern, "example.com.evil.net"), "{} for example.com.evil.net", pattern);
+        }
+    }
+
+    #[test]
+    fn bypasses_everything_or_nothing() {
+        assert!(bypasses("*", "anything.net"));
+        assert!(bypasses("*", "10.0.0.1"));
+        assert!(!bypasses("", "example.com"));
+        assert!(!bypasses("   ", "example.com"));
+    }
+
+    #[test]
+    fn bypasses_networks() {
+        assert!(bypasses("10.0.0.0/8", "10.1.2.3"));
+        assert!(!bypasses("10.0.0.0/8", "11.0.0.1"));
+        assert!(bypasses("192.168.1.5/32", "192.168.1.5"));
+        assert!(bypasses("fd00::/8", "fd12::1"));
+        assert!(!bypasses("fd00::/8", "2001:db8::1"));
+        // A network doesn't match hostnames
+        assert!(!bypasses("10.0.0.0/8", "ten.example"));
+    }
+
+    #[test]
+    fn bypasses_exact_addresses() {
+        assert!(bypasses("192.168.1.5", "192.168.1.5"));
+        assert!(!bypasses("192.168.1.5", "192.168.1.50"));
+    }
+}
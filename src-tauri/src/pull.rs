@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::BackendKind;
+use crate::connection::refresh_models;
+use crate::events::EventSink;
+use crate::ollama::{self, PullProgress};
+use crate::state::RunnerState;
+
+// How often a request waiting on someone else's pull checks on it
+const PULL_WAIT_POLL: Duration = Duration::from_secs(1);
+
+// Extras for a pull started on behalf of a chat request
+#[derive(Default)]
+pub struct PullOptions<'a> {
+    // Stop once the layers seen so far add up to more than this
+    pub max_bytes: Option<u64>,
+    pub on_progress: Option<&'a (dyn Fn(&PullProgress) + Send + Sync)>,
+}
+
+// Downloads a model into the Ollama backend, emitting `model-pull-progress`
+// events along the way. A pull stops when `RunnerState::cancel_pull` is
+// called for the model; pulling a model that is already being pulled fails.
+// The model list is refreshed afterwards, so the relay learns about it.
+pub async fn run(
+    events: &dyn EventSink,
+    state: &RunnerState,
+    model: &str,
+    options: PullOptions<'_>,
+) -> Result<(), String> {
+    let config = state.config();
+    if config.backend.kind != BackendKind::Ollama {
+        return Err("Models can only be pulled into an Ollama backend".to_string());
+    }
+    let mut cancelled = state.track_pull(model).ok_or_else(|| format!("{} is already being pulled", model))?;
+
+    let layers: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+    let on_progress = |progress: PullProgress| {
+        if let (Some(limit), Some(digest), Some(total)) = (options.max_bytes, &progress.digest, progress.total) {
+            let size: u64 = layers
+                .lock()
+                .map(|mut layers| {
+                    layers.insert(digest.clone(), total);
+                    layers.values().sum()
+                })
+                .unwrap_or_default();
+            if size > limit {
+                return Err(format!("{} is larger than the {} MB auto-pull limit", model, limit / 1_000_000));
+            }
+        }
+        if let Some(watch) = options.on_progress {
+            watch(&progress);
+        }
+        let mut payload = serde_json::json!(progress);
+        payload["model"] = model.into();
+        events.emit("model-pull-progress", payload);
+        Ok(())
+    };
+    let result = tokio::select! {
+        result = ollama::pull(&config.backend.url, model, &on_progress) => result,
+        _ = &mut cancelled => Err("cancelled".to_string()),
+    };
+    state.finish_pull(model);
+
+    match &result {
+        Ok(()) => {
+            tracing::info!(model = %model, success = true, "Pulled {}", model);
+            let _ = refresh_models(events, state).await;
+        }
+        Err(e) => {
+            events.emit("model-pull-progress", serde_json::json!({
+                "model": model,
+                "status": if e == "cancelled" { "cancelled" } else { "error" },
+                "error": e
+            }));
+            tracing::error!(model = %model, "Pull of {} failed: {}", model, e);
+        }
+    }
+    result
+}
+
+// Makes sure `model` is installed before a request is served: pulls it, or
+// waits for a pull that is already under way
+pub async fn ensure(
+    events: &dyn EventSink,
+    state: &RunnerState,
+    model: &str,
+    options: PullOptions<'_>,
+) -> Result<(), String> {
+    if state.is_pulling(model) {
+        while state.is_pulling(model) {
+            tokio::time::sleep(PULL_WAIT_POLL).await;
+        }
+        if state.models().iter().any(|m| m == model) {
+            return Ok(());
+        }
+    }
+    run(events, state, model, options).await
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::RateLimitConfig;
+use crate::error::RunnerError;
+
+// Requesters tracked before those with full buckets are forgotten
+const PRUNE_ABOVE: usize = 256;
+
+// Token buckets for `RateLimitConfig`. A request takes one from the request
+// buckets when it is let in; its tokens come off the token buckets once it
+// is answered, which can leave them below zero until they refill.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    global: Option<Limits>,
+    requesters: HashMap<String, Limits>,
+}
+
+struct Limits {
+    requests: Bucket,
+    tokens: Bucket,
+}
+
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+// A cap of `capacity` per `period` seconds; 0 is no cap
+#[derive(Clone, Copy)]
+struct Cap {
+    capacity: f64,
+    period: f64,
+}
+
+impl Cap {
+    fn per_minute(capacity: u32) -> Self {
+        Self { capacity: capacity as f64, period: 60.0 }
+    }
+
+    fn per_hour(capacity: u64) -> Self {
+        Self { capacity: capacity as f64, period: 3600.0 }
+    }
+
+    fn off(&self) -> bool {
+        self.capacity <= 0.0
+    }
+}
+
+impl Bucket {
+    fn full(cap: Cap, now: Instant) -> Self {
+        Self { level: cap.capacity, updated: now }
+    }
+
+    fn refill(&mut self, cap: Cap, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.level = (self.level + elapsed * cap.capacity / cap.period).min(cap.capacity);
+        self.updated = now;
+    }
+
+    fn take(&mut self, cap: Cap, amount: f64) {
+        if !cap.off() {
+            self.level -= amount;
+        }
+    }
+
+    // Seconds until at least one is left
+    fn wait(&self, cap: Cap) -> f64 {
+        if cap.off() || self.level >= 1.0 {
+            0.0
+        } else {
+            (1.0 - self.level) * cap.period / cap.capacity
+        }
+    }
+}
+
+impl Limits {
+    fn new(caps: (Cap, Cap), now: Instant) -> Self {
+        Self {
+            requests: Bucket::full(caps.0, now),
+            tokens: Bucket::full(caps.1, now),
+        }
+    }
+
+    fn refill(&mut self, caps: (Cap, Cap), now: Instant) {
+        self.requests.refill(caps.0, now);
+        self.tokens.refill(caps.1, now);
+    }
+
+    fn wait(&self, caps: (Cap, Cap)) -> f64 {
+        self.requests.wait(caps.0).max(self.tokens.wait(caps.1))
+    }
+
+    fn is_full(&self, caps: (Cap, Cap)) -> bool {
+        self.requests.level >= caps.0.capacity && self.tokens.level >= caps.1.capacity
+    }
+}
+
+fn requester_caps(config: &RateLimitConfig) -> (Cap, Cap) {
+    (Cap::per_minute(config.requests_per_minute), Cap::per_hour(config.tokens_per_hour))
+}
+
+fn global_caps(config: &RateLimitConfig) -> (Cap, Cap) {
+    (Cap::per_minute(config.global_requests_per_minute), Cap::per_hour(config.global_tokens_per_hour))
+}
+
+impl RateLimiter {
+    // Lets a request in, or says how long until it would be. Requests
+    // without a requester only count against the global caps.
+    pub fn admit(&self, config: &RateLimitConfig, requester: Option<&str>) -> Result<(), RunnerError> {
+        let (global_caps, requester_caps) = (global_caps(config), requester_caps(config));
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if buckets.requesters.len() > PRUNE_ABOVE {
+            buckets.requesters.retain(|_, limits| {
+                limits.refill(requester_caps, now);
+                !limits.is_full(requester_caps)
+            });
+        }
+        let Buckets { global, requesters } = &mut *buckets;
+        let global = global.get_or_insert_with(|| Limits::new(global_caps, now));
+        global.refill(global_caps, now);
+        let mut wait = global.wait(global_caps);
+        let mut own = requester.map(|id| {
+            requesters
+                .entry(id.to_string())
+                .or_insert_with(|| Limits::new(requester_caps, now))
+        });
+        if let Some(limits) = own.as_mut() {
+            limits.refill(requester_caps, now);
+            wait = wait.max(limits.wait(requester_caps));
+        }
+        if wait > 0.0 {
+            return Err(RunnerError::RateLimited {
+                retry_after_secs: wait.ceil() as u64,
+            });
+        }
+        global.requests.take(global_caps.0, 1.0);
+        if let Some(limits) = own {
+            limits.requests.take(requester_caps.0, 1.0);
+        }
+        Ok(())
+    }
+
+    // Takes an answered request's tokens off its buckets
+    pub fn charge(&self, config: &RateLimitConfig, requester: Option<&str>, tokens: u64) {
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return;
+        };
+        let (global_caps, requester_caps) = (global_caps(config), requester_caps(config));
+        let now = Instant::now();
+        let Buckets { global, requesters } = &mut *buckets;
+        if let Some(global) = global {
+            global.refill(global_caps, now);
+            global.tokens.take(global_caps.1, tokens as f64);
+        }
+        if let Some(limits) = requester.and_then(|id| requesters.get_mut(id)) {
+            limits.refill(requester_caps, now);
+            limits.tokens.take(requester_caps.1, tokens as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry_after(result: Result<(), RunnerError>) -> Option<u64> {
+        match result {
+            Ok(()) => None,
+            Err(RunnerError::RateLimited { retry_after_secs }) => Some(retry_after_secs),
+            Err(e) => panic!("unexpected {}", e),
+        }
+    }
+
+    #[test]
+    fn no_caps_by_default() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig::default();
+        for _ in 0..1000 {
+            assert!(limiter.admit(&config, Some("a")).is_ok());
+        }
+        limiter.charge(&config, Some("a"), 1_000_000);
+        assert!(limiter.admit(&config, Some("a")).is_ok());
+    }
+
+    #[test]
+    fn requests_per_requester() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig { requests_per_minute: 2, ..Default::default() };
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), None);
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), None);
+        // One request comes back every 30s
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), Some(30));
+        assert_eq!(retry_after(limiter.admit(&config, Some("b"))), None);
+        // Without a requester only the global caps apply
+        assert_eq!(retry_after(limiter.admit(&config, None)), None);
+    }
+
+    #[test]
+    fn global_requests() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig { global_requests_per_minute: 1, ..Default::default() };
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), None);
+        assert_eq!(retry_after(limiter.admit(&config, Some("b"))), Some(60));
+        assert_eq!(retry_after(limiter.admit(&config, None)), Some(60));
+    }
+
+    #[test]
+    fn tokens_charged_after_the_answer() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig { tokens_per_hour: 100, ..Default::default() };
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), None);
+        // Over the cap, which leaves the bucket below zero
+        limiter.charge(&config, Some("a"), 150);
+        let wait = retry_after(limiter.admit(&config, Some("a"))).unwrap();
+        // 51 tokens at 100 an hour
+        assert!((1835..=1836).contains(&wait), "waited {}", wait);
+        assert_eq!(retry_after(limiter.admit(&config, Some("b"))), None);
+    }
+
+    #[test]
+    fn global_tokens() {
+        let limiter = RateLimiter::default();
+        let config = RateLimitConfig { global_tokens_per_hour: 3600, ..Default::default() };
+        assert_eq!(retry_after(limiter.admit(&config, Some("a"))), None);
+        limiter.charge(&config, Some("a"), 3600);
+        assert_eq!(retry_after(limiter.admit(&config, Some("b"))), Some(1));
+    }
+}
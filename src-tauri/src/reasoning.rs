@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+// What to do with the `<think>...</think>` blocks reasoning models such as
+// deepseek-r1 write before their answer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningMode {
+    // Left in the content as the model wrote it
+    #[default]
+    Passthrough,
+    // Dropped
+    Strip,
+    // Moved to the response's `reasoning` field
+    Separate,
+}
+
+const OPEN: &str = "<think>";
+const CLOSE: &str = "</think>";
+
+// Splits model output into answer and reasoning as it streams in. A tag cut
+// in two by chunk boundaries is held back until the next piece shows what
+// it is.
+#[derive(Default)]
+pub struct Splitter {
+    inside: bool,
+    pending: String,
+    // Whitespace is trimmed from the start of each part
+    answer_started: bool,
+    reasoning_started: bool,
+}
+
+#[derive(Default)]
+pub struct Split {
+    pub answer: String,
+    pub reasoning: String,
+}
+
+impl Splitter {
+    pub fn push(&mut self, piece: &str) -> Split {
+        let mut text = std::mem::take(&mut self.pending);
+        text.push_str(piece);
+        let mut split = Split::default();
+        loop {
+            let tag = if self.inside { CLOSE } else { OPEN };
+            match text.find(tag) {
+                Some(at) => {
+                    self.emit(&text[..at], &mut split);
+                    self.inside = !self.inside;
+                    text.drain(..at + tag.len());
+                }
+                None => {
+                    // The longest end of `text` that could start the tag
+                    let keep = (1..tag.len()).rev().find(|len| text.ends_with(&tag[..*len])).unwrap_or(0);
+                    let cut = text.len() - keep;
+                    self.emit(&text[..cut], &mut split);
+                    self.pending = text[cut..].to_string();
+                    return split;
+                }
+            }
+        }
+    }
+
+    // What was held back, once the output is complete
+    pub fn finish(&mut self) -> Split {
+        let pending = std::mem::take(&mut self.pending);
+        let mut split = Split::default();
+        self.emit(&pending, &mut split);
+        split
+    }
+
+    fn emit(&mut self, text: &str, split: &mut Split) {
+        let (out, started) = if self.inside {
+            (&mut split.reasoning, &mut self.reasoning_started)
+        } else {
+            (&mut split.answer, &mut self.answer_started)
+        };
+        let text = if *started { text } else { text.trim_start() };
+        if !text.is_empty() {
+            *started = true;
+            out.push_str(text);
+        }
+    }
+}
+
+// Splits a complete reply
+pub fn split(content: &str) -> Split {
+    let mut splitter = Splitter::default();
+    let mut split = splitter.push(content);
+    let rest = splitter.finish();
+    split.answer.push_str(&rest.answer);
+    split.reasoning.push_str(&rest.reasoning);
+    split.answer.truncate(split.answer.trim_end().len());
+    split.reasoning.truncate(split.reasoning.trim_end().len());
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The whole output fed through a splitter in `pieces`
+    fn stream(pieces: &[&str]) -> (String, String) {
+        let mut splitter = Splitter::default();
+        let (mut answer, mut reasoning) = (String::new(), String::new());
+        for piece in pieces {
+            let split = splitter.push(piece);
+            answer.push_str(&split.answer);
+            reasoning.push_str(&split.reasoning);
+        }
+        let rest = splitter.finish();
+        answer.push_str(&rest.answer);
+        reasoning.push_str(&rest.reasoning);
+        (answer, reasoning)
+    }
+
+    #[test]
+    fn no_reasoning() {
+        assert_eq!(stream(&["Hello", " world"]), ("Hello world".to_string(), String::new()));
+    }
+
+    #[test]
+    fn reasoning_then_answer() {
+        let expected = ("The answer".to_string(), "Let me think".to_string());
+        assert_eq!(stream(&["<think>Let me think</think>\n\nThe answer"]), expected);
+        assert_eq!(stream(&["<think>", "Let me ", "think", "</think>", "\n\nThe ", "answer"]), expected);
+    }
+
+    #[test]
+    fn tags_split_across_pieces() {
+        let expected = ("42".to_string(), "hmm".to_string());
+        assert_eq!(stream(&["<th", "ink>hmm</", "think>42"]), expected);
+        assert_eq!(stream(&["<", "t", "h", "i", "n", "k", ">", "hmm", "<", "/think", ">", "42"]), expected);
+    }
+
+    #[test]
+    fn held_back_text_that_is_not_a_tag() {
+        assert_eq!(stream(&["a <thi", "s is fine"]), ("a <this is fine".to_string(), String::new()));
+        // Released by `finish` when the output ends on it
+        assert_eq!(stream(&["x <thin"]), ("x <thin".to_string(), String::new()));
+    }
+
+    #[test]
+    fn pieces_hold_back_a_possible_tag() {
+        let mut splitter = Splitter::default();
+        assert_eq!(splitter.push("abc<thi").answer, "abc");
+        let split = splitter.push("nk>r");
+        assert_eq!((split.answer.as_str(), split.reasoning.as_str()), ("", "r"));
+    }
+
+    #[test]
+    fn unclosed_reasoning() {
+        assert_eq!(stream(&["<think>still going"]), (String::new(), "still going".to_string()));
+    }
+
+    #[test]
+    fn split_trims_both_parts() {
+        let split = split("  <think> why \n</think>\n because  \n");
+        assert_eq!(split.answer, "because");
+        assert_eq!(split.reasoning, "why");
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tokio::sync::oneshot;
+
+use crate::schedule;
+use crate::events::EventSink;
+use crate::state::RunnerState;
+use crate::connection::{run_connection, ConnectionOutcome};
+
+// Keeps the app's relay connection up until it is cancelled: dropped
+// connections are retried with jittered exponential backoff, moving on to
+// the fallback relays when one keeps failing. Rejected tokens are not
+// retried.
+pub async fn run(
+    token: String,
+    events: Arc<dyn EventSink>,
+    state: Arc<RunnerState>,
+    mut cancel_rx: oneshot::Receiver<()>,
+) {
+    let mut attempt: u32 = 0;
+    state.reset_relay();
+
+    loop {
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let started = Instant::now();
+        let connection = run_connection(token.clone(), events.clone(), state.clone(), stop_rx);
+        tokio::pin!(connection);
+
+        let outcome = tokio::select! {
+            outcome = &mut connection => outcome,
+            _ = &mut cancel_rx => {
+                let _ = stop_tx.send(());
+                let _ = connection.await;
+                return;
+            }
+        };
+
+        let (error, unreachable) = match outcome {
+            ConnectionOutcome::Cancelled | ConnectionOutcome::AuthFailed(_) => return,
+            // Not a failure; connect again once the schedule opens
+            ConnectionOutcome::Scheduled => {
+                tokio::select! {
+                    _ = schedule::wait_until_open(&state) => {}
+                    _ = &mut cancel_rx => {
+                        state.set_connection_status("disconnected");
+                        events.emit("connection-status", serde_json::json!({
+                            "status": "disconnected"
+                        }));
+                        return;
+                    }
+                }
+                attempt = 0;
+                continue;
+            }
+            ConnectionOutcome::Closed => ("Connection closed".to_string(), false),
+            ConnectionOutcome::ConnectFailed(e) => (e, true),
+            ConnectionOutcome::SocketError(e) => (e, false),
+        };
+
+        let policy = state.config().reconnect;
+        if started.elapsed() >= Duration::from_secs(policy.stable_after_secs) {
+            attempt = 0;
+            state.relay_recovered();
+        }
+        attempt += 1;
+        if let Some(relay) = state.relay_failed(unreachable) {
+            tracing::info!(relay = %relay, "Switching to relay {}", relay);
+        }
+
+        if policy.max_retries.is_some_and(|max| attempt > max) {
+            let error = format!("Giving up after {} reconnect attempts: {}", attempt - 1, error);
+            state.set_connection_status("error");
+            events.emit("connection-status", serde_json::json!({
+                "status": "error",
+                "error": error
+            }));
+            return;
+        }
+
+        let delay = backoff(policy.min_delay_ms, policy.max_delay_ms, attempt);
+        state.prometheus().record_reconnect();
+        state.set_connection_status("reconnecting");
+        events.emit("connection-status", serde_json::json!({
+            "status": "reconnecting",
+            "attempt": attempt,
+            "delayMs": delay.as_millis() as u64,
+            "error": error,
+            "relay": state.relay_url()
+        }));
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut cancel_rx => {
+                state.set_connection_status("disconnected");
+                events.emit("connection-status", serde_json::json!({
+                    "status": "disconnected"
+                }));
+                return;
+            }
+        }
+    }
+}
+
+// Doubles from `min_delay_ms` up to `max_delay_ms`, then picks a random
+// point in the upper half so runners dropped together don't reconnect in
+// lockstep. Backend retries are spaced the same way.
+pub fn backoff(min_delay_ms: u64, max_delay_ms: u64, attempt: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+    let ceiling = min_delay_ms.saturating_mul(factor).min(max_delay_ms).max(1);
+    Duration::from_millis(rand::thread_rng().gen_range(ceiling / 2..=ceiling))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        for (attempt, ceiling) in [(1, 500), (2, 1000), (3, 2000), (4, 4000), (5, 5000), (40, 5000)] {
+            let delay = backoff(500, 5000, attempt).as_millis() as u64;
+            assert!((ceiling / 2..=ceiling).contains(&delay), "attempt {}: {}ms", attempt, delay);
+        }
+    }
+}
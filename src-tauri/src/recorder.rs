@@ -0,0 +1,93 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::RecordingConfig;
+use crate::privacy::{self, REDACTED};
+
+#[derive(Clone, Copy)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+}
+
+// Appends relay frames to a JSON lines file:
+// {"ts": <unix ms>, "direction": "in" | "out", "frame": <message>}
+pub struct Recorder {
+    file: Mutex<File>,
+    include_content: bool,
+}
+
+impl Recorder {
+    pub fn open(config: &RecordingConfig) -> std::io::Result<Option<Self>> {
+        let path = match (&config.path, config.enabled) {
+            (Some(path), true) => path,
+            _ => return Ok(None),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Some(Self {
+            file: Mutex::new(file),
+            include_content: config.include_content,
+        }))
+    }
+
+    pub fn record(&self, direction: Direction, frame: &str) {
+        let mut frame: serde_json::Value =
+            serde_json::from_str(frame).unwrap_or_else(|_| serde_json::Value::String(frame.to_string()));
+        self.redact(&mut frame);
+
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let line = serde_json::json!({
+            "ts": ts,
+            "direction": direction.as_str(),
+            "frame": frame,
+        });
+
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    // The token is always removed; prompt and response text only when
+    // content recording is off, which privacy mode forces.
+    fn redact(&self, frame: &mut serde_json::Value) {
+        if frame.get("token").is_some() {
+            frame["token"] = REDACTED.into();
+        }
+        if !self.include_content || privacy::enabled() {
+            privacy::redact(frame);
+        }
+    }
+}
+
+// Inbound frames of a recorded session, in order
+pub fn read_inbound(path: &Path) -> Result<Vec<serde_json::Value>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut frames = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e))?;
+        if entry["direction"] == "in" {
+            frames.push(entry["frame"].clone());
+        }
+    }
+    Ok(frames)
+}
@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use crate::config::RetryConfig;
+use crate::error::RunnerError;
+use crate::reconnect;
+
+// Failures that may pass on another try: the backend couldn't be reached or
+// failed on its own side
+pub fn is_transient(error: &RunnerError) -> bool {
+    match error {
+        RunnerError::OllamaUnreachable(_) => true,
+        RunnerError::OllamaHttp { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
+
+// How long to wait before trying again, spaced out like reconnects
+pub fn backoff(config: &RetryConfig, attempt: u32) -> Duration {
+    reconnect::backoff(config.min_delay_ms, config.max_delay_ms, attempt)
+}
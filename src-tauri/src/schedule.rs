@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::config::{ScheduleAction, ScheduleConfig, ScheduleWindow};
+use crate::events::EventSink;
+use crate::state::RunnerState;
+
+// How often the schedule is checked, connected or waiting to connect
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Whether `now` (local time) falls in the schedule, or there is none
+pub fn is_open(config: &ScheduleConfig, now: NaiveDateTime) -> bool {
+    if !config.enabled || config.windows.is_empty() {
+        return true;
+    }
+    config.windows.iter().any(|window| contains(window, now.weekday(), now.time()))
+}
+
+pub fn is_open_now(config: &ScheduleConfig) -> bool {
+    is_open(config, chrono::Local::now().naive_local())
+}
+
+fn contains(window: &ScheduleWindow, day: Weekday, time: NaiveTime) -> bool {
+    // Checked when the config was read
+    let parse = |value: &str| NaiveTime::parse_from_str(value, "%H:%M").unwrap_or_default();
+    let (start, end) = (parse(&window.start), parse(&window.end));
+    let on = |day: Weekday| window.days.is_empty() || window.days.iter().any(|d| d.parse::<Weekday>().ok() == Some(day));
+    if start < end {
+        on(day) && time >= start && time < end
+    } else {
+        (on(day) && time >= start) || (on(day.pred()) && time < end)
+    }
+}
+
+// Called from the connection loop every `CHECK_INTERVAL`. With the pause
+// action the runner goes offline outside the schedule and back online inside
+// it, emitting `schedule-status`; returns true when the connection should
+// close instead.
+pub fn check(events: &dyn EventSink, state: &RunnerState) -> bool {
+    let config = state.config().schedule;
+    let open = is_open_now(&config);
+    if !open && config.action == ScheduleAction::Disconnect {
+        tracing::info!("Outside the schedule; disconnecting until it opens");
+        state.set_schedule_hold(false);
+        return true;
+    }
+    if state.set_schedule_hold(!open) {
+        if open {
+            tracing::info!("Schedule opened; back online");
+        } else {
+            tracing::info!("Outside the schedule; going offline");
+        }
+        events.emit("schedule-status", serde_json::json!({ "open": open }));
+    }
+    false
+}
+
+// Waits for the schedule to open, for a runner disconnected by it
+pub async fn wait_until_open(state: &RunnerState) {
+    while !is_open_now(&state.config().schedule) {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
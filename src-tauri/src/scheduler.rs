@@ -0,0 +1,310 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::config::RelayConfig;
+use crate::error::RunnerError;
+use crate::protocol::Load;
+
+// Weight of the newest request in the tokens/sec average
+const THROUGHPUT_WEIGHT: f64 = 0.2;
+
+// Hands out generation slots to relay requests, within the global and
+// per-model concurrency limits, highest priority first. Also counts those
+// waiting and running plus a moving average of generation speed, for the
+// load the runner reports to the relay.
+#[derive(Default)]
+pub struct Scheduler {
+    slots: Mutex<Slots>,
+    // Woken whenever a slot frees up or the queue changes
+    changed: Notify,
+    tokens_per_sec: Mutex<Option<f64>>,
+}
+
+// Queue position: higher priority first, then first come first served
+type QueueKey = (Reverse<i32>, u64);
+
+#[derive(Default)]
+struct Slots {
+    active: usize,
+    // Generations running, by model
+    running: HashMap<String, usize>,
+    queue: BTreeMap<QueueKey, Waiting>,
+    next_seq: u64,
+}
+
+struct Waiting {
+    model: String,
+    limits: Limits,
+}
+
+impl Slots {
+    fn fits(&self, model: &str, limits: &Limits) -> bool {
+        self.active < limits.global
+            && limits.model.is_none_or(|limit| self.running.get(model).copied().unwrap_or(0) < limit)
+    }
+
+    // Nothing queued ahead of `key` could start now. A request held back by
+    // its model's limit doesn't hold up requests for other models.
+    fn first_in_line(&self, key: &QueueKey) -> bool {
+        !self.queue.range(..key).any(|(_, waiting)| self.fits(&waiting.model, &waiting.limits))
+    }
+
+    fn take(&mut self, model: &str) {
+        self.active += 1;
+        *self.running.entry(model.to_string()).or_default() += 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub global: usize,
+    pub model: Option<usize>,
+    // Requests waiting for a slot before more are refused as busy
+    pub queued: usize,
+}
+
+impl Limits {
+    pub fn for_model(config: &RelayConfig, model: &str) -> Self {
+        Self {
+            global: config.max_concurrent_requests.max(1),
+            model: config.model_concurrency.get(model).map(|limit| (*limit).max(1)),
+            queued: config.max_queued_requests,
+        }
+    }
+}
+
+// Held while a request is queued, then while it runs; dropping it (request
+// done, cancelled or aborted) takes it off the counts.
+pub struct Ticket<'a> {
+    scheduler: &'a Scheduler,
+    model: String,
+    // Set while waiting for a slot
+    queued: Option<QueueKey>,
+}
+
+impl Scheduler {
+    // Starts right away when a slot is free and nothing queued ahead could
+    // take it; otherwise joins the queue, or fails with `RunnerError::Busy`
+    // when the queue is full
+    pub fn enqueue(&self, model: &str, limits: Limits, priority: i32) -> Result<Ticket<'_>, RunnerError> {
+        let Ok(mut slots) = self.slots.lock() else {
+            return Err(RunnerError::Busy);
+        };
+        let key = (Reverse(priority), slots.next_seq);
+        slots.next_seq += 1;
+        // Made only once the request is in: dropping a ticket gives back a
+        // slot, and takes the lock held here
+        let ticket = |queued| Ticket {
+            scheduler: self,
+            model: model.to_string(),
+            queued,
+        };
+        if slots.fits(model, &limits) && slots.first_in_line(&key) {
+            slots.take(model);
+            return Ok(ticket(None));
+        }
+        if slots.queue.len() >= limits.queued {
+            return Err(RunnerError::Busy);
+        }
+        slots.queue.insert(key, Waiting { model: model.to_string(), limits });
+        Ok(ticket(Some(key)))
+    }
+
+    pub fn record_throughput(&self, output_tokens: u64, busy: Duration) {
+        if output_tokens == 0 || busy.is_zero() {
+            return;
+        }
+        let rate = output_tokens as f64 / busy.as_secs_f64();
+        if let Ok(mut average) = self.tokens_per_sec.lock() {
+            *average = Some(match *average {
+                Some(previous) => previous + THROUGHPUT_WEIGHT * (rate - previous),
+                None => rate,
+            });
+        }
+    }
+
+    pub fn load(&self) -> Load {
+        let average = self.tokens_per_sec.lock().ok().and_then(|average| *average);
+        let (queue_depth, active, by_priority) = match self.slots.lock() {
+            Ok(slots) => {
+                let mut by_priority = BTreeMap::new();
+                for (Reverse(priority), _) in slots.queue.keys() {
+                    *by_priority.entry(*priority).or_default() += 1;
+                }
+                (slots.queue.len(), slots.active, by_priority)
+            }
+            Err(_) => (0, 0, BTreeMap::new()),
+        };
+        Load {
+            queueDepth: queue_depth,
+            activeRequests: active,
+            avgTokensPerSec: (average.unwrap_or(0.0) * 10.0).round() / 10.0,
+            queuedByPriority: by_priority,
+        }
+    }
+}
+
+impl Ticket<'_> {
+    // Whether it had to wait for a slot
+    pub fn is_queued(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    // Waits for a slot
+    pub async fn start(&mut self) {
+        while let Some(key) = self.queued {
+            let changed = self.scheduler.changed.notified();
+            tokio::pin!(changed);
+            // Registered before checking, so a change in between isn't missed
+            changed.as_mut().enable();
+            if let Ok(mut slots) = self.scheduler.slots.lock() {
+                let waiting = slots.queue.get(&key).map(|waiting| waiting.limits);
+                if waiting.is_some_and(|limits| slots.fits(&self.model, &limits) && slots.first_in_line(&key)) {
+                    slots.queue.remove(&key);
+                    slots.take(&self.model);
+                    self.queued = None;
+                }
+            }
+            if self.queued.is_none() {
+                // Those behind may fit in what is left
+                self.scheduler.changed.notify_waiters();
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut slots) = self.scheduler.slots.lock() {
+            match self.queued {
+                Some(key) => {
+                    slots.queue.remove(&key);
+                }
+                None => {
+                    slots.active -= 1;
+                    if let Some(count) = slots.running.get_mut(&self.model) {
+                        *count -= 1;
+                        if *count == 0 {
+                            slots.running.remove(&self.model);
+                        }
+                    }
+                }
+            }
+        }
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn limits(global: usize, model: Option<usize>, queued: usize) -> Limits {
+        Limits { global, model, queued }
+    }
+
+    // Whether `ticket` gets a slot without waiting for another to free up
+    async fn starts(ticket: &mut Ticket<'_>) -> bool {
+        tokio::time::timeout(Duration::from_millis(50), ticket.start()).await.is_ok()
+    }
+
+    #[test]
+    fn starts_while_slots_are_free() {
+        let scheduler = Scheduler::default();
+        let first = scheduler.enqueue("m", limits(2, None, 0), 0).unwrap();
+        let second = scheduler.enqueue("m", limits(2, None, 0), 0).unwrap();
+        assert!(!first.is_queued() && !second.is_queued());
+        assert_eq!(scheduler.load().activeRequests, 2);
+        drop(first);
+        assert_eq!(scheduler.load().activeRequests, 1);
+    }
+
+    #[test]
+    fn busy_when_the_queue_is_full() {
+        let scheduler = Scheduler::default();
+        let limits = limits(1, None, 1);
+        let _running = scheduler.enqueue("m", limits, 0).unwrap();
+        let queued = scheduler.enqueue("m", limits, 0).unwrap();
+        assert!(queued.is_queued());
+        assert!(matches!(scheduler.enqueue("m", limits, 0), Err(RunnerError::Busy)));
+        // Busy requests never count
+        let load = scheduler.load();
+        assert_eq!((load.activeRequests, load.queueDepth), (1, 1));
+        drop(queued);
+        assert_eq!(scheduler.load().queueDepth, 0);
+        assert!(scheduler.enqueue("m", limits, 0).unwrap().is_queued());
+    }
+
+    #[tokio::test]
+    async fn queued_request_starts_when_a_slot_frees() {
+        let scheduler = Scheduler::default();
+        let limits = limits(1, None, 4);
+        let running = scheduler.enqueue("m", limits, 0).unwrap();
+        let mut queued = scheduler.enqueue("m", limits, 0).unwrap();
+        assert!(!starts(&mut queued).await);
+        drop(running);
+        assert!(starts(&mut queued).await);
+        let load = scheduler.load();
+        assert_eq!((load.activeRequests, load.queueDepth), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn higher_priority_first() {
+        let scheduler = Scheduler::default();
+        let limits = limits(1, None, 4);
+        let running = scheduler.enqueue("m", limits, 0).unwrap();
+        let mut low = scheduler.enqueue("m", limits, 0).unwrap();
+        let mut high = scheduler.enqueue("m", limits, 5).unwrap();
+        assert_eq!(scheduler.load().queuedByPriority, BTreeMap::from([(0, 1), (5, 1)]));
+        drop(running);
+        assert!(!starts(&mut low).await);
+        assert!(starts(&mut high).await);
+        drop(high);
+        assert!(starts(&mut low).await);
+    }
+
+    #[test]
+    fn arrivals_wait_behind_the_queue() {
+        let scheduler = Scheduler::default();
+        let limits = limits(1, None, 4);
+        let running = scheduler.enqueue("m", limits, 0).unwrap();
+        let _queued = scheduler.enqueue("m", limits, 0).unwrap();
+        drop(running);
+        // A slot is free, but the request queued first gets it
+        assert!(scheduler.enqueue("m", limits, 0).unwrap().is_queued());
+    }
+
+    #[tokio::test]
+    async fn model_limit_only_holds_up_that_model() {
+        let scheduler = Scheduler::default();
+        let one_per_model = limits(3, Some(1), 4);
+        let _a = scheduler.enqueue("a", one_per_model, 0).unwrap();
+        let mut second_a = scheduler.enqueue("a", one_per_model, 0).unwrap();
+        assert!(second_a.is_queued());
+        let b = scheduler.enqueue("b", one_per_model, 0).unwrap();
+        assert!(!b.is_queued());
+        assert!(!starts(&mut second_a).await);
+    }
+
+    #[test]
+    fn throughput_average() {
+        let scheduler = Scheduler::default();
+        assert_eq!(scheduler.load().avgTokensPerSec, 0.0);
+        scheduler.record_throughput(100, Duration::from_secs(10));
+        assert_eq!(scheduler.load().avgTokensPerSec, 10.0);
+        scheduler.record_throughput(200, Duration::from_secs(10));
+        assert_eq!(scheduler.load().avgTokensPerSec, 12.0);
+        // Nothing generated, or no time spent, leaves it alone
+        scheduler.record_throughput(0, Duration::from_secs(10));
+        scheduler.record_throughput(10, Duration::ZERO);
+        assert_eq!(scheduler.load().avgTokensPerSec, 12.0);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::error::RunnerError;
+
+const SERVICE: &str = "bottlecap-runner";
+const KEY_INFO: &[u8] = b"bottlecap-runner secret store v1";
+
+// Secrets such as runner tokens, by name ("token", "token:<account>"). They
+// go to the OS keyring; where it fails, e.g. Linux without a Secret Service,
+// to `secrets.json`, encrypted with AES-256-GCM under a key derived from
+// this machine's id.
+pub fn get(name: &str) -> Result<Option<String>, RunnerError> {
+    match keyring::Entry::new(SERVICE, name).and_then(|entry| entry.get_password()) {
+        Ok(secret) => Ok(Some(secret)),
+        // Saved to the file while the keyring was unavailable
+        Err(keyring::Error::NoEntry) => Ok(file_get(name).unwrap_or(None)),
+        Err(e) => {
+            tracing::debug!("Keyring unavailable, reading {} from the encrypted file: {}", name, e);
+            file_get(name).map_err(|file_error| RunnerError::KeyringError(format!("{}; {}", e, file_error)))
+        }
+    }
+}
+
+pub fn set(name: &str, secret: &str) -> Result<(), RunnerError> {
+    match keyring::Entry::new(SERVICE, name).and_then(|entry| entry.set_password(secret)) {
+        Ok(()) => {
+            // A copy left from when the keyring failed would go stale
+            let _ = file_delete(name);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::warn!("Keyring unavailable ({}); saving {} to the encrypted file instead", e, name);
+            file_set(name, secret).map_err(|file_error| RunnerError::KeyringError(format!("{}; {}", e, file_error)))
+        }
+    }
+}
+
+pub fn delete(name: &str) -> Result<(), RunnerError> {
+    let keyring = match keyring::Entry::new(SERVICE, name).and_then(|entry| entry.delete_password()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e),
+    };
+    let file = file_delete(name);
+    match (keyring, file) {
+        // Either store may be the one in use
+        (Err(e), Err(file_error)) => Err(RunnerError::KeyringError(format!("{}; {}", e, file_error))),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SecretFile {
+    // HKDF salt, base64
+    salt: String,
+    entries: BTreeMap<String, Sealed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+fn path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("bottlecap-runner").join("secrets.json"))
+}
+
+fn read_file() -> Result<SecretFile, String> {
+    let path = path().ok_or("No config directory for secrets")?;
+    match fs::read_to_string(&path) {
+        Ok(source) => serde_json::from_str(&source).map_err(|e| format!("Failed to read {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SecretFile::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn write_file(file: &SecretFile) -> Result<(), String> {
+    let path = path().ok_or("No config directory for secrets")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(file).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&path, fs::Permissions::from_mode(0o600));
+    }
+    Ok(())
+}
+
+fn file_get(name: &str) -> Result<Option<String>, String> {
+    let file = read_file()?;
+    let Some(sealed) = file.entries.get(name) else {
+        return Ok(None);
+    };
+    let cipher = cipher(&file.salt)?;
+    let nonce = STANDARD.decode(&sealed.nonce).map_err(|e| e.to_string())?;
+    let ciphertext = STANDARD.decode(&sealed.ciphertext).map_err(|e| e.to_string())?;
+    if nonce.len() != 12 {
+        return Err("Corrupt secrets file".to_string());
+    }
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        // Most likely written on another machine
+        .map_err(|_| "Could not decrypt the secrets file on this machine".to_string())?;
+    String::from_utf8(plaintext).map(Some).map_err(|e| e.to_string())
+}
+
+fn file_set(name: &str, secret: &str) -> Result<(), String> {
+    let mut file = read_file()?;
+    if file.salt.is_empty() {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        file.salt = STANDARD.encode(salt);
+    }
+    let cipher = cipher(&file.salt)?;
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+        .map_err(|_| "Encryption failed".to_string())?;
+    file.entries.insert(
+        name.to_string(),
+        Sealed {
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        },
+    );
+    write_file(&file)
+}
+
+fn file_delete(name: &str) -> Result<(), String> {
+    let mut file = read_file()?;
+    if file.entries.remove(name).is_some() {
+        write_file(&file)?;
+    }
+    Ok(())
+}
+
+fn cipher(salt: &str) -> Result<Aes256Gcm, String> {
+    let salt = STANDARD.decode(salt).map_err(|e| e.to_string())?;
+    let hkdf = hkdf::Hkdf::<Sha256>::new(Some(&salt), machine_secret().as_bytes());
+    let mut key = [0u8; 32];
+    hkdf.expand(KEY_INFO, &mut key).map_err(|e| e.to_string())?;
+    Aes256Gcm::new_from_slice(&key).map_err(|e| e.to_string())
+}
+
+// The machine id plus the user, so the file is useless copied elsewhere.
+// This keeps tokens out of plain sight, not away from the user's own
+// processes, which can derive the same key.
+fn machine_secret() -> String {
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+    format!("{}:{}", machine_id().unwrap_or_default(), user)
+}
+
+#[cfg(target_os = "linux")]
+fn machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .or_else(hostname_id)
+}
+
+// IOPlatformUUID from `ioreg -rd1 -c IOPlatformExpertDevice`
+#[cfg(target_os = "macos")]
+fn machine_id() -> Option<String> {
+    let output = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .find(|line| line.contains("IOPlatformUUID"))
+        .and_then(|line| line.split('"').nth(3))
+        .map(str::to_string)
+        .or_else(hostname_id)
+}
+
+// MachineGuid under HKLM\SOFTWARE\Microsoft\Cryptography
+#[cfg(windows)]
+fn machine_id() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    let output = std::process::Command::new("reg")
+        .args(["query", r"HKLM\SOFTWARE\Microsoft\Cryptography", "/v", "MachineGuid"])
+        // CREATE_NO_WINDOW
+        .creation_flags(0x0800_0000)
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .find(|line| line.contains("MachineGuid"))
+        .and_then(|line| line.split_whitespace().last())
+        .map(str::to_string)
+        .or_else(hostname_id)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn machine_id() -> Option<String> {
+    hostname_id()
+}
+
+fn hostname_id() -> Option<String> {
+    hostname::get().ok().and_then(|name| name.into_string().ok())
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client_tls;
+use crate::config::{
+    self, BackendKind, Config, E2eConfig, HealthConfig, KeepAliveConfig, LimitsConfig, LogLevel, ModelFilterConfig, PolicyConfig,
+    ProxyConfig, RateLimitConfig, RetryConfig, ScheduleConfig, SigningConfig, SshConfig, SystemPromptConfig,
+};
+use crate::reasoning::ReasoningMode;
+
+// Settings changed from the app and kept across launches. Headless runners
+// use `config.toml` instead. Unset fields keep the built-in defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    pub relay_url: Option<String>,
+    pub relay_fallback_urls: Option<Vec<String>>,
+    pub ollama_url: Option<String>,
+    // What serves `ollama_url`; Ollama unless a discovered LM Studio server
+    // was picked
+    pub backend_kind: Option<BackendKind>,
+    pub ollama_endpoints: Option<Vec<String>>,
+    pub max_concurrent_requests: Option<usize>,
+    pub model_concurrency: Option<HashMap<String, usize>>,
+    pub max_queued_requests: Option<usize>,
+    pub relay_compression: Option<bool>,
+    pub request_timeout_secs: Option<u64>,
+    pub model_filter: Option<ModelFilterConfig>,
+    pub auto_connect: Option<bool>,
+    // Keep the window hidden when launched at login
+    pub start_minimized: Option<bool>,
+    pub auto_pull: Option<bool>,
+    pub auto_pull_max_gb: Option<f64>,
+    pub keep_alive: Option<KeepAliveConfig>,
+    pub retry: Option<RetryConfig>,
+    pub reasoning: Option<ReasoningMode>,
+    // Read at launch
+    pub prometheus_addr: Option<SocketAddr>,
+    // Serves the local OpenAI-compatible API there; read at launch
+    pub api_addr: Option<SocketAddr>,
+    pub log_level: Option<LogLevel>,
+    pub privacy_mode: Option<bool>,
+    pub prevent_sleep: Option<bool>,
+    pub pause_on_battery: Option<bool>,
+    // 0 turns the threshold off
+    pub min_charge_percent: Option<u8>,
+    pub schedule: Option<ScheduleConfig>,
+    pub proxy: Option<ProxyConfig>,
+    // Root certificates for the relay and HTTPS backends, as PEM files
+    pub ca_certs: Option<Vec<PathBuf>>,
+    pub tls_insecure: Option<bool>,
+    pub e2e: Option<E2eConfig>,
+    // Takes effect on the next connection
+    pub signing: Option<SigningConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub limits: Option<LimitsConfig>,
+    pub policy: Option<PolicyConfig>,
+    pub system_prompt: Option<SystemPromptConfig>,
+    pub health: Option<HealthConfig>,
+    // The tunnel is opened at launch when enabled
+    pub ssh: Option<SshConfig>,
+    pub notifications: Option<NotificationSettings>,
+}
+
+// Desktop notifications the app shows, each on its own switch
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NotificationSettings {
+    // The relay connection dropped after being up
+    pub connection_lost: bool,
+    pub auth_failed: bool,
+    // The first request served after `idle_minutes` without one (or since launch)
+    pub first_request: bool,
+    pub idle_minutes: u64,
+    pub backend_unreachable: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            connection_lost: true,
+            auth_failed: true,
+            first_request: false,
+            idle_minutes: 30,
+            backend_unreachable: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn apply(&self, mut config: Config) -> Config {
+        if let Some(url) = &self.relay_url {
+            config.relay.url = url.clone();
+        }
+        if let Some(urls) = &self.relay_fallback_urls {
+            config.relay.fallback_urls = urls.clone();
+        }
+        if let Some(url) = &self.ollama_url {
+            config.backend.url = url.clone();
+        }
+        if let Some(kind) = self.backend_kind {
+            config.backend.kind = kind;
+        }
+        if let Some(urls) = &self.ollama_endpoints {
+            config.backend.endpoints = urls.clone();
+        }
+        if let Some(max) = self.max_concurrent_requests {
+            config.relay.max_concurrent_requests = max;
+        }
+        if let Some(limits) = &self.model_concurrency {
+            config.relay.model_concurrency = limits.clone();
+        }
+        if let Some(max) = self.max_queued_requests {
+            config.relay.max_queued_requests = max;
+        }
+        if let Some(enabled) = self.relay_compression {
+            config.relay.compression = enabled;
+        }
+        if let Some(secs) = self.request_timeout_secs {
+            config.backend.request_timeout_secs = secs;
+        }
+        if let Some(filter) = &self.model_filter {
+            config.models = filter.clone();
+        }
+        if let Some(enabled) = self.auto_pull {
+            config.backend.auto_pull = enabled;
+        }
+        if let Some(max) = self.auto_pull_max_gb {
+            config.backend.auto_pull_max_gb = max;
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            config.backend.keep_alive = keep_alive.clone();
+        }
+        if let Some(retry) = &self.retry {
+            config.backend.retry = retry.clone();
+        }
+        if let Some(mode) = self.reasoning {
+            config.backend.reasoning = mode;
+        }
+        if let Some(addr) = self.prometheus_addr {
+            config.metrics.prometheus_addr = Some(addr);
+        }
+        if let Some(addr) = self.api_addr {
+            config.api.enabled = true;
+            config.api.addr = addr;
+        }
+        if let Some(level) = self.log_level {
+            config.logging.level = level;
+        }
+        if let Some(enabled) = self.privacy_mode {
+            config.privacy.enabled = enabled;
+        }
+        if let Some(enabled) = self.prevent_sleep {
+            config.power.prevent_sleep = enabled;
+        }
+        if let Some(enabled) = self.pause_on_battery {
+            config.battery.pause_on_battery = enabled;
+        }
+        if let Some(percent) = self.min_charge_percent {
+            config.battery.min_charge_percent = (percent > 0).then_some(percent);
+        }
+        if let Some(schedule) = &self.schedule {
+            config.schedule = schedule.clone();
+        }
+        if let Some(proxy) = &self.proxy {
+            config.proxy = proxy.clone();
+        }
+        if let Some(e2e) = &self.e2e {
+            config.e2e = e2e.clone();
+        }
+        if let Some(signing) = &self.signing {
+            config.signing = signing.clone();
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            config.rate_limit = rate_limit.clone();
+        }
+        if let Some(limits) = &self.limits {
+            config.limits = limits.clone();
+        }
+        if let Some(policy) = &self.policy {
+            config.policy = policy.clone();
+        }
+        if let Some(system_prompt) = &self.system_prompt {
+            config.system_prompt = system_prompt.clone();
+        }
+        if let Some(health) = &self.health {
+            config.health = health.clone();
+        }
+        if let Some(ssh) = &self.ssh {
+            config.ssh = ssh.clone();
+        }
+        if let Some(paths) = &self.ca_certs {
+            config.tls.ca_certs = paths.clone();
+        }
+        if let Some(insecure) = self.tls_insecure {
+            config.tls.insecure = insecure;
+        }
+        config
+    }
+
+    // Fields set in `update` replace ours
+    pub fn merge(&mut self, update: Settings) {
+        self.relay_url = update.relay_url.or(self.relay_url.take());
+        self.relay_fallback_urls = update.relay_fallback_urls.or(self.relay_fallback_urls.take());
+        self.ollama_url = update.ollama_url.or(self.ollama_url.take());
+        self.backend_kind = update.backend_kind.or(self.backend_kind);
+        self.ollama_endpoints = update.ollama_endpoints.or(self.ollama_endpoints.take());
+        self.max_concurrent_requests = update.max_concurrent_requests.or(self.max_concurrent_requests);
+        self.model_concurrency = update.model_concurrency.or(self.model_concurrency.take());
+        self.max_queued_requests = update.max_queued_requests.or(self.max_queued_requests);
+        self.relay_compression = update.relay_compression.or(self.relay_compression);
+        self.request_timeout_secs = update.request_timeout_secs.or(self.request_timeout_secs);
+        self.model_filter = update.model_filter.or(self.model_filter.take());
+        self.auto_connect = update.auto_connect.or(self.auto_connect);
+        self.start_minimized = update.start_minimized.or(self.start_minimized);
+        self.auto_pull = update.auto_pull.or(self.auto_pull);
+        self.auto_pull_max_gb = update.auto_pull_max_gb.or(self.auto_pull_max_gb);
+        self.keep_alive = update.keep_alive.or(self.keep_alive.take());
+        self.retry = update.retry.or(self.retry.take());
+        self.reasoning = update.reasoning.or(self.reasoning);
+        self.prometheus_addr = update.prometheus_addr.or(self.prometheus_addr);
+        self.api_addr = update.api_addr.or(self.api_addr);
+        self.log_level = update.log_level.or(self.log_level);
+        self.privacy_mode = update.privacy_mode.or(self.privacy_mode);
+        self.prevent_sleep = update.prevent_sleep.or(self.prevent_sleep);
+        self.pause_on_battery = update.pause_on_battery.or(self.pause_on_battery);
+        self.min_charge_percent = update.min_charge_percent.or(self.min_charge_percent);
+        self.schedule = update.schedule.or(self.schedule.take());
+        self.proxy = update.proxy.or(self.proxy.take());
+        self.e2e = update.e2e.or(self.e2e.take());
+        self.signing = update.signing.or(self.signing.take());
+        self.rate_limit = update.rate_limit.or(self.rate_limit.take());
+        self.limits = update.limits.or(self.limits.take());
+        self.policy = update.policy.or(self.policy.take());
+        self.system_prompt = update.system_prompt.or(self.system_prompt.take());
+        self.health = update.health.or(self.health.take());
+        self.ssh = update.ssh.or(self.ssh.take());
+        self.ca_certs = update.ca_certs.or(self.ca_certs.take());
+        self.tls_insecure = update.tls_insecure.or(self.tls_insecure);
+        self.notifications = update.notifications.or(self.notifications.take());
+    }
+
+    // Normalizes values coming from the frontend, rejecting unusable ones
+    pub fn validated(mut self) -> Result<Settings, String> {
+        self.relay_url = self.relay_url.map(|url| config::relay_url(&url)).transpose()?;
+        self.relay_fallback_urls = self
+            .relay_fallback_urls
+            .map(|urls| urls.iter().map(|url| config::relay_url(url)).collect())
+            .transpose()?;
+        self.ollama_url = self.ollama_url.map(|url| config::ollama_url(&url)).transpose()?;
+        self.ollama_endpoints = self
+            .ollama_endpoints
+            .map(|urls| urls.iter().map(|url| config::ollama_url(url)).collect())
+            .transpose()?;
+        if self.max_concurrent_requests == Some(0) {
+            return Err("maxConcurrentRequests must be at least 1".to_string());
+        }
+        if self.model_concurrency.as_ref().is_some_and(|limits| limits.values().any(|limit| *limit == 0)) {
+            return Err("modelConcurrency limits must be at least 1".to_string());
+        }
+        if self.request_timeout_secs == Some(0) {
+            return Err("requestTimeoutSecs must be at least 1".to_string());
+        }
+        if self.auto_pull_max_gb.is_some_and(|max| max.is_nan() || max <= 0.0) {
+            return Err("autoPullMaxGb must be greater than 0".to_string());
+        }
+        if self.min_charge_percent.is_some_and(|percent| percent > 100) {
+            return Err("minChargePercent must be between 0 and 100".to_string());
+        }
+        self.keep_alive = self.keep_alive.map(KeepAliveConfig::validated).transpose()?;
+        self.proxy = self.proxy.map(ProxyConfig::validated).transpose()?;
+        self.signing = self.signing.map(SigningConfig::validated).transpose()?;
+        for path in self.ca_certs.iter().flatten() {
+            client_tls::read_certificates(path)?;
+        }
+        Ok(self)
+    }
+
+    // Every field filled in, from `config` where not saved
+    pub fn effective(&self, config: &Config) -> Settings {
+        Settings {
+            relay_url: Some(config.relay.url.clone()),
+            relay_fallback_urls: Some(config.relay.fallback_urls.clone()),
+            ollama_url: Some(config.backend.url.clone()),
+            backend_kind: Some(config.backend.kind),
+            ollama_endpoints: Some(config.backend.endpoints.clone()),
+            max_concurrent_requests: Some(config.relay.max_concurrent_requests),
+            model_concurrency: Some(config.relay.model_concurrency.clone()),
+            max_queued_requests: Some(config.relay.max_queued_requests),
+            relay_compression: Some(config.relay.compression),
+            request_timeout_secs: Some(config.backend.request_timeout_secs),
+            model_filter: Some(config.models.clone()),
+            auto_connect: Some(self.auto_connect.unwrap_or(false)),
+            start_minimized: Some(self.start_minimized.unwrap_or(false)),
+            auto_pull: Some(config.backend.auto_pull),
+            auto_pull_max_gb: Some(config.backend.auto_pull_max_gb),
+            keep_alive: Some(config.backend.keep_alive.clone()),
+            retry: Some(config.backend.retry.clone()),
+            reasoning: Some(config.backend.reasoning),
+            prometheus_addr: config.metrics.prometheus_addr,
+            api_addr: config.api.enabled.then_some(config.api.addr),
+            log_level: Some(config.logging.level),
+            privacy_mode: Some(config.privacy.enabled),
+            prevent_sleep: Some(config.power.prevent_sleep),
+            pause_on_battery: Some(config.battery.pause_on_battery),
+            min_charge_percent: Some(config.battery.min_charge_percent.unwrap_or(0)),
+            schedule: Some(config.schedule.clone()),
+            proxy: Some(config.proxy.clone()),
+            ca_certs: Some(config.tls.ca_certs.clone()),
+            tls_insecure: Some(config.tls.insecure),
+            e2e: Some(config.e2e.clone()),
+            signing: Some(config.signing.clone()),
+            rate_limit: Some(config.rate_limit.clone()),
+            limits: Some(config.limits.clone()),
+            policy: Some(config.policy.clone()),
+            system_prompt: Some(config.system_prompt.clone()),
+            health: Some(config.health.clone()),
+            ssh: Some(config.ssh.clone()),
+            notifications: Some(self.notifications.clone().unwrap_or_default()),
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("bottlecap-runner").join("settings.json"))
+}
+
+// A missing or unreadable file means nothing has been changed yet
+pub fn load() -> Settings {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|source| serde_json::from_str(&source).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(settings: &Settings) -> Result<(), String> {
+    let path = path().ok_or("No config directory for settings")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde_json::{Map, Value};
+
+use crate::config::SigningConfig;
+use crate::secret_store;
+
+// Signed frames carry `ts` (unix ms), `nonce` and `sig`: an Ed25519
+// signature over the frame without `sig`, written with sorted keys and no
+// whitespace. That form doesn't depend on key order or on the frame having
+// travelled as JSON or MessagePack.
+
+// Secret store entry with the device key, as base64 PKCS#8
+const KEY_NAME: &str = "device_key";
+
+static DEVICE_KEY: OnceLock<Ed25519KeyPair> = OnceLock::new();
+// Held while the key is loaded or created, so two callers can't each save one
+static LOADING: Mutex<()> = Mutex::new(());
+// Nonces of accepted requests with their `ts`, kept for `max_age_secs`
+static SEEN: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+fn device_key() -> Result<&'static Ed25519KeyPair, String> {
+    if let Some(key) = DEVICE_KEY.get() {
+        return Ok(key);
+    }
+    let _loading = LOADING.lock().map_err(|e| e.to_string())?;
+    if let Some(key) = DEVICE_KEY.get() {
+        return Ok(key);
+    }
+    let pkcs8 = match secret_store::get(KEY_NAME).map_err(String::from)? {
+        Some(saved) => STANDARD.decode(saved.trim()).map_err(|e| format!("The saved device key is invalid: {}", e))?,
+        None => {
+            let document = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                .map_err(|_| "Could not generate a device key".to_string())?;
+            secret_store::set(KEY_NAME, &STANDARD.encode(document.as_ref())).map_err(String::from)?;
+            tracing::info!("Created a device signing key");
+            document.as_ref().to_vec()
+        }
+    };
+    let key = Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(|e| format!("The saved device key is invalid: {}", e))?;
+    Ok(DEVICE_KEY.get_or_init(|| key))
+}
+
+// The device's public key, base64, created on first use
+pub fn public_key() -> Result<String, String> {
+    device_key().map(|key| STANDARD.encode(key.public_key().as_ref()))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+fn canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => canonical_object(fields, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn canonical_object(fields: &Map<String, Value>, out: &mut String) {
+    let mut keys: Vec<&String> = fields.keys().collect();
+    keys.sort();
+    out.push('{');
+    for (index, key) in keys.into_iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        out.push_str(&Value::String(key.clone()).to_string());
+        out.push(':');
+        canonical(&fields[key], out);
+    }
+    out.push('}');
+}
+
+// An outgoing frame with `ts`, `nonce` and `sig` added. Left unsigned, with
+// an error logged, when the device key is unavailable.
+pub fn sign(frame: Value) -> Value {
+    let Value::Object(mut fields) = frame else {
+        return frame;
+    };
+    let key = match device_key() {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("Sending a relay frame unsigned: {}", e);
+            return Value::Object(fields);
+        }
+    };
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    fields.insert("ts".to_string(), Value::from(now_ms()));
+    fields.insert("nonce".to_string(), Value::String(STANDARD.encode(nonce)));
+    let mut message = String::new();
+    canonical_object(&fields, &mut message);
+    let signature = key.sign(message.as_bytes());
+    fields.insert("sig".to_string(), Value::String(STANDARD.encode(signature.as_ref())));
+    Value::Object(fields)
+}
+
+pub enum Refusal {
+    Invalid(String),
+    // A nonce seen before. Not answered, as the request it first came with
+    // may still be running under the same requestId.
+    Replayed,
+}
+
+impl From<&str> for Refusal {
+    fn from(reason: &str) -> Self {
+        Refusal::Invalid(reason.to_string())
+    }
+}
+
+// Checks a request frame against `relay_key`: a valid signature, a `ts`
+// within `max_age_secs` of now and a nonce not used before. Anything passes
+// without a `relay_key`.
+pub fn verify(config: &SigningConfig, frame: &Value) -> Result<(), Refusal> {
+    let Some(relay_key) = &config.relay_key else {
+        return Ok(());
+    };
+    let relay_key = STANDARD.decode(relay_key).map_err(|e| Refusal::Invalid(format!("invalid relay_key: {}", e)))?;
+    let Value::Object(mut fields) = frame.clone() else {
+        return Err("not a JSON object".into());
+    };
+    let signature = fields
+        .remove("sig")
+        .and_then(|sig| sig.as_str().and_then(|sig| STANDARD.decode(sig).ok()))
+        .ok_or("not signed")?;
+    let mut message = String::new();
+    canonical_object(&fields, &mut message);
+    UnparsedPublicKey::new(&ED25519, &relay_key)
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| Refusal::from("invalid signature"))?;
+
+    let ts = fields.get("ts").and_then(Value::as_u64).ok_or("no ts")?;
+    let nonce = fields.get("nonce").and_then(Value::as_str).ok_or("no nonce")?;
+    let now = now_ms();
+    let max_age = config.max_age_secs.saturating_mul(1000);
+    if ts.abs_diff(now) > max_age {
+        let off = ts.abs_diff(now) / 1000;
+        return Err(Refusal::Invalid(format!("signed {}s away from this machine's clock", off)));
+    }
+    let mut seen = SEEN.lock().map_err(|e| Refusal::Invalid(e.to_string()))?;
+    let seen = seen.get_or_insert_with(HashMap::new);
+    // A nonce older than the window can't come back, as its ts is refused
+    seen.retain(|_, signed| signed.abs_diff(now) <= max_age);
+    if seen.insert(nonce.to_string(), ts).is_some() {
+        return Err(Refusal::Replayed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn canonical_text(value: Value) -> String {
+        let Value::Object(fields) = value else {
+            panic!("not an object");
+        };
+        let mut out = String::new();
+        canonical_object(&fields, &mut out);
+        out
+    }
+
+    #[test]
+    fn canonical_object_sorts_keys_at_every_level() {
+        assert_eq!(canonical_text(json!({})), "{}");
+        assert_eq!(
+            canonical_text(json!({"b": 1, "a": {"d": [true, null], "c": "x"}})),
+            r#"{"a":{"c":"x","d":[true,null]},"b":1}"#
+        );
+        assert_eq!(canonical_text(json!({"list": [{"z": 1, "y": 2}, []]})), r#"{"list":[{"y":2,"z":1},[]]}"#);
+    }
+
+    #[test]
+    fn canonical_object_escapes_like_json() {
+        assert_eq!(
+            canonical_text(json!({"k\"ey": "line\nbreak \u{1} \u{e9}", "n": -1.5})),
+            "{\"k\\\"ey\":\"line\\nbreak \\u0001 \u{e9}\",\"n\":-1.5}"
+        );
+    }
+
+    // A request signed with `key` the way the relay signs them
+    fn signed(key: &Ed25519KeyPair, ts: u64, nonce: &str) -> Value {
+        let mut fields = json!({"type": "chat_request", "requestId": "r1", "ts": ts, "nonce": nonce});
+        let mut message = String::new();
+        canonical_object(fields.as_object().unwrap(), &mut message);
+        fields["sig"] = json!(STANDARD.encode(key.sign(message.as_bytes()).as_ref()));
+        fields
+    }
+
+    fn relay_key() -> (Ed25519KeyPair, SigningConfig) {
+        let document = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap();
+        let config = SigningConfig {
+            relay_key: Some(STANDARD.encode(key.public_key().as_ref())),
+            max_age_secs: 60,
+            ..Default::default()
+        };
+        (key, config)
+    }
+
+    fn refusal(result: Result<(), Refusal>) -> String {
+        match result {
+            Ok(()) => "accepted".to_string(),
+            Err(Refusal::Replayed) => "replayed".to_string(),
+            Err(Refusal::Invalid(reason)) => reason,
+        }
+    }
+
+    #[test]
+    fn verify_accepts_a_signed_request_once() {
+        let (key, config) = relay_key();
+        let frame = signed(&key, now_ms(), "verify-once");
+        assert_eq!(refusal(verify(&config, &frame)), "accepted");
+        assert_eq!(refusal(verify(&config, &frame)), "replayed");
+    }
+
+    #[test]
+    fn verify_refuses_tampering_and_other_keys() {
+        let (key, config) = relay_key();
+        let mut frame = signed(&key, now_ms(), "verify-tampered");
+        frame["requestId"] = json!("r2");
+        assert_eq!(refusal(verify(&config, &frame)), "invalid signature");
+
+        let (other, _) = relay_key();
+        assert_eq!(refusal(verify(&config, &signed(&other, now_ms(), "verify-other"))), "invalid signature");
+
+        let mut unsigned = signed(&key, now_ms(), "verify-unsigned");
+        unsigned.as_object_mut().unwrap().remove("sig");
+        assert_eq!(refusal(verify(&config, &unsigned)), "not signed");
+    }
+
+    #[test]
+    fn verify_refuses_stale_requests() {
+        let (key, config) = relay_key();
+        let frame = signed(&key, now_ms() - 120_000, "verify-stale");
+        assert!(refusal(verify(&config, &frame)).contains("away from this machine's clock"));
+        let frame = signed(&key, now_ms() + 120_000, "verify-ahead");
+        assert!(refusal(verify(&config, &frame)).contains("away from this machine's clock"));
+    }
+
+    #[test]
+    fn verify_without_a_relay_key() {
+        let config = SigningConfig::default();
+        assert_eq!(refusal(verify(&config, &json!({"type": "chat_request"}))), "accepted");
+    }
+}
@@ -0,0 +1,207 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::config::SshConfig;
+use crate::events::EventSink;
+
+const READY_TIMEOUT: Duration = Duration::from_secs(15);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A tunnel that stays up this long resets the backoff
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+// Local port forward to a backend on a remote machine, run through the
+// system `ssh` client so keys, the agent, known_hosts and ~/.ssh/config all
+// work as they do on the command line. The forward is re-established when
+// ssh exits and torn down when the tunnel is dropped.
+pub struct SshTunnel {
+    local_url: String,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SshTunnel {
+    // Returns once the local end of the forward accepts connections
+    pub async fn start(config: &SshConfig, events: Arc<dyn EventSink>) -> Result<Self, String> {
+        let destination = match (&config.host, &config.user) {
+            (Some(host), Some(user)) => format!("{}@{}", user, host),
+            (Some(host), None) => host.clone(),
+            (None, _) => return Err("ssh.host is required for an SSH tunnel".to_string()),
+        };
+        let forward = format!("127.0.0.1:{}:{}", config.local_port, config.remote_addr);
+        let mut args = vec![
+            "-N".to_string(),
+            "-L".to_string(),
+            forward,
+            "-p".to_string(),
+            config.port.to_string(),
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            "ExitOnForwardFailure=yes".to_string(),
+            "-o".to_string(),
+            "ServerAliveInterval=15".to_string(),
+            "-o".to_string(),
+            "ServerAliveCountMax=3".to_string(),
+        ];
+        if let Some(identity) = &config.identity_file {
+            args.push("-i".to_string());
+            args.push(identity.display().to_string());
+        }
+        args.push(destination.clone());
+
+        let mut child = spawn(&args)?;
+        if let Err(e) = wait_ready(&mut child, config.local_port).await {
+            let _ = child.kill().await;
+            return Err(format!("SSH tunnel to {} failed: {}", destination, e));
+        }
+        log_stderr(&mut child, &destination);
+        events.emit("ssh-tunnel", serde_json::json!({
+            "status": "connected",
+            "host": destination,
+            "localPort": config.local_port,
+        }));
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(supervise(child, args, destination, config.local_port, events, shutdown_rx));
+
+        Ok(Self {
+            local_url: format!("http://127.0.0.1:{}", config.local_port),
+            shutdown: Some(shutdown_tx),
+            task: Some(task),
+        })
+    }
+
+    pub fn local_url(&self) -> &str {
+        &self.local_url
+    }
+
+    // Tears the tunnel down and waits for ssh to be gone, for when the
+    // process is about to be replaced and nothing would be left to kill it
+    pub async fn close(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+fn spawn(args: &[String]) -> Result<Child, String> {
+    Command::new("ssh")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run ssh: {}", e))
+}
+
+// Lost forwards, host key changes and the like, which ssh reports on stderr
+// while the tunnel runs. The pipe is read to its end, so ssh never blocks on
+// a full one.
+fn log_stderr(child: &mut Child, destination: &str) {
+    let Some(stderr) = child.stderr.take() else {
+        return;
+    };
+    let destination = destination.to_string();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::warn!("ssh {}: {}", destination, line);
+        }
+    });
+}
+
+async fn wait_ready(child: &mut Child, local_port: u16) -> Result<(), String> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            let stderr = stderr.trim();
+            return Err(if stderr.is_empty() { status.to_string() } else { stderr.to_string() });
+        }
+        if TcpStream::connect(("127.0.0.1", local_port)).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    Err("timed out waiting for the port forward".to_string())
+}
+
+async fn supervise(
+    mut child: Child,
+    args: Vec<String>,
+    destination: String,
+    local_port: u16,
+    events: Arc<dyn EventSink>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    let mut backoff = MIN_BACKOFF;
+    let mut started = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown_rx => {
+                let _ = child.kill().await;
+                events.emit("ssh-tunnel", serde_json::json!({ "status": "closed", "host": destination }));
+                return;
+            }
+            status = child.wait() => {
+                if started.elapsed() >= STABLE_AFTER {
+                    backoff = MIN_BACKOFF;
+                }
+                events.emit("ssh-tunnel", serde_json::json!({
+                    "status": "reconnecting",
+                    "host": destination,
+                    "error": status.map(|s| s.to_string()).unwrap_or_else(|e| e.to_string()),
+                    "delayMs": backoff.as_millis() as u64,
+                }));
+            }
+        }
+
+        tokio::select! {
+            _ = &mut shutdown_rx => return,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+
+        child = match spawn(&args) {
+            Ok(child) => child,
+            Err(e) => {
+                events.emit("ssh-tunnel", serde_json::json!({ "status": "error", "host": destination, "error": e }));
+                return;
+            }
+        };
+        started = Instant::now();
+        let ready = wait_ready(&mut child, local_port).await;
+        log_stderr(&mut child, &destination);
+        if ready.is_ok() {
+            events.emit("ssh-tunnel", serde_json::json!({
+                "status": "connected",
+                "host": destination,
+                "localPort": local_port,
+            }));
+        }
+    }
+}
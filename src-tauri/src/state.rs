@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{oneshot, watch};
+
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::{backend_auth, client_tls, e2e, privacy, prometheus, proxy, token};
+use crate::protocol::LoadedModel;
+use crate::dedup::RecentRequests;
+use crate::outbox::Outbox;
+use crate::rate_limit::RateLimiter;
+use crate::scheduler::Scheduler;
+
+// Days of usage history kept for `get_stats`
+const DAYS_KEPT: usize = 30;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DayUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // Time spent waiting on the backend
+    pub busy: Duration,
+}
+
+// Runner state shared between the connection loop and anything that reports
+// on or controls it (admin endpoint, tray, commands).
+pub struct RunnerState {
+    config: RwLock<Config>,
+    paused: AtomicBool,
+    // Offline under the battery policy or the schedule; separate from
+    // `paused`, which is only changed by hand
+    battery_hold: AtomicBool,
+    schedule_hold: AtomicBool,
+    // The circuit breaker: open after `[health] failure_threshold` failed
+    // backend checks in a row, until one succeeds
+    backend_down: AtomicBool,
+    backend_failures: AtomicU32,
+    // Bumped when the status advertised to the relay (paused, model list)
+    // changes, so connections can send it again
+    status_changed: watch::Sender<()>,
+    connection_status: Mutex<String>,
+    started_at: Instant,
+    requests_served: AtomicU64,
+    requests_failed: AtomicU64,
+    last_served: Mutex<Option<Instant>>,
+    // Keyed by local date (YYYY-MM-DD)
+    daily_usage: Mutex<BTreeMap<String, DayUsage>>,
+    metrics: Metrics,
+    prometheus: prometheus::Registry,
+    // Cancel signals for relay requests being served, by requestId. The
+    // signal carries why, for the log.
+    in_flight: Mutex<HashMap<String, oneshot::Sender<&'static str>>>,
+    // Chunk frames streamed for each of them so far; kept past a cancel so
+    // the final frame can say how many went out
+    frames_sent: Mutex<HashMap<String, Arc<AtomicU64>>>,
+    // Shared with the states of other accounts
+    scheduler: Arc<Scheduler>,
+    // Also shared, so the global caps cover every account
+    rate_limiter: Arc<RateLimiter>,
+    // Outlives connections, so responses are resent after a reconnect
+    outbox: Outbox,
+    // Also kept across connections, as redeliveries follow reconnects
+    recent_requests: RecentRequests,
+    // Response keys of sealed requests being served
+    e2e_sessions: e2e::Sessions,
+    // Last model list advertised or fetched
+    models: Mutex<Vec<String>>,
+    // Last `/api/ps` answer
+    loaded_models: Mutex<Vec<LoadedModel>>,
+    // Cancel signals for model downloads, by model name
+    pulls: Mutex<HashMap<String, oneshot::Sender<()>>>,
+    // Position in `RelayConfig::endpoints` and failures seen there
+    relay_index: AtomicUsize,
+    relay_failures: AtomicU32,
+    // From the relay's `capabilities` message; None until it sends one
+    relay_capabilities: Mutex<Option<Vec<String>>>,
+    // Secret store entry the token was loaded from, where refreshed tokens
+    // are saved
+    token_secret: Mutex<Option<String>>,
+    // The token connections are started with and the one a refresh replaced
+    // it with
+    refreshed_token: Mutex<Option<(String, String)>>,
+}
+
+impl Default for RunnerState {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl RunnerState {
+    pub fn new(config: Config) -> Self {
+        privacy::set_enabled(config.privacy.enabled);
+        proxy::set(&config.proxy);
+        client_tls::set(&config.tls);
+        backend_auth::set(&config.backend);
+        Self {
+            config: RwLock::new(config),
+            paused: AtomicBool::new(false),
+            battery_hold: AtomicBool::new(false),
+            schedule_hold: AtomicBool::new(false),
+            backend_down: AtomicBool::new(false),
+            backend_failures: AtomicU32::new(0),
+            status_changed: watch::Sender::new(()),
+            connection_status: Mutex::new("disconnected".to_string()),
+            started_at: Instant::now(),
+            requests_served: AtomicU64::new(0),
+            requests_failed: AtomicU64::new(0),
+            last_served: Mutex::new(None),
+            daily_usage: Mutex::new(BTreeMap::new()),
+            metrics: Metrics::default(),
+            prometheus: prometheus::Registry::default(),
+            in_flight: Mutex::new(HashMap::new()),
+            frames_sent: Mutex::new(HashMap::new()),
+            scheduler: Arc::default(),
+            rate_limiter: Arc::default(),
+            outbox: Outbox::default(),
+            recent_requests: RecentRequests::default(),
+            e2e_sessions: e2e::Sessions::default(),
+            models: Mutex::new(Vec::new()),
+            loaded_models: Mutex::new(Vec::new()),
+            pulls: Mutex::new(HashMap::new()),
+            relay_index: AtomicUsize::new(0),
+            relay_failures: AtomicU32::new(0),
+            relay_capabilities: Mutex::new(None),
+            token_secret: Mutex::new(None),
+            refreshed_token: Mutex::new(None),
+        }
+    }
+
+    // For another account's relay connection: its own connection status,
+    // requests and counters, but the same config and generation slots, so
+    // accounts together stay within the concurrency limits
+    pub fn for_account(&self) -> Self {
+        let mut state = Self::new(self.config());
+        state.scheduler = self.scheduler.clone();
+        state.rate_limiter = self.rate_limiter.clone();
+        state.set_paused(self.is_paused());
+        state
+    }
+
+    pub fn config(&self) -> Config {
+        self.config.read().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    pub fn update_config(&self, update: impl FnOnce(&mut Config)) {
+        if let Ok(mut config) = self.config.write() {
+            update(&mut config);
+            privacy::set_enabled(config.privacy.enabled);
+            proxy::set(&config.proxy);
+            client_tls::set(&config.tls);
+            backend_auth::set(&config.backend);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.swap(paused, Ordering::Relaxed) != paused {
+            self.announce_status();
+        }
+    }
+
+    pub fn battery_hold(&self) -> bool {
+        self.battery_hold.load(Ordering::Relaxed)
+    }
+
+    // Returns whether it changed
+    pub fn set_battery_hold(&self, hold: bool) -> bool {
+        self.set_hold(&self.battery_hold, hold)
+    }
+
+    pub fn schedule_hold(&self) -> bool {
+        self.schedule_hold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_schedule_hold(&self, hold: bool) -> bool {
+        self.set_hold(&self.schedule_hold, hold)
+    }
+
+    pub fn backend_down(&self) -> bool {
+        self.backend_down.load(Ordering::Relaxed)
+    }
+
+    pub fn set_backend_down(&self, down: bool) -> bool {
+        self.set_hold(&self.backend_down, down)
+    }
+
+    // Counts a backend health check; returns the failures in a row
+    pub fn record_backend_check(&self, up: bool) -> u32 {
+        if up {
+            self.backend_failures.store(0, Ordering::Relaxed);
+            0
+        } else {
+            self.backend_failures.fetch_add(1, Ordering::Relaxed) + 1
+        }
+    }
+
+    fn set_hold(&self, flag: &AtomicBool, hold: bool) -> bool {
+        let changed = flag.swap(hold, Ordering::Relaxed) != hold;
+        if changed {
+            self.announce_status();
+        }
+        changed
+    }
+
+    // Why the runner is offline without being paused by hand
+    pub fn hold_reason(&self) -> Option<&'static str> {
+        if self.battery_hold() {
+            Some("battery")
+        } else if self.schedule_hold() {
+            Some("schedule")
+        } else {
+            None
+        }
+    }
+
+    // What `status` messages tell the relay
+    pub fn availability(&self) -> &'static str {
+        if self.hold_reason().is_some() {
+            "offline"
+        } else if self.is_paused() {
+            "paused"
+        } else if self.backend_down() {
+            "degraded"
+        } else {
+            "online"
+        }
+    }
+
+    pub fn announce_status(&self) {
+        self.status_changed.send_replace(());
+    }
+
+    pub fn watch_status(&self) -> watch::Receiver<()> {
+        self.status_changed.subscribe()
+    }
+
+    pub fn connection_status(&self) -> String {
+        self.connection_status
+            .lock()
+            .map(|s| s.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_connection_status(&self, status: &str) {
+        if let Ok(mut current) = self.connection_status.lock() {
+            *current = status.to_string();
+        }
+    }
+
+    // The relay the next connection goes to
+    pub fn relay_url(&self) -> String {
+        let relay = self.config().relay;
+        let endpoints = relay.endpoints();
+        endpoints[self.relay_index.load(Ordering::Relaxed) % endpoints.len()].to_string()
+    }
+
+    // Starts over from the primary relay
+    pub fn reset_relay(&self) {
+        self.relay_index.store(0, Ordering::Relaxed);
+        self.relay_failures.store(0, Ordering::Relaxed);
+    }
+
+    // Called after a stable connection ended, so earlier failures on this
+    // relay no longer count
+    pub fn relay_recovered(&self) {
+        self.relay_failures.store(0, Ordering::Relaxed);
+    }
+
+    // Counts a failed or dropped connection and moves to the next relay when
+    // this one has failed too often. Returns the new relay when it moved.
+    pub fn relay_failed(&self, unreachable: bool) -> Option<String> {
+        let relay = self.config().relay;
+        if relay.fallback_urls.is_empty() {
+            return None;
+        }
+        let failures = self.relay_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if !unreachable && failures < relay.failover_after_disconnects.max(1) {
+            return None;
+        }
+        self.relay_failures.store(0, Ordering::Relaxed);
+        self.relay_index.fetch_add(1, Ordering::Relaxed);
+        Some(self.relay_url())
+    }
+
+    pub fn set_relay_capabilities(&self, capabilities: Option<Vec<String>>) {
+        if let Ok(mut current) = self.relay_capabilities.lock() {
+            *current = capabilities;
+        }
+    }
+
+    // Relays that never said what they support are assumed to support it
+    pub fn relay_supports(&self, capability: &str) -> bool {
+        match self.relay_capabilities.lock() {
+            Ok(current) => current.as_ref().is_none_or(|list| list.iter().any(|c| c == capability)),
+            Err(_) => true,
+        }
+    }
+
+    // The returned receiver fires when `cancel_request` is called for the id
+    pub fn set_token_secret(&self, name: Option<&str>) {
+        if let Ok(mut secret) = self.token_secret.lock() {
+            *secret = name.map(str::to_string);
+        }
+    }
+
+    // The token to authenticate with, given the one a connection was started
+    // with: its latest refresh if it had one
+    pub fn current_token(&self, original: &str) -> String {
+        match self.refreshed_token.lock().ok().and_then(|refreshed| refreshed.clone()) {
+            Some((from, to)) if from == original => to,
+            _ => original.to_string(),
+        }
+    }
+
+    // Keeps a refreshed token for the next connections and saves it where
+    // `previous` was saved
+    pub fn token_refreshed(&self, original: &str, previous: &str, token: &str) {
+        if let Ok(mut refreshed) = self.refreshed_token.lock() {
+            *refreshed = Some((original.to_string(), token.to_string()));
+        }
+        let secret = self.token_secret.lock().ok().and_then(|secret| secret.clone());
+        let saved = secret.map(|secret| token::save_refreshed(&secret, previous, token));
+        match saved {
+            Some(Ok(true)) => tracing::info!("Saved the refreshed token"),
+            Some(Err(e)) => tracing::error!("Failed to save the refreshed token: {}", e),
+            _ => tracing::warn!("The refreshed token is kept in memory only; it isn't saved where the runner read its token"),
+        }
+    }
+
+    pub fn track_request(&self, request_id: &str) -> oneshot::Receiver<&'static str> {
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.insert(request_id.to_string(), cancel_tx);
+        }
+        if let Ok(mut frames_sent) = self.frames_sent.lock() {
+            frames_sent.insert(request_id.to_string(), Arc::default());
+        }
+        cancel_rx
+    }
+
+    pub fn finish_request(&self, request_id: &str) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(request_id);
+        }
+        if let Ok(mut frames_sent) = self.frames_sent.lock() {
+            frames_sent.remove(request_id);
+        }
+    }
+
+    // Counts the chunk frames streamed for a request; a fresh counter for
+    // requests not tracked, such as the local API's
+    pub fn frames_sent(&self, request_id: &str) -> Arc<AtomicU64> {
+        self.frames_sent
+            .lock()
+            .ok()
+            .and_then(|frames_sent| frames_sent.get(request_id).cloned())
+            .unwrap_or_default()
+    }
+
+    // False when the request is unknown or already finished
+    pub fn cancel_request(&self, request_id: &str) -> bool {
+        let cancel = self.in_flight.lock().ok().and_then(|mut in_flight| in_flight.remove(request_id));
+        cancel.is_some_and(|cancel| cancel.send("cancelled").is_ok())
+    }
+
+    // Stops every request still being served, e.g. when the connection they
+    // came in on is gone
+    pub fn cancel_all_requests(&self, reason: &'static str) {
+        let cancels: Vec<_> = match self.in_flight.lock() {
+            Ok(mut in_flight) => in_flight.drain().map(|(_, cancel)| cancel).collect(),
+            Err(_) => return,
+        };
+        for cancel in cancels {
+            let _ = cancel.send(reason);
+        }
+    }
+
+    pub fn models(&self) -> Vec<String> {
+        self.models.lock().map(|models| models.clone()).unwrap_or_default()
+    }
+
+    pub fn set_models(&self, models: Vec<String>) {
+        if let Ok(mut current) = self.models.lock() {
+            *current = models;
+        }
+    }
+
+    pub fn loaded_models(&self) -> Vec<LoadedModel> {
+        self.loaded_models.lock().map(|models| models.clone()).unwrap_or_default()
+    }
+
+    pub fn set_loaded_models(&self, models: Vec<LoadedModel>) {
+        if let Ok(mut current) = self.loaded_models.lock() {
+            *current = models;
+        }
+    }
+
+    // None when the model is already being pulled
+    pub fn track_pull(&self, model: &str) -> Option<oneshot::Receiver<()>> {
+        let mut pulls = self.pulls.lock().ok()?;
+        if pulls.get(model).is_some_and(|cancel| !cancel.is_closed()) {
+            return None;
+        }
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        pulls.insert(model.to_string(), cancel_tx);
+        Some(cancel_rx)
+    }
+
+    pub fn finish_pull(&self, model: &str) {
+        if let Ok(mut pulls) = self.pulls.lock() {
+            pulls.remove(model);
+        }
+    }
+
+    pub fn is_pulling(&self, model: &str) -> bool {
+        self.pulls
+            .lock()
+            .is_ok_and(|pulls| pulls.get(model).is_some_and(|cancel| !cancel.is_closed()))
+    }
+
+    pub fn cancel_pull(&self, model: &str) -> bool {
+        let cancel = self.pulls.lock().ok().and_then(|mut pulls| pulls.remove(model));
+        cancel.is_some_and(|cancel| cancel.send(()).is_ok())
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    pub fn recent_requests(&self) -> &RecentRequests {
+        &self.recent_requests
+    }
+
+    pub fn e2e_sessions(&self) -> &e2e::Sessions {
+        &self.e2e_sessions
+    }
+
+    pub fn record_request(&self, success: bool) {
+        let counter = if success {
+            &self.requests_served
+        } else {
+            &self.requests_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if success {
+            if let Ok(mut last) = self.last_served.lock() {
+                *last = Some(Instant::now());
+            }
+        }
+    }
+
+    // Time since the last request served; None before the first one
+    pub fn idle_for(&self) -> Option<Duration> {
+        self.last_served.lock().ok().and_then(|last| last.map(|last| last.elapsed()))
+    }
+
+    pub fn record_usage(&self, input_tokens: u64, output_tokens: u64, busy: Duration) {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        if let Ok(mut days) = self.daily_usage.lock() {
+            let day = days.entry(today).or_default();
+            day.requests += 1;
+            day.input_tokens += input_tokens;
+            day.output_tokens += output_tokens;
+            day.busy += busy;
+            self.scheduler.record_throughput(output_tokens, busy);
+            while days.len() > DAYS_KEPT {
+                days.pop_first();
+            }
+        }
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn prometheus(&self) -> &prometheus::Registry {
+        &self.prometheus
+    }
+
+    // Oldest day first
+    pub fn daily_usage(&self) -> Vec<(String, DayUsage)> {
+        self.daily_usage
+            .lock()
+            .map(|days| days.iter().map(|(date, day)| (date.clone(), *day)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> serde_json::Value {
+        let load = self.scheduler.load();
+        serde_json::json!({
+            "status": self.connection_status(),
+            "paused": self.is_paused(),
+            "batteryHold": self.battery_hold(),
+            "scheduleHold": self.schedule_hold(),
+            "uptimeSecs": self.started_at.elapsed().as_secs(),
+            "requestsServed": self.requests_served.load(Ordering::Relaxed),
+            "requestsFailed": self.requests_failed.load(Ordering::Relaxed),
+            "queueDepth": load.queueDepth,
+            "activeRequests": load.activeRequests,
+            "avgTokensPerSec": load.avgTokensPerSec,
+            "version": env!("CARGO_PKG_VERSION"),
+        })
+    }
+}
@@ -0,0 +1,251 @@
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+use crate::events::EventSink;
+use crate::headless::ExitReason;
+use crate::state::RunnerState;
+use crate::backend;
+use crate::connection::ConnectionOutcome;
+use crate::ssh_tunnel::SshTunnel;
+
+// Carried across exec() restarts so crash-loop backoff survives them
+const RESTARTS_ENV: &str = "BOTTLECAP_SUPERVISOR_RESTARTS";
+
+pub struct Policy {
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    // A connection that stays up this long resets the backoff
+    pub stable_after: Duration,
+    pub check_interval: Duration,
+    pub max_memory_bytes: Option<u64>,
+    // Give up after this many consecutive connection failures
+    pub max_failures: Option<u32>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+            stable_after: Duration::from_secs(60),
+            check_interval: Duration::from_secs(30),
+            max_memory_bytes: Some(2 * 1024 * 1024 * 1024),
+            max_failures: None,
+        }
+    }
+}
+
+impl Policy {
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.min_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+enum Stop {
+    Outcome(ConnectionOutcome),
+    Shutdown,
+    Restart(&'static str),
+}
+
+// Keeps the relay connection alive in headless mode. Transient relay
+// failures are retried with exponential backoff, a backend that comes back
+// triggers a reconnect (so the model list is re-advertised), and exceeding
+// the memory limit re-executes the whole process. `tunnel`, the SSH tunnel
+// to the backend if there is one, is closed before returning or restarting,
+// as neither gives its destructor a chance to kill ssh.
+pub async fn run(
+    token: String,
+    events: Arc<dyn EventSink>,
+    state: Arc<RunnerState>,
+    policy: Policy,
+    mut tunnel: Option<SshTunnel>,
+) -> (ExitReason, String) {
+    let result = supervise(token, events, state, policy, &mut tunnel).await;
+    if let Some(tunnel) = tunnel {
+        tunnel.close().await;
+    }
+    result
+}
+
+async fn supervise(
+    token: String,
+    events: Arc<dyn EventSink>,
+    state: Arc<RunnerState>,
+    policy: Policy,
+    tunnel: &mut Option<SshTunnel>,
+) -> (ExitReason, String) {
+    let mut failures = process_restarts();
+    let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
+    let mut checks = tokio::time::interval(policy.check_interval);
+    let mut backend_up = true;
+    // Run aside, so the connection is still served while a backend that
+    // doesn't answer holds the check up
+    let mut probe: Option<JoinHandle<bool>> = None;
+    let mut system = sysinfo::System::new();
+    state.reset_relay();
+
+    loop {
+        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
+        let started = Instant::now();
+        let connection = crate::connection::run_connection(token.clone(), events.clone(), state.clone(), cancel_rx);
+        tokio::pin!(connection);
+
+        let stop = loop {
+            tokio::select! {
+                outcome = &mut connection => break Stop::Outcome(outcome),
+                _ = &mut ctrl_c => break Stop::Shutdown,
+                Some(result) = async { Some(probe.as_mut()?.await) }, if probe.is_some() => {
+                    probe = None;
+                    let up = result.unwrap_or(false);
+                    if up != backend_up {
+                        backend_up = up;
+                        events.emit("backend-health", serde_json::json!({
+                            "status": if up { "healthy" } else { "unreachable" }
+                        }));
+                        if up {
+                            break Stop::Restart("backend recovered");
+                        }
+                    }
+                }
+                _ = checks.tick() => {
+                    if probe.is_none() {
+                        let backend = state.config().backend;
+                        probe = Some(tokio::spawn(async move {
+                            tokio::time::timeout(backend::PROBE_TIMEOUT, backend::reachable(&backend)).await.unwrap_or(false)
+                        }));
+                    }
+
+                    if let (Some(limit), Some(used)) = (policy.max_memory_bytes, memory_usage(&mut system)) {
+                        if used > limit {
+                            events.emit("supervisor", serde_json::json!({
+                                "action": "restart-process",
+                                "reason": "memory limit exceeded",
+                                "memoryBytes": used,
+                                "limitBytes": limit,
+                            }));
+                            let _ = cancel_tx.send(());
+                            let _ = connection.await;
+                            if let Some(tunnel) = tunnel.take() {
+                                tunnel.close().await;
+                            }
+                            let error = restart_process(&policy).await;
+                            return (ExitReason::Internal, format!("Failed to restart process: {}", error));
+                        }
+                    }
+                }
+            }
+        };
+
+        // Whether the relay failed, and if so whether it was unreachable
+        let mut relay_failure = Some(false);
+        let reason = match stop {
+            Stop::Outcome(ConnectionOutcome::Cancelled) | Stop::Shutdown => {
+                let _ = cancel_tx.send(());
+                let _ = connection.await;
+                return (ExitReason::Success, "Shutting down".to_string());
+            }
+            Stop::Outcome(ConnectionOutcome::AuthFailed(e)) => return (ExitReason::AuthFailed, e),
+            Stop::Outcome(ConnectionOutcome::Scheduled) => {
+                events.emit("supervisor", serde_json::json!({ "action": "wait-for-schedule" }));
+                tokio::select! {
+                    _ = crate::schedule::wait_until_open(&state) => {}
+                    _ = &mut ctrl_c => return (ExitReason::Success, "Shutting down".to_string()),
+                }
+                failures = 0;
+                continue;
+            }
+            Stop::Outcome(ConnectionOutcome::Closed) => "relay closed the connection".to_string(),
+            Stop::Outcome(ConnectionOutcome::ConnectFailed(e)) => {
+                relay_failure = Some(true);
+                e
+            }
+            Stop::Outcome(ConnectionOutcome::SocketError(e)) => e,
+            Stop::Restart(reason) => {
+                let _ = cancel_tx.send(());
+                let _ = connection.await;
+                failures = 0;
+                relay_failure = None;
+                reason.to_string()
+            }
+        };
+
+        if started.elapsed() >= policy.stable_after {
+            failures = 0;
+            std::env::remove_var(RESTARTS_ENV);
+            state.relay_recovered();
+        }
+        if let Some(unreachable) = relay_failure {
+            if let Some(relay) = state.relay_failed(unreachable) {
+                events.emit("supervisor", serde_json::json!({
+                    "action": "switch-relay",
+                    "relay": relay,
+                }));
+            }
+        }
+        failures += 1;
+        if policy.max_failures.is_some_and(|max| failures > max) {
+            return (ExitReason::RelayUnreachable, reason);
+        }
+        let delay = policy.backoff(failures);
+        state.prometheus().record_reconnect();
+
+        events.emit("supervisor", serde_json::json!({
+            "action": "restart-connection",
+            "reason": reason,
+            "attempt": failures,
+            "delayMs": delay.as_millis() as u64,
+        }));
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut ctrl_c => return (ExitReason::Success, "Shutting down".to_string()),
+        }
+    }
+}
+
+fn process_restarts() -> u32 {
+    std::env::var(RESTARTS_ENV)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+fn memory_usage(system: &mut sysinfo::System) -> Option<u64> {
+    let pid = sysinfo::get_current_pid().ok()?;
+    system.refresh_process(pid);
+    system.process(pid).map(|p| p.memory())
+}
+
+// Replaces the current process with a fresh copy of itself. Only returns if
+// that failed.
+async fn restart_process(policy: &Policy) -> std::io::Error {
+    let restarts = process_restarts() + 1;
+    tokio::time::sleep(policy.backoff(restarts)).await;
+
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => return e,
+    };
+    let mut command = Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(RESTARTS_ENV, restarts.to_string());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.exec()
+    }
+
+    #[cfg(not(unix))]
+    {
+        match command.spawn() {
+            Ok(_) => std::process::exit(0),
+            Err(e) => e,
+        }
+    }
+}
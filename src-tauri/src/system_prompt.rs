@@ -0,0 +1,43 @@
+use crate::config::SystemPromptConfig;
+use crate::protocol::{ChatMessage, Input};
+
+// The runner's system prompt for `model`, if it has one
+pub fn render(config: &SystemPromptConfig, model: &str) -> Option<String> {
+    let template = config.models.get(model).or(config.default.as_ref())?;
+    if template.is_empty() {
+        return None;
+    }
+    let device_name = hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_default();
+    Some(template.replace("{device_name}", &device_name).replace("{model}", model))
+}
+
+// Puts the runner's system prompt ahead of the request's own messages. A
+// completion gets it as its system prompt, before any it brought; raw ones
+// skip the template that would use it and are left alone.
+pub fn apply(config: &SystemPromptConfig, model: &str, input: &mut Input) {
+    let Some(prompt) = render(config, model) else {
+        return;
+    };
+    match input {
+        Input::Chat(messages) => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".to_string(),
+                content: prompt,
+                images: Vec::new(),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            },
+        ),
+        Input::Completion(completion) if !completion.raw => {
+            completion.system = Some(match completion.system.take() {
+                Some(system) => format!("{}\n\n{}", prompt, system),
+                None => prompt,
+            });
+        }
+        Input::Completion(_) => {}
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+const OLLAMA_PORT: u16 = 11434;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// CLI locations tried in order; the macOS app does not put it on PATH
+const CLI_PATHS: &[&str] = &["tailscale", "/Applications/Tailscale.app/Contents/MacOS/Tailscale"];
+
+// MagicDNS names seen in the last `status` call, used to recognise short
+// tailnet hostnames that carry no `.ts.net` suffix
+static KNOWN_HOSTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StatusJson {
+    backend_state: String,
+    #[serde(rename = "MagicDNSSuffix", default)]
+    magic_dns_suffix: String,
+    #[serde(rename = "Self")]
+    self_node: Option<NodeJson>,
+    #[serde(default)]
+    peer: HashMap<String, NodeJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct NodeJson {
+    host_name: String,
+    #[serde(rename = "DNSName", default)]
+    dns_name: String,
+    #[serde(default)]
+    tailscale_ips: Vec<IpAddr>,
+    #[serde(default)]
+    online: bool,
+    #[serde(rename = "OS", default)]
+    os: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TailnetBackend {
+    pub host_name: String,
+    // MagicDNS name, usable as the backend host
+    pub dns_name: String,
+    pub ips: Vec<IpAddr>,
+    pub os: String,
+    pub online: bool,
+    // Set when an Ollama server answered on the peer
+    pub url: Option<String>,
+    pub models: Vec<String>,
+}
+
+// Lists the peers of the running Tailscale (or Headscale) network and checks
+// which of them serve Ollama on the default port.
+pub async fn backends() -> Result<Vec<TailnetBackend>, String> {
+    let status = status().await?;
+    let probes = status.peer.into_values().map(|peer| async move {
+        let host = peer.dns_name.trim_end_matches('.').to_string();
+        let mut backend = TailnetBackend {
+            host_name: peer.host_name,
+            dns_name: host.clone(),
+            ips: peer.tailscale_ips,
+            os: peer.os,
+            online: peer.online,
+            url: None,
+            models: Vec::new(),
+        };
+        if peer.online && !host.is_empty() {
+            let url = format!("http://{}:{}", host, OLLAMA_PORT);
+            if let Ok(Ok(models)) = tokio::time::timeout(PROBE_TIMEOUT, crate::ollama::list_models(&url)).await {
+                backend.url = Some(url);
+                backend.models = models;
+            }
+        }
+        backend
+    });
+
+    let mut backends = join_all(probes).await;
+    backends.sort_by(|a, b| b.url.is_some().cmp(&a.url.is_some()).then(a.dns_name.cmp(&b.dns_name)));
+    Ok(backends)
+}
+
+async fn status() -> Result<StatusJson, String> {
+    let mut last_error = String::new();
+    for cli in CLI_PATHS {
+        let output = match Command::new(cli).args(["status", "--json"]).output().await {
+            Ok(output) => output,
+            Err(e) => {
+                last_error = e.to_string();
+                continue;
+            }
+        };
+        if !output.status.success() {
+            return Err(format!(
+                "tailscale status failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let status: StatusJson = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Unexpected tailscale status output: {}", e))?;
+        if status.backend_state != "Running" {
+            return Err(format!("Tailscale is not connected ({})", status.backend_state));
+        }
+        remember_hosts(&status);
+        return Ok(status);
+    }
+    Err(format!("Tailscale CLI not found: {}", last_error))
+}
+
+fn remember_hosts(status: &StatusJson) {
+    let suffix = status.magic_dns_suffix.trim_matches('.').to_lowercase();
+    let mut names: Vec<String> = Vec::new();
+    for node in status.peer.values().chain(status.self_node.iter()) {
+        let dns_name = node.dns_name.trim_end_matches('.').to_lowercase();
+        if let Some(short) = dns_name.strip_suffix(&format!(".{}", suffix)).filter(|_| !suffix.is_empty()) {
+            names.push(short.to_string());
+        }
+        names.push(dns_name);
+        names.push(node.host_name.to_lowercase());
+    }
+    if let Ok(mut known) = KNOWN_HOSTS.lock() {
+        *known = names;
+    }
+}
+
+// Whether `host` (from a backend URL) points into a tailnet: a Tailscale
+// address, a `*.ts.net` name, or a MagicDNS name from the last status call.
+pub fn is_tailnet_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return match ip {
+            // 100.64.0.0/10 (CGNAT range Tailscale allocates from)
+            IpAddr::V4(v4) => v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64,
+            // fd7a:115c:a1e0::/48
+            IpAddr::V6(v6) => v6.segments()[..3] == [0xfd7a, 0x115c, 0xa1e0],
+        };
+    }
+
+    let host = host.trim_end_matches('.').to_lowercase();
+    if host.ends_with(".ts.net") {
+        return true;
+    }
+    KNOWN_HOSTS
+        .lock()
+        .map(|known| known.contains(&host))
+        .unwrap_or(false)
+}
@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::error::RunnerError;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::{proxy, secret_store};
+
+pub const TOKEN_ENV: &str = "BOTTLECAP_TOKEN";
+pub const TOKEN_FILE_ENV: &str = "BOTTLECAP_TOKEN_FILE";
+
+const VALIDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where a runner token was found. `resolve` checks these in declaration
+// order and stops at the first one that yields a token.
+pub enum TokenSource {
+    Arg,
+    Flag(PathBuf),
+    Env,
+    EnvFile(PathBuf),
+    Keyring,
+}
+
+impl TokenSource {
+    pub fn describe(&self) -> String {
+        match self {
+            TokenSource::Arg => "--token".to_string(),
+            TokenSource::Flag(path) => format!("--token-file {}", path.display()),
+            TokenSource::Env => TOKEN_ENV.to_string(),
+            TokenSource::EnvFile(path) => format!("{} ({})", TOKEN_FILE_ENV, path.display()),
+            TokenSource::Keyring => "keyring".to_string(),
+        }
+    }
+}
+
+// The saved token: from the keyring, or the encrypted file used where the
+// keyring fails
+pub fn load_from_keyring() -> Result<Option<String>, RunnerError> {
+    secret_store::get("token")
+}
+
+pub fn load_from_file(path: &Path) -> Result<String, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read token file {}: {}", path.display(), e))?;
+    let token = contents.trim();
+    if token.is_empty() {
+        return Err(format!("Token file {} is empty", path.display()));
+    }
+    Ok(token.to_string())
+}
+
+// When the token stops working, in unix seconds, for tokens that say so: a
+// JWT with an `exp` claim, after the `bc_runner_` prefix if there is one
+pub fn expiry(token: &str) -> Option<u64> {
+    use base64::Engine;
+    let jwt = token.strip_prefix("bc_runner_").unwrap_or(token);
+    let parts: Vec<&str> = jwt.split('.').collect();
+    let [_, payload, _] = parts[..] else {
+        return None;
+    };
+    let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    serde_json::from_slice::<serde_json::Value>(&claims).ok()?["exp"].as_u64()
+}
+
+// Saves a refreshed token over `previous` in the `secret` entry, if that is
+// where `previous` was saved. A token typed in but never saved stays unsaved.
+pub fn save_refreshed(secret: &str, previous: &str, token: &str) -> Result<bool, RunnerError> {
+    if secret_store::get(secret)?.as_deref() != Some(previous) {
+        return Ok(false);
+    }
+    secret_store::set(secret, token)?;
+    Ok(true)
+}
+
+// Precedence: --token, --token-file, BOTTLECAP_TOKEN, BOTTLECAP_TOKEN_FILE,
+// keyring. The keyring is only consulted when nothing else is configured so
+// that servers without a secret service never touch it.
+pub fn resolve(token: Option<&str>, token_file: Option<&Path>) -> Result<Option<(String, TokenSource)>, String> {
+    if let Some(token) = token.map(str::trim).filter(|t| !t.is_empty()) {
+        return Ok(Some((token.to_string(), TokenSource::Arg)));
+    }
+    if let Some(path) = token_file {
+        return load_from_file(path).map(|t| Some((t, TokenSource::Flag(path.to_path_buf()))));
+    }
+
+    if let Ok(token) = std::env::var(TOKEN_ENV) {
+        let token = token.trim();
+        if !token.is_empty() {
+            return Ok(Some((token.to_string(), TokenSource::Env)));
+        }
+    }
+
+    if let Some(path) = std::env::var_os(TOKEN_FILE_ENV) {
+        let path = PathBuf::from(path);
+        return load_from_file(&path).map(|t| Some((t, TokenSource::EnvFile(path))));
+    }
+
+    Ok(load_from_keyring()?.map(|t| (t, TokenSource::Keyring)))
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Validity {
+    Valid,
+    Invalid,
+    Expired,
+    Revoked,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TokenCheck {
+    pub status: Validity,
+    // The relay's reason when the token was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Runs the relay's auth handshake on a throwaway connection. An Err means
+// no answer was obtained (relay unreachable or too slow), not that the token
+// is bad.
+pub async fn validate(relay_url: &str, token: &str) -> Result<TokenCheck, String> {
+    tokio::time::timeout(VALIDATE_TIMEOUT, handshake(relay_url, token))
+        .await
+        .map_err(|_| "Timed out waiting for the relay to answer".to_string())?
+}
+
+async fn handshake(relay_url: &str, token: &str) -> Result<TokenCheck, String> {
+    let (mut ws, _) = proxy::connect_websocket(relay_url)
+        .await
+        .map_err(|e| format!("WebSocket connection failed: {}", e))?;
+    let auth = serde_json::to_string(&ClientMessage::auth(token.to_string(), None)).map_err(|e| e.to_string())?;
+    ws.send(Message::Text(auth)).await.map_err(|e| e.to_string())?;
+
+    let check = loop {
+        let text = match ws.next().await {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Close(_))) | None => return Err("Relay closed the connection".to_string()),
+            Some(Err(e)) => return Err(format!("WebSocket error: {}", e)),
+            Some(Ok(_)) => continue,
+        };
+        match serde_json::from_str::<ServerMessage>(&text) {
+            Ok(ServerMessage::AuthSuccess { .. }) => {
+                break TokenCheck {
+                    status: Validity::Valid,
+                    error: None,
+                }
+            }
+            Ok(ServerMessage::AuthError { error }) => {
+                let error = error.unwrap_or_else(|| "Authentication failed".to_string());
+                break TokenCheck {
+                    status: rejection(&error),
+                    error: Some(error),
+                };
+            }
+            _ => continue,
+        }
+    };
+    let _ = ws.close(None).await;
+    Ok(check)
+}
+
+// The relay only sends a message; look for the usual wording
+fn rejection(error: &str) -> Validity {
+    let error = error.to_lowercase();
+    if error.contains("expired") {
+        Validity::Expired
+    } else if error.contains("revoked") {
+        Validity::Revoked
+    } else {
+        Validity::Invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A token file in a directory of its own, removed with it
+    struct TokenFile(PathBuf);
+
+    impl TokenFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("bottlecap-token-test-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("token");
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TokenFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    fn found(resolved: Result<Option<(String, TokenSource)>, String>) -> (String, String) {
+        let (token, source) = resolved.unwrap().unwrap();
+        (token, source.describe())
+    }
+
+    // In one test, as the environment is shared by the test threads
+    #[test]
+    fn resolve_precedence() {
+        let flag = TokenFile::new("flag", "  from-flag\n");
+        let env_file = TokenFile::new("env-file", "from-env-file\n");
+        std::env::set_var(TOKEN_ENV, " from-env ");
+        std::env::set_var(TOKEN_FILE_ENV, &env_file.0);
+
+        let flag_source = format!("--token-file {}", flag.0.display());
+        assert_eq!(found(resolve(Some(" from-arg "), Some(&flag.0))), ("from-arg".to_string(), "--token".to_string()));
+        // A blank --token counts as not given
+        assert_eq!(found(resolve(Some("  "), Some(&flag.0))), ("from-flag".to_string(), flag_source));
+        assert_eq!(found(resolve(None, None)), ("from-env".to_string(), TOKEN_ENV.to_string()));
+
+        std::env::set_var(TOKEN_ENV, "   ");
+        assert_eq!(
+            found(resolve(None, None)),
+            ("from-env-file".to_string(), format!("{} ({})", TOKEN_FILE_ENV, env_file.0.display()))
+        );
+
+        // A file that is named but unusable is an error, not a fall through
+        let empty = TokenFile::new("empty", " \n");
+        assert!(resolve(None, Some(&empty.0)).err().unwrap().contains("is empty"));
+        std::env::set_var(TOKEN_FILE_ENV, empty.0.parent().unwrap().join("missing"));
+        assert!(resolve(None, None).err().unwrap().starts_with("Failed to read token file"));
+
+        std::env::remove_var(TOKEN_ENV);
+        std::env::remove_var(TOKEN_FILE_ENV);
+    }
+
+    #[test]
+    fn expiry_from_jwt() {
+        use base64::Engine;
+        let encode = |json: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+        let jwt = format!("{}.{}.sig", encode(r#"{"alg":"none"}"#), encode(r#"{"exp":1700000000}"#));
+        assert_eq!(expiry(&jwt), Some(1700000000));
+        assert_eq!(expiry(&format!("bc_runner_{}", jwt)), Some(1700000000));
+        assert_eq!(expiry(&format!("x.{}.sig", encode(r#"{"sub":"a"}"#))), None);
+        assert_eq!(expiry("bc_runner_opaque"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::protocol::{Input, Usage};
+
+// Token counts made on this machine, for when the backend doesn't report
+// one. Without the model's own tokenizer they are estimates, shaped after
+// BPE vocabularies: common words are one token, long words and numbers
+// split into a few, and punctuation and non-Latin characters cost one each.
+
+// Role markers and separators chat templates add around each message
+const PER_MESSAGE: usize = 4;
+
+// Estimated tokens in `text`
+pub fn count(text: &str) -> usize {
+    let mut tokens = 0usize;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_alphabetic() {
+            let mut len = 1;
+            while chars.next_if(char::is_ascii_alphabetic).is_some() {
+                len += 1;
+            }
+            tokens += 1 + len / 8;
+        } else if c.is_ascii_digit() {
+            let mut len = 1usize;
+            while chars.next_if(char::is_ascii_digit).is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(3);
+        } else if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens
+}
+
+// Estimated prompt tokens for a request, template overhead included
+pub fn count_input(input: &Input) -> usize {
+    match input {
+        Input::Chat(messages) => messages.iter().map(|message| PER_MESSAGE + count(&message.content)).sum(),
+        Input::Completion(prompt) => count(&prompt.prompt) + prompt.system.as_deref().map_or(0, count),
+    }
+}
+
+// Fills in `inputTokens` where the backend reported none, as Ollama does when
+// the whole prompt came from its cache, and marks it as estimated. Both
+// counts are kept alongside.
+pub fn reconcile(usage: &mut Usage, input: &Input) {
+    let local = count_input(input).min(i32::MAX as usize) as i32;
+    usage.backendInputTokens = (usage.inputTokens > 0).then_some(usage.inputTokens);
+    usage.localInputTokens = Some(local);
+    if usage.inputTokens <= 0 {
+        usage.inputTokens = local;
+        usage.inputTokensEstimated = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ChatMessage;
+
+    fn input(content: &str) -> Input {
+        Input::Chat(vec![ChatMessage {
+            role: "user".to_string(),
+            content: content.to_string(),
+            images: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }])
+    }
+
+    #[test]
+    fn counts_words_numbers_and_punctuation() {
+        assert_eq!(count("Hello, world!"), 4);
+        assert_eq!(count("1234567"), 3);
+        assert_eq!(count("internationalization"), 3);
+        assert_eq!(count("   "), 0);
+    }
+
+    #[test]
+    fn keeps_the_backend_count() {
+        let mut usage = Usage { inputTokens: 30, outputTokens: 5, ..Usage::default() };
+        reconcile(&mut usage, &input("How far is the moon?"));
+        assert_eq!(usage.inputTokens, 30);
+        assert_eq!(usage.backendInputTokens, Some(30));
+        assert_eq!(usage.localInputTokens, Some(10));
+        assert!(!usage.inputTokensEstimated);
+    }
+
+    #[test]
+    fn estimates_a_missing_count() {
+        let mut usage = Usage { outputTokens: 5, ..Usage::default() };
+        reconcile(&mut usage, &input("How far is the moon?"));
+        assert_eq!(usage.inputTokens, 10);
+        assert_eq!(usage.backendInputTokens, None);
+        assert!(usage.inputTokensEstimated);
+    }
+}
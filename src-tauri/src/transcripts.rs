@@ -0,0 +1,130 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::TranscriptConfig;
+use crate::privacy;
+use crate::protocol::{ChatMessage, Usage};
+
+// One served conversation per line of the transcript file
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transcript {
+    pub ts: u64,
+    pub request_id: String,
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub response: String,
+    pub usage: Option<Usage>,
+}
+
+// Inclusive bounds in unix milliseconds; either side may be open
+#[derive(Deserialize, Default)]
+pub struct TimeRange {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Jsonl,
+}
+
+pub fn path(config: &TranscriptConfig) -> Option<PathBuf> {
+    config.path.clone().or_else(|| {
+        dirs_next::data_dir().map(|dir| dir.join("bottlecap-runner").join("transcripts.jsonl"))
+    })
+}
+
+pub fn append(
+    config: &TranscriptConfig,
+    request_id: &str,
+    model: &str,
+    messages: &[ChatMessage],
+    response: &str,
+    usage: &Usage,
+) -> Result<(), String> {
+    // Transcripts are nothing but content
+    if privacy::enabled() {
+        return Ok(());
+    }
+    let path = path(config).ok_or("No data directory for transcripts")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let line = serde_json::json!({
+        "ts": ts,
+        "requestId": request_id,
+        "model": model,
+        "messages": messages,
+        "response": response,
+        "usage": usage,
+    });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())
+}
+
+pub fn export(config: &TranscriptConfig, range: &TimeRange, format: ExportFormat) -> Result<String, String> {
+    let path = path(config).ok_or("No data directory for transcripts")?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => return Err(format!("Failed to open {}: {}", path.display(), e)),
+    };
+
+    let mut out = String::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        // Lines from an interrupted write are skipped
+        let Ok(transcript) = serde_json::from_str::<Transcript>(&line) else {
+            continue;
+        };
+        if range.from.is_some_and(|from| transcript.ts < from) || range.to.is_some_and(|to| transcript.ts > to) {
+            continue;
+        }
+        match format {
+            ExportFormat::Jsonl => {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            ExportFormat::Markdown => write_markdown(&mut out, &transcript),
+        }
+    }
+    Ok(out)
+}
+
+fn write_markdown(out: &mut String, transcript: &Transcript) {
+    let when = Utc
+        .timestamp_millis_opt(transcript.ts as i64)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default();
+    out.push_str(&format!("## {} · {} · {}\n\n", when, transcript.model, transcript.request_id));
+    for message in &transcript.messages {
+        out.push_str(&format!("**{}:**\n\n{}\n\n", message.role, message.content));
+    }
+    out.push_str(&format!("**assistant:**\n\n{}\n\n", transcript.response));
+    if let Some(usage) = &transcript.usage {
+        out.push_str(&format!(
+            "_{} input / {} output tokens_\n\n",
+            usage.inputTokens, usage.outputTokens
+        ));
+    }
+    out.push_str("---\n\n");
+}
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Deserializer};
+
+use crate::protocol::ChatOptions;
+
+// Maps OpenAI-style request parameters onto a backend's native options.
+// Parameters a backend can't honour are dropped and reported as warnings in
+// the response rather than failing the request.
+
+pub struct OllamaParams {
+    pub options: serde_json::Map<String, serde_json::Value>,
+    // Top-level `format`: "json" or a JSON schema
+    pub format: Option<serde_json::Value>,
+    // Top-level `tools`, in OpenAI's form, which Ollama takes as is
+    pub tools: Option<Vec<serde_json::Value>>,
+    pub warnings: Vec<String>,
+}
+
+pub fn ollama(options: &ChatOptions) -> OllamaParams {
+    let mut params = OllamaParams {
+        options: serde_json::Map::new(),
+        format: None,
+        tools: None,
+        warnings: Vec::new(),
+    };
+    if let Some(extra) = &options.extra {
+        params.options.extend(extra.clone());
+    }
+    let mut set = |key: &str, value: serde_json::Value| {
+        params.options.insert(key.to_string(), value);
+    };
+
+    if let Some(temperature) = options.temperature {
+        set("temperature", temperature.into());
+    }
+    if let Some(max_tokens) = options.max_tokens {
+        set("num_predict", max_tokens.into());
+    }
+    // Same -2.0..2.0 scale and meaning as OpenAI
+    if let Some(penalty) = options.frequency_penalty {
+        set("frequency_penalty", penalty.into());
+    }
+    if let Some(penalty) = options.presence_penalty {
+        set("presence_penalty", penalty.into());
+    }
+    if let Some(stop) = options.stop.as_ref().filter(|s| !s.is_empty()) {
+        set("stop", stop.clone().into());
+    }
+    if let Some(top_p) = options.top_p {
+        set("top_p", top_p.into());
+    }
+    if let Some(top_k) = options.top_k {
+        set("top_k", top_k.into());
+    }
+    if let Some(seed) = options.seed {
+        set("seed", seed.into());
+    }
+    if let Some(penalty) = options.repeat_penalty {
+        set("repeat_penalty", penalty.into());
+    }
+    if let Some(num_ctx) = options.num_ctx {
+        set("num_ctx", num_ctx.into());
+    }
+
+    if options.logit_bias.as_ref().is_some_and(|b| !b.is_empty()) {
+        params
+            .warnings
+            .push("logit_bias is not supported by Ollama and was ignored".to_string());
+    }
+
+    match output_format(options) {
+        Ok(OutputFormat::Text) => {}
+        Ok(OutputFormat::Json) => params.format = Some("json".into()),
+        Ok(OutputFormat::Schema(schema)) => params.format = Some(schema),
+        Err(warning) => params.warnings.push(warning),
+    }
+
+    // Ollama decides on its own whether to call a tool; "none" is honoured
+    // by not offering any
+    let tools = options.tools.clone().filter(|tools| !tools.is_empty());
+    match options.tool_choice.as_ref().map(|choice| choice.as_str()) {
+        None | Some(Some("auto")) => params.tools = tools,
+        Some(Some("none")) => {}
+        Some(_) => {
+            params
+                .warnings
+                .push("tool_choice is not supported by Ollama; the model chooses whether to call a tool".to_string());
+            params.tools = tools;
+        }
+    }
+
+    params
+}
+
+pub struct OpenAiParams {
+    // Top-level fields of the completion request
+    pub body: serde_json::Map<String, serde_json::Value>,
+    pub warnings: Vec<String>,
+}
+
+// OpenAI-compatible servers take OpenAI's parameters as they are. `top_k`
+// and `extra` are passed along for servers that understand them (vLLM,
+// llama.cpp); Ollama-only options are dropped.
+pub fn openai(options: &ChatOptions) -> OpenAiParams {
+    let mut params = OpenAiParams {
+        body: options.extra.clone().unwrap_or_default(),
+        warnings: Vec::new(),
+    };
+    let mut set = |key: &str, value: Option<serde_json::Value>| {
+        if let Some(value) = value {
+            params.body.insert(key.to_string(), value);
+        }
+    };
+
+    set("temperature", options.temperature.map(Into::into));
+    set("max_tokens", options.max_tokens.map(Into::into));
+    set("frequency_penalty", options.frequency_penalty.map(Into::into));
+    set("presence_penalty", options.presence_penalty.map(Into::into));
+    set("stop", options.stop.clone().filter(|s| !s.is_empty()).map(Into::into));
+    set(
+        "logit_bias",
+        options
+            .logit_bias
+            .as_ref()
+            .filter(|b| !b.is_empty())
+            .and_then(|b| serde_json::to_value(b).ok()),
+    );
+    // Ollama's forms become OpenAI's; OpenAI's pass as they are
+    let openai_form = options
+        .response_format
+        .as_ref()
+        .is_some_and(|format| format["type"].as_str().is_some_and(is_openai_format));
+    match output_format(options) {
+        _ if openai_form => set("response_format", options.response_format.clone()),
+        Ok(OutputFormat::Text) => {}
+        Ok(OutputFormat::Json) => set("response_format", Some(serde_json::json!({ "type": "json_object" }))),
+        Ok(OutputFormat::Schema(schema)) => set(
+            "response_format",
+            Some(serde_json::json!({ "type": "json_schema", "json_schema": { "name": "response", "schema": schema } })),
+        ),
+        Err(warning) => params.warnings.push(warning),
+    }
+    set("tools", options.tools.clone().filter(|tools| !tools.is_empty()).map(Into::into));
+    set("tool_choice", options.tool_choice.clone());
+    set("top_p", options.top_p.map(Into::into));
+    set("top_k", options.top_k.map(Into::into));
+    set("seed", options.seed.map(Into::into));
+
+    for (name, present) in [
+        ("repeat_penalty", options.repeat_penalty.is_some()),
+        ("num_ctx", options.num_ctx.is_some()),
+    ] {
+        if present {
+            params
+                .warnings
+                .push(format!("{} is not supported by OpenAI-compatible backends and was ignored", name));
+        }
+    }
+    params
+}
+
+// The mock backend only honours max_tokens and stop
+pub fn mock(options: &ChatOptions) -> Vec<String> {
+    let mut ignored = Vec::new();
+    if options.frequency_penalty.is_some() {
+        ignored.push("frequency_penalty");
+    }
+    if options.presence_penalty.is_some() {
+        ignored.push("presence_penalty");
+    }
+    if options.logit_bias.as_ref().is_some_and(|b| !b.is_empty()) {
+        ignored.push("logit_bias");
+    }
+    if options.response_format.is_some() {
+        ignored.push("response_format");
+    }
+    if options.top_p.is_some() {
+        ignored.push("top_p");
+    }
+    if options.top_k.is_some() {
+        ignored.push("top_k");
+    }
+    if options.seed.is_some() {
+        ignored.push("seed");
+    }
+    if options.repeat_penalty.is_some() {
+        ignored.push("repeat_penalty");
+    }
+    if options.num_ctx.is_some() {
+        ignored.push("num_ctx");
+    }
+    if options.extra.as_ref().is_some_and(|extra| !extra.is_empty()) {
+        ignored.push("extra");
+    }
+    if options.tools.as_ref().is_some_and(|tools| !tools.is_empty()) {
+        ignored.push("tools");
+    }
+    ignored
+        .into_iter()
+        .map(|name| format!("{} is not supported by the mock backend and was ignored", name))
+        .collect()
+}
+
+pub enum OutputFormat {
+    Text,
+    Json,
+    Schema(serde_json::Value),
+}
+
+fn is_openai_format(kind: &str) -> bool {
+    matches!(kind, "text" | "json_object" | "json_schema")
+}
+
+// `response_format` in OpenAI's form (`{"type": "json_object"}` or
+// `{"type": "json_schema", "json_schema": {"schema": ...}}`) or in Ollama's
+// (`"json"` or a bare JSON schema). Errors are warnings for the response.
+pub fn output_format(options: &ChatOptions) -> Result<OutputFormat, String> {
+    let Some(format) = &options.response_format else {
+        return Ok(OutputFormat::Text);
+    };
+    match format["type"].as_str() {
+        _ if format.as_str() == Some("json") => Ok(OutputFormat::Json),
+        Some("text") => Ok(OutputFormat::Text),
+        Some("json_object") => Ok(OutputFormat::Json),
+        Some("json_schema") => match format["json_schema"].get("schema") {
+            Some(schema) => Ok(OutputFormat::Schema(schema.clone())),
+            None => Err("response_format json_schema has no schema and was ignored".to_string()),
+        },
+        Some("object" | "array" | "string" | "number" | "integer" | "boolean") => Ok(OutputFormat::Schema(format.clone())),
+        _ if format.get("properties").is_some() => Ok(OutputFormat::Schema(format.clone())),
+        other => Err(format!(
+            "response_format {} is not supported and was ignored",
+            other.map(|kind| format!("type {}", kind)).unwrap_or_else(|| format.to_string())
+        )),
+    }
+}
+
+// OpenAI accepts `stop` as a single string or a list of strings
+pub fn stop_sequences<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stop {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<Stop>::deserialize(deserializer)? {
+        Some(Stop::One(stop)) => Some(vec![stop]),
+        Some(Stop::Many(stops)) => Some(stops),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn format(response_format: Option<Value>) -> Result<OutputFormat, String> {
+        output_format(&ChatOptions { response_format, ..Default::default() })
+    }
+
+    #[test]
+    fn output_format_defaults_to_text() {
+        assert!(matches!(format(None), Ok(OutputFormat::Text)));
+        assert!(matches!(format(Some(json!({"type": "text"}))), Ok(OutputFormat::Text)));
+    }
+
+    #[test]
+    fn output_format_json() {
+        assert!(matches!(format(Some(json!("json"))), Ok(OutputFormat::Json)));
+        assert!(matches!(format(Some(json!({"type": "json_object"}))), Ok(OutputFormat::Json)));
+    }
+
+    #[test]
+    fn output_format_openai_schema() {
+        let schema = json!({"type": "object", "properties": {"a": {"type": "string"}}});
+        let response_format = json!({"type": "json_schema", "json_schema": {"name": "x", "schema": schema}});
+        match format(Some(response_format)) {
+            Ok(OutputFormat::Schema(found)) => assert_eq!(found, schema),
+            _ => panic!("expected the schema"),
+        }
+        assert!(format(Some(json!({"type": "json_schema", "json_schema": {"name": "x"}}))).is_err());
+    }
+
+    #[test]
+    fn output_format_bare_schema() {
+        for schema in [
+            json!({"type": "object", "properties": {}}),
+            json!({"type": "array", "items": {"type": "integer"}}),
+            json!({"type": "boolean"}),
+            json!({"properties": {"a": {"type": "number"}}}),
+        ] {
+            match format(Some(schema.clone())) {
+                Ok(OutputFormat::Schema(found)) => assert_eq!(found, schema),
+                _ => panic!("expected {} as a schema", schema),
+            }
+        }
+    }
+
+    #[test]
+    fn output_format_unsupported() {
+        assert_eq!(
+            format(Some(json!({"type": "xml"}))).err().unwrap(),
+            "response_format type xml is not supported and was ignored"
+        );
+        assert_eq!(
+            format(Some(json!("yaml"))).err().unwrap(),
+            "response_format \"yaml\" is not supported and was ignored"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use tauri::{
+    AppHandle, CustomMenuItem, Icon, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem,
+};
+
+use bottlecap_runner_core::token;
+
+use crate::commands::{connect, disconnect_relay, set_paused, AppState};
+
+const SHOW: &str = "show";
+const CONNECT: &str = "connect";
+const DISCONNECT: &str = "disconnect";
+const PAUSE: &str = "pause";
+const QUIT: &str = "quit";
+
+const ICON_SIZE: u32 = 32;
+
+pub fn build() -> SystemTray {
+    let menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(SHOW, "Show Window"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(CONNECT, "Connect"))
+        .add_item(CustomMenuItem::new(DISCONNECT, "Disconnect").disabled())
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(PAUSE, "Pause Requests"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT, "Quit"));
+    SystemTray::new().with_menu(menu).with_tooltip("BottleCapAI Runner")
+}
+
+pub fn handle_event(app: &AppHandle, event: SystemTrayEvent) {
+    let SystemTrayEvent::MenuItemClick { id, .. } = event else {
+        return;
+    };
+    match id.as_str() {
+        SHOW => show_window(app),
+        CONNECT => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                match token::load_from_keyring() {
+                    Ok(Some(token)) => connect(token, app.clone(), &app.state::<AppState>()).await,
+                    // Nothing to connect with yet; the window has the token field
+                    _ => show_window(&app),
+                }
+            });
+        }
+        DISCONNECT => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                disconnect_relay(&app.state::<AppState>()).await;
+            });
+        }
+        PAUSE => {
+            let runner = &app.state::<AppState>().runner;
+            set_paused(app, runner, !runner.is_paused());
+        }
+        QUIT => app.exit(0),
+        _ => {}
+    }
+}
+
+pub fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+// Called wherever `connection-status` is emitted
+pub fn show_status(app: &AppHandle, status: &str) {
+    let tray = app.tray_handle();
+    let (label, color) = match status {
+        "connected" => ("Connected", [0x22, 0xc5, 0x5e]),
+        "connecting" | "reconnecting" | "stale" => ("Reconnecting", [0xf5, 0x9e, 0x0b]),
+        "scheduled" => ("Outside schedule", [0x60, 0xa5, 0xfa]),
+        "error" | "auth_failed" => ("Error", [0xef, 0x44, 0x44]),
+        _ => ("Disconnected", [0x9c, 0xa3, 0xaf]),
+    };
+    #[cfg(target_os = "macos")]
+    let _ = tray.set_icon_as_template(false);
+    let _ = tray.set_icon(status_icon(color));
+    let _ = tray.set_tooltip(&format!("BottleCapAI Runner - {}", label));
+
+    let online = !matches!(status, "disconnected" | "error" | "auth_failed");
+    let _ = tray.get_item(CONNECT).set_enabled(!online);
+    let _ = tray.get_item(DISCONNECT).set_enabled(online);
+}
+
+pub fn show_paused(app: &AppHandle, paused: bool) {
+    let item = app.tray_handle().get_item(PAUSE);
+    let _ = item.set_title(if paused { "Resume Requests" } else { "Pause Requests" });
+}
+
+// A filled circle in the status color, anti-aliased at the edge
+fn status_icon([r, g, b]: [u8; 3]) -> Icon {
+    let center = ICON_SIZE as f32 / 2.0;
+    let radius = center - 4.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = ((x as f32 + 0.5 - center).powi(2) + (y as f32 + 0.5 - center).powi(2)).sqrt();
+            let alpha = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&[r, g, b, (alpha * 255.0) as u8]);
+        }
+    }
+    Icon::Rgba {
+        rgba,
+        width: ICON_SIZE,
+        height: ICON_SIZE,
+    }
+}
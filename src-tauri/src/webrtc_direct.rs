@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use crate::events::EventSink;
+use crate::state::RunnerState;
+use crate::protocol::ClientMessage;
+
+// Direct data channels between a requester and this runner. The relay only
+// carries the offer and answer; chat requests then arrive on the channel and
+// their responses go back the same way. An answer with an error (feature
+// missing, disabled, negotiation failed) tells the requester to keep using
+// the relay path, and so does a channel that fails later on.
+#[derive(Default)]
+pub struct Sessions {
+    #[cfg(feature = "webrtc")]
+    peers: std::collections::HashMap<String, Arc<imp::PeerConnection>>,
+}
+
+impl Sessions {
+    pub async fn answer(
+        &mut self,
+        session_id: String,
+        sdp: String,
+        events: Arc<dyn EventSink>,
+        state: Arc<RunnerState>,
+    ) -> ClientMessage {
+        let result = self.open(session_id.clone(), sdp, events.clone(), state).await;
+        if let Err(e) = &result {
+            tracing::info!(sessionId = %session_id, "Direct connection {} unavailable, using relay: {}", session_id, e);
+        }
+        let (sdp, error) = match result {
+            Ok(sdp) => (Some(sdp), None),
+            Err(e) => (None, Some(e)),
+        };
+        ClientMessage::RtcAnswer { sessionId: session_id, sdp, error }
+    }
+
+    #[cfg(not(feature = "webrtc"))]
+    async fn open(
+        &mut self,
+        _session_id: String,
+        _sdp: String,
+        _events: Arc<dyn EventSink>,
+        _state: Arc<RunnerState>,
+    ) -> Result<String, String> {
+        Err("webrtc_unavailable".to_string())
+    }
+
+    #[cfg(feature = "webrtc")]
+    async fn open(
+        &mut self,
+        session_id: String,
+        sdp: String,
+        events: Arc<dyn EventSink>,
+        state: Arc<RunnerState>,
+    ) -> Result<String, String> {
+        let config = state.config().webrtc;
+        if !config.enabled {
+            return Err("webrtc_disabled".to_string());
+        }
+
+        self.peers.retain(|_, peer| !imp::is_finished(peer));
+        if let Some(previous) = self.peers.remove(&session_id) {
+            imp::close(previous);
+        }
+
+        let peer = imp::accept(&session_id, &sdp, &config, events, state).await?;
+        let local = peer.local_description().await;
+        match local {
+            Some(local) => {
+                self.peers.insert(session_id, peer);
+                Ok(local.sdp)
+            }
+            None => {
+                imp::close(peer);
+                Err("No local description".to_string())
+            }
+        }
+    }
+
+    pub fn close(&mut self, session_id: &str) {
+        #[cfg(feature = "webrtc")]
+        if let Some(peer) = self.peers.remove(session_id) {
+            imp::close(peer);
+        }
+        #[cfg(not(feature = "webrtc"))]
+        let _ = session_id;
+    }
+}
+
+#[cfg(feature = "webrtc")]
+impl Drop for Sessions {
+    fn drop(&mut self) {
+        for (_, peer) in self.peers.drain() {
+            imp::close(peer);
+        }
+    }
+}
+
+#[cfg(feature = "webrtc")]
+mod imp {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use webrtc::api::APIBuilder;
+    use webrtc::data_channel::data_channel_message::DataChannelMessage;
+    use webrtc::data_channel::RTCDataChannel;
+    use webrtc::ice_transport::ice_server::RTCIceServer;
+    use webrtc::peer_connection::configuration::RTCConfiguration;
+    use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+    pub use webrtc::peer_connection::RTCPeerConnection as PeerConnection;
+
+    use crate::config::WebRtcConfig;
+    use crate::events::EventSink;
+    use crate::state::RunnerState;
+    use crate::connection::handle_request;
+    use crate::protocol::ServerMessage;
+
+    // ICE candidates are bundled into the answer rather than trickled through
+    // the relay; this bounds how long an offer can hold up the relay loop.
+    const GATHER_TIMEOUT: Duration = Duration::from_secs(3);
+
+    pub async fn accept(
+        session_id: &str,
+        sdp: &str,
+        config: &WebRtcConfig,
+        events: Arc<dyn EventSink>,
+        state: Arc<RunnerState>,
+    ) -> Result<Arc<PeerConnection>, String> {
+        let api = APIBuilder::new().build();
+        let rtc_config = RTCConfiguration {
+            ice_servers: vec![RTCIceServer {
+                urls: config.ice_servers.clone(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let peer = Arc::new(api.new_peer_connection(rtc_config).await.map_err(|e| e.to_string())?);
+
+        let id = session_id.to_string();
+        peer.on_peer_connection_state_change(Box::new(move |connection_state| {
+            if connection_state == RTCPeerConnectionState::Failed {
+                tracing::error!(sessionId = %id, "Direct connection {}: {}", id, connection_state);
+            } else {
+                tracing::info!(sessionId = %id, "Direct connection {}: {}", id, connection_state);
+            }
+            Box::pin(async {})
+        }));
+
+        peer.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+            let events = events.clone();
+            let state = state.clone();
+            let weak = Arc::downgrade(&channel);
+            channel.on_message(Box::new(move |message: DataChannelMessage| {
+                if let Some(channel) = weak.upgrade() {
+                    tokio::spawn(serve(channel, message, events.clone(), state.clone()));
+                }
+                Box::pin(async {})
+            }));
+            Box::pin(async {})
+        }));
+
+        if let Err(e) = negotiate(&peer, sdp).await {
+            close(peer);
+            return Err(e);
+        }
+        Ok(peer)
+    }
+
+    async fn negotiate(peer: &PeerConnection, sdp: &str) -> Result<(), String> {
+        let offer = RTCSessionDescription::offer(sdp.to_string()).map_err(|e| e.to_string())?;
+        peer.set_remote_description(offer).await.map_err(|e| e.to_string())?;
+        let answer = peer.create_answer(None).await.map_err(|e| e.to_string())?;
+        let mut gathered = peer.gathering_complete_promise().await;
+        peer.set_local_description(answer).await.map_err(|e| e.to_string())?;
+        let _ = tokio::time::timeout(GATHER_TIMEOUT, gathered.recv()).await;
+        Ok(())
+    }
+
+    // Channel frames use the relay's chat_request / chat_response format
+    async fn serve(
+        channel: Arc<RTCDataChannel>,
+        message: DataChannelMessage,
+        events: Arc<dyn EventSink>,
+        state: Arc<RunnerState>,
+    ) {
+        let Some((request_id, model, input, options)) = serde_json::from_slice::<ServerMessage>(&message.data)
+            .ok()
+            .and_then(ServerMessage::into_request)
+        else {
+            return;
+        };
+        let (replies, mut chunks) = tokio::sync::mpsc::unbounded_channel();
+        let forward_channel = channel.clone();
+        let forward = tokio::spawn(async move {
+            while let Some(chunk) = chunks.recv().await {
+                if let Ok(json) = serde_json::to_string(&chunk) {
+                    let _ = forward_channel.send_text(json).await;
+                }
+            }
+        });
+
+        let response = handle_request(request_id, model, input, options, events.as_ref(), &state, Some(&replies)).await;
+        drop(replies);
+        let _ = forward.await;
+        if let Ok(json) = serde_json::to_string(&response) {
+            let _ = channel.send_text(json).await;
+        }
+    }
+
+    pub fn is_finished(peer: &PeerConnection) -> bool {
+        matches!(
+            peer.connection_state(),
+            RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+        )
+    }
+
+    pub fn close(peer: Arc<PeerConnection>) {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _ = peer.close().await;
+            });
+        }
+    }
+}
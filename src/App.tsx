@@ -2,7 +2,7 @@ import { useState, useEffect, useCallback } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
 import { listen } from '@tauri-apps/api/event';
 
-type ConnectionStatus = 'disconnected' | 'connecting' | 'connected' | 'error';
+type ConnectionStatus = 'disconnected' | 'connecting' | 'connected' | 'reconnecting' | 'stale' | 'scheduled' | 'error' | 'auth_failed';
 
 interface LogEntry {
   timestamp: string;
@@ -10,14 +10,69 @@ interface LogEntry {
   type: 'info' | 'error' | 'success';
 }
 
+interface ModelStats {
+  model: string;
+  requests: number;
+  errors: number;
+  inputTokens: number;
+  outputTokens: number;
+  avgLatencyMs: number;
+  tokensPerSec: number;
+}
+
+interface NotificationSettings {
+  connectionLost: boolean;
+  authFailed: boolean;
+  firstRequest: boolean;
+  idleMinutes: number;
+  backendUnreachable: boolean;
+}
+
+interface Account {
+  id: string;
+  name: string;
+  status: ConnectionStatus;
+}
+
+type NotificationKey = 'connectionLost' | 'authFailed' | 'firstRequest' | 'backendUnreachable';
+
+const NOTIFICATION_OPTIONS: [NotificationKey, string][] = [
+  ['connectionLost', 'The relay connection drops'],
+  ['authFailed', 'The token is rejected'],
+  ['firstRequest', 'A request is served after a quiet spell'],
+  ['backendUnreachable', 'Ollama stops answering'],
+];
+
+// Commands reject with `{ code, message, retryable }` or a plain string
+function describeError(err: unknown): string {
+  if (err instanceof Error) return err.message;
+  if (typeof err === 'object' && err !== null && 'message' in err) return String(err.message);
+  return String(err);
+}
+
 function App() {
   const [status, setStatus] = useState<ConnectionStatus>('disconnected');
   const [token, setToken] = useState('');
   const [savedToken, setSavedToken] = useState('');
   const [models, setModels] = useState<string[]>([]);
+  const [loadedModels, setLoadedModels] = useState<string[]>([]);
+  const [modelStats, setModelStats] = useState<ModelStats[]>([]);
   const [error, setError] = useState<string | null>(null);
   const [logs, setLogs] = useState<LogEntry[]>([]);
   const [ollamaStatus, setOllamaStatus] = useState<'unknown' | 'running' | 'stopped'>('unknown');
+  const [paused, setPaused] = useState(false);
+  const [autoConnect, setAutoConnect] = useState(false);
+  const [privacyMode, setPrivacyMode] = useState(false);
+  const [preventSleep, setPreventSleep] = useState(true);
+  const [pauseOnBattery, setPauseOnBattery] = useState(false);
+  const [autostart, setAutostart] = useState(false);
+  const [startMinimized, setStartMinimized] = useState(false);
+  const [notifications, setNotifications] = useState<NotificationSettings | null>(null);
+  const [accounts, setAccounts] = useState<Account[]>([]);
+  const [newAccount, setNewAccount] = useState({ name: '', token: '' });
+  const [pairingRequested, setPairingRequested] = useState(false);
+  const [pairingCode, setPairingCode] = useState<{ code: string; qrPayload: string } | null>(null);
+  const [pull, setPull] = useState<{ model: string; status: string; percent?: number } | null>(null);
 
   const addLog = useCallback((message: string, type: LogEntry['type'] = 'info') => {
     const entry: LogEntry = {
@@ -42,6 +97,51 @@ function App() {
         console.error('Failed to load token:', err);
       });
 
+    // Runner log lines from before this window was listening
+    invoke<{ ts: number; level: string; message: string; success?: boolean }[]>('get_recent_logs', { limit: 100 })
+      .then((records) => {
+        const earlier = records.map((record): LogEntry => ({
+          timestamp: new Date(record.ts).toLocaleTimeString(),
+          message: record.message,
+          type: record.level === 'error' || record.level === 'warn' ? 'error' : record.success ? 'success' : 'info',
+        }));
+        setLogs((prev) => [...earlier, ...prev].slice(-100));
+      })
+      .catch(() => {});
+
+    // Auto-connect may have started before this window was listening
+    invoke<{ status: ConnectionStatus; paused: boolean; models: ModelStats[] }>('get_stats')
+      .then((stats) => {
+        setStatus(stats.status);
+        setPaused(stats.paused);
+        setModelStats(stats.models);
+      })
+      .catch(() => {});
+
+    invoke<{ autoConnect: boolean; startMinimized: boolean; privacyMode: boolean; preventSleep: boolean; pauseOnBattery: boolean; notifications: NotificationSettings }>('get_settings')
+      .then((settings) => {
+        setPreventSleep(settings.preventSleep);
+        setPauseOnBattery(settings.pauseOnBattery);
+        setAutoConnect(settings.autoConnect);
+        setStartMinimized(settings.startMinimized);
+        setPrivacyMode(settings.privacyMode);
+        setNotifications(settings.notifications);
+      })
+      .catch(() => {});
+
+    invoke<boolean>('is_autostart_enabled')
+      .then(setAutostart)
+      .catch(() => {});
+
+    invoke<Account[]>('list_accounts')
+      .then(setAccounts)
+      .catch(() => {});
+
+    // A pairing link may have launched the app
+    invoke<boolean>('pending_pairing')
+      .then(setPairingRequested)
+      .catch(() => {});
+
     // Check Ollama status
     invoke<boolean>('check_ollama')
       .then((running) => {
@@ -54,12 +154,27 @@ function App() {
       });
 
     // Listen for events from Rust backend
-    const unlistenStatus = listen<{ status: ConnectionStatus; error?: string }>('connection-status', (event) => {
+    const unlistenStatus = listen<{ status: ConnectionStatus; error?: string; attempt?: number; delayMs?: number; account?: string }>('connection-status', (event) => {
+      // Only the other accounts' events carry their id
+      const account = event.payload.account ?? 'default';
+      setAccounts((prev) => prev.map((a) => (a.id === account ? { ...a, status: event.payload.status } : a)));
+      if (event.payload.account) {
+        if (event.payload.status === 'auth_failed' || event.payload.status === 'error') {
+          addLog(`${event.payload.account}: ${event.payload.error ?? 'connection failed'}`, 'error');
+        }
+        return;
+      }
       setStatus(event.payload.status);
-      if (event.payload.error) {
+      if (event.payload.status === 'reconnecting') {
+        const seconds = Math.round((event.payload.delayMs ?? 0) / 1000);
+        addLog(`${event.payload.error ?? 'Connection lost'}; reconnecting in ${seconds}s (attempt ${event.payload.attempt})`, 'error');
+      } else if (event.payload.status === 'stale') {
+        addLog(`Connection went quiet: ${event.payload.error ?? 'no response from relay'}`, 'error');
+      } else if (event.payload.error) {
         setError(event.payload.error);
         addLog(event.payload.error, 'error');
       } else if (event.payload.status === 'connected') {
+        setError(null);
         addLog('Connected to BottleCapAI', 'success');
       } else if (event.payload.status === 'disconnected') {
         addLog('Disconnected from BottleCapAI', 'info');
@@ -71,14 +186,66 @@ function App() {
       addLog(`Found ${event.payload.length} models: ${event.payload.join(', ')}`, 'info');
     });
 
+    const unlistenLoaded = listen<{ name: string }[]>('loaded-models', (event) => {
+      setLoadedModels(event.payload.map((model) => model.name));
+    });
+
+    const unlistenStats = listen<{ models: ModelStats[] }>('stats-updated', (event) => {
+      setModelStats(event.payload.models);
+    });
+
+    const unlistenPaused = listen<{ paused: boolean }>('runner-paused', (event) => {
+      setPaused(event.payload.paused);
+      addLog(event.payload.paused ? 'Paused: new requests are rejected' : 'Resumed serving requests', 'info');
+    });
+
     const unlistenLog = listen<{ message: string; type: LogEntry['type'] }>('log-message', (event) => {
       addLog(event.payload.message, event.payload.type);
     });
 
+    const unlistenOllamaStart = listen<{ stage: string; message: string }>('ollama-start-progress', (event) => {
+      const { stage, message } = event.payload;
+      addLog(message, stage === 'failed' ? 'error' : stage === 'ready' ? 'success' : 'info');
+      if (stage === 'ready') setOllamaStatus('running');
+    });
+
+    const unlistenPull = listen<{ model: string; status: string; total?: number; completed?: number }>('model-pull-progress', (event) => {
+      const { model, status, total, completed } = event.payload;
+      if (status === 'success' || status === 'error' || status === 'cancelled') {
+        setPull(null);
+      } else {
+        const percent = total ? Math.round(((completed ?? 0) / total) * 100) : undefined;
+        setPull({ model, status, percent });
+      }
+    });
+
+    const unlistenPairingRequested = listen('pairing-requested', () => {
+      setPairingRequested(true);
+    });
+
+    const unlistenPaired = listen('paired', () => {
+      setPairingRequested(false);
+      setPairingCode(null);
+      addLog('Paired with BottleCapAI', 'success');
+      invoke<string | null>('get_saved_token')
+        .then((token) => {
+          setSavedToken(token ?? '');
+          setToken(token ?? '');
+        })
+        .catch(() => {});
+    });
+
     return () => {
       unlistenStatus.then((fn) => fn());
       unlistenModels.then((fn) => fn());
+      unlistenLoaded.then((fn) => fn());
+      unlistenStats.then((fn) => fn());
+      unlistenPaused.then((fn) => fn());
       unlistenLog.then((fn) => fn());
+      unlistenOllamaStart.then((fn) => fn());
+      unlistenPull.then((fn) => fn());
+      unlistenPairingRequested.then((fn) => fn());
+      unlistenPaired.then((fn) => fn());
     };
   }, [addLog]);
 
@@ -99,7 +266,7 @@ function App() {
       setSavedToken(token);
     } catch (err: unknown) {
       setStatus('error');
-      const errorMessage = err instanceof Error ? err.message : String(err);
+      const errorMessage = describeError(err);
       setError(errorMessage);
       addLog(`Connection failed: ${errorMessage}`, 'error');
     }
@@ -116,6 +283,123 @@ function App() {
     }
   };
 
+  const toggleAutoConnect = async (enabled: boolean) => {
+    try {
+      await invoke('update_settings', { settings: { autoConnect: enabled } });
+      setAutoConnect(enabled);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const toggleAutostart = async (enabled: boolean) => {
+    try {
+      await invoke(enabled ? 'enable_autostart' : 'disable_autostart');
+      setAutostart(enabled);
+    } catch (err) {
+      addLog(`Failed to change launch at login: ${describeError(err)}`, 'error');
+    }
+  };
+
+  const toggleStartMinimized = async (enabled: boolean) => {
+    try {
+      await invoke('update_settings', { settings: { startMinimized: enabled } });
+      setStartMinimized(enabled);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const togglePreventSleep = async (enabled: boolean) => {
+    try {
+      await invoke('update_settings', { settings: { preventSleep: enabled } });
+      setPreventSleep(enabled);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const togglePauseOnBattery = async (enabled: boolean) => {
+    try {
+      await invoke('update_settings', { settings: { pauseOnBattery: enabled } });
+      setPauseOnBattery(enabled);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const togglePrivacyMode = async (enabled: boolean) => {
+    try {
+      await invoke('update_settings', { settings: { privacyMode: enabled } });
+      setPrivacyMode(enabled);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const toggleNotification = async (key: NotificationKey, enabled: boolean) => {
+    if (!notifications) return;
+    const updated = { ...notifications, [key]: enabled };
+    try {
+      await invoke('update_settings', { settings: { notifications: updated } });
+      setNotifications(updated);
+    } catch (err) {
+      console.error('Settings error:', err);
+    }
+  };
+
+  const togglePaused = async () => {
+    try {
+      await invoke(paused ? 'resume_runner' : 'pause_runner');
+    } catch (err) {
+      console.error('Pause error:', err);
+    }
+  };
+
+  const addAccount = async () => {
+    try {
+      await invoke('add_account', newAccount);
+      setNewAccount({ name: '', token: '' });
+      setAccounts(await invoke<Account[]>('list_accounts'));
+    } catch (err) {
+      addLog(`Failed to add account: ${describeError(err)}`, 'error');
+    }
+  };
+
+  const removeAccount = async (id: string) => {
+    try {
+      await invoke('remove_account', { id });
+      setAccounts(await invoke<Account[]>('list_accounts'));
+    } catch (err) {
+      addLog(`Failed to remove account: ${describeError(err)}`, 'error');
+    }
+  };
+
+  const toggleAccount = async (account: Account) => {
+    try {
+      await invoke(account.status === 'disconnected' ? 'connect_account' : 'disconnect_account', { id: account.id });
+    } catch (err) {
+      addLog(`${account.name}: ${describeError(err)}`, 'error');
+    }
+  };
+
+  const answerPairing = async (accept: boolean) => {
+    setPairingRequested(false);
+    try {
+      await invoke(accept ? 'accept_pairing' : 'reject_pairing');
+    } catch (err) {
+      addLog(`Pairing failed: ${describeError(err)}`, 'error');
+    }
+  };
+
+  const showPairingCode = async () => {
+    try {
+      setPairingCode(await invoke<{ code: string; qrPayload: string }>('get_pairing_code'));
+    } catch (err) {
+      addLog(`Could not create a pairing code: ${describeError(err)}`, 'error');
+    }
+  };
+
   const clearToken = async () => {
     await invoke('clear_token');
     setSavedToken('');
@@ -165,6 +449,14 @@ function App() {
                   Open Terminal and run: <code className="bg-amber-100 px-1 rounded">ollama pull llama3.2</code>
                 </li>
               </ol>
+              <button
+                onClick={() => {
+                  invoke('start_ollama').catch(() => {});
+                }}
+                className="mt-3 mr-4 text-xs text-amber-800 font-medium hover:text-amber-900"
+              >
+                Start Ollama
+              </button>
               <button
                 onClick={() => {
                   invoke<boolean>('check_ollama').then((running) => {
@@ -190,6 +482,27 @@ function App() {
               <p className="text-xs text-blue-600 mt-2">
                 Other popular models: <code className="bg-blue-100 px-1 rounded">mistral</code>, <code className="bg-blue-100 px-1 rounded">codellama</code>, <code className="bg-blue-100 px-1 rounded">deepseek-r1</code>
               </p>
+              {pull ? (
+                <div className="mt-3 flex items-center justify-between text-xs text-blue-800">
+                  <span>
+                    {pull.model}: {pull.status}
+                    {pull.percent !== undefined && ` (${pull.percent}%)`}
+                  </span>
+                  <button onClick={() => invoke('cancel_pull', { model: pull.model })} className="font-medium hover:text-blue-900">
+                    Cancel
+                  </button>
+                </div>
+              ) : (
+                <button
+                  onClick={() => {
+                    setPull({ model: 'llama3.2', status: 'starting' });
+                    invoke('pull_model', { model: 'llama3.2' }).catch(() => setPull(null));
+                  }}
+                  className="mt-3 text-xs text-blue-800 font-medium hover:text-blue-900"
+                >
+                  Pull llama3.2 now →
+                </button>
+              )}
             </div>
           )}
         </div>
@@ -201,8 +514,23 @@ function App() {
             <StatusBadge status={status} />
           </div>
 
-          {status === 'disconnected' || status === 'error' || status === 'connecting' ? (
+          {status === 'disconnected' || status === 'error' || status === 'auth_failed' || status === 'connecting' ? (
             <div className="space-y-4">
+              {pairingRequested && (
+                <div className="p-3 bg-blue-50 border border-blue-200 rounded-lg">
+                  <p className="text-blue-800 text-sm font-medium">BottleCapAI sent a runner token</p>
+                  <p className="text-blue-700 text-xs mt-1">Connect with it? Only accept if you just asked for it in your browser.</p>
+                  <div className="flex gap-3 mt-2">
+                    <button onClick={() => answerPairing(true)} className="text-xs text-blue-800 font-medium hover:text-blue-900">
+                      Connect
+                    </button>
+                    <button onClick={() => answerPairing(false)} className="text-xs text-slate-500 hover:text-slate-700">
+                      Ignore
+                    </button>
+                  </div>
+                </div>
+              )}
+
               <div>
                 <label className="block text-sm font-medium text-slate-600 mb-1">Runner Token</label>
                 <input
@@ -220,6 +548,23 @@ function App() {
                 </p>
               </div>
 
+              {pairingCode ? (
+                <div className="p-3 bg-slate-50 border border-slate-200 rounded-lg text-xs text-slate-600">
+                  <p>
+                    Pairing code <code className="bg-slate-100 px-1 rounded font-semibold">{pairingCode.code}</code>, valid for 10 minutes.
+                    Open{' '}
+                    <a href={pairingCode.qrPayload} target="_blank" rel="noopener noreferrer" className="text-blue-600 hover:underline">
+                      the pairing page
+                    </a>{' '}
+                    or scan it as a QR code: <span className="break-all">{pairingCode.qrPayload}</span>
+                  </p>
+                </div>
+              ) : (
+                <button onClick={showPairingCode} className="text-xs text-blue-600 hover:underline">
+                  Pair with the browser instead
+                </button>
+              )}
+
               {savedToken && token !== savedToken && (
                 <button onClick={() => setToken(savedToken)} className="text-xs text-blue-600 hover:underline">
                   Use saved token
@@ -234,6 +579,50 @@ function App() {
                 {status === 'connecting' ? 'Connecting...' : 'Connect'}
               </button>
 
+              <label className="flex items-center gap-2 text-xs text-slate-600">
+                <input type="checkbox" checked={autoConnect} onChange={(e) => toggleAutoConnect(e.target.checked)} />
+                Connect automatically when the app starts
+              </label>
+
+              <label className="flex items-center gap-2 text-xs text-slate-600">
+                <input type="checkbox" checked={autostart} onChange={(e) => toggleAutostart(e.target.checked)} />
+                Launch when I log in
+              </label>
+
+              {autostart && (
+                <label className="flex items-center gap-2 text-xs text-slate-600 pl-5">
+                  <input type="checkbox" checked={startMinimized} onChange={(e) => toggleStartMinimized(e.target.checked)} />
+                  Start minimized to the tray
+                </label>
+              )}
+
+              <label className="flex items-center gap-2 text-xs text-slate-600">
+                <input type="checkbox" checked={privacyMode} onChange={(e) => togglePrivacyMode(e.target.checked)} />
+                Privacy mode: never save prompts or responses
+              </label>
+
+              <label className="flex items-center gap-2 text-xs text-slate-600">
+                <input type="checkbox" checked={preventSleep} onChange={(e) => togglePreventSleep(e.target.checked)} />
+                Keep the computer awake while serving requests
+              </label>
+
+              <label className="flex items-center gap-2 text-xs text-slate-600">
+                <input type="checkbox" checked={pauseOnBattery} onChange={(e) => togglePauseOnBattery(e.target.checked)} />
+                Go offline while on battery power
+              </label>
+
+              {notifications && (
+                <div className="space-y-1">
+                  <p className="text-xs font-medium text-slate-700">Notify me when</p>
+                  {NOTIFICATION_OPTIONS.map(([key, label]) => (
+                    <label key={key} className="flex items-center gap-2 text-xs text-slate-600">
+                      <input type="checkbox" checked={notifications[key]} onChange={(e) => toggleNotification(key, e.target.checked)} />
+                      {label}
+                    </label>
+                  ))}
+                </div>
+              )}
+
               {savedToken && (
                 <button onClick={clearToken} className="w-full text-xs text-slate-500 hover:text-slate-700">
                   Clear saved token
@@ -242,10 +631,28 @@ function App() {
             </div>
           ) : (
             <div className="space-y-4">
-              <div className="p-3 bg-green-50 border border-green-200 rounded-lg">
-                <p className="text-green-700 text-sm font-medium">Connected and ready</p>
-                <p className="text-green-600 text-xs mt-1">Waiting for requests from BottleCapAI</p>
-              </div>
+              {status === 'reconnecting' || status === 'stale' ? (
+                <div className="p-3 bg-yellow-50 border border-yellow-200 rounded-lg">
+                  <p className="text-yellow-700 text-sm font-medium">Connection lost</p>
+                  <p className="text-yellow-600 text-xs mt-1">Reconnecting automatically</p>
+                </div>
+              ) : paused ? (
+                <div className="p-3 bg-slate-50 border border-slate-200 rounded-lg">
+                  <p className="text-slate-700 text-sm font-medium">Paused</p>
+                  <p className="text-slate-500 text-xs mt-1">Still connected; new requests are turned away</p>
+                </div>
+              ) : (
+                <div className="p-3 bg-green-50 border border-green-200 rounded-lg">
+                  <p className="text-green-700 text-sm font-medium">Connected and ready</p>
+                  <p className="text-green-600 text-xs mt-1">Waiting for requests from BottleCapAI</p>
+                </div>
+              )}
+              <button
+                onClick={togglePaused}
+                className="w-full py-2 bg-slate-100 text-slate-700 rounded-lg hover:bg-slate-200 transition-colors font-medium"
+              >
+                {paused ? 'Resume Requests' : 'Pause Requests'}
+              </button>
               <button
                 onClick={handleDisconnect}
                 className="w-full py-2 bg-slate-100 text-slate-700 rounded-lg hover:bg-slate-200 transition-colors font-medium"
@@ -262,13 +669,64 @@ function App() {
           )}
         </div>
 
+        {/* Accounts */}
+        <div className="bg-white rounded-xl p-5 shadow-sm border border-slate-200">
+          <h2 className="font-semibold text-slate-700 mb-3">Accounts</h2>
+          <div className="space-y-2">
+            {accounts.map((account) => (
+              <div key={account.id} className="flex items-center justify-between text-sm">
+                <span className="text-slate-700">{account.name}</span>
+                <div className="flex items-center gap-3">
+                  <StatusBadge status={account.status} />
+                  <button onClick={() => toggleAccount(account)} className="text-xs text-blue-600 hover:underline">
+                    {account.status === 'disconnected' ? 'Connect' : 'Disconnect'}
+                  </button>
+                  {account.id !== 'default' && (
+                    <button onClick={() => removeAccount(account.id)} className="text-xs text-slate-500 hover:text-slate-700">
+                      Remove
+                    </button>
+                  )}
+                </div>
+              </div>
+            ))}
+          </div>
+          <div className="flex gap-2 mt-4">
+            <input
+              value={newAccount.name}
+              onChange={(e) => setNewAccount({ ...newAccount, name: e.target.value })}
+              placeholder="Workspace name"
+              className="flex-1 px-3 py-1.5 border border-slate-300 rounded-lg outline-none text-sm"
+            />
+            <input
+              type="password"
+              value={newAccount.token}
+              onChange={(e) => setNewAccount({ ...newAccount, token: e.target.value })}
+              placeholder="bc_runner_..."
+              className="flex-1 px-3 py-1.5 border border-slate-300 rounded-lg outline-none text-sm"
+            />
+            <button
+              onClick={addAccount}
+              disabled={!newAccount.name || !newAccount.token.startsWith('bc_runner_')}
+              className="px-3 py-1.5 bg-slate-100 text-slate-700 rounded-lg hover:bg-slate-200 disabled:opacity-50 text-sm font-medium"
+            >
+              Add
+            </button>
+          </div>
+        </div>
+
         {/* Available Models */}
         {status === 'connected' && models.length > 0 && (
           <div className="bg-white rounded-xl p-5 shadow-sm border border-slate-200">
             <h2 className="font-semibold text-slate-700 mb-3">Available Models</h2>
             <div className="flex flex-wrap gap-2">
               {models.map((model) => (
-                <span key={model} className="px-3 py-1 bg-slate-100 text-slate-700 rounded-full text-sm">
+                <span
+                  key={model}
+                  className={`px-3 py-1 rounded-full text-sm ${
+                    loadedModels.includes(model) ? 'bg-green-100 text-green-800' : 'bg-slate-100 text-slate-700'
+                  }`}
+                  title={loadedModels.includes(model) ? 'Loaded in memory' : undefined}
+                >
                   {model}
                 </span>
               ))}
@@ -276,6 +734,39 @@ function App() {
           </div>
         )}
 
+        {/* Per-model Usage */}
+        {modelStats.length > 0 && (
+          <div className="bg-white rounded-xl p-5 shadow-sm border border-slate-200">
+            <h2 className="font-semibold text-slate-700 mb-3">Usage by Model</h2>
+            <table className="w-full text-sm">
+              <thead>
+                <tr className="text-left text-slate-500">
+                  <th className="font-medium pb-2">Model</th>
+                  <th className="font-medium pb-2 text-right">Requests</th>
+                  <th className="font-medium pb-2 text-right">Errors</th>
+                  <th className="font-medium pb-2 text-right">Tokens in / out</th>
+                  <th className="font-medium pb-2 text-right">Avg latency</th>
+                  <th className="font-medium pb-2 text-right">Tokens/s</th>
+                </tr>
+              </thead>
+              <tbody className="text-slate-700">
+                {modelStats.map((stats) => (
+                  <tr key={stats.model} className="border-t border-slate-100">
+                    <td className="py-1">{stats.model}</td>
+                    <td className="py-1 text-right">{stats.requests}</td>
+                    <td className={`py-1 text-right ${stats.errors > 0 ? 'text-red-600' : ''}`}>{stats.errors}</td>
+                    <td className="py-1 text-right">
+                      {stats.inputTokens} / {stats.outputTokens}
+                    </td>
+                    <td className="py-1 text-right">{(stats.avgLatencyMs / 1000).toFixed(1)}s</td>
+                    <td className="py-1 text-right">{stats.tokensPerSec}</td>
+                  </tr>
+                ))}
+              </tbody>
+            </table>
+          </div>
+        )}
+
         {/* Activity Log */}
         <div className="bg-white rounded-xl p-5 shadow-sm border border-slate-200">
           <h2 className="font-semibold text-slate-700 mb-3">Activity</h2>
@@ -306,14 +797,22 @@ function StatusBadge({ status }: { status: ConnectionStatus }) {
     disconnected: 'bg-slate-100 text-slate-600',
     connecting: 'bg-yellow-100 text-yellow-700',
     connected: 'bg-green-100 text-green-700',
+    reconnecting: 'bg-yellow-100 text-yellow-700',
+    stale: 'bg-yellow-100 text-yellow-700',
+    scheduled: 'bg-blue-100 text-blue-700',
     error: 'bg-red-100 text-red-700',
+    auth_failed: 'bg-red-100 text-red-700',
   };
 
   const labels = {
     disconnected: 'Disconnected',
     connecting: 'Connecting...',
     connected: 'Connected',
+    reconnecting: 'Reconnecting...',
+    stale: 'Reconnecting...',
+    scheduled: 'Outside schedule',
     error: 'Error',
+    auth_failed: 'Token rejected',
   };
 
   return <span className={`px-3 py-1 rounded-full text-xs font-medium ${styles[status]}`}>{labels[status]}</span>;